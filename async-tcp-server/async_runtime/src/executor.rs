@@ -1,16 +1,31 @@
 use std::{
     collections::VecDeque,
     future::Future,
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
     pin::Pin,
     sync::{Arc, mpsc},
     task::{Context, Poll, Waker},
 };
 
-use crate::waker::create_raw_waker;
+use crate::{
+    trace::{self, TaskEventKind},
+    waker::create_raw_waker,
+};
 
 pub struct Task {
+    id: u64,
     future: Pin<Box<dyn Future<Output = ()> + Send>>,
     waker: Arc<Waker>,
+    completed: bool,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        // パニックによる破棄は、エグゼキューターがパニックイベントとして通知する
+        if !self.completed && !std::thread::panicking() {
+            trace::emit(TaskEventKind::Cancelled, self.id, None);
+        }
+    }
 }
 
 #[derive(Default)]
@@ -31,10 +46,14 @@ impl Executor {
             // Futureは`()`を返す。
             // Futureの結果はmpscチャネルの受信側から受け取る。
         });
+        let id = trace::next_task_id();
         let task = Task {
+            id,
             future,
-            waker: self.create_waker(),
+            waker: self.create_waker(id),
+            completed: false,
         };
+        trace::emit(TaskEventKind::Spawned, id, None);
         self.polling.push_back(task);
         rx
     }
@@ -46,15 +65,33 @@ impl Executor {
         };
         let waker = task.waker.clone();
         let context = &mut Context::from_waker(&waker);
-        match task.future.as_mut().poll(context) {
-            Poll::Ready(()) => {}
+        trace::emit(TaskEventKind::PollStarted, task.id, None);
+        let poll = catch_unwind(AssertUnwindSafe(|| {
+            let _enter = trace::EnterTask::new(task.id);
+            task.future.as_mut().poll(context)
+        }));
+        let poll = match poll {
+            Ok(poll) => poll,
+            Err(payload) => {
+                // パニックはこれまでどおり呼び出し元に伝搬させる
+                trace::emit(TaskEventKind::Panicked, task.id, None);
+                resume_unwind(payload);
+            }
+        };
+        match poll {
+            Poll::Ready(()) => {
+                task.completed = true;
+                trace::emit(TaskEventKind::PollEnded, task.id, None);
+                trace::emit(TaskEventKind::Completed, task.id, None);
+            }
             Poll::Pending => {
+                trace::emit(TaskEventKind::PollEnded, task.id, None);
                 self.polling.push_back(task);
             }
         }
     }
 
-    pub fn create_waker(&self) -> Arc<Waker> {
-        Arc::new(unsafe { Waker::from_raw(create_raw_waker()) })
+    pub fn create_waker(&self, task_id: u64) -> Arc<Waker> {
+        Arc::new(unsafe { Waker::from_raw(create_raw_waker(task_id)) })
     }
 }
@@ -0,0 +1,95 @@
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::sync::{Arc, Mutex};
+
+/// タスクの結果を受け取れなかった
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinError;
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("task was dropped before completing")
+    }
+}
+
+impl Error for JoinError {}
+
+struct State<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+    /// 結果を設定する側が破棄された
+    closed: bool,
+}
+
+/// タスクの結果を[`JoinHandle`]に渡す側
+///
+/// 結果を設定せずに破棄すると、[`JoinHandle`]は[`JoinError`]を返す。
+pub struct Completion<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+/// タスクの結果を待つフューチャー
+///
+/// 結果を待つためにスレッドを起動せず、結果が設定されたときに最後にポーリングしたタスクを起こす。
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+/// タスクの結果を渡す[`Completion`]と、結果を待つ[`JoinHandle`]を作成する。
+pub fn join_handle<T>() -> (Completion<T>, JoinHandle<T>) {
+    let state = Arc::new(Mutex::new(State {
+        result: None,
+        waker: None,
+        closed: false,
+    }));
+    (
+        Completion {
+            state: state.clone(),
+        },
+        JoinHandle { state },
+    )
+}
+
+impl<T> Completion<T> {
+    /// 結果を設定して、待っているタスクを起こす。
+    pub fn complete(self, value: T) {
+        self.state.lock().unwrap().result = Some(value);
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.result.take() {
+            return Poll::Ready(Ok(value));
+        }
+        if state.closed {
+            return Poll::Ready(Err(JoinError));
+        }
+        match &mut state.waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            waker @ None => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
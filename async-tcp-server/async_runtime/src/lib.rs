@@ -1,5 +1,9 @@
 pub mod executor;
+pub mod join;
+pub mod park;
 pub mod receiver;
 pub mod sender;
 pub mod sleep;
+mod sync;
+pub mod trace;
 pub mod waker;
@@ -0,0 +1,40 @@
+use crate::sync::{AtomicBool, Ordering, Thread, thread};
+
+/// 処理がないワーカースレッドを待機させ、処理を渡した側が起こすためのフラグ
+///
+/// ワーカーは、待機することを知らせてから処理がないことを確かめ直して待機する。
+/// 処理を渡す側は、処理をキューに入れてからフラグを下ろし、フラグが立っていた場合だけワーカーを起こす。
+/// この順序により、ワーカーが処理を確かめてから待機するまでの間に渡された処理の通知は失われない。
+#[derive(Debug, Default)]
+pub struct Parker {
+    parked: AtomicBool,
+}
+
+impl Parker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `poll`で処理を取り出せない場合は、[`Parker::unpark`]が呼び出されるまで現在のスレッドを待機させる。
+    ///
+    /// 取り出した処理を返す。待機した後は`None`を返すため、呼び出し元はもう一度処理を取り出す。
+    pub fn park_unless<T>(&self, poll: impl FnOnce() -> Option<T>) -> Option<T> {
+        self.parked.store(true, Ordering::SeqCst);
+        // 待機することを知らせた後で確かめ直す
+        let item = poll();
+        if item.is_none() {
+            thread::park();
+        }
+        self.parked.store(false, Ordering::SeqCst);
+        item
+    }
+
+    /// [`Parker::park_unless`]で待機している、または待機しようとしているスレッドを起こす。
+    ///
+    /// 処理をワーカーのキューに入れた後に呼び出す。
+    pub fn unpark(&self, thread: &Thread) {
+        if self.parked.swap(false, Ordering::SeqCst) {
+            thread.unpark();
+        }
+    }
+}
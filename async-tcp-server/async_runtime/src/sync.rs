@@ -0,0 +1,19 @@
+// loomでモデル検査するときは、ワーカーの待機とタスクの完了の通知に使う同期プリミティブをloomのものに置き換える。
+
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, Thread},
+};
+
+#[cfg(feature = "loom")]
+pub(crate) use loom::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, Thread},
+};
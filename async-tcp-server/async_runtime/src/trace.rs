@@ -0,0 +1,369 @@
+use std::{
+    cell::Cell,
+    fmt,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// タスクのライフサイクルで発生するイベントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskEventKind {
+    /// タスクが生成された。
+    Spawned,
+    /// エグゼキューターがタスクのポーリングを開始した。
+    PollStarted,
+    /// エグゼキューターがタスクのポーリングを終了した。
+    PollEnded,
+    /// タスクのウェイカーが呼び出された。
+    Woken,
+    /// タスクのフューチャーが完了した。
+    Completed,
+    /// タスクのポーリング中にパニックが発生した。
+    Panicked,
+    /// タスクが完了する前に破棄された。
+    Cancelled,
+}
+
+impl fmt::Display for TaskEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Spawned => "spawned",
+            Self::PollStarted => "poll_started",
+            Self::PollEnded => "poll_ended",
+            Self::Woken => "woken",
+            Self::Completed => "completed",
+            Self::Panicked => "panicked",
+            Self::Cancelled => "cancelled",
+        };
+        f.write_str(name)
+    }
+}
+
+/// タスクの優先度
+///
+/// 優先度を持つランタイムが、イベントに付与する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Priority {
+    High,
+    Low,
+}
+
+/// タスクのライフサイクルイベント
+#[derive(Debug, Clone)]
+pub struct TaskEvent {
+    pub kind: TaskEventKind,
+    pub task_id: u64,
+    /// タスクの優先度
+    ///
+    /// このクレートのエグゼキューターのように、タスクに優先度がないランタイムでは`None`。
+    pub priority: Option<Priority>,
+    /// イベントが発生したワーカーのID
+    ///
+    /// [`set_worker_id`]でIDが設定されていないスレッドで発生した場合は`None`。
+    pub worker_id: Option<usize>,
+    pub timestamp: SystemTime,
+}
+
+impl fmt::Display for TaskEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "[{}.{:06}] task={}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_micros(),
+            self.task_id,
+        )?;
+        if let Some(priority) = self.priority {
+            write!(f, " priority={priority:?}")?;
+        }
+        f.write_str(" worker=")?;
+        write_worker_id(f, self.worker_id)?;
+        write!(f, " {}", self.kind)
+    }
+}
+
+/// 接続で発生するイベントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionEventKind {
+    /// 接続の処理を開始した。
+    Accepted,
+    /// 接続の処理を終了した。
+    Closed,
+}
+
+impl fmt::Display for ConnectionEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Accepted => "accepted",
+            Self::Closed => "closed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// サーバーが受け付けた接続のイベント
+///
+/// 接続を処理するタスクの中で[`emit_connection`]を呼び出して通知する。
+/// `Accepted`から`Closed`までが、1つの接続を処理した区間になる。
+#[derive(Debug, Clone)]
+pub struct ConnectionEvent {
+    pub kind: ConnectionEventKind,
+    /// 接続先のアドレス
+    ///
+    /// アドレスを取得できなかった場合は`None`。
+    pub peer: Option<SocketAddr>,
+    /// 接続を処理しているタスクのID
+    ///
+    /// エグゼキューターがポーリングしているタスクの外で発生した場合は`None`。
+    pub task_id: Option<u64>,
+    pub worker_id: Option<usize>,
+    pub timestamp: SystemTime,
+}
+
+impl fmt::Display for ConnectionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "[{}.{:06}] peer=",
+            since_epoch.as_secs(),
+            since_epoch.subsec_micros(),
+        )?;
+        match self.peer {
+            Some(peer) => write!(f, "{peer}")?,
+            None => f.write_str("-")?,
+        }
+        f.write_str(" task=")?;
+        match self.task_id {
+            Some(id) => write!(f, "{id}")?,
+            None => f.write_str("-")?,
+        }
+        f.write_str(" worker=")?;
+        write_worker_id(f, self.worker_id)?;
+        write!(f, " connection_{}", self.kind)
+    }
+}
+
+fn write_worker_id(f: &mut fmt::Formatter<'_>, worker_id: Option<usize>) -> fmt::Result {
+    match worker_id {
+        Some(id) => write!(f, "{id}"),
+        None => f.write_str("-"),
+    }
+}
+
+/// タスクのライフサイクルイベントを受け取るサブスクライバー
+///
+/// イベントはエグゼキューターを実行しているスレッド上で同期的に通知されるため、
+/// `on_event`で長時間ブロックしてはならない。
+pub trait Subscriber: Send + Sync {
+    fn on_event(&self, event: &TaskEvent);
+
+    /// 接続のイベントを受け取る。
+    ///
+    /// 既定では何もしない。
+    fn on_connection(&self, _event: &ConnectionEvent) {}
+}
+
+impl<S: Subscriber + ?Sized> Subscriber for Arc<S> {
+    fn on_event(&self, event: &TaskEvent) {
+        (**self).on_event(event);
+    }
+
+    fn on_connection(&self, event: &ConnectionEvent) {
+        (**self).on_connection(event);
+    }
+}
+
+/// イベントを標準エラー出力に1行ずつ出力するサブスクライバー
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSubscriber {
+    polls: bool,
+}
+
+impl StderrSubscriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ポーリングの開始と終了、起床のイベントも出力するか設定する。
+    ///
+    /// このランタイムのエグゼキューターは未完了のタスクを繰り返しポーリングするため、既定では出力しない。
+    pub fn with_polls(mut self, polls: bool) -> Self {
+        self.polls = polls;
+        self
+    }
+}
+
+impl Subscriber for StderrSubscriber {
+    fn on_event(&self, event: &TaskEvent) {
+        let is_poll = matches!(
+            event.kind,
+            TaskEventKind::PollStarted | TaskEventKind::PollEnded | TaskEventKind::Woken
+        );
+        if self.polls || !is_poll {
+            eprintln!("{event}");
+        }
+    }
+
+    fn on_connection(&self, event: &ConnectionEvent) {
+        eprintln!("{event}");
+    }
+}
+
+/// イベントをメモリに記録するサブスクライバー
+///
+/// テストで発生したイベントを検証するために使用する。
+#[derive(Debug, Default)]
+pub struct MemorySubscriber {
+    events: Mutex<Vec<TaskEvent>>,
+    connections: Mutex<Vec<ConnectionEvent>>,
+}
+
+impl MemorySubscriber {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// 記録したイベントのコピーを返す。
+    pub fn events(&self) -> Vec<TaskEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// 指定したタスクのイベントの種類を、発生した順に返す。
+    pub fn kinds_of(&self, task_id: u64) -> Vec<TaskEventKind> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.task_id == task_id)
+            .map(|event| event.kind)
+            .collect()
+    }
+
+    /// 記録した接続のイベントのコピーを返す。
+    pub fn connections(&self) -> Vec<ConnectionEvent> {
+        self.connections.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+        self.connections.lock().unwrap().clear();
+    }
+}
+
+impl Subscriber for MemorySubscriber {
+    fn on_event(&self, event: &TaskEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+
+    fn on_connection(&self, event: &ConnectionEvent) {
+        self.connections.lock().unwrap().push(event.clone());
+    }
+}
+
+static SUBSCRIBER: RwLock<Option<Arc<dyn Subscriber>>> = RwLock::new(None);
+
+thread_local! {
+    static WORKER_ID: Cell<Option<usize>> = const { Cell::new(None) };
+    static CURRENT_TASK: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// イベントを通知するサブスクライバーを設定する。
+///
+/// すでにサブスクライバーが設定されている場合は置き換える。
+pub fn set_subscriber<S: Subscriber + 'static>(subscriber: S) {
+    *SUBSCRIBER.write().unwrap() = Some(Arc::new(subscriber));
+}
+
+/// サブスクライバーを解除して、イベントの通知を停止する。
+pub fn clear_subscriber() {
+    *SUBSCRIBER.write().unwrap() = None;
+}
+
+/// 現在のスレッドで発生したイベントに付与するワーカーIDを設定する。
+///
+/// エグゼキューターを実行するスレッドの開始時に呼び出す。
+pub fn set_worker_id(id: usize) {
+    WORKER_ID.with(|worker_id| worker_id.set(Some(id)));
+}
+
+/// 現在のスレッドに設定されたワーカーIDを返す。
+pub fn current_worker_id() -> Option<usize> {
+    WORKER_ID.with(Cell::get)
+}
+
+/// 現在のスレッドでポーリングしているタスクのIDを返す。
+pub fn current_task_id() -> Option<u64> {
+    CURRENT_TASK.with(Cell::get)
+}
+
+/// タスクのポーリング中に、[`current_task_id`]が返すIDを設定するガード
+pub(crate) struct EnterTask {
+    previous: Option<u64>,
+}
+
+impl EnterTask {
+    pub(crate) fn new(task_id: u64) -> Self {
+        Self {
+            previous: CURRENT_TASK.with(|current| current.replace(Some(task_id))),
+        }
+    }
+}
+
+impl Drop for EnterTask {
+    fn drop(&mut self) {
+        CURRENT_TASK.with(|current| current.set(self.previous));
+    }
+}
+
+/// タスクにIDを採番する。
+///
+/// 独自のランタイムでイベントを通知する場合も、IDが重複しないようにこの関数で採番する。
+pub fn next_task_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// サブスクライバーにタスクのイベントを通知する。
+///
+/// ワーカーIDと時刻は、現在のスレッドと時刻から付与する。
+pub fn emit(kind: TaskEventKind, task_id: u64, priority: Option<Priority>) {
+    // サブスクライバーの中でサブスクライバーを変更できるように、ロックを解放してから通知する
+    let subscriber = SUBSCRIBER.read().unwrap().clone();
+    if let Some(subscriber) = subscriber {
+        subscriber.on_event(&TaskEvent {
+            kind,
+            task_id,
+            priority,
+            worker_id: current_worker_id(),
+            timestamp: SystemTime::now(),
+        });
+    }
+}
+
+/// サブスクライバーに接続のイベントを通知する。
+///
+/// 接続を処理するタスクの中で呼び出すと、イベントにタスクのIDが付与される。
+pub fn emit_connection(kind: ConnectionEventKind, peer: Option<SocketAddr>) {
+    let subscriber = SUBSCRIBER.read().unwrap().clone();
+    if let Some(subscriber) = subscriber {
+        subscriber.on_connection(&ConnectionEvent {
+            kind,
+            peer,
+            task_id: current_task_id(),
+            worker_id: current_worker_id(),
+            timestamp: SystemTime::now(),
+        });
+    }
+}
@@ -1,22 +1,31 @@
 use std::task::{RawWaker, RawWakerVTable};
 
+use crate::trace::{self, TaskEventKind};
+
 static VTABLE: RawWakerVTable = RawWakerVTable::new(my_clone, my_wake, my_wake_by_ref, my_drop);
 
+// ウェイカーのデータには、ウェイカーを所有するタスクのIDを格納する。
+
 unsafe fn my_clone(raw_waker: *const ()) -> RawWaker {
-    RawWaker::new(raw_waker, &VTABLE)
+    let task_id = unsafe { *(raw_waker as *const u64) };
+    create_raw_waker(task_id)
 }
 
 unsafe fn my_wake(raw_waker: *const ()) {
-    drop(unsafe { Box::from_raw(raw_waker as *mut u32) });
+    unsafe { my_wake_by_ref(raw_waker) };
+    unsafe { my_drop(raw_waker) };
 }
 
-unsafe fn my_wake_by_ref(_raw_waker: *const ()) {}
+unsafe fn my_wake_by_ref(raw_waker: *const ()) {
+    let task_id = unsafe { *(raw_waker as *const u64) };
+    trace::emit(TaskEventKind::Woken, task_id, None);
+}
 
 unsafe fn my_drop(raw_waker: *const ()) {
-    drop(unsafe { Box::from_raw(raw_waker as *mut u32) });
+    drop(unsafe { Box::from_raw(raw_waker as *mut u64) });
 }
 
-pub fn create_raw_waker() -> RawWaker {
-    let data = Box::into_raw(Box::new(42u32));
+pub fn create_raw_waker(task_id: u64) -> RawWaker {
+    let data = Box::into_raw(Box::new(task_id));
     RawWaker::new(data as *const (), &VTABLE)
 }
@@ -11,6 +11,7 @@ use std::{
     time::Duration,
 };
 
+use async_runtime::join::{JoinHandle, join_handle};
 use async_std::task::sleep;
 use waker_fn::waker_fn;
 
@@ -119,11 +120,10 @@ impl Executor {
         F: Future<Output = T> + 'static,
         T: Send + 'static,
     {
-        let rx = self.spawn_rcv(future);
-        JoinHandle {
-            receiver: Some(rx),
-            cache: Arc::new(None.into()),
-        }
+        // 結果はmpscチャネルではなく、JoinHandleに直接渡す
+        let (completion, handle) = join_handle();
+        self.spawn_rcv(async move { completion.complete(future.await) });
+        handle
     }
 
     fn poll(&self) {
@@ -162,55 +162,3 @@ impl Executor {
         }
     }
 }
-
-struct JoinHandle<T>
-where
-    T: 'static,
-{
-    receiver: Option<mpsc::Receiver<T>>,
-    cache: Arc<Mutex<Option<T>>>,
-}
-
-impl<T> Future for JoinHandle<T>
-where
-    T: Send + 'static,
-{
-    type Output = Result<T, mpsc::RecvError>;
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        {
-            // すでに結果がキャッシュされている場合はその値を返す
-            let mut guard = self.cache.lock().unwrap();
-            if guard.is_some() {
-                let value = guard.take().unwrap();
-                return Poll::Ready(Ok(value));
-            }
-        }
-
-        if self.receiver.is_none() {
-            // 結果を受信する受信機は、すでに受信を待機するスレッドにムーブされているため、待機を継続
-            return Poll::Pending;
-        }
-
-        // 結果の受信を試行
-        match self.receiver.as_ref().unwrap().try_recv() {
-            // 結果を受信できたた場合は、受信した値を結果として返す
-            Ok(value) => Poll::Ready(Ok(value)),
-            // 受信していない場合は、結果を受信するスレッドを起動
-            Err(mpsc::TryRecvError::Empty) => {
-                let waker = cx.waker().clone();
-                let receiver = self.receiver.take().unwrap();
-                let cache = Arc::clone(&self.cache);
-                std::thread::spawn(move || {
-                    // 結果を受信したらキャッシュに値を設定して、ウェイカーを呼び出すことで、
-                    // 再度pollを呼び出すようにする。
-                    let value = receiver.recv().unwrap();
-                    cache.lock().unwrap().replace(value);
-                    waker.wake();
-                });
-                Poll::Pending
-            }
-            _ => Poll::Ready(Err(mpsc::RecvError)),
-        }
-    }
-}
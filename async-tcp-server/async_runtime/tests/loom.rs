@@ -0,0 +1,61 @@
+//! `cargo test --release -p async_runtime --features loom --test loom`で実行する。
+#![cfg(feature = "loom")]
+
+use std::collections::VecDeque;
+
+use async_runtime::{
+    join::{JoinError, join_handle},
+    park::Parker,
+};
+use loom::{
+    future::block_on,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[test]
+fn unpark_after_enqueue_is_never_lost() {
+    loom::model(|| {
+        let parker = Arc::new(Parker::new());
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let worker = {
+            let parker = parker.clone();
+            let queue = queue.clone();
+            thread::spawn(move || {
+                // 処理を受け取るまで待機を繰り返す（通知が失われるとデッドロックする）
+                loop {
+                    let item = queue.lock().unwrap().pop_front();
+                    if let Some(item) = item {
+                        return item;
+                    }
+                    if let Some(item) = parker.park_unless(|| queue.lock().unwrap().pop_front()) {
+                        return item;
+                    }
+                }
+            })
+        };
+        queue.lock().unwrap().push_back(7);
+        parker.unpark(worker.thread());
+        assert_eq!(worker.join().unwrap(), 7);
+    });
+}
+
+#[test]
+fn join_handle_receives_the_result_from_another_thread() {
+    loom::model(|| {
+        let (completion, handle) = join_handle();
+        let task = thread::spawn(move || completion.complete(42));
+        assert_eq!(block_on(handle), Ok(42));
+        task.join().unwrap();
+    });
+}
+
+#[test]
+fn join_handle_fails_when_the_task_is_dropped() {
+    loom::model(|| {
+        let (completion, handle) = join_handle::<i32>();
+        let task = thread::spawn(move || drop(completion));
+        assert_eq!(block_on(handle), Err(JoinError));
+        task.join().unwrap();
+    });
+}
@@ -0,0 +1,108 @@
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind},
+    task::Poll,
+};
+
+use async_runtime::{
+    executor::Executor,
+    trace::{self, ConnectionEventKind, MemorySubscriber, TaskEventKind},
+};
+
+#[test]
+fn executor_lifecycle_events() {
+    let subscriber = MemorySubscriber::new();
+    trace::set_subscriber(subscriber.clone());
+    trace::set_worker_id(7);
+
+    let mut executor = Executor::default();
+    let mut yielded = false;
+    let rx = executor.spawn(std::future::poll_fn(move |cx| {
+        if yielded {
+            Poll::Ready(42)
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }));
+    while !executor.polling.is_empty() {
+        executor.poll();
+    }
+    assert_eq!(rx.recv().unwrap(), 42);
+
+    let events = subscriber.events();
+    let id = events[0].task_id;
+    assert!(events.iter().all(|e| e.worker_id == Some(7)));
+    assert_eq!(
+        subscriber.kinds_of(id),
+        [
+            TaskEventKind::Spawned,
+            TaskEventKind::PollStarted,
+            TaskEventKind::Woken,
+            TaskEventKind::PollEnded,
+            TaskEventKind::PollStarted,
+            TaskEventKind::PollEnded,
+            TaskEventKind::Completed,
+        ]
+    );
+
+    // 完了する前にエグゼキューターごと破棄されたタスク
+    subscriber.clear();
+    let mut executor = Executor::default();
+    let _rx = executor.spawn(std::future::pending::<()>());
+    executor.poll();
+    drop(executor);
+    let id = subscriber.events()[0].task_id;
+    assert_eq!(
+        subscriber.kinds_of(id),
+        [
+            TaskEventKind::Spawned,
+            TaskEventKind::PollStarted,
+            TaskEventKind::PollEnded,
+            TaskEventKind::Cancelled,
+        ]
+    );
+
+    // パニックは呼び出し元に伝搬する
+    subscriber.clear();
+    let mut executor = Executor::default();
+    let _rx = executor.spawn(async { panic!("boom") });
+    assert!(catch_unwind(AssertUnwindSafe(|| executor.poll())).is_err());
+    let id = subscriber.events()[0].task_id;
+    assert_eq!(
+        subscriber.kinds_of(id),
+        [
+            TaskEventKind::Spawned,
+            TaskEventKind::PollStarted,
+            TaskEventKind::Panicked,
+        ]
+    );
+
+    // 接続のイベントには、接続を処理しているタスクのIDが付与される
+    subscriber.clear();
+    let peer = "127.0.0.1:7878".parse().unwrap();
+    let mut executor = Executor::default();
+    let _rx = executor.spawn(async move {
+        trace::emit_connection(ConnectionEventKind::Accepted, Some(peer));
+        trace::emit_connection(ConnectionEventKind::Closed, Some(peer));
+    });
+    executor.poll();
+    trace::emit_connection(ConnectionEventKind::Accepted, None);
+    let id = subscriber.events()[0].task_id;
+    let connections = subscriber.connections();
+    assert_eq!(
+        connections.iter().map(|e| e.kind).collect::<Vec<_>>(),
+        [
+            ConnectionEventKind::Accepted,
+            ConnectionEventKind::Closed,
+            ConnectionEventKind::Accepted,
+        ]
+    );
+    assert!(connections[..2].iter().all(|e| e.peer == Some(peer)));
+    assert!(connections[..2].iter().all(|e| e.task_id == Some(id)));
+    assert!(connections.iter().all(|e| e.worker_id == Some(7)));
+    // タスクの外で発生したイベント
+    assert_eq!(connections[2].task_id, None);
+
+    trace::clear_subscriber();
+}
@@ -0,0 +1,95 @@
+use std::{io, time::Duration};
+
+pub const USAGE: &str = "\
+usage: client [OPTIONS]
+
+options:
+    --addr <ADDR>            接続先のアドレス (default: 127.0.0.1:7878)
+    --mode <open|closed>     open: 一定のレートでリクエストを送信
+                             closed: 一定の同時実行数でリクエストを送信 (default: closed)
+    --rate <N>               openモードで1秒あたりに送信するリクエスト数 (default: 100)
+    --concurrency <N>        closedモードで同時に実行するリクエスト数 (default: 100)
+    --duration <SECS>        負荷をかける秒数 (default: 10)
+    --json                   結果をJSONで標準出力に出力
+    -h, --help               このメッセージを表示";
+
+/// 負荷のかけ方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// オープンループ
+    ///
+    /// サーバーの応答を待たずに、一定のレートでリクエストを送信する。
+    Open { rate: f64 },
+    /// クローズドループ
+    ///
+    /// 一定の数のリクエストを同時に実行し、応答を受け取るたびに次のリクエストを送信する。
+    Closed { concurrency: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub addr: String,
+    pub mode: Mode,
+    pub duration: Duration,
+    pub json: bool,
+}
+
+impl Config {
+    /// コマンドライン引数（プログラム名を除く）から設定を構築する。
+    ///
+    /// `--help`が指定された場合は`Ok(None)`を返す。
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> io::Result<Option<Self>> {
+        let mut addr = String::from("127.0.0.1:7878");
+        let mut mode = String::from("closed");
+        let mut rate: f64 = 100.0;
+        let mut concurrency = 100;
+        let mut duration: f64 = 10.0;
+        let mut json = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--addr" => addr = value(&arg, args.next())?,
+                "--mode" => mode = value(&arg, args.next())?,
+                "--rate" => rate = parse(&arg, args.next())?,
+                "--concurrency" => concurrency = parse(&arg, args.next())?,
+                "--duration" => duration = parse(&arg, args.next())?,
+                "--json" => json = true,
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(invalid_input(format!("unknown option: {arg}"))),
+            }
+        }
+
+        let mode = match mode.as_str() {
+            "open" if rate.is_finite() && rate > 0.0 => Mode::Open { rate },
+            "open" => return Err(invalid_input("--rate must be greater than 0")),
+            "closed" if concurrency > 0 => Mode::Closed { concurrency },
+            "closed" => return Err(invalid_input("--concurrency must be greater than 0")),
+            _ => return Err(invalid_input(format!("unknown mode: {mode}"))),
+        };
+        if !duration.is_finite() || duration <= 0.0 {
+            return Err(invalid_input("--duration must be greater than 0"));
+        }
+
+        Ok(Some(Self {
+            addr,
+            mode,
+            duration: Duration::from_secs_f64(duration),
+            json,
+        }))
+    }
+}
+
+fn value(name: &str, value: Option<String>) -> io::Result<String> {
+    value.ok_or_else(|| invalid_input(format!("{name} requires a value")))
+}
+
+fn parse<T: std::str::FromStr>(name: &str, raw: Option<String>) -> io::Result<T> {
+    let raw = value(name, raw)?;
+    raw.parse()
+        .map_err(|_| invalid_input(format!("invalid value for {name}: {raw}")))
+}
+
+fn invalid_input(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
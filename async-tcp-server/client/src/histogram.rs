@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+/// 下位バケットのビット数
+///
+/// 2の累乗ごとの区間を64個のバケットに分割するため、記録される値の誤差は1.6%未満になる。
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKET_COUNT: u64 = 1 << SUB_BUCKET_BITS;
+const SUB_BUCKET_HALF: usize = (SUB_BUCKET_COUNT / 2) as usize;
+
+/// レイテンシをマイクロ秒単位で記録する対数線形ヒストグラム
+///
+/// HDR Histogramと同様に、値の大きさに比例した精度でバケットを割り当てるため、
+/// 数マイクロ秒から数時間までの値を固定サイズのメモリで記録できる。
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        let value = latency.as_micros().min(u64::MAX as u128) as u64;
+        let index = index_of(value);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        if self.total == 0 || value < self.min {
+            self.min = value;
+        }
+        self.max = self.max.max(value);
+        self.total += 1;
+        self.sum += value as u128;
+    }
+
+    /// 別のヒストグラムの記録をこのヒストグラムに加算する。
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.total == 0 {
+            return;
+        }
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        if self.total == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.total += other.total;
+        self.sum += other.sum;
+    }
+
+    /// 記録した値の数
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.total as f64
+        }
+    }
+
+    /// 指定したパーセンタイル（0.0〜100.0）の値をマイクロ秒で返す。
+    ///
+    /// 該当するバケットに入る最大の値を返すが、記録された最大値を超えることはない。
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((percentile / 100.0) * self.total as f64).ceil() as u64;
+        let rank = rank.clamp(1, self.total);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return highest_equivalent(index).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+fn index_of(value: u64) -> usize {
+    if value < SUB_BUCKET_COUNT {
+        return value as usize;
+    }
+    let msb = 63 - value.leading_zeros();
+    let shift = msb - (SUB_BUCKET_BITS - 1);
+    shift as usize * SUB_BUCKET_HALF + (value >> shift) as usize
+}
+
+fn highest_equivalent(index: usize) -> u64 {
+    if index < SUB_BUCKET_COUNT as usize {
+        return index as u64;
+    }
+    let shift = index / SUB_BUCKET_HALF - 1;
+    let sub = (index - shift * SUB_BUCKET_HALF) as u64;
+    ((sub + 1) << shift).wrapping_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values: &[u64]) -> Histogram {
+        let mut histogram = Histogram::default();
+        for &value in values {
+            histogram.record(Duration::from_micros(value));
+        }
+        histogram
+    }
+
+    #[test]
+    fn small_values_have_their_own_buckets() {
+        for value in 0..SUB_BUCKET_COUNT {
+            let index = index_of(value);
+            assert_eq!(index, value as usize);
+            assert_eq!(highest_equivalent(index), value);
+        }
+    }
+
+    #[test]
+    fn bucket_boundaries_cover_every_value_once() {
+        // 各バケットの最大値の次の値は、次のバケットに入る
+        for index in SUB_BUCKET_COUNT as usize..2000 {
+            let highest = highest_equivalent(index);
+            assert_eq!(index_of(highest), index, "index {index}");
+            assert_eq!(index_of(highest + 1), index + 1, "index {index}");
+        }
+        assert_eq!(index_of(128), index_of(129));
+        assert_ne!(index_of(129), index_of(130));
+        assert_eq!(highest_equivalent(index_of(256)), 259);
+    }
+
+    #[test]
+    fn bucket_error_is_bounded() {
+        for value in [200, 1_000, 12_345, 1_000_000, 987_654_321] {
+            let highest = highest_equivalent(index_of(value));
+            assert!(highest >= value);
+            assert!((highest - value) as f64 / value as f64 <= 1.0 / SUB_BUCKET_HALF as f64);
+        }
+    }
+
+    #[test]
+    fn empty_histogram_reports_zero() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.mean(), 0.0);
+        assert_eq!(histogram.percentile(0.0), 0);
+        assert_eq!(histogram.percentile(50.0), 0);
+        assert_eq!(histogram.percentile(100.0), 0);
+    }
+
+    #[test]
+    fn percentiles_pick_the_ranked_bucket() {
+        let histogram = histogram(&[10, 20, 30, 40, 1_000]);
+        assert_eq!(histogram.percentile(0.0), 10);
+        assert_eq!(histogram.percentile(50.0), 30);
+        // 最大値のバケットは1000〜1007だが、記録された最大値を超えない
+        assert_eq!(histogram.percentile(100.0), 1_000);
+        assert_eq!(histogram.min(), 10);
+        assert_eq!(histogram.max(), 1_000);
+        assert_eq!(histogram.mean(), 220.0);
+    }
+
+    #[test]
+    fn values_at_the_top_of_the_range_are_recorded() {
+        let index = index_of(u64::MAX);
+        assert_eq!(highest_equivalent(index), u64::MAX);
+        assert_eq!(index_of(u64::MAX - 1), index);
+
+        let mut histogram = histogram(&[1]);
+        histogram.record(Duration::MAX);
+        assert_eq!(histogram.max(), u64::MAX);
+        assert_eq!(histogram.percentile(50.0), 1);
+        assert_eq!(histogram.percentile(100.0), u64::MAX);
+    }
+
+    #[test]
+    fn merge_adds_counts() {
+        let mut merged = histogram(&[5, 500]);
+        merged.merge(&histogram(&[1, 50_000]));
+        merged.merge(&Histogram::default());
+        assert_eq!(merged.count(), 4);
+        assert_eq!(merged.min(), 1);
+        assert_eq!(merged.max(), 50_000);
+        assert_eq!(merged.percentile(50.0), 5);
+    }
+}
@@ -1,15 +1,24 @@
+mod config;
+mod histogram;
+mod report;
+
 use std::{
     io,
     net::TcpStream,
-    sync::{Arc, Mutex},
-    time::Instant,
+    sync::{Arc, Mutex, mpsc},
+    time::{Duration, Instant},
 };
 
 use async_runtime::{executor::Executor, receiver::TcpReceiver, sender::TcpSender};
 use data_layer::data::Data;
 
-async fn send_data(field1: u32, field2: u16, field3: String) -> io::Result<String> {
-    let stream = Arc::new(Mutex::new(TcpStream::connect("127.0.0.1:7878")?));
+use crate::{
+    config::{Config, Mode, USAGE},
+    report::{Stats, Summary},
+};
+
+async fn send_data(addr: String, field1: u32, field2: u16, field3: String) -> io::Result<String> {
+    let stream = Arc::new(Mutex::new(TcpStream::connect(addr)?));
     let message = Data {
         field1,
         field2,
@@ -28,33 +37,130 @@ async fn send_data(field1: u32, field2: u16, field3: String) -> io::Result<Strin
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "INvalid UTF-8"))
 }
 
-fn main() -> io::Result<()> {
-    let mut executor = Executor::default();
-    let mut handles = vec![];
-    let start = Instant::now();
-    for i in 0..4000 {
-        let handle = executor.spawn(send_data(i, i as u16, format!("Hello, server! {i}")));
-        handles.push(handle);
+/// 送信するメッセージを生成して、サーバーにリクエストを送信する。
+async fn request(addr: String, i: u32) -> io::Result<String> {
+    send_data(addr, i, i as u16, format!("Hello, server! {i}")).await
+}
+
+/// クローズドループのワーカー
+///
+/// 期限に達するまで、応答を受け取るたびに次のリクエストを送信する。
+async fn closed_loop_worker(addr: String, worker: u32, deadline: Instant) -> Stats {
+    let mut stats = Stats::default();
+    let mut i = worker;
+    while Instant::now() < deadline {
+        let start = Instant::now();
+        let result = request(addr.clone(), i).await;
+        stats.record(start.elapsed(), &result);
+        i = i.wrapping_add(1);
     }
+    stats
+}
+
+/// クローズドループで負荷をかける。
+fn run_closed_loop(
+    executor: &mut Executor,
+    addr: &str,
+    concurrency: usize,
+    duration: Duration,
+) -> Stats {
+    let deadline = Instant::now() + duration;
+    let handles: Vec<_> = (0..concurrency)
+        .map(|worker| {
+            let worker = (worker as u32).wrapping_mul(1_000_000);
+            executor.spawn(closed_loop_worker(addr.to_string(), worker, deadline))
+        })
+        .collect();
+
+    while !executor.polling.is_empty() {
+        executor.poll();
+    }
+
+    let mut stats = Stats::default();
+    for handle in handles {
+        if let Ok(worker_stats) = handle.recv() {
+            stats.merge(&worker_stats);
+        }
+    }
+    stats
+}
+
+/// オープンループで負荷をかける。
+///
+/// レイテンシはリクエストを送信する予定だった時刻から計測するため、
+/// クライアント側の送信の遅れもレイテンシに含まれる（coordinated omissionの補正）。
+fn run_open_loop(executor: &mut Executor, addr: &str, rate: f64, duration: Duration) -> Stats {
+    let interval = Duration::from_secs_f64(1.0 / rate);
+    let start = Instant::now();
+    let deadline = start + duration;
+    let mut next = start;
+    let mut i: u32 = 0;
+    let mut handles: Vec<mpsc::Receiver<(Duration, io::Result<String>)>> = vec![];
 
-    std::thread::spawn(move || {
-        loop {
+    loop {
+        let now = Instant::now();
+        while next <= now && next < deadline {
+            let scheduled = next;
+            let future = request(addr.to_string(), i);
+            handles.push(executor.spawn(async move {
+                let result = future.await;
+                (scheduled.elapsed(), result)
+            }));
+            next += interval;
+            i = i.wrapping_add(1);
+        }
+        if executor.polling.is_empty() {
+            if next >= deadline {
+                break;
+            }
+            // 実行中のリクエストがなければ次の送信時刻まで待機
+            std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        } else {
             executor.poll();
         }
-    });
+    }
 
-    println!("Waiting for result...");
+    let mut stats = Stats::default();
     for handle in handles {
-        match handle.recv().unwrap() {
-            Ok(result) => {
-                println!("Result: {result}");
-            }
-            Err(e) => {
-                eprintln!("Error: {e}");
-            }
+        if let Ok((latency, result)) = handle.recv() {
+            stats.record(latency, &result);
+        }
+    }
+    stats
+}
+
+fn main() -> io::Result<()> {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return Err(e);
         }
+    };
+
+    let mut executor = Executor::default();
+    eprintln!(
+        "Sending requests to {} for {:?} ({:?})",
+        config.addr, config.duration, config.mode
+    );
+    let start = Instant::now();
+    let stats = match config.mode {
+        Mode::Open { rate } => run_open_loop(&mut executor, &config.addr, rate, config.duration),
+        Mode::Closed { concurrency } => {
+            run_closed_loop(&mut executor, &config.addr, concurrency, config.duration)
+        }
+    };
+    let summary = Summary::new(&config, start.elapsed(), &stats);
+
+    if config.json {
+        let json = serde_json::to_string_pretty(&summary).map_err(io::Error::other)?;
+        println!("{json}");
+    } else {
+        summary.print();
     }
-    let duration = start.elapsed();
-    println!("Time elapsed in expensive_function() is: {duration:?}");
     Ok(())
 }
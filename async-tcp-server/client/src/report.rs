@@ -0,0 +1,125 @@
+use std::{collections::BTreeMap, io, time::Duration};
+
+use serde::Serialize;
+
+use crate::{
+    config::{Config, Mode},
+    histogram::Histogram,
+};
+
+/// リクエストの結果を集計する。
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// 成功したリクエストのレイテンシ
+    pub latency: Histogram,
+    /// `io::ErrorKind`ごとの失敗したリクエストの数
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    pub fn record<T>(&mut self, latency: Duration, result: &io::Result<T>) {
+        match result {
+            Ok(_) => self.latency.record(latency),
+            Err(e) => *self.errors.entry(format!("{:?}", e.kind())).or_default() += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.latency.merge(&other.latency);
+        for (kind, count) in &other.errors {
+            *self.errors.entry(kind.clone()).or_default() += count;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub mode: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    pub duration_secs: f64,
+    pub elapsed_secs: f64,
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub throughput_rps: f64,
+    pub latency_us: LatencySummary,
+    pub errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencySummary {
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl Summary {
+    pub fn new(config: &Config, elapsed: Duration, stats: &Stats) -> Self {
+        let (mode, rate, concurrency) = match config.mode {
+            Mode::Open { rate } => ("open", Some(rate), None),
+            Mode::Closed { concurrency } => ("closed", None, Some(concurrency)),
+        };
+        let successes = stats.latency.count();
+        let failures = stats.errors.values().sum::<u64>();
+        let latency = &stats.latency;
+        Self {
+            mode,
+            rate,
+            concurrency,
+            duration_secs: config.duration.as_secs_f64(),
+            elapsed_secs: elapsed.as_secs_f64(),
+            requests: successes + failures,
+            successes,
+            failures,
+            throughput_rps: successes as f64 / elapsed.as_secs_f64(),
+            latency_us: LatencySummary {
+                min: latency.min(),
+                mean: latency.mean(),
+                p50: latency.percentile(50.0),
+                p90: latency.percentile(90.0),
+                p99: latency.percentile(99.0),
+                p999: latency.percentile(99.9),
+                max: latency.max(),
+            },
+            errors: stats.errors.clone(),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("mode:        {}", self.mode);
+        if let Some(rate) = self.rate {
+            println!("rate:        {rate} req/s");
+        }
+        if let Some(concurrency) = self.concurrency {
+            println!("concurrency: {concurrency}");
+        }
+        println!("elapsed:     {:.3} s", self.elapsed_secs);
+        println!(
+            "requests:    {} (ok: {}, failed: {})",
+            self.requests, self.successes, self.failures
+        );
+        println!("throughput:  {:.1} req/s", self.throughput_rps);
+        let latency = &self.latency_us;
+        println!("latency (us):");
+        println!("    min   {}", latency.min);
+        println!("    mean  {:.1}", latency.mean);
+        println!("    p50   {}", latency.p50);
+        println!("    p90   {}", latency.p90);
+        println!("    p99   {}", latency.p99);
+        println!("    p99.9 {}", latency.p999);
+        println!("    max   {}", latency.max);
+        if !self.errors.is_empty() {
+            println!("errors:");
+            for (kind, count) in &self.errors {
+                println!("    {kind}: {count}");
+            }
+        }
+    }
+}
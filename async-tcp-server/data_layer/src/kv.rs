@@ -0,0 +1,230 @@
+use std::io::{self, Cursor, Read, Write};
+
+const REQUEST_GET: u8 = 1;
+const REQUEST_SET: u8 = 2;
+const REQUEST_DELETE: u8 = 3;
+const REQUEST_SCAN: u8 = 4;
+const REQUEST_PROMOTE: u8 = 5;
+
+const RESPONSE_VALUE: u8 = 1;
+const RESPONSE_WRITTEN: u8 = 2;
+const RESPONSE_ENTRIES: u8 = 3;
+const RESPONSE_ERROR: u8 = 4;
+
+/// キーバリューストアサーバーへのリクエスト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: Vec<u8>,
+        /// 有効期限（ミリ秒）
+        ttl_millis: Option<u64>,
+    },
+    Delete {
+        key: String,
+    },
+    /// 指定した文字列で始まるキーを、キーの順に最大`limit`個返す。
+    Scan {
+        prefix: String,
+        limit: u32,
+    },
+    /// レプリカを昇格して、書き込みを受け付けるようにする。
+    Promote,
+}
+
+/// キーと値、値を書き込んだ操作のバージョン
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Vec<u8>,
+    pub version: u64,
+}
+
+/// キーバリューストアサーバーからのレスポンス
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// `Get`の結果
+    Value(Option<Entry>),
+    /// `Set`と`Delete`の結果
+    ///
+    /// `Promote`の場合は、昇格した時点のバージョンを返す。
+    Written { version: u64 },
+    /// `Scan`の結果
+    Entries(Vec<Entry>),
+    Error(String),
+}
+
+impl Request {
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        match self {
+            Self::Get { key } => {
+                bytes.write_all(&[REQUEST_GET])?;
+                write_bytes(&mut bytes, key.as_bytes())?;
+            }
+            Self::Set {
+                key,
+                value,
+                ttl_millis,
+            } => {
+                bytes.write_all(&[REQUEST_SET])?;
+                write_bytes(&mut bytes, key.as_bytes())?;
+                write_bytes(&mut bytes, value)?;
+                // 有効期限がない場合は0を書き込む
+                bytes.write_all(&ttl_millis.unwrap_or(0).to_le_bytes())?;
+            }
+            Self::Delete { key } => {
+                bytes.write_all(&[REQUEST_DELETE])?;
+                write_bytes(&mut bytes, key.as_bytes())?;
+            }
+            Self::Scan { prefix, limit } => {
+                bytes.write_all(&[REQUEST_SCAN])?;
+                write_bytes(&mut bytes, prefix.as_bytes())?;
+                bytes.write_all(&limit.to_le_bytes())?;
+            }
+            Self::Promote => bytes.write_all(&[REQUEST_PROMOTE])?,
+        }
+        Ok(bytes)
+    }
+
+    pub fn deserialize(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        match read_u8(cursor)? {
+            REQUEST_GET => Ok(Self::Get {
+                key: read_string(cursor)?,
+            }),
+            REQUEST_SET => Ok(Self::Set {
+                key: read_string(cursor)?,
+                value: read_bytes(cursor)?,
+                ttl_millis: Some(read_u64(cursor)?).filter(|&ttl| ttl != 0),
+            }),
+            REQUEST_DELETE => Ok(Self::Delete {
+                key: read_string(cursor)?,
+            }),
+            REQUEST_SCAN => Ok(Self::Scan {
+                prefix: read_string(cursor)?,
+                limit: read_u32(cursor)?,
+            }),
+            REQUEST_PROMOTE => Ok(Self::Promote),
+            tag => Err(invalid_data(format!("unknown request: {tag}"))),
+        }
+    }
+}
+
+impl Entry {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> io::Result<()> {
+        write_bytes(bytes, self.key.as_bytes())?;
+        write_bytes(bytes, &self.value)?;
+        bytes.write_all(&self.version.to_le_bytes())
+    }
+
+    fn deserialize(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        Ok(Self {
+            key: read_string(cursor)?,
+            value: read_bytes(cursor)?,
+            version: read_u64(cursor)?,
+        })
+    }
+}
+
+impl Response {
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        match self {
+            Self::Value(entry) => {
+                bytes.write_all(&[RESPONSE_VALUE])?;
+                match entry {
+                    Some(entry) => {
+                        bytes.write_all(&[1])?;
+                        entry.serialize(&mut bytes)?;
+                    }
+                    None => bytes.write_all(&[0])?,
+                }
+            }
+            Self::Written { version } => {
+                bytes.write_all(&[RESPONSE_WRITTEN])?;
+                bytes.write_all(&version.to_le_bytes())?;
+            }
+            Self::Entries(entries) => {
+                bytes.write_all(&[RESPONSE_ENTRIES])?;
+                bytes.write_all(&(entries.len() as u32).to_le_bytes())?;
+                for entry in entries {
+                    entry.serialize(&mut bytes)?;
+                }
+            }
+            Self::Error(message) => {
+                bytes.write_all(&[RESPONSE_ERROR])?;
+                write_bytes(&mut bytes, message.as_bytes())?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn deserialize(cursor: &mut Cursor<&[u8]>) -> io::Result<Self> {
+        match read_u8(cursor)? {
+            RESPONSE_VALUE => match read_u8(cursor)? {
+                0 => Ok(Self::Value(None)),
+                _ => Ok(Self::Value(Some(Entry::deserialize(cursor)?))),
+            },
+            RESPONSE_WRITTEN => Ok(Self::Written {
+                version: read_u64(cursor)?,
+            }),
+            RESPONSE_ENTRIES => {
+                let count = read_u32(cursor)?;
+                let entries = (0..count)
+                    .map(|_| Entry::deserialize(cursor))
+                    .collect::<io::Result<_>>()?;
+                Ok(Self::Entries(entries))
+            }
+            RESPONSE_ERROR => Ok(Self::Error(read_string(cursor)?)),
+            tag => Err(invalid_data(format!("unknown response: {tag}"))),
+        }
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// 長さ（u32）を前置したバイト列を書き込む。
+fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) -> io::Result<()> {
+    bytes.write_all(&(value.len() as u32).to_le_bytes())?;
+    bytes.write_all(value)
+}
+
+fn read_u8(cursor: &mut Cursor<&[u8]>) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    cursor.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(cursor: &mut Cursor<&[u8]>) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    cursor.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(cursor: &mut Cursor<&[u8]>) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    cursor.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<u8>> {
+    let len = read_u32(cursor)? as usize;
+    // 長さが壊れている場合に、大きなバッファを確保しない
+    let remaining = (cursor.get_ref().len() as u64).saturating_sub(cursor.position()) as usize;
+    if len > remaining {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    let mut buf = vec![0u8; len];
+    cursor.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
+    String::from_utf8(read_bytes(cursor)?)
+        .map_err(|_| invalid_data(String::from("Invalid UTF-8 bytes")))
+}
@@ -1,56 +1,53 @@
 use std::{
     io::{self, Cursor, ErrorKind, Read, Write},
     net::{TcpListener, TcpStream},
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::channel,
-    },
-    thread,
+    sync::{Arc, mpsc::channel},
 };
 
-use async_runtime::{executor::Executor, sleep::Sleep};
+use async_runtime::{
+    executor::Executor,
+    park::Parker,
+    sleep::Sleep,
+    trace::{self, ConnectionEventKind, StderrSubscriber},
+};
 use data_layer::data::Data;
 
-static PARKING_FLAGS: [AtomicBool; 3] = [
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-];
-
 macro_rules! spawn_worker {
-    ($name:expr, $rx:expr, $flag:expr) => {
+    ($id:expr, $rx:expr, $parker:expr) => {{
+        let parker = $parker.clone();
         std::thread::spawn(move || {
+            trace::set_worker_id($id);
             let mut executor = Executor::default();
             loop {
                 // 受信したデータを取得
                 if let Ok(stream) = $rx.try_recv() {
-                    println!(
-                        "{} Received connection: {}",
-                        $name,
-                        stream.peer_addr().unwrap()
-                    );
                     executor.spawn(handle_client(stream));
-                } else {
-                    if executor.polling.len() == 0 {
-                        println!("{} is sleeping", $name);
-                        $flag.store(true, Ordering::SeqCst);
-                        thread::park();
+                } else if executor.polling.is_empty() {
+                    // 処理がない場合は、接続を受け付けたスレッドに起こされるまで待機
+                    if let Some(stream) = parker.park_unless(|| $rx.try_recv().ok()) {
+                        executor.spawn(handle_client(stream));
                     }
                 }
                 executor.poll();
             }
         })
-    };
+    }};
 }
 
 fn main() -> io::Result<()> {
+    // 環境変数TRACE_POLLSが設定されている場合は、ポーリングのイベントも出力
+    trace::set_subscriber(
+        StderrSubscriber::new().with_polls(std::env::var_os("TRACE_POLLS").is_some()),
+    );
+
     let (one_tx, one_rx) = channel::<TcpStream>();
     let (two_tx, two_rx) = channel::<TcpStream>();
     let (three_tx, three_rx) = channel::<TcpStream>();
 
-    let one = spawn_worker!("One", one_rx, &PARKING_FLAGS[0]);
-    let two = spawn_worker!("Two", two_rx, &PARKING_FLAGS[1]);
-    let three = spawn_worker!("Three", three_rx, &PARKING_FLAGS[2]);
+    let parkers: [Arc<Parker>; 3] = Default::default();
+    let one = spawn_worker!(0, one_rx, parkers[0]);
+    let two = spawn_worker!(1, two_rx, parkers[1]);
+    let three = spawn_worker!(2, three_rx, parkers[2]);
 
     let router = [one_tx, two_tx, three_tx];
     let threads = [one, two, three];
@@ -62,10 +59,7 @@ fn main() -> io::Result<()> {
         match stream {
             Ok(stream) => {
                 let _ = router[index].send(stream);
-                if PARKING_FLAGS[index].load(Ordering::SeqCst) {
-                    PARKING_FLAGS[index].store(false, Ordering::SeqCst);
-                    threads[index].thread().unpark();
-                }
+                parkers[index].unpark(threads[index].thread());
                 index += 1;
                 if index == 3 {
                     index = 0;
@@ -80,7 +74,16 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-async fn handle_client(mut stream: TcpStream) -> io::Result<()> {
+/// 接続を処理し、処理の開始と終了をトレースに通知する。
+async fn handle_client(stream: TcpStream) -> io::Result<()> {
+    let peer = stream.peer_addr().ok();
+    trace::emit_connection(ConnectionEventKind::Accepted, peer);
+    let result = serve(stream).await;
+    trace::emit_connection(ConnectionEventKind::Closed, peer);
+    result
+}
+
+async fn serve(mut stream: TcpStream) -> io::Result<()> {
     stream.set_nonblocking(true)?;
     let mut buffer = vec![];
     let mut local_buf = [0; 1024];
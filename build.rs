@@ -0,0 +1,29 @@
+use std::{env, path::Path};
+
+/// 設定すると、リポジトリのinclude/async_rust.hも生成し直す環境変数
+const UPDATE_HEADER_ENV: &str = "ASYNC_RUST_UPDATE_HEADER";
+
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    // C言語の結合テストで、Rustと同じターゲット向けにコンパイルするため
+    println!(
+        "cargo:rustc-env=BUILD_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed={UPDATE_HEADER_ENV}");
+
+    // C言語から呼び出すためのヘッダーファイルを生成
+    // ビルドのたびにソースツリーを書き換えないように、通常はOUT_DIRにだけ出力する
+    let bindings = cbindgen::generate(&crate_dir).expect("failed to generate the C header");
+    let include_dir = Path::new(&out_dir).join("include");
+    bindings.write_to_file(include_dir.join("async_rust.h"));
+    println!("cargo:rustc-env=HEADER_DIR={}", include_dir.display());
+
+    // コミットしているヘッダーは、`ASYNC_RUST_UPDATE_HEADER=1 cargo build`で明示的に生成し直す
+    if env::var_os(UPDATE_HEADER_ENV).is_some() {
+        bindings.write_to_file(Path::new(&crate_dir).join("include/async_rust.h"));
+    }
+}
@@ -5,23 +5,38 @@ use std::{
     time::Duration,
 };
 
+use async_task::Task;
+
 use async_rust::{
+    blocking::spawn_blocking,
     futures::{CounterFuture, async_fn},
     join,
     runtime::{FutureType, Runtime},
     spawn_task,
 };
 
-struct BackgroundProcess;
+#[derive(Default)]
+struct BackgroundProcess {
+    /// ブロッキング処理用のスレッドプールで実行している待機処理
+    sleep: Option<Task<()>>,
+}
 
 impl Future for BackgroundProcess {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        println!("background process firing");
-        std::thread::sleep(Duration::from_secs(1));
-        cx.waker().wake_by_ref();
-        Poll::Pending
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            if let Some(sleep) = self.sleep.as_mut()
+                && Pin::new(sleep).poll(cx).is_pending()
+            {
+                return Poll::Pending;
+            }
+            println!("background process firing");
+            // ワーカーをブロックしないように、待機処理をオフロードする
+            self.sleep = Some(spawn_blocking(|| {
+                std::thread::sleep(Duration::from_secs(1))
+            }));
+        }
     }
 }
 
@@ -30,10 +45,10 @@ fn main() {
 
     // 次のコードは、_backgroundがスコープ外になると、タスクがドロップされて、タスクが実行されない
     // 可能性がある。
-    // let _background = spawn_task!(BackgroundProcess {});
+    // let _background = spawn_task!(BackgroundProcess::default());
     // したがって、`detach`メソッドを使用して、バックグラウンドスレッドの実行を続けさせる。
 
-    spawn_task!(BackgroundProcess {}).detach();
+    spawn_task!(BackgroundProcess::default()).detach();
     let one = CounterFuture::new(0, 3);
     let two = CounterFuture::new(0, 3);
 
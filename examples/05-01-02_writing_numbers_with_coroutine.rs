@@ -1,13 +1,13 @@
-#![feature(coroutines, coroutine_trait)]
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
 
 use std::{
     fs::{File, OpenOptions},
     io::{self, Write},
-    ops::{Coroutine, CoroutineState},
     pin::Pin,
     time::Instant,
 };
 
+use async_rust::coroutine::{Coroutine, CoroutineState};
 use rand::Rng;
 
 struct WriteCoroutine {
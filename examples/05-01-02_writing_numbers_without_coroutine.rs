@@ -1,5 +1,3 @@
-#![feature(coroutines, coroutine_trait)]
-
 use std::{
     fs::OpenOptions,
     io::{self, Write},
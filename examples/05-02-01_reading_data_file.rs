@@ -1,12 +1,13 @@
-#![feature(coroutines, coroutine_trait)]
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
 
 use std::{
     fs::{File, OpenOptions},
     io::{self, BufRead, BufReader},
-    ops::{Coroutine, CoroutineState},
     pin::Pin,
 };
 
+use async_rust::coroutine::{Coroutine, CoroutineState};
+
 struct ReadCoroutine {
     lines: io::Lines<BufReader<File>>,
 }
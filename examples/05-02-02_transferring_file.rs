@@ -1,14 +1,42 @@
-#![feature(coroutines, coroutine_trait)]
+//! ファイルの転送
+//!
+//! 数値を読み込むコルーチンをソース、書き込むコルーチンをシンクとして`pipeline`で接続する。
+//! 解析できない行は出力せずにエラーとして後段に渡し、転送が終わった後にまとめて報告する。
+//! 書き込みに失敗した場合は、シンクがエラーで完了してパイプラインを止める。
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
 
 use std::{
+    fmt,
     fs::{File, OpenOptions},
     io::{self, BufRead, BufReader, BufWriter, Write},
-    ops::{Coroutine, CoroutineState},
+    num::ParseIntError,
     pin::Pin,
 };
 
+use async_rust::{
+    coroutine::{Coroutine, CoroutineState},
+    pipeline,
+};
+
+/// 読み込んだ行を数値として解析できなかった
+#[derive(Debug)]
+struct ParseError {
+    line: usize,
+    source: ParseIntError,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.source)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 struct ReadCoroutine {
-    lines: io::Lines<BufReader<File>>,
+    /// 読み込みに失敗した後は`None`になる
+    lines: Option<io::Lines<BufReader<File>>>,
+    line: usize,
 }
 
 impl ReadCoroutine {
@@ -16,76 +44,113 @@ impl ReadCoroutine {
         let file = OpenOptions::new().read(true).open(path)?;
         let reader = BufReader::new(file);
         let lines = reader.lines();
-        Ok(Self { lines })
+        Ok(Self {
+            lines: Some(lines),
+            line: 0,
+        })
     }
 }
 
+/// 1行ずつ読み込んで、解析した数値か解析のエラーを生成する。
+///
+/// 読み込みに失敗した場合は、その後の行を読めないため、エラーを生成した後に完了する。
 impl Coroutine<()> for ReadCoroutine {
-    type Yield = i32;
+    type Yield = io::Result<Result<i32, ParseError>>;
     type Return = ();
 
     fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<Self::Yield, Self::Return> {
-        match self.lines.next() {
-            Some(Ok(line)) => {
-                if let Ok(number) = line.parse::<i32>() {
-                    CoroutineState::Yielded(number)
-                } else {
-                    CoroutineState::Complete(())
-                }
+        self.line += 1;
+        let line = self.line;
+        let Some(lines) = self.lines.as_mut() else {
+            return CoroutineState::Complete(());
+        };
+        match lines.next() {
+            Some(Ok(text)) => CoroutineState::Yielded(Ok(text
+                .trim()
+                .parse::<i32>()
+                .map_err(|source| ParseError { line, source }))),
+            Some(Err(e)) => {
+                self.lines = None;
+                CoroutineState::Yielded(Err(e))
             }
-            Some(Err(_)) | None => CoroutineState::Complete(()),
+            None => CoroutineState::Complete(()),
         }
     }
 }
 
+/// 転送の結果
+#[derive(Debug, Default)]
+struct Transfer {
+    written: usize,
+    skipped: Vec<ParseError>,
+}
+
 struct WriteCoroutine {
     writer: BufWriter<File>,
+    transfer: Transfer,
 }
 
 impl WriteCoroutine {
     fn new(path: &str) -> io::Result<Self> {
-        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
         let writer = BufWriter::new(file);
-        Ok(Self { writer })
+        Ok(Self {
+            writer,
+            transfer: Transfer::default(),
+        })
     }
-}
-
-impl Coroutine<i32> for WriteCoroutine {
-    type Yield = ();
-    type Return = ();
 
-    fn resume(mut self: Pin<&mut Self>, arg: i32) -> CoroutineState<Self::Yield, Self::Return> {
-        if let Err(e) = writeln!(self.as_mut().writer, "{arg}") {
-            eprintln!("Failed to write a number: {e}");
+    fn write(&mut self, input: io::Result<Result<i32, ParseError>>) -> io::Result<()> {
+        match input? {
+            Ok(number) => {
+                writeln!(self.writer, "{number}")?;
+                self.transfer.written += 1;
+            }
+            Err(e) => self.transfer.skipped.push(e),
         }
-        CoroutineState::Yielded(())
+        Ok(())
     }
 }
 
-struct CoroutineManager {
-    reader: ReadCoroutine,
-    writer: WriteCoroutine,
-}
-
-impl CoroutineManager {
-    fn new(read_path: &str, write_path: &str) -> io::Result<Self> {
-        Ok(Self {
-            reader: ReadCoroutine::new(read_path)?,
-            writer: WriteCoroutine::new(write_path)?,
-        })
-    }
+/// 数値を書き込み、入力が終端に達したら書き込んだ内容をフラッシュして完了する。
+///
+/// 読み込みと書き込みのエラーは、転送を止めるためにエラーで完了する。
+impl Coroutine<Option<io::Result<Result<i32, ParseError>>>> for WriteCoroutine {
+    type Yield = ();
+    type Return = io::Result<Transfer>;
 
-    fn run(&mut self) {
-        let mut read_pin = Pin::new(&mut self.reader);
-        let mut write_pin = Pin::new(&mut self.writer);
-        while let CoroutineState::Yielded(number) = read_pin.as_mut().resume(()) {
-            write_pin.as_mut().resume(number);
+    fn resume(
+        mut self: Pin<&mut Self>,
+        arg: Option<io::Result<Result<i32, ParseError>>>,
+    ) -> CoroutineState<Self::Yield, Self::Return> {
+        let result = match arg {
+            Some(input) => self.write(input),
+            None => {
+                return CoroutineState::Complete(
+                    self.writer
+                        .flush()
+                        .map(|()| std::mem::take(&mut self.transfer)),
+                );
+            }
+        };
+        match result {
+            Ok(()) => CoroutineState::Yielded(()),
+            Err(e) => CoroutineState::Complete(Err(e)),
         }
     }
 }
 
 fn main() -> io::Result<()> {
-    let mut manager = CoroutineManager::new("numbers.txt", "output.txt")?;
-    manager.run();
+    let transfer = pipeline::source(ReadCoroutine::new("numbers.txt")?)
+        .sink(WriteCoroutine::new("output.txt")?)
+        .run()?;
+    println!("Transferred {} numbers", transfer.written);
+    for e in &transfer.skipped {
+        println!("Skipped {e}");
+    }
     Ok(())
 }
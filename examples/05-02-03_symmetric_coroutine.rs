@@ -1,15 +1,17 @@
+//! 対称型コルーチン
+//!
+//! 読み込むコルーチンと書き込むコルーチンを、`main`が交互に再開して値を受け渡す。
+//! `async_rust::coroutine::Coroutine`は安定版のRustでも使えるため、独自のトレイトを定義する必要はない。
+//! エラーは出力して読み飛ばさずに、コルーチンの戻り値として呼び出し側に返す。
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
+
 use std::{
     fs::{File, OpenOptions},
     io::{self, BufRead, BufReader, BufWriter, Write},
     pin::Pin,
 };
 
-trait SymmetricCoroutine {
-    type Input;
-    type Output;
-
-    fn resume_with_input(self: Pin<&mut Self>, input: Self::Input) -> Self::Output;
-}
+use async_rust::coroutine::{Coroutine, CoroutineState};
 
 struct ReadCoroutine {
     lines: io::Lines<BufReader<File>>,
@@ -24,24 +26,20 @@ impl ReadCoroutine {
     }
 }
 
-impl SymmetricCoroutine for ReadCoroutine {
-    type Input = ();
-    type Output = Option<i32>;
+impl Coroutine<()> for ReadCoroutine {
+    type Yield = i32;
+    type Return = io::Result<()>;
 
-    fn resume_with_input(mut self: Pin<&mut Self>, _input: Self::Input) -> Self::Output {
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<Self::Yield, Self::Return> {
         match self.as_mut().lines.next() {
             Some(Ok(line)) => match line.parse::<i32>() {
-                Ok(number) => Some(number),
+                Ok(number) => CoroutineState::Yielded(number),
                 Err(e) => {
-                    eprintln!("Failed to parse a line: {e}");
-                    None
+                    CoroutineState::Complete(Err(io::Error::new(io::ErrorKind::InvalidData, e)))
                 }
             },
-            Some(Err(e)) => {
-                eprintln!("Failed to read a line: {e}");
-                None
-            }
-            None => None,
+            Some(Err(e)) => CoroutineState::Complete(Err(e)),
+            None => CoroutineState::Complete(Ok(())),
         }
     }
 }
@@ -62,13 +60,14 @@ impl WriteCoroutine {
     }
 }
 
-impl SymmetricCoroutine for WriteCoroutine {
-    type Input = i32;
-    type Output = ();
+impl Coroutine<i32> for WriteCoroutine {
+    type Yield = ();
+    type Return = io::Error;
 
-    fn resume_with_input(mut self: Pin<&mut Self>, input: Self::Input) -> Self::Output {
-        if let Err(e) = writeln!(self.as_mut().writer, "{input}") {
-            eprintln!("Failed to write a number: {e}");
+    fn resume(mut self: Pin<&mut Self>, input: i32) -> CoroutineState<Self::Yield, Self::Return> {
+        match writeln!(self.as_mut().writer, "{input}") {
+            Ok(()) => CoroutineState::Yielded(()),
+            Err(e) => CoroutineState::Complete(e),
         }
     }
 }
@@ -76,8 +75,17 @@ impl SymmetricCoroutine for WriteCoroutine {
 fn main() -> io::Result<()> {
     let mut reader = ReadCoroutine::new("numbers.txt")?;
     let mut writer = WriteCoroutine::new("output.txt")?;
-    while let Some(number) = Pin::new(&mut reader).resume_with_input(()) {
-        Pin::new(&mut writer).resume_with_input(number);
+    loop {
+        match Pin::new(&mut reader).resume(()) {
+            CoroutineState::Yielded(number) => {
+                if let CoroutineState::Complete(e) = Pin::new(&mut writer).resume(number) {
+                    return Err(e);
+                }
+            }
+            CoroutineState::Complete(result) => {
+                result?;
+                return writer.writer.flush();
+            }
+        }
     }
-    Ok(())
 }
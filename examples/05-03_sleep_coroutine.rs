@@ -1,11 +1,12 @@
-#![feature(coroutines, coroutine_trait)]
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
 use std::{
     collections::VecDeque,
-    ops::{Coroutine, CoroutineState},
     pin::Pin,
     time::{Duration, Instant},
 };
 
+use async_rust::coroutine::{Coroutine, CoroutineState};
+
 struct SleepCoroutine {
     pub start: Instant,
     pub duration: Duration,
@@ -1,13 +1,22 @@
-#![feature(coroutines, coroutine_trait)]
+//! スリープするコルーチンを、スケジューラーとフューチャーで実行する
+//!
+//! `SleepCoroutine`は起床する時刻を`Wait::Until`として生成するため、
+//! スケジューラーは時刻になるまでコルーチンを再開せずにスレッドを停止する。
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
 use std::{
-    collections::VecDeque,
     future::Future,
-    ops::{Coroutine, CoroutineState},
     pin::Pin,
     task::{Context, Poll},
     time::{Duration, Instant},
 };
 
+use async_rust::{
+    coroutine::{
+        Coroutine, CoroutineState,
+        scheduler::{Scheduler, Wait},
+    },
+    runtime::FutureType,
+};
 use futures::executor::block_on;
 
 struct SleepCoroutine {
@@ -25,14 +34,14 @@ impl SleepCoroutine {
 }
 
 impl Coroutine<()> for SleepCoroutine {
-    type Yield = ();
+    type Yield = Wait;
     type Return = ();
 
     fn resume(self: Pin<&mut Self>, _arg: ()) -> CoroutineState<Self::Yield, Self::Return> {
         if self.start.elapsed() >= self.duration {
             CoroutineState::Complete(())
         } else {
-            CoroutineState::Yielded(())
+            CoroutineState::Yielded(Wait::Until(self.start + self.duration))
         }
     }
 }
@@ -51,42 +60,16 @@ impl Future for SleepCoroutine {
     }
 }
 
-type PinnedCoroutine = Pin<Box<dyn Coroutine<(), Yield = (), Return = ()>>>;
-type CoroutineVecDeque = VecDeque<PinnedCoroutine>;
-
-#[derive(Default)]
-struct Executor {
-    coroutines: CoroutineVecDeque,
-}
-
-impl Executor {
-    fn add(&mut self, coroutine: PinnedCoroutine) {
-        self.coroutines.push_back(coroutine);
-    }
-
-    fn poll(&mut self) {
-        println!("Polling {} coroutines", self.coroutines.len());
-        let mut coroutine = self.coroutines.pop_front().unwrap();
-        match coroutine.as_mut().resume(()) {
-            CoroutineState::Yielded(_) => {
-                self.coroutines.push_back(coroutine);
-            }
-            CoroutineState::Complete(_) => {}
-        }
-    }
-}
-
 fn main() {
-    let mut executor = Executor::default();
+    let mut scheduler = Scheduler::new();
     for _ in 0..3 {
         let coroutine = SleepCoroutine::new(Duration::from_secs(1));
-        executor.add(Box::pin(coroutine));
+        scheduler.spawn(coroutine, FutureType::Low);
     }
 
+    println!("Scheduling {} coroutines", scheduler.len());
     let start = Instant::now();
-    while !executor.coroutines.is_empty() {
-        executor.poll();
-    }
+    scheduler.run();
     println!("Took {:?}", start.elapsed());
 
     println!("----------");
@@ -1,10 +1,7 @@
-#![feature(coroutines, coroutine_trait)]
-use std::{
-    ops::{Coroutine, CoroutineState},
-    pin::Pin,
-    time::Duration,
-};
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
+use std::{pin::Pin, time::Duration};
 
+use async_rust::coroutine::{Coroutine, CoroutineState};
 use rand::Rng;
 
 struct RandCoroutine {
@@ -1,18 +1,25 @@
-#![feature(coroutines, coroutine_trait)]
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
 use std::{
-    ops::{Coroutine, CoroutineState},
     pin::Pin,
     sync::{Arc, Mutex},
 };
 
-#[allow(dead_code)]
+use async_rust::{
+    coroutine::{
+        Coroutine, CoroutineState,
+        scheduler::{Scheduler, Wait},
+    },
+    runtime::FutureType,
+};
+
 struct MutexCoroutine {
     handle: Arc<Mutex<u8>>,
     threshold: u8,
 }
 
+/// ロックを取得できなかった場合は、ロックが解放されるまで待機する条件を生成する。
 impl Coroutine<()> for MutexCoroutine {
-    type Yield = ();
+    type Yield = Wait;
     type Return = ();
 
     fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<Self::Yield, Self::Return> {
@@ -21,14 +28,14 @@ impl Coroutine<()> for MutexCoroutine {
                 *handle += 1;
             }
             Err(_) => {
-                return CoroutineState::Yielded(());
+                return CoroutineState::Yielded(Wait::lock(&self.handle));
             }
         }
         self.threshold -= 1;
         if self.threshold == 0 {
             CoroutineState::Complete(())
         } else {
-            CoroutineState::Yielded(())
+            CoroutineState::Yielded(Wait::Yield)
         }
     }
 }
@@ -36,11 +43,15 @@ impl Coroutine<()> for MutexCoroutine {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{
-        future::Future,
-        task::{Context, Poll},
-        time::Duration,
+    use async_rust::{
+        coroutine::scheduler::DEFAULT_POLL_INTERVAL,
+        runtime::{
+            sim::{SEED_ENV, Simulation},
+            time,
+        },
+        spawn_task,
     };
+    use futures_lite::future;
 
     // 同期テストのインターフェイス
     fn check_yield(coroutine: &mut MutexCoroutine) -> bool {
@@ -51,16 +62,13 @@ mod tests {
     }
 
     // 非同期ランタイムのインターフェイス
-    impl Future for MutexCoroutine {
-        type Output = ();
-
-        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            match Pin::new(&mut self).resume(()) {
-                CoroutineState::Yielded(_) => {
-                    cx.waker().wake_by_ref();
-                    Poll::Pending
-                }
-                CoroutineState::Complete(_) => Poll::Ready(()),
+    // ロックの解放は通知されないため、スケジューラーと同じ間隔でタイマーを待ってから再開する
+    async fn resume_until_complete(mut coroutine: MutexCoroutine) {
+        loop {
+            match Pin::new(&mut coroutine).resume(()) {
+                CoroutineState::Yielded(Wait::Yield) => future::yield_now().await,
+                CoroutineState::Yielded(_) => time::sleep(DEFAULT_POLL_INTERVAL).await,
+                CoroutineState::Complete(()) => return,
             }
         }
     }
@@ -95,28 +103,73 @@ mod tests {
         assert_eq!(*handle.lock().unwrap(), 4);
     }
 
-    #[tokio::test]
-    async fn async_test() {
+    // スケジューラーは、ロックが解放されるまでコルーチンを再開しない
+    #[test]
+    fn scheduler_test() {
         let handle = Arc::new(Mutex::new(0));
-        let first_coroutine = MutexCoroutine {
-            handle: handle.clone(),
-            threshold: 2,
+        let mut scheduler = Scheduler::new();
+        for _ in 0..2 {
+            let coroutine = MutexCoroutine {
+                handle: handle.clone(),
+                threshold: 2,
+            };
+            scheduler.spawn(coroutine, FutureType::Low);
+        }
+
+        let lock = handle.lock().unwrap();
+        let resumed = (0..10).filter(|_| scheduler.tick()).count();
+        assert_eq!(resumed, 2);
+        assert_eq!(*lock, 0);
+        drop(lock);
+
+        scheduler.run();
+        assert_eq!(*handle.lock().unwrap(), 4);
+    }
+
+    // 仮想時間のシミュレーションで、シードごとに異なる順序でポーリングする
+    // 失敗した場合は、出力されたシードをASYNC_RUST_SIM_SEEDに指定すると同じ順序を再現できる
+    #[test]
+    fn async_test() {
+        let seeds = match std::env::var(SEED_ENV) {
+            Ok(_) => vec![Simulation::from_env()],
+            Err(_) => (0..32).map(Simulation::new).collect(),
         };
-        let second_coroutine = MutexCoroutine {
+        for simulation in seeds {
+            let handle = Arc::new(Mutex::new(0));
+            let first_coroutine = MutexCoroutine {
+                handle: handle.clone(),
+                threshold: 2,
+            };
+            let second_coroutine = MutexCoroutine {
+                handle: handle.clone(),
+                threshold: 2,
+            };
+
+            simulation.block_on(async {
+                let handle_one = spawn_task!(async move {
+                    resume_until_complete(first_coroutine).await;
+                });
+                let handle_two = spawn_task!(async move {
+                    resume_until_complete(second_coroutine).await;
+                });
+                handle_one.await;
+                handle_two.await;
+            });
+            assert_eq!(*handle.lock().unwrap(), 4);
+        }
+    }
+}
+
+fn main() {
+    let handle = Arc::new(Mutex::new(0));
+    let mut scheduler = Scheduler::new();
+    for _ in 0..2 {
+        let coroutine = MutexCoroutine {
             handle: handle.clone(),
             threshold: 2,
         };
-
-        let handle_one = tokio::spawn(async move {
-            first_coroutine.await;
-        });
-        let handle_two = tokio::spawn(async move {
-            second_coroutine.await;
-        });
-        handle_one.await.unwrap();
-        handle_two.await.unwrap();
-        assert_eq!(*handle.lock().unwrap(), 4);
+        scheduler.spawn(coroutine, FutureType::Low);
     }
+    scheduler.run();
+    println!("counter: {}", *handle.lock().unwrap());
 }
-
-fn main() {}
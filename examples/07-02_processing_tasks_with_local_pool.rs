@@ -1,21 +1,18 @@
-use std::cell::RefCell;
+use async_rust::per_thread::PerThread;
 use tokio_util::task::LocalPoolHandle;
 
-thread_local! {
-    static COUNTER: RefCell<u32> = const {RefCell::new(1)};
-}
+// スレッドごとのカウンター
+//
+// 同じスレッドに固定したタスクは、同じカウンターを更新する。
+static COUNTER: PerThread<u32> = PerThread::new(|| 1);
 
 async fn something(number: u32) -> u32 {
     // std::thread::sleep(std::time::Duration::from_secs(3));
     tokio::time::sleep(std::time::Duration::from_secs(3)).await;
-    COUNTER.with_borrow_mut(|counter| {
+    COUNTER.with(|counter| {
         *counter += 1;
         println!("Counter: {counter} for: {number}");
     });
-    // COUNTER.with(|counter| {
-    //     *counter.borrow_mut() += 1;
-    //     println!("Counter: {} for: {number}", *counter.borrow());
-    // });
     number
 }
 
@@ -52,4 +49,6 @@ async fn main() {
         one + two + three
     };
     println!("result: {}", result.await);
+    // すべてのタスクをインデックス0のスレッドに固定したため、カウンターは1つだけ
+    println!("counters: {:?}", COUNTER.snapshot());
 }
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use async_rust::per_thread::PerThread;
+use tokio_util::task::LocalPoolHandle;
+
+// スレッドごとのカウンター
+//
+// `thread_local!`に`UnsafeCell`を格納すると、値を読み出すために`unsafe`が必要になり、
+// 読み出すスレッドにタスクを生成しなければならない。
+// `PerThread`は、各スレッドの値を任意のスレッドから安全に読み出せる。
+static COUNTER: PerThread<HashMap<u32, u32>> = PerThread::new(HashMap::new);
+
+async fn something(number: u32) {
+    tokio::time::sleep(std::time::Duration::from_secs(number as u64)).await;
+    COUNTER.with(|counter| *counter.entry(number).or_insert(0) += 1);
+}
+
+fn print_statement() {
+    for counter in COUNTER.snapshot() {
+        println!("Counter: {counter:?}");
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let pool = LocalPoolHandle::new(1);
+    let sequence = [1, 2, 3, 4, 5];
+    let repeated_sequence: Vec<_> = sequence.iter().cycle().take(500_000).cloned().collect();
+
+    let mut futures = vec![];
+    for number in repeated_sequence {
+        futures.push(pool.spawn_pinned(move || async move {
+            something(number).await;
+            something(number).await;
+        }));
+    }
+
+    for f in futures {
+        f.await.unwrap();
+    }
+    print_statement();
+}
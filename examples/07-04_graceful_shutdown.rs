@@ -1,5 +1,6 @@
-use std::{cell::UnsafeCell, collections::HashMap, sync::LazyLock};
+use std::{collections::HashMap, sync::LazyLock};
 
+use async_rust::per_thread::PerThread;
 use tokio::signal::unix::{SignalKind, signal};
 use tokio_util::task::LocalPoolHandle;
 
@@ -20,49 +21,23 @@ async fn cleanup() {
 
 static RUNTIME: LazyLock<LocalPoolHandle> = LazyLock::new(|| LocalPoolHandle::new(4));
 
-thread_local! {
-    static COUNTER: UnsafeCell<HashMap<u32, u32>> = UnsafeCell::new(HashMap::new());
-}
-
-fn extract_data_from_thread() -> HashMap<u32, u32> {
-    let mut extracted_counter = HashMap::new();
-    COUNTER.with(|counter| {
-        let counter = unsafe { &mut *counter.get() };
-        extracted_counter = counter.clone();
-    });
-    extracted_counter
-}
+// スレッドごとのカウンター
+//
+// 各スレッドのカウンターを集計するために、スレッドごとにタスクを生成して値を取り出す必要はない。
+static COUNTER: PerThread<HashMap<u32, u32>> = PerThread::new(HashMap::new);
 
-async fn get_complete_count() -> HashMap<u32, u32> {
-    let mut complete_counter = HashMap::new();
-    let mut extracted_counters = vec![];
-    for i in 0..4 {
-        extracted_counters
-            .push(RUNTIME.spawn_pinned_by_idx(|| async move { extract_data_from_thread() }, i));
-    }
-    for counter_future in extracted_counters {
-        let extracted_counter = counter_future.await.unwrap_or_default();
-        for (key, count) in extracted_counter {
-            *complete_counter.entry(key).or_insert(0) += count;
+fn get_complete_count() -> HashMap<u32, u32> {
+    COUNTER.fold(HashMap::new(), |mut complete_counter, counter| {
+        for (key, count) in counter {
+            *complete_counter.entry(*key).or_insert(0) += count;
         }
-    }
-    complete_counter
+        complete_counter
+    })
 }
 
 async fn something(number: u32) {
     tokio::time::sleep(std::time::Duration::from_secs(number as u64)).await;
-    COUNTER.with(|counter| {
-        let counter = unsafe { &mut *counter.get() };
-        match counter.get_mut(&number) {
-            Some(count) => {
-                let placeholder = *count + 1;
-                *count = placeholder;
-            }
-            None => {
-                counter.insert(number, 1);
-            }
-        }
-    });
+    COUNTER.with(|counter| *counter.entry(number).or_insert(0) += 1);
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -88,6 +63,6 @@ async fn main() {
     let mut stream = signal(SignalKind::hangup()).unwrap();
     stream.recv().await;
 
-    let complete_counter = get_complete_count().await;
+    let complete_counter = get_complete_count();
     println!("Complete counter: {complete_counter:?}");
 }
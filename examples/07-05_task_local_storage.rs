@@ -0,0 +1,63 @@
+use std::{cell::Cell, collections::HashMap, time::Duration};
+
+use async_rust::{
+    join,
+    per_thread::PerThread,
+    runtime::{FutureType, Runtime},
+    spawn_task, task_local,
+};
+
+task_local! {
+    // タスクごとのカウンター
+    //
+    // タスクが優先度の高いワーカーと低いワーカーの間を移動しても、同じ値を参照する。
+    static COUNTER: Cell<u32> = Cell::new(0);
+}
+
+// スレッドごとに処理したタスクの数
+static PROCESSED: PerThread<HashMap<u32, u32>> = PerThread::new(HashMap::new);
+
+/// 1回だけPendingを返して、ワーカーにタスクを再度スケジューリングさせる。
+async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+async fn something(number: u32) -> u32 {
+    for _ in 0..number {
+        COUNTER.with(|counter| counter.set(counter.get() + 1));
+        PROCESSED.with(|processed| *processed.entry(number).or_insert(0) += 1);
+        std::thread::sleep(Duration::from_millis(10));
+        yield_now().await;
+    }
+    COUNTER.with(Cell::get)
+}
+
+fn main() {
+    Runtime::new().with_high_num(2).with_low_num(2).run();
+
+    let one = spawn_task!(something(1), FutureType::High);
+    let two = spawn_task!(something(2));
+    let three = spawn_task!(something(3), FutureType::High);
+    let four = spawn_task!(something(4));
+
+    let counters: Vec<u32> = join!(one, two, three, four);
+    println!("counters: {counters:?}");
+
+    let processed = PROCESSED.fold(HashMap::new(), |mut total, processed| {
+        for (number, count) in processed {
+            *total.entry(*number).or_insert(0) += count;
+        }
+        total
+    });
+    println!("processed: {processed:?}");
+}
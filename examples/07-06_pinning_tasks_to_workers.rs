@@ -0,0 +1,53 @@
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use async_rust::{
+    runtime::{FutureType, Runtime, spawn_local, spawn_pinned},
+    spawn_task,
+};
+
+thread_local! {
+    static COUNTER: RefCell<u32> = const { RefCell::new(1) };
+}
+
+async fn something(number: u32) -> u32 {
+    std::thread::sleep(Duration::from_millis(100));
+    COUNTER.with_borrow_mut(|counter| {
+        *counter += 1;
+        println!("Counter: {counter} for: {number}");
+    });
+    number
+}
+
+fn main() {
+    Runtime::new().with_high_num(2).with_low_num(1).run();
+
+    // 3つのタスクを同じワーカーに固定するため、スレッドローカルなカウンターを共有する
+    let one = spawn_pinned(0, || async {
+        println!("one");
+        something(1).await
+    });
+    let two = spawn_pinned(0, || async {
+        println!("two");
+        something(2).await
+    });
+    let three = spawn_pinned(0, || async {
+        println!("three");
+        // Rcはワーカー間で移動できないが、固定されたタスクでは保持できる
+        let shared = Rc::new(something(3).await);
+        let cloned = shared.clone();
+        // 同じワーカーにSendでないタスクを生成
+        spawn_local(async move { *cloned * 10 }).await + *shared
+    });
+
+    // 固定されたタスクのハンドルは、他のワーカーのタスクから待ち合わせできる
+    let result = spawn_task!(
+        async {
+            let one = one.await;
+            let two = two.await;
+            let three = three.await;
+            one + two + three
+        },
+        FutureType::High
+    );
+    println!("result: {}", futures_lite::future::block_on(result));
+}
@@ -1,18 +1,23 @@
-use tokio::sync::{
-    mpsc::{Receiver, channel},
-    oneshot,
-};
+use std::time::Duration;
+
+use async_rust::actor::{self, Actor, Context, Reply};
 
 struct RespMessage {
     value: i64,
-    responder: oneshot::Sender<i64>,
+    responder: Reply<i64>,
+}
+
+#[derive(Default)]
+struct RespActor {
+    state: i64,
 }
 
-async fn resp_actor(mut rx: Receiver<RespMessage>) {
-    let mut state = 0;
-    while let Some(msg) = rx.recv().await {
-        state += msg.value;
-        if msg.responder.send(state).is_err() {
+impl Actor for RespActor {
+    type Message = RespMessage;
+
+    async fn handle(&mut self, msg: RespMessage, _ctx: &mut Context<Self>) {
+        self.state += msg.value;
+        if msg.responder.send(self.state).is_err() {
             eprintln!("Failed o send response");
         }
     }
@@ -20,16 +25,21 @@ async fn resp_actor(mut rx: Receiver<RespMessage>) {
 
 #[tokio::main]
 async fn main() {
-    let (tx, rx) = channel::<RespMessage>(100);
+    let addr = actor::spawn_with_capacity(RespActor::default(), 100);
 
-    let _resp_actor_handle = tokio::spawn(async { resp_actor(rx).await });
     for i in 0..10 {
-        let (resp_tx, resp_rx) = oneshot::channel::<i64>();
-        let msg = RespMessage {
-            value: i,
-            responder: resp_tx,
-        };
-        tx.send(msg).await.unwrap();
-        println!("Response: {}", resp_rx.await.unwrap());
+        let response = addr
+            .ask(
+                |responder| RespMessage {
+                    value: i,
+                    responder,
+                },
+                Duration::from_secs(1),
+            )
+            .await;
+        match response {
+            Ok(response) => println!("Response: {response}"),
+            Err(e) => eprintln!("Failed to receive response: {e}"),
+        }
     }
 }
@@ -1,18 +1,23 @@
-use tokio::sync::{
-    mpsc::{Receiver, channel},
-    oneshot,
-};
+use std::time::Duration;
+
+use async_rust::actor::{self, Actor, Context, Reply};
 
 struct RespMessage {
     value: usize,
-    responder: oneshot::Sender<usize>,
+    responder: Reply<usize>,
+}
+
+#[derive(Default)]
+struct RespActor {
+    state: usize,
 }
 
-async fn resp_actor(mut rx: Receiver<RespMessage>) {
-    let mut state = 0;
-    while let Some(msg) = rx.recv().await {
-        state += msg.value;
-        if msg.responder.send(state).is_err() {
+impl Actor for RespActor {
+    type Message = RespMessage;
+
+    async fn handle(&mut self, msg: RespMessage, _ctx: &mut Context<Self>) {
+        self.state += msg.value;
+        if msg.responder.send(self.state).is_err() {
             eprintln!("Failed o send response");
         }
     }
@@ -24,23 +29,23 @@ async fn main() {
     // let max_count = 50_000_000;
     let max_count = 10_000_000;
     // let max_count = 1_000_000;
-    let (tx, rx) = channel::<RespMessage>(max_count);
-    let _resp_actor_handle = tokio::spawn(async {
-        resp_actor(rx).await;
-    });
+    let addr = actor::spawn_with_capacity(RespActor::default(), max_count);
 
     let now = tokio::time::Instant::now();
     let mut handles = vec![];
     for i in 0..max_count {
-        let cloned_tx = tx.clone();
+        let addr = addr.clone();
         let future = async move {
-            let (resp_tx, resp_rx) = oneshot::channel::<usize>();
-            let msg = RespMessage {
-                value: i,
-                responder: resp_tx,
-            };
-            cloned_tx.send(msg).await.unwrap();
-            let _ = resp_rx.await.unwrap();
+            let _ = addr
+                .ask(
+                    |responder| RespMessage {
+                        value: i,
+                        responder,
+                    },
+                    Duration::from_secs(60),
+                )
+                .await
+                .unwrap();
         };
         handles.push(tokio::spawn(future));
     }
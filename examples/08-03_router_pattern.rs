@@ -1,136 +1,122 @@
-use std::sync::OnceLock;
+use std::{collections::HashMap, io, time::Duration};
 
-use tokio::sync::{
-    mpsc::{Receiver, Sender, channel},
-    oneshot,
-};
+use async_rust::actor::{self, Actor, Addr, Context, Reply, system::ActorSystem};
 
-static ROUTER_SENDER: OnceLock<Sender<RoutingMessage>> = OnceLock::new();
-
-struct SetKeyValueMessage {
-    key: String,
-    value: Vec<u8>,
-    response: oneshot::Sender<()>,
-}
-
-struct GetKeyValueMessage {
-    key: String,
-    response: oneshot::Sender<Option<Vec<u8>>>,
-}
-
-struct DeleteKeyValueMessage {
-    key: String,
-    response: oneshot::Sender<()>,
-}
+/// アクターの応答を待つ時間
+const TIMEOUT: Duration = Duration::from_secs(1);
 
 enum KeyValueMessage {
-    Get(GetKeyValueMessage),
-    Set(SetKeyValueMessage),
-    Delete(DeleteKeyValueMessage),
+    Get {
+        key: String,
+        response: Reply<Option<Vec<u8>>>,
+    },
+    Set {
+        key: String,
+        value: Vec<u8>,
+        response: Reply<()>,
+    },
+    Delete {
+        key: String,
+        response: Reply<()>,
+    },
 }
 
 enum RoutingMessage {
     KeyValue(KeyValueMessage),
 }
 
-async fn key_value_actor(mut receiver: Receiver<KeyValueMessage>) {
-    let mut map = std::collections::HashMap::new();
-    while let Some(message) = receiver.recv().await {
+#[derive(Default)]
+struct KeyValueActor {
+    map: HashMap<String, Vec<u8>>,
+}
+
+impl Actor for KeyValueActor {
+    type Message = KeyValueMessage;
+
+    async fn handle(&mut self, message: KeyValueMessage, _ctx: &mut Context<Self>) {
         match message {
-            KeyValueMessage::Get(GetKeyValueMessage { key, response }) => {
-                let _ = response.send(map.get(&key).cloned());
+            KeyValueMessage::Get { key, response } => {
+                let _ = response.send(self.map.get(&key).cloned());
             }
-            KeyValueMessage::Set(SetKeyValueMessage {
+            KeyValueMessage::Set {
                 key,
                 value,
                 response,
-            }) => {
-                map.insert(key, value);
+            } => {
+                self.map.insert(key, value);
                 let _ = response.send(());
             }
-            KeyValueMessage::Delete(DeleteKeyValueMessage { key, response }) => {
-                map.remove(&key);
+            KeyValueMessage::Delete { key, response } => {
+                self.map.remove(&key);
                 let _ = response.send(());
             }
         }
     }
 }
 
-async fn router(mut receiver: Receiver<RoutingMessage>) {
-    let (key_value_sender, key_value_receiver) = channel(32);
-    tokio::spawn(key_value_actor(key_value_receiver));
+struct Router {
+    key_value: Addr<KeyValueActor>,
+}
+
+impl Actor for Router {
+    type Message = RoutingMessage;
 
-    while let Some(message) = receiver.recv().await {
+    async fn handle(&mut self, message: RoutingMessage, _ctx: &mut Context<Self>) {
         match message {
             RoutingMessage::KeyValue(message) => {
-                let _ = key_value_sender.send(message).await;
+                let _ = self.key_value.send(message).await;
             }
         }
     }
 }
 
-async fn get(key: String) -> Result<Option<Vec<u8>>, std::io::Error> {
-    let (tx, rx) = oneshot::channel();
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::KeyValue(KeyValueMessage::Get(
-            GetKeyValueMessage { key, response: tx },
-        )))
-        .await
-        .unwrap();
-    Ok(rx.await.unwrap())
+/// アクターシステムに登録したルーターアクターのアドレスを返す。
+fn router(system: &ActorSystem) -> io::Result<Addr<Router>> {
+    system.lookup_type::<Router>().map_err(io::Error::other)
 }
 
-async fn set(key: String, value: Vec<u8>) -> Result<(), std::io::Error> {
-    // キーバリューストアにキーと値を保存したことを通知するワンショットチャネル
-    let (tx, rx) = oneshot::channel();
-    // キーバーリューストアに保存するキーと値とともに、上記のトランスミッターをMPSCチャネルを
-    // 介して送信
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::KeyValue(KeyValueMessage::Set(
-            SetKeyValueMessage {
-                key,
-                value,
-                response: tx,
-            },
-        )))
+async fn ask_key_value<R>(
+    system: &ActorSystem,
+    f: impl FnOnce(Reply<R>) -> KeyValueMessage,
+) -> io::Result<R> {
+    router(system)?
+        .ask(|response| RoutingMessage::KeyValue(f(response)), TIMEOUT)
         .await
-        .unwrap();
-    // ワンショットチャネルからキーと値を保存したことを示す通知を受信
-    rx.await.unwrap();
-    Ok(())
+        .map_err(io::Error::other)
 }
 
-async fn delete(key: String) -> Result<(), std::io::Error> {
-    let (tx, rx) = oneshot::channel();
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::KeyValue(KeyValueMessage::Delete(
-            DeleteKeyValueMessage { key, response: tx },
-        )))
-        .await
-        .unwrap();
-    rx.await.unwrap();
-    Ok(())
+async fn get(system: &ActorSystem, key: String) -> io::Result<Option<Vec<u8>>> {
+    ask_key_value(system, |response| KeyValueMessage::Get { key, response }).await
+}
+
+async fn set(system: &ActorSystem, key: String, value: Vec<u8>) -> io::Result<()> {
+    // キーバーリューストアに保存するキーと値とともに、保存したことを通知する応答先をルーターに送信して、
+    // 応答を待つ
+    ask_key_value(system, |response| KeyValueMessage::Set {
+        key,
+        value,
+        response,
+    })
+    .await
+}
+
+async fn delete(system: &ActorSystem, key: String) -> io::Result<()> {
+    ask_key_value(system, |response| KeyValueMessage::Delete { key, response }).await
 }
 
 #[tokio::main]
-#[allow(clippy::let_unit_value)]
-async fn main() -> Result<(), std::io::Error> {
-    // MPSCなルーティングチャネル
-    let (sender, receiver) = channel(32);
-    ROUTER_SENDER.set(sender).unwrap();
-    tokio::spawn(router(receiver));
-
-    let _ = set(String::from("hello"), b"world".to_vec()).await?;
-    let value = get(String::from("hello")).await?;
+async fn main() -> io::Result<()> {
+    let system = ActorSystem::new("key_value_store");
+    let key_value = actor::spawn(KeyValueActor::default());
+    system
+        .register_type(&actor::spawn(Router { key_value }))
+        .map_err(io::Error::other)?;
+
+    set(&system, String::from("hello"), b"world".to_vec()).await?;
+    let value = get(&system, String::from("hello")).await?;
     println!("{:?}", String::from_utf8(value.unwrap()));
-    let _ = delete(String::from("hello")).await?;
-    let value = get(String::from("hello")).await?;
+    delete(&system, String::from("hello")).await?;
+    let value = get(&system, String::from("hello")).await?;
     println!("{value:?}");
 
     Ok(())
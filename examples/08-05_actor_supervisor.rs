@@ -1,342 +1,268 @@
-use std::{collections::HashMap, sync::OnceLock};
-
-use tokio::{
-    fs::File,
-    io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
-    sync::{
-        mpsc::{Receiver, Sender, channel},
-        oneshot,
+//! スーパーバイザーによるアクターの再起動
+//!
+//! 永続化ファイルライタアクター（`kv::writer::WriterActor`）とキーバリューストアアクターを、`RestForOne`戦略のスーパーバイザーで監視する。
+//! ライタアクターが異常終了した場合は両方のアクターを、キーバリューストアアクターが異常終了した場合は
+//! キーバリューストアアクターだけを再起動する。
+//!
+//! メッセージの処理に`STALL_TIMEOUT`を超えたアクターは、停滞していると判断されて再起動される。
+//! アクターのアドレスは再起動しても変わらず、メールボックスに残っていたメッセージは新しいインスタンスが処理する。
+//!
+//! 停止したアクターに届かなかったメッセージは、アクターシステムのデッドレターに記録される。
+use std::{collections::HashMap, io, time::Duration};
+
+use async_rust::{
+    actor::{
+        self, Actor, Addr, Context, Reply,
+        supervisor::{Backoff, ChildSpec, Restart, Strategy, Supervisor},
+        system::{ActorSystem, Terminated},
+    },
+    kv::{
+        KvError, Operation,
+        writer::{WriterActor, WriterLogMessage},
     },
-    time::{self, Duration, Instant},
+    runtime::time,
 };
 
-static ROUTER_SENDER: OnceLock<Sender<RoutingMessage>> = OnceLock::new();
-
-struct SetKeyValueMessage {
-    key: String,
-    value: Vec<u8>,
-    response: oneshot::Sender<()>,
-}
-
-struct GetKeyValueMessage {
-    key: String,
-    response: oneshot::Sender<Option<Vec<u8>>>,
-}
-
-struct DeleteKeyValueMessage {
-    key: String,
-    response: oneshot::Sender<()>,
-}
+/// アクターの応答を待つ時間
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// 1つのメッセージの処理にこの時間を超えたアクターを停滞していると判断する
+const STALL_TIMEOUT: Duration = Duration::from_millis(700);
 
 enum KeyValueMessage {
-    Get(GetKeyValueMessage),
-    Set(SetKeyValueMessage),
+    Get {
+        key: String,
+        response: Reply<Option<Vec<u8>>>,
+    },
+    Set {
+        key: String,
+        value: Vec<u8>,
+        response: Reply<Result<u64, KvError>>,
+    },
     #[allow(dead_code)]
-    Delete(DeleteKeyValueMessage),
+    Delete {
+        key: String,
+        response: Reply<Result<u64, KvError>>,
+    },
+    /// 指定した時間だけ処理を止めて、アクターを停滞させる
+    Stall(Duration),
 }
 
 enum RoutingMessage {
     KeyValue(KeyValueMessage),
-    Heartbeat(ActorType),
-    Reset(ActorType),
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum ActorType {
-    KeyValue,
-    Writer,
+    /// スーパーバイザーがキーバリューストアアクターを停止した
+    Terminated(Terminated),
 }
 
-enum WriterLogMessage {
-    Set(String, Vec<u8>),
-    Delete(String),
-    Get(oneshot::Sender<HashMap<String, Vec<u8>>>),
+struct KeyValueActor {
+    map: HashMap<String, Vec<u8>>,
+    writer: Addr<WriterActor>,
 }
 
-impl WriterLogMessage {
-    fn from_key_value_message(message: &KeyValueMessage) -> Option<Self> {
-        match message {
-            KeyValueMessage::Get(_) => None,
-            KeyValueMessage::Set(message) => {
-                Some(Self::Set(message.key.clone(), message.value.clone()))
-            }
-            KeyValueMessage::Delete(message) => Some(Self::Delete(message.key.clone())),
+impl KeyValueActor {
+    fn new(writer: Addr<WriterActor>) -> Self {
+        Self {
+            map: HashMap::new(),
+            writer,
         }
     }
 }
 
-async fn read_data_from_file(file_path: &str) -> io::Result<HashMap<String, Vec<u8>>> {
-    let mut file = File::open(file_path).await?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).await?;
-    let data: HashMap<String, Vec<u8>> = serde_json::from_str(&contents)?;
-    Ok(data)
-}
-
-async fn load_map(file_path: &str) -> HashMap<String, Vec<u8>> {
-    match read_data_from_file(file_path).await {
-        Ok(data) => {
-            println!("Data loaded from file: {data:?}");
-            data
-        }
-        Err(e) => {
-            eprintln!("Failed to read from file: {e:?}");
-            println!("Starting with an empty hashmap");
-            HashMap::new()
-        }
+impl Actor for KeyValueActor {
+    type Message = KeyValueMessage;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        println!("Starting key_value_actor");
+        self.map = self
+            .writer
+            .ask(WriterLogMessage::Get, TIMEOUT)
+            .await
+            .map(|data| {
+                data.iter()
+                    .map(|(key, entry)| (key.clone(), entry.value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
     }
-}
-
-async fn key_value_actor(mut receiver: Receiver<KeyValueMessage>) {
-    println!("Starting key_value_actor");
-    let (writer_key_value_sender, writer_key_value_receiver) = channel(32);
-    tokio::spawn(writer_actor(writer_key_value_receiver));
-
-    let (get_sender, get_receiver) = oneshot::channel();
-    let _ = writer_key_value_sender
-        .send(WriterLogMessage::Get(get_sender))
-        .await;
-    let mut map = get_receiver.await.unwrap();
 
-    let timeout_duration = Duration::from_millis(200);
-    let router_sender = ROUTER_SENDER.get().unwrap().clone();
-
-    loop {
-        match time::timeout(timeout_duration, receiver.recv()).await {
-            Ok(Some(message)) => {
-                if let Some(write_message) = WriterLogMessage::from_key_value_message(&message) {
-                    let _ = writer_key_value_sender.send(write_message).await;
-                }
-                match message {
-                    KeyValueMessage::Get(GetKeyValueMessage { key, response }) => {
-                        let _ = response.send(map.get(&key).cloned());
-                    }
-                    KeyValueMessage::Delete(DeleteKeyValueMessage { key, response }) => {
-                        map.remove(&key);
-                        let _ = response.send(());
-                    }
-                    KeyValueMessage::Set(SetKeyValueMessage {
-                        key,
-                        value,
-                        response,
-                    }) => {
-                        map.insert(key, value);
-                        let _ = response.send(());
-                    }
-                }
+    async fn handle(&mut self, message: KeyValueMessage, _ctx: &mut Context<Self>) {
+        let (operation, response) = match message {
+            KeyValueMessage::Get { key, response } => {
+                let _ = response.send(self.map.get(&key).cloned());
+                return;
             }
-            Ok(None) => break,
-            Err(_) => {
-                router_sender
-                    .send(RoutingMessage::Heartbeat(ActorType::KeyValue))
-                    .await
-                    .unwrap();
+            KeyValueMessage::Delete { key, response } => {
+                self.map.remove(&key);
+                (Operation::Delete { key }, response)
             }
-        }
-    }
-}
-
-async fn writer_actor(mut receiver: Receiver<WriterLogMessage>) -> io::Result<()> {
-    time::sleep(Duration::from_millis(100)).await;
-    println!("Starting writer_actor");
-
-    let mut map = load_map("./data.json").await;
-    let mut file = File::create("./data.json").await.unwrap();
-
-    let timeout_duration = Duration::from_millis(200);
-    let router_sender = ROUTER_SENDER.get().unwrap().clone();
-    let now = Instant::now();
-
-    loop {
-        println!("writer instance: {now:?}");
-        match time::timeout(timeout_duration, receiver.recv()).await {
-            Ok(Some(message)) => {
-                match message {
-                    WriterLogMessage::Get(response) => {
-                        let _ = response.send(map.clone());
-                    }
-                    WriterLogMessage::Set(key, value) => {
-                        map.insert(key, value);
-                    }
-                    WriterLogMessage::Delete(key) => {
-                        map.remove(&key);
-                    }
+            KeyValueMessage::Set {
+                key,
+                value,
+                response,
+            } => {
+                self.map.insert(key.clone(), value.clone());
+                let operation = Operation::Set {
+                    key,
+                    value,
+                    expires_at: None,
                 };
-                let contents = serde_json::to_string(&map).unwrap();
-                file.set_len(0).await?;
-                file.seek(std::io::SeekFrom::Start(0)).await?;
-                file.write_all(contents.as_bytes()).await?;
-                file.flush().await?;
+                (operation, response)
             }
-            Ok(None) => break,
-            Err(_) => {
-                router_sender
-                    .send(RoutingMessage::Heartbeat(ActorType::Writer))
-                    .await
-                    .unwrap();
+            KeyValueMessage::Stall(duration) => {
+                time::sleep(duration).await;
+                return;
             }
         };
+        // ライタアクターが、操作をログに同期した後に応答する
+        let _ = self
+            .writer
+            .send(WriterLogMessage::Append {
+                operation,
+                ack: Some(response),
+            })
+            .await;
     }
-    Ok(())
 }
 
-async fn heartbeat_actor(mut receiver: Receiver<ActorType>) {
-    println!("Starting heartbeat_actor");
-    let mut map = HashMap::new();
-    let timeout_duration = Duration::from_millis(200);
-    let now = Instant::now();
-
-    loop {
-        match time::timeout(timeout_duration, receiver.recv()).await {
-            Ok(Some(actor_name)) => map.insert(actor_name, Instant::now()),
-            Ok(None) => break,
-            Err(_) => continue,
-        };
-
-        let little_second_ago = Instant::now() - Duration::from_millis(700);
-        let mut key_reset = false;
-        for (key, &value) in map.iter() {
-            if value < little_second_ago {
-                match key {
-                    ActorType::KeyValue | ActorType::Writer => {
-                        println!("sending reset message form {now:?} {key:?}");
-                        ROUTER_SENDER
-                            .get()
-                            .unwrap()
-                            .send(RoutingMessage::Reset(ActorType::KeyValue))
-                            .await
-                            .unwrap();
-                        key_reset = true;
-                    }
-                }
-            }
-            if key_reset {
-                break;
-            }
-        }
-        if key_reset {
-            map.remove(&ActorType::KeyValue);
-            map.remove(&ActorType::Writer);
-        }
-    }
+struct RouterActor {
+    key_value: Addr<KeyValueActor>,
 }
 
-async fn router_actor(mut receiver: Receiver<RoutingMessage>) {
-    println!("Starting router_actor");
+impl Actor for RouterActor {
+    type Message = RoutingMessage;
 
-    let (mut key_value_sender, mut key_value_receiver) = channel(32);
-    let mut key_value_handle = tokio::spawn(key_value_actor(key_value_receiver));
-
-    let (heartbeat_sender, heartbeat_receiver) = channel(32);
-    tokio::spawn(heartbeat_actor(heartbeat_receiver));
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        println!("Starting router_actor");
+    }
 
-    while let Some(message) = receiver.recv().await {
+    async fn handle(&mut self, message: RoutingMessage, ctx: &mut Context<Self>) {
         match message {
             RoutingMessage::KeyValue(message) => {
-                let _ = key_value_sender.send(message).await;
+                let _ = self.key_value.send(message).await;
             }
-            RoutingMessage::Heartbeat(message) => {
-                let _ = heartbeat_sender.send(message).await;
+            RoutingMessage::Terminated(terminated) => {
+                println!("{} ({}) terminated", terminated.name, terminated.id);
+                ctx.stop();
             }
-            RoutingMessage::Reset(message) => match message {
-                ActorType::KeyValue | ActorType::Writer => {
-                    let (new_key_value_sender, new_key_value_receiver) = channel(32);
-                    key_value_handle.abort();
-                    key_value_sender = new_key_value_sender;
-                    key_value_receiver = new_key_value_receiver;
-                    key_value_handle = tokio::spawn(key_value_actor(key_value_receiver));
-                    time::sleep(Duration::from_millis(100)).await;
-                }
-            },
         }
     }
 }
 
-async fn get(key: String) -> Result<Option<Vec<u8>>, std::io::Error> {
-    let (tx, rx) = oneshot::channel();
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::KeyValue(KeyValueMessage::Get(
-            GetKeyValueMessage { key, response: tx },
-        )))
-        .await
-        .unwrap();
-    Ok(rx.await.unwrap())
+/// アクターシステムに登録したルーターアクターのアドレスを返す。
+fn router(system: &ActorSystem) -> io::Result<Addr<RouterActor>> {
+    system
+        .lookup_type::<RouterActor>()
+        .map_err(io::Error::other)
 }
 
-async fn set(key: String, value: Vec<u8>) -> Result<(), std::io::Error> {
-    // キーバリューストアにキーと値を保存したことを通知するワンショットチャネル
-    let (tx, rx) = oneshot::channel();
-    // キーバーリューストアに保存するキーと値とともに、上記のトランスミッターをMPSCチャネルを
-    // 介して送信
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::KeyValue(KeyValueMessage::Set(
-            SetKeyValueMessage {
-                key,
-                value,
-                response: tx,
-            },
-        )))
+async fn ask_key_value<R>(
+    system: &ActorSystem,
+    f: impl FnOnce(Reply<R>) -> KeyValueMessage,
+) -> io::Result<R> {
+    router(system)?
+        .ask(|response| RoutingMessage::KeyValue(f(response)), TIMEOUT)
         .await
-        .unwrap();
-    // ワンショットチャネルからキーと値を保存したことを示す通知を受信
-    rx.await.unwrap();
-    Ok(())
+        .map_err(io::Error::other)
 }
 
-#[allow(dead_code)]
-async fn delete(key: String) -> Result<(), std::io::Error> {
-    let (tx, rx) = oneshot::channel();
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::KeyValue(KeyValueMessage::Delete(
-            DeleteKeyValueMessage { key, response: tx },
-        )))
-        .await
-        .unwrap();
-    rx.await.unwrap();
-    Ok(())
+async fn get(system: &ActorSystem, key: String) -> io::Result<Option<Vec<u8>>> {
+    ask_key_value(system, |response| KeyValueMessage::Get { key, response }).await
 }
 
-impl From<&KeyValueMessage> for Option<WriterLogMessage> {
-    fn from(message: &KeyValueMessage) -> Self {
-        match message {
-            KeyValueMessage::Get(_) => None,
-            KeyValueMessage::Set(message) => Some(WriterLogMessage::Set(
-                message.key.clone(),
-                message.value.clone(),
-            )),
-            KeyValueMessage::Delete(message) => Some(WriterLogMessage::Delete(message.key.clone())),
-        }
-    }
+async fn set(system: &ActorSystem, key: String, value: Vec<u8>) -> io::Result<()> {
+    // キーバーリューストアに保存するキーと値とともに、保存したことを通知する応答先をルーターに送信して、
+    // 応答を待つ
+    ask_key_value(system, |response| KeyValueMessage::Set {
+        key,
+        value,
+        response,
+    })
+    .await?
+    .map(|_| ())
+    .map_err(io::Error::other)
+}
+
+#[allow(dead_code)]
+async fn delete(system: &ActorSystem, key: String) -> io::Result<()> {
+    ask_key_value(system, |response| KeyValueMessage::Delete { key, response })
+        .await?
+        .map(|_| ())
+        .map_err(io::Error::other)
 }
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
-    let (sender, receiver) = channel(32);
-    ROUTER_SENDER.set(sender).unwrap();
-    tokio::spawn(router_actor(receiver));
+async fn main() -> io::Result<()> {
+    let mut supervisor = Supervisor::new("key_value_store", Strategy::RestForOne)
+        .with_intensity(3, Duration::from_secs(5))
+        .with_backoff(Backoff::new(
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+        ))
+        .on_child_exit(|name, reason| println!("{name} exited: {reason}"));
+    // ライタアクターを先に追加して、キーバリューストアアクターより先に起動する
+    let writer = supervisor.add(
+        ChildSpec::new("writer", || WriterActor::new("./data")).with_restart(Restart::Permanent),
+    );
+    let key_value = supervisor.add(
+        ChildSpec::new("key_value", move || KeyValueActor::new(writer.clone()))
+            .with_restart(Restart::Permanent)
+            .with_stall_timeout(STALL_TIMEOUT),
+    );
+    let handle = supervisor.start();
+
+    let system = ActorSystem::new("key_value_store");
+    system
+        .register("key_value", &key_value)
+        .map_err(io::Error::other)?;
+    let router = actor::spawn(RouterActor { key_value });
+    system.register_type(&router).map_err(io::Error::other)?;
+    // スーパーバイザーがキーバリューストアアクターを停止したら、ルーターアクターも停止する
+    system
+        .watch("key_value", &router, RoutingMessage::Terminated)
+        .map_err(io::Error::other)?;
+    let mut dead_letters = system
+        .dead_letters()
+        .subscribe()
+        .await
+        .map_err(io::Error::other)?;
 
-    set("hello".to_string(), b"world".to_vec()).await?;
+    set(&system, "hello".to_string(), b"world".to_vec()).await?;
 
-    let value = get("hello".to_string()).await;
+    let value = get(&system, "hello".to_string()).await;
     println!("value: {value:?}");
 
-    ROUTER_SENDER
-        .get()
-        .unwrap()
-        .send(RoutingMessage::Reset(ActorType::KeyValue))
+    // キーバリューストアアクターを停滞させる
+    router
+        .send(RoutingMessage::KeyValue(KeyValueMessage::Stall(
+            Duration::from_secs(2),
+        )))
         .await
-        .unwrap();
+        .map_err(io::Error::other)?;
 
-    let value = get("hello".to_string()).await?;
+    // 停滞している間に送信したメッセージは、再起動したキーバリューストアアクターが処理する
+    set(&system, "test".to_string(), b"world".to_vec()).await?;
+    let value = get(&system, "hello".to_string()).await?;
     println!("value: {value:?}");
 
-    set("test".to_string(), b"world".to_vec()).await?;
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    let reason = handle.shutdown().await;
+    println!("supervisor exited: {reason}");
+    router.stopped().await;
+
+    // 停止したアクターへの要求はエラーになり、デッドレターに記録される
+    if let Err(e) = get(&system, "hello".to_string()).await {
+        println!("get after shutdown: {e}");
+    }
+    if let Err(e) = router
+        .send(RoutingMessage::KeyValue(KeyValueMessage::Stall(
+            Duration::ZERO,
+        )))
+        .await
+    {
+        println!("send after shutdown: {e}");
+    }
+    time::sleep(Duration::from_millis(10)).await;
+    while let Ok(letter) = dead_letters.try_recv() {
+        println!("dead letter: {letter}");
+    }
+    println!("dead letters: {}", system.dead_letters().stats().total());
     Ok(())
 }
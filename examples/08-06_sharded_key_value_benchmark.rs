@@ -0,0 +1,107 @@
+//! シャーディングしたキーバリューストアのスループット
+//!
+//! ルーターアクター（`kv::router::Router`）は、コンシステントハッシュのリング（`kv::ring::HashRing`）で
+//! キーをシャードに割り当てる。シャードは、それぞれが永続用ファイルライタアクターとキーバリューストアアクターを持ち、
+//! 独立したディレクトリに永続化する。
+//!
+//! シャードの数を1、2、4、8と変えて、同時に書き込むクライアントのタスクから一定数のキーを書き込み、
+//! 1秒あたりの書き込み数を表示する。書き込みはログの同期を待ってから応答するため、シャードが増えると
+//! 並行して同期できる書き込みが増えて、スループットが向上する。
+//! 向上の度合いは、利用できるCPUのコア数とストレージの同期の遅延に依存する
+//! （コアが1つで同期が速い環境では、ルーターアクターの転送が支配的になって向上しない）。
+//!
+//! 最後に、稼働中のストアにシャードを追加・削除して、キーの移行にかかった時間を表示する。
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use async_rust::{
+    actor::system::ActorSystem,
+    kv::{client::Client, router::Router},
+};
+
+/// 同時に書き込むクライアントのタスクの数
+const WRITERS: usize = 64;
+/// 各タスクが書き込むキーの数
+const WRITES_PER_WRITER: usize = 100;
+
+async fn start(dir: &Path, shards: usize) -> io::Result<Client> {
+    let _ = std::fs::remove_dir_all(dir);
+    let system = ActorSystem::new("sharded_key_value_store");
+    Ok(
+        Client::start_with(&system, Router::new(dir).with_shards(shards))
+            .await
+            .map_err(io::Error::other)?
+            .with_timeout(Duration::from_secs(30)),
+    )
+}
+
+/// `WRITERS`個のタスクから同時に書き込んで、1秒あたりの書き込み数を返す。
+async fn write_throughput(client: &Client) -> io::Result<f64> {
+    let started = Instant::now();
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                for i in 0..WRITES_PER_WRITER {
+                    client
+                        .set(format!("user/{writer:03}/{i:04}"), i.to_string())
+                        .await?;
+                }
+                Ok::<_, async_rust::kv::KvError>(())
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await?.map_err(io::Error::other)?;
+    }
+    Ok((WRITERS * WRITES_PER_WRITER) as f64 / started.elapsed().as_secs_f64())
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let root = Path::new("./data/sharded");
+
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{WRITERS} writers x {WRITES_PER_WRITER} writes, {cores} cores");
+    println!("{:>6} {:>12} {:>8}", "shards", "writes/s", "speedup");
+    let mut baseline = None;
+    for shards in [1, 2, 4, 8] {
+        let client = start(&root.join(format!("{shards}")), shards).await?;
+        let throughput = write_throughput(&client).await?;
+        let baseline = *baseline.get_or_insert(throughput);
+        println!(
+            "{shards:>6} {throughput:>12.0} {:>7.2}x",
+            throughput / baseline
+        );
+        client.shutdown().await;
+    }
+
+    // シャードの追加と削除では、割り当てが変わるキーだけを移行する
+    let client = start(&root.join("resharding"), 4).await?;
+    write_throughput(&client).await?;
+    let keys = client
+        .scan_prefix("user/")
+        .await
+        .map_err(io::Error::other)?
+        .len();
+
+    let started = Instant::now();
+    let shard = client.add_shard().await.map_err(io::Error::other)?;
+    println!("added shard {shard} in {:?}", started.elapsed());
+    let started = Instant::now();
+    client.remove_shard(0).await.map_err(io::Error::other)?;
+    println!("removed shard 0 in {:?}", started.elapsed());
+
+    let shards = client.shards().await.map_err(io::Error::other)?;
+    let remaining = client
+        .scan_prefix("user/")
+        .await
+        .map_err(io::Error::other)?
+        .len();
+    println!("shards: {shards:?}, keys: {remaining}/{keys}");
+    client.shutdown().await;
+    Ok(())
+}
@@ -2,7 +2,7 @@ use async_rust::async_mod;
 
 fn main() {
     println!("Hello, world!");
-    let id = async_mod::send_add(1, 2).unwrap();
+    let id = async_mod::send_add(1, 2);
     println!("id: {id}");
     std::thread::sleep(std::time::Duration::from_secs(4));
     println!("main sleep done");
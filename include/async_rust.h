@@ -0,0 +1,162 @@
+#ifndef ASYNC_RUST_H
+#define ASYNC_RUST_H
+
+/* このファイルはbuild.rsでcbindgenにより生成される。直接編集しないこと。 */
+
+#include <stdint.h>
+
+/**
+ * C言語から呼び出す関数の結果
+ */
+typedef enum AsyncRustStatus {
+  /**
+   * 成功した。
+   */
+  ASYNC_RUST_STATUS_OK = 0,
+  /**
+   * ジョブが完了していない。
+   */
+  ASYNC_RUST_STATUS_PENDING = 1,
+  /**
+   * ジョブが存在しない。
+   */
+  ASYNC_RUST_STATUS_NOT_FOUND = 2,
+  /**
+   * 指定した時間内にジョブが完了しなかった。
+   */
+  ASYNC_RUST_STATUS_TIMEOUT = 3,
+  /**
+   * ジョブがキャンセルされた。
+   */
+  ASYNC_RUST_STATUS_CANCELLED = 4,
+  /**
+   * ジョブがパニックした。
+   */
+  ASYNC_RUST_STATUS_PANICKED = 5,
+  /**
+   * 引数が不正（NULLポインターなど）。
+   */
+  ASYNC_RUST_STATUS_INVALID_ARGUMENT = 6,
+} AsyncRustStatus;
+
+/**
+ * ジョブの状態
+ */
+typedef enum AsyncRustJobStatus {
+  ASYNC_RUST_JOB_STATUS_PENDING = 0,
+  ASYNC_RUST_JOB_STATUS_RUNNING = 1,
+  ASYNC_RUST_JOB_STATUS_DONE = 2,
+  ASYNC_RUST_JOB_STATUS_FAILED = 3,
+} AsyncRustJobStatus;
+
+/**
+ * ジョブを識別するID
+ *
+ * 値として受け渡しでき、解放する必要はない。
+ */
+typedef struct AsyncRustJobId {
+  uint8_t bytes[16];
+} AsyncRustJobId;
+
+/**
+ * ジョブが完了したときに呼び出されるコールバック
+ *
+ * `status`が`Ok`の場合に限り、`result`にジョブの結果が格納される。
+ */
+typedef void (*AsyncRustCallback)(void *user_data,
+                                  struct AsyncRustJobId id,
+                                  enum AsyncRustStatus status,
+                                  int32_t result);
+
+/**
+ * `a + b`を非同期に計算するジョブを投入して、IDを`out_id`に書き込む。
+ *
+ * # Safety
+ *
+ * `out_id`は、NULLまたは書き込み可能な`AsyncRustJobId`へのポインターでなければならない。
+ */
+enum AsyncRustStatus async_rust_add_submit(int32_t a,
+                                           int32_t b,
+                                           struct AsyncRustJobId *out_id);
+
+/**
+ * ジョブの状態を`out_status`に書き込む。
+ *
+ * # Safety
+ *
+ * `out_status`は、NULLまたは書き込み可能な`AsyncRustJobStatus`へのポインターでなければならない。
+ */
+enum AsyncRustStatus async_rust_add_status(struct AsyncRustJobId id,
+                                           enum AsyncRustJobStatus *out_status);
+
+/**
+ * ブロックせずにジョブの結果を回収して、`out_result`に書き込む。
+ *
+ * ジョブが完了していない場合は`Pending`を返す。
+ * 結果を回収したジョブ（失敗したジョブを含む）は削除される。
+ *
+ * # Safety
+ *
+ * `out_result`は、NULLまたは書き込み可能なi32へのポインターでなければならない。
+ */
+enum AsyncRustStatus async_rust_add_poll(struct AsyncRustJobId id,
+                                         int32_t *out_result);
+
+/**
+ * ジョブが完了するまで最大`timeout_ms`ミリ秒の間ブロックして、結果を`out_result`に書き込む。
+ *
+ * # Safety
+ *
+ * `out_result`は、NULLまたは書き込み可能なi32へのポインターでなければならない。
+ */
+enum AsyncRustStatus async_rust_add_wait(struct AsyncRustJobId id,
+                                         uint64_t timeout_ms,
+                                         int32_t *out_result);
+
+/**
+ * 完了していないジョブをキャンセルする。
+ */
+enum AsyncRustStatus async_rust_add_cancel(struct AsyncRustJobId id);
+
+/**
+ * ジョブが完了したときに呼び出すコールバックを登録する。
+ *
+ * コールバックはランタイムのスレッドで1度だけ呼び出され、結果はコールバックに渡される。
+ * コールバックの中では、このライブラリの関数（`async_rust_last_error_message`を含む）を呼び出せる。
+ * ただし、ランタイムのスレッドをブロックするため、`async_rust_add_wait`を呼び出してはならない。
+ *
+ * # Safety
+ *
+ * `user_data`は、コールバックが呼び出されるまで有効で、任意のスレッドから使用できなければならない。
+ */
+enum AsyncRustStatus async_rust_add_on_complete(struct AsyncRustJobId id,
+                                                AsyncRustCallback callback,
+                                                void *user_data);
+
+/**
+ * 現在のスレッドで最後に発生したエラーのメッセージを返す。
+ *
+ * エラーが発生していない場合はNULLを返す。
+ * 返された文字列は呼び出し側が所有し、`async_rust_string_free`で解放しなければならない。
+ */
+char *async_rust_last_error_message(void);
+
+/**
+ * ジョブのIDを文字列に変換する。
+ *
+ * 返された文字列は呼び出し側が所有し、`async_rust_string_free`で解放しなければならない。
+ */
+char *async_rust_job_id_to_string(struct AsyncRustJobId id);
+
+/**
+ * このライブラリが返した文字列を解放する。
+ *
+ * NULLを渡した場合は何もしない。
+ *
+ * # Safety
+ *
+ * `s`は、NULLまたはこのライブラリが返した解放していない文字列でなければならない。
+ */
+void async_rust_string_free(char *s);
+
+#endif  /* ASYNC_RUST_H */
@@ -0,0 +1,665 @@
+pub mod dead_letter;
+pub mod supervisor;
+pub mod system;
+
+use std::{
+    any::{Any, type_name},
+    fmt,
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use futures::FutureExt;
+use tokio::sync::{Notify, mpsc, oneshot, watch};
+
+use dead_letter::{DeadLetter, DeadLetterReason, DeadLetters};
+
+use crate::runtime::{spawn_on_tokio, time};
+
+/// メールボックスに格納できるメッセージの数の既定値
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 32;
+
+/// アクター
+///
+/// アクターは自分の状態を所有し、メールボックスに届いたメッセージを1つずつ処理する。
+/// メッセージの処理中に別のメッセージが処理されることはないため、状態をロックで保護する必要はない。
+///
+/// 応答が必要なメッセージには[`Reply`]を含めて、[`Addr::ask`]で送信する。
+pub trait Actor: Sized + Send + 'static {
+    /// アクターが受け取るメッセージ
+    type Message: Send + 'static;
+
+    /// 最初のメッセージを処理する前に呼び出される。
+    fn started(&mut self, _ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// メッセージを処理する。
+    fn handle(
+        &mut self,
+        msg: Self::Message,
+        ctx: &mut Context<Self>,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// アクターが停止する前に呼び出される。
+    ///
+    /// 呼び出された後に、メッセージが処理されることはない。
+    /// パニックや[`Addr::kill`]などで異常終了した場合は呼び出されない。
+    fn stopping(&mut self, _ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// アクターを識別するID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ActorId(u64);
+
+impl ActorId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor-{}", self.0)
+    }
+}
+
+/// メールボックスに格納される要素
+enum Envelope<M> {
+    /// メッセージと、送信したアクターのID
+    Message(M, Option<ActorId>),
+    /// それまでに届いたメッセージを処理した後に停止する。
+    Stop,
+}
+
+/// アクターの状態のうち、すべてのアドレスで共有するもの
+struct Shared {
+    id: ActorId,
+    /// アクターが停止したときに`true`になる
+    ///
+    /// スーパーバイザーが再起動している間は`false`のまま。
+    stopped: watch::Sender<bool>,
+    /// アクターを直ちに停止させる
+    kill: Notify,
+    /// 届かなかったメッセージを送るデッドレター
+    dead_letters: OnceLock<DeadLetters>,
+    /// このアクターを起動したアクター
+    ///
+    /// デッドレターを指定していない場合は、親のデッドレターを使用する。
+    parent: Option<Arc<Shared>>,
+}
+
+impl Shared {
+    fn dead_letters(&self) -> Option<&DeadLetters> {
+        self.dead_letters
+            .get()
+            .or_else(|| self.parent.as_ref()?.dead_letters())
+    }
+
+    /// このアクターに届かなかったメッセージを、デッドレターに送る。
+    fn dead_letter<M>(&self, sender: Option<ActorId>, reason: DeadLetterReason) {
+        if let Some(dead_letters) = self.dead_letters() {
+            dead_letters.publish(DeadLetter {
+                sender,
+                target: self.id,
+                message_type: type_name::<M>(),
+                reason,
+            });
+        }
+    }
+}
+
+tokio::task_local! {
+    /// メッセージを処理しているアクター
+    static CURRENT: Arc<Shared>;
+}
+
+/// 呼び出したアクターのIDを返す。
+///
+/// アクターの外から呼び出した場合は`None`を返す。
+fn current_actor() -> Option<ActorId> {
+    CURRENT.try_with(|shared| shared.id).ok()
+}
+
+/// アクターのインスタンスが終了した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    /// アクターが自ら停止したか、すべてのアドレスが破棄された。
+    Normal,
+    /// スーパーバイザーに停止させられた。
+    Shutdown,
+    /// [`Addr::kill`]で停止させられた。
+    Killed,
+    /// パニックした。
+    Panicked(String),
+    /// メッセージの処理が制限時間内に終わらなかった。
+    Stalled,
+    /// 子の再起動の回数が上限を超えたため、スーパーバイザーが停止した。
+    Escalated,
+}
+
+impl ExitReason {
+    /// 異常終了の場合に`true`を返す。
+    pub fn is_abnormal(&self) -> bool {
+        !matches!(self, Self::Normal | Self::Shutdown)
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => f.write_str("normal"),
+            Self::Shutdown => f.write_str("shutdown"),
+            Self::Killed => f.write_str("killed"),
+            Self::Panicked(message) => write!(f, "panicked: {message}"),
+            Self::Stalled => f.write_str("stalled"),
+            Self::Escalated => f.write_str("escalated"),
+        }
+    }
+}
+
+/// メッセージの送信に失敗したときのエラー
+///
+/// 送信できなかったメッセージを保持する。
+#[derive(PartialEq, Eq)]
+pub enum SendError<M> {
+    /// アクターが停止している。
+    Closed(M),
+    /// メールボックスが満杯（`try_send`の場合のみ）
+    Full(M),
+}
+
+impl<M> SendError<M> {
+    /// 送信できなかったメッセージを返す。
+    pub fn into_inner(self) -> M {
+        match self {
+            Self::Closed(msg) | Self::Full(msg) => msg,
+        }
+    }
+}
+
+impl<M> fmt::Debug for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed(_) => f.write_str("Closed(..)"),
+            Self::Full(_) => f.write_str("Full(..)"),
+        }
+    }
+}
+
+impl<M> fmt::Display for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed(_) => f.write_str("actor has stopped"),
+            Self::Full(_) => f.write_str("actor's mailbox is full"),
+        }
+    }
+}
+
+impl<M> std::error::Error for SendError<M> {}
+
+/// 要求に対する応答を得られなかったときのエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AskError {
+    /// アクターが停止している。
+    Closed,
+    /// 指定した時間内に応答がなかった。
+    Timeout,
+    /// アクターが応答せずに`Reply`を破棄した。
+    NoReply,
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => f.write_str("actor has stopped"),
+            Self::Timeout => f.write_str("actor did not reply in time"),
+            Self::NoReply => f.write_str("actor dropped the reply without responding"),
+        }
+    }
+}
+
+impl std::error::Error for AskError {}
+
+/// 要求に対する応答を送信する。
+///
+/// メッセージに含めてアクターに渡し、アクターが[`Reply::send`]で応答する。
+/// 応答せずに破棄した場合と、要求した側が待っていない応答は、要求を受けたアクターのデッドレターに送る。
+pub struct Reply<R> {
+    sender: Option<oneshot::Sender<R>>,
+    /// 要求を受けたアクター
+    target: Arc<Shared>,
+    /// 要求したアクター
+    requester: Option<ActorId>,
+}
+
+impl<R> Reply<R> {
+    /// 応答を送信する。
+    ///
+    /// 要求した側がタイムアウトなどで応答を待っていない場合は、応答を返す。
+    pub fn send(mut self, value: R) -> Result<(), R> {
+        let sender = self.sender.take().expect("reply is sent only once");
+        sender.send(value).inspect_err(|_| {
+            self.target
+                .dead_letter::<R>(self.requester, DeadLetterReason::ReplyUnclaimed);
+        })
+    }
+
+    /// 要求した側が応答を待っていない場合に`true`を返す。
+    pub fn is_closed(&self) -> bool {
+        self.sender.as_ref().is_none_or(oneshot::Sender::is_closed)
+    }
+}
+
+impl<R> Drop for Reply<R> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take()
+            && !sender.is_closed()
+        {
+            self.target
+                .dead_letter::<R>(self.requester, DeadLetterReason::ReplyDropped);
+        }
+    }
+}
+
+impl<R> fmt::Debug for Reply<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reply")
+            .field("target", &self.target.id)
+            .field("requester", &self.requester)
+            .finish()
+    }
+}
+
+/// アクターにメッセージを送信するアドレス
+///
+/// すべてのアドレスが破棄されると、アクターはメールボックスに残っているメッセージを処理した後に停止する。
+pub struct Addr<A: Actor> {
+    sender: mpsc::Sender<Envelope<A::Message>>,
+    shared: Arc<Shared>,
+}
+
+impl<A: Actor> Clone for Addr<A> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<A: Actor> fmt::Debug for Addr<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Addr").field(&self.shared.id).finish()
+    }
+}
+
+impl<A: Actor> Addr<A> {
+    pub fn id(&self) -> ActorId {
+        self.shared.id
+    }
+
+    /// メッセージを送信する（応答を待たない）。
+    ///
+    /// メールボックスが満杯の場合は、空きができるまで待機する。
+    /// 送信できなかったメッセージは、エラーとして返すとともにデッドレターとして記録する。
+    pub async fn send(&self, msg: A::Message) -> Result<(), SendError<A::Message>> {
+        let sender = current_actor();
+        self.sender
+            .send(Envelope::Message(msg, sender))
+            .await
+            .map_err(|e| {
+                self.shared
+                    .dead_letter::<A::Message>(sender, DeadLetterReason::Stopped);
+                SendError::Closed(e.0.into_message())
+            })
+    }
+
+    /// 待機せずにメッセージを送信する。
+    pub fn try_send(&self, msg: A::Message) -> Result<(), SendError<A::Message>> {
+        let sender = current_actor();
+        self.sender
+            .try_send(Envelope::Message(msg, sender))
+            .map_err(|e| {
+                let (reason, error): (_, fn(_) -> _) = match e {
+                    mpsc::error::TrySendError::Full(_) => {
+                        (DeadLetterReason::MailboxFull, SendError::Full)
+                    }
+                    mpsc::error::TrySendError::Closed(_) => {
+                        (DeadLetterReason::Stopped, SendError::Closed)
+                    }
+                };
+                self.shared.dead_letter::<A::Message>(sender, reason);
+                error(e.into_inner().into_message())
+            })
+    }
+
+    /// 応答を要求するメッセージを送信して、応答を待つ。
+    ///
+    /// `f`に応答を送信する[`Reply`]が渡されるため、それを含めたメッセージを返す。
+    /// `timeout`には、メールボックスの空きを待つ時間と応答を待つ時間の合計を指定する。
+    pub async fn ask<R, F>(&self, f: F, timeout: Duration) -> Result<R, AskError>
+    where
+        F: FnOnce(Reply<R>) -> A::Message,
+    {
+        let (tx, rx) = oneshot::channel();
+        let msg = f(Reply {
+            sender: Some(tx),
+            target: self.shared.clone(),
+            requester: current_actor(),
+        });
+        let request = async {
+            if let Err(e) = self.send(msg).await {
+                // 送信できなかったメッセージの`Reply`は、応答を破棄したとはみなさない
+                drop(rx);
+                drop(e);
+                return Err(AskError::Closed);
+            }
+            rx.await.map_err(|_| AskError::NoReply)
+        };
+        time::timeout(timeout, request)
+            .await
+            .map_err(|_| AskError::Timeout)?
+    }
+
+    /// メールボックスに残っているメッセージを処理した後に、アクターを停止させる。
+    pub async fn stop(&self) {
+        let _ = self.sender.send(Envelope::Stop).await;
+    }
+
+    /// アクターを直ちに停止させる。
+    ///
+    /// 処理中のメッセージは中断され、[`Actor::stopping`]は呼び出されない。
+    /// スーパーバイザーが監視しているアクターは、異常終了として扱われて再起動される。
+    pub fn kill(&self) {
+        self.shared.kill.notify_one();
+    }
+
+    /// アクターが停止するまで待機する。
+    ///
+    /// スーパーバイザーが再起動したアクターは、停止したとはみなさない。
+    pub async fn stopped(&self) {
+        let mut stopped = self.shared.stopped.subscribe();
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    }
+
+    /// アクターが停止している場合に`true`を返す。
+    pub fn is_stopped(&self) -> bool {
+        *self.shared.stopped.borrow()
+    }
+
+    /// アクターを停止させない弱いアドレスを返す。
+    pub fn downgrade(&self) -> WeakAddr<A> {
+        WeakAddr {
+            sender: self.sender.downgrade(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// アクターを停止させないアドレス
+///
+/// [`Addr::downgrade`]で作成し、[`WeakAddr::upgrade`]でアドレスに戻す。
+pub struct WeakAddr<A: Actor> {
+    sender: mpsc::WeakSender<Envelope<A::Message>>,
+    shared: Arc<Shared>,
+}
+
+impl<A: Actor> Clone for WeakAddr<A> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<A: Actor> WeakAddr<A> {
+    pub fn id(&self) -> ActorId {
+        self.shared.id
+    }
+
+    /// アドレスに戻す。
+    ///
+    /// すべてのアドレスが破棄されている場合は`None`を返す。
+    pub fn upgrade(&self) -> Option<Addr<A>> {
+        Some(Addr {
+            sender: self.sender.upgrade()?,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+impl<M> Envelope<M> {
+    fn into_message(self) -> M {
+        match self {
+            Self::Message(msg, _) => msg,
+            Self::Stop => unreachable!("stop envelope is never returned to senders"),
+        }
+    }
+}
+
+/// メールボックスに残っているメッセージを取り出して、デッドレターとして記録した後に`f`に渡す。
+fn drain<M>(receiver: &mut Mailbox<M>, shared: &Shared, mut f: impl FnMut(M)) {
+    while let Ok(envelope) = receiver.try_recv() {
+        if let Envelope::Message(msg, sender) = envelope {
+            shared.dead_letter::<M>(sender, DeadLetterReason::Unprocessed);
+            f(msg);
+        }
+    }
+}
+
+/// メッセージを処理しているアクターの実行環境
+pub struct Context<A: Actor> {
+    addr: WeakAddr<A>,
+    stopping: bool,
+}
+
+impl<A: Actor> Context<A> {
+    pub fn id(&self) -> ActorId {
+        self.addr.id()
+    }
+
+    /// 自分のアドレスを返す。
+    ///
+    /// アクター以外がアドレスをすべて破棄している場合は`None`を返す。
+    pub fn addr(&self) -> Option<Addr<A>> {
+        self.addr.upgrade()
+    }
+
+    /// 自分の弱いアドレスを返す。
+    pub fn weak_addr(&self) -> WeakAddr<A> {
+        self.addr.clone()
+    }
+
+    /// 処理中のメッセージを処理した後に停止する。
+    pub fn stop(&mut self) {
+        self.stopping = true;
+    }
+
+    /// `period`ごとに`f`で作成したメッセージを自分に送信する。
+    ///
+    /// アクターが停止するか、アドレスがすべて破棄されると送信を止める。
+    /// アクターがメッセージを処理できない状態になると、メッセージが処理されなくなるため、
+    /// ハートビートの送信に使用できる。
+    pub fn send_interval<F>(&self, period: Duration, mut f: F)
+    where
+        F: FnMut() -> A::Message + Send + 'static,
+    {
+        let weak = self.addr.clone();
+        spawn_on_tokio(async move {
+            loop {
+                let Some(addr) = weak.upgrade() else {
+                    break;
+                };
+                if addr.send(f()).await.is_err() {
+                    break;
+                }
+                drop(addr);
+                time::sleep(period).await;
+            }
+        })
+        .detach();
+    }
+}
+
+/// アクターが停止したこと（パニックを含む）を通知するガード
+struct StoppedGuard(Arc<Shared>);
+
+impl Drop for StoppedGuard {
+    fn drop(&mut self) {
+        self.0.stopped.send_replace(true);
+    }
+}
+
+type Mailbox<M> = mpsc::Receiver<Envelope<M>>;
+
+/// メールボックスを作成して、そのアドレスと受信側を返す。
+///
+/// アクターの中から呼び出した場合は、そのアクターを親とする。
+fn mailbox<A: Actor>(capacity: usize) -> (Addr<A>, Mailbox<A::Message>) {
+    let parent = CURRENT.try_with(Arc::clone).ok();
+    mailbox_with_parent(capacity, parent)
+}
+
+fn mailbox_with_parent<A: Actor>(
+    capacity: usize,
+    parent: Option<Arc<Shared>>,
+) -> (Addr<A>, Mailbox<A::Message>) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let addr = Addr {
+        sender,
+        shared: Arc::new(Shared {
+            id: ActorId::next(),
+            stopped: watch::Sender::new(false),
+            kill: Notify::new(),
+            dead_letters: OnceLock::new(),
+            parent,
+        }),
+    };
+    (addr, receiver)
+}
+
+/// アクターを既定の容量のメールボックスで起動する。
+///
+/// Tokioランタイムか[`Simulation`](crate::runtime::sim::Simulation)の中から呼び出さなければならない。
+/// シミュレーションの中で起動したアクターは、仮想時間で待機する。
+pub fn spawn<A: Actor>(actor: A) -> Addr<A> {
+    spawn_with_capacity(actor, DEFAULT_MAILBOX_CAPACITY)
+}
+
+/// アクターを`capacity`個のメッセージを格納できるメールボックスで起動する。
+///
+/// メールボックスが満杯のとき、[`Addr::send`]は空きができるまで待機する。
+pub fn spawn_with_capacity<A: Actor>(actor: A, capacity: usize) -> Addr<A> {
+    let (addr, receiver) = mailbox::<A>(capacity);
+    start(actor, addr, receiver)
+}
+
+/// メールボックスを作成したアクターを、タスクで実行する。
+fn start<A: Actor>(mut actor: A, addr: Addr<A>, mut receiver: Mailbox<A::Message>) -> Addr<A> {
+    let mut ctx = Context {
+        addr: addr.downgrade(),
+        stopping: false,
+    };
+    let shared = addr.shared.clone();
+    let guard = StoppedGuard(shared.clone());
+    spawn_on_tokio(CURRENT.scope(shared.clone(), async move {
+        let _guard = guard;
+        let reason = run(&mut actor, &mut ctx, &mut receiver, None, None).await;
+        receiver.close();
+        drain(&mut receiver, &shared, drop);
+        if !reason.is_abnormal() {
+            let _ = guarded(actor.stopping(&mut ctx), &shared.kill, None).await;
+        }
+    }))
+    .detach();
+    addr
+}
+
+/// パニックのペイロードからメッセージを取り出す。
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+/// アクターのフックを実行する。
+///
+/// パニックした場合、`kill`が通知された場合、`stall_timeout`を過ぎた場合は、フックを中断して終了した理由を返す。
+async fn guarded<F: Future>(
+    future: F,
+    kill: &Notify,
+    stall_timeout: Option<Duration>,
+) -> Result<F::Output, ExitReason> {
+    let stall = async {
+        match stall_timeout {
+            Some(timeout) => time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        biased;
+        _ = kill.notified() => Err(ExitReason::Killed),
+        output = AssertUnwindSafe(future).catch_unwind() => {
+            output.map_err(|payload| ExitReason::Panicked(panic_message(payload)))
+        }
+        _ = stall => Err(ExitReason::Stalled),
+    }
+}
+
+/// アクターのインスタンスを、停止するまで実行する。
+///
+/// メールボックスはインスタンスより長く存続するため、スーパーバイザーは同じメールボックスで新しいインスタンスを実行できる。
+/// `shutdown`が通知された場合は、処理中のメッセージを処理した後に停止する。
+/// `stall_timeout`を指定した場合は、1つのメッセージの処理にその時間を超えたときに停止する。
+/// [`Actor::stopping`]は呼び出さない。
+async fn run<A: Actor>(
+    actor: &mut A,
+    ctx: &mut Context<A>,
+    receiver: &mut Mailbox<A::Message>,
+    shutdown: Option<&Notify>,
+    stall_timeout: Option<Duration>,
+) -> ExitReason {
+    let shared = ctx.addr.shared.clone();
+    let shutdown = async {
+        match shutdown {
+            Some(shutdown) => shutdown.notified().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(shutdown);
+
+    ctx.stopping = false;
+    if let Err(reason) = guarded(actor.started(ctx), &shared.kill, None).await {
+        return reason;
+    }
+    while !ctx.stopping {
+        let envelope = tokio::select! {
+            biased;
+            _ = shared.kill.notified() => return ExitReason::Killed,
+            _ = &mut shutdown => return ExitReason::Shutdown,
+            envelope = receiver.recv() => envelope,
+        };
+        match envelope {
+            Some(Envelope::Message(msg, _)) => {
+                if let Err(reason) =
+                    guarded(actor.handle(msg, ctx), &shared.kill, stall_timeout).await
+                {
+                    return reason;
+                }
+            }
+            Some(Envelope::Stop) | None => break,
+        }
+    }
+    ExitReason::Normal
+}
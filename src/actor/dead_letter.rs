@@ -0,0 +1,205 @@
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::sync::mpsc;
+
+use super::{Actor, ActorId, Addr, AskError, Context, Envelope, Reply, mailbox_with_parent, start};
+
+/// デッドレターアクターのメールボックスの容量
+///
+/// 満杯のときに届いたデッドレターは、数えるだけで購読している受信側には送らない。
+const MAILBOX_CAPACITY: usize = 1_024;
+
+/// 購読している受信側に格納できるデッドレターの数
+///
+/// 受信側が満杯のときに届いたデッドレターは、その受信側には送らない。
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+/// デッドレターアクターの応答を待つ時間
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// メッセージまたは応答が届かなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeadLetterReason {
+    /// 宛先のアクターが停止していた。
+    Stopped,
+    /// 宛先のアクターのメールボックスが満杯だった（`try_send`の場合のみ）。
+    MailboxFull,
+    /// メールボックスに届いたが、処理される前にアクターが停止したか、再起動のときに取り出された。
+    Unprocessed,
+    /// アクターが応答せずに`Reply`を破棄した。
+    ReplyDropped,
+    /// 要求した側がタイムアウトなどで応答を待っていなかった。
+    ReplyUnclaimed,
+}
+
+impl DeadLetterReason {
+    const ALL: [Self; 5] = [
+        Self::Stopped,
+        Self::MailboxFull,
+        Self::Unprocessed,
+        Self::ReplyDropped,
+        Self::ReplyUnclaimed,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for DeadLetterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stopped => f.write_str("actor has stopped"),
+            Self::MailboxFull => f.write_str("mailbox is full"),
+            Self::Unprocessed => f.write_str("left in the mailbox"),
+            Self::ReplyDropped => f.write_str("reply was dropped without responding"),
+            Self::ReplyUnclaimed => f.write_str("requester stopped waiting for the reply"),
+        }
+    }
+}
+
+/// 宛先に届かなかったメッセージまたは応答の記録
+///
+/// メッセージそのものは、送信した側に[`super::SendError`]として返すか、破棄する。
+/// 応答の場合は、要求したアクターを`sender`、要求を受けたアクターを`target`とする。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    /// メッセージを送信したアクター（アクターの外から送信した場合は`None`）
+    pub sender: Option<ActorId>,
+    /// メッセージの宛先のアクター
+    pub target: ActorId,
+    /// メッセージ（応答の場合は応答）の型名
+    pub message_type: &'static str,
+    pub reason: DeadLetterReason,
+}
+
+impl fmt::Display for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.message_type)?;
+        if let Some(sender) = self.sender {
+            write!(f, "from {sender} ")?;
+        }
+        write!(f, "to {}: {}", self.target, self.reason)
+    }
+}
+
+/// 理由ごとのデッドレターの数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeadLetterStats {
+    counts: [u64; DeadLetterReason::ALL.len()],
+}
+
+impl DeadLetterStats {
+    pub fn count(&self, reason: DeadLetterReason) -> u64 {
+        self.counts[reason.index()]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+enum DeadLetterMessage {
+    Deliver(DeadLetter),
+    Subscribe(Reply<mpsc::Receiver<DeadLetter>>),
+}
+
+/// デッドレターを購読している受信側に送信するアクター
+#[derive(Default)]
+struct DeadLetterActor {
+    subscribers: Vec<mpsc::Sender<DeadLetter>>,
+}
+
+impl Actor for DeadLetterActor {
+    type Message = DeadLetterMessage;
+
+    async fn handle(&mut self, message: DeadLetterMessage, _ctx: &mut Context<Self>) {
+        match message {
+            DeadLetterMessage::Deliver(letter) => {
+                self.subscribers.retain(|subscriber| {
+                    !matches!(
+                        subscriber.try_send(letter.clone()),
+                        Err(mpsc::error::TrySendError::Closed(_))
+                    )
+                });
+            }
+            DeadLetterMessage::Subscribe(response) => {
+                let (sender, receiver) = mpsc::channel(SUBSCRIBER_CAPACITY);
+                self.subscribers.push(sender);
+                let _ = response.send(receiver);
+            }
+        }
+    }
+}
+
+/// アクターに届かなかったメッセージと応答を記録するデッドレター
+///
+/// [`DeadLetters::track`]で指定したアクターと、そのアクターが起動したアクターのデッドレターを、
+/// 理由ごとに数えてデッドレターアクターに送る。
+/// [`super::system::ActorSystem`]は、登録したアクターのデッドレターを記録する。
+#[derive(Clone)]
+pub struct DeadLetters {
+    addr: Addr<DeadLetterActor>,
+    counts: Arc<[AtomicU64; DeadLetterReason::ALL.len()]>,
+}
+
+impl fmt::Debug for DeadLetters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetters")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl DeadLetters {
+    /// デッドレターアクターを起動する。
+    ///
+    /// Tokioランタイムか[`Simulation`](crate::runtime::sim::Simulation)の中から呼び出さなければならない。
+    pub fn spawn() -> Self {
+        // デッドレターアクター自身のデッドレターは記録しない
+        let (addr, receiver) = mailbox_with_parent(MAILBOX_CAPACITY, None);
+        Self {
+            addr: start(DeadLetterActor::default(), addr, receiver),
+            counts: Arc::default(),
+        }
+    }
+
+    /// アクターのデッドレターを記録する。
+    ///
+    /// すでに別のデッドレターを指定しているアクターは変更せずに、`false`を返す。
+    pub fn track<A: Actor>(&self, addr: &Addr<A>) -> bool {
+        addr.shared.dead_letters.set(self.clone()).is_ok()
+    }
+
+    /// これまでに記録したデッドレターの数を返す。
+    pub fn stats(&self) -> DeadLetterStats {
+        let mut stats = DeadLetterStats::default();
+        for (count, counter) in stats.counts.iter_mut().zip(self.counts.iter()) {
+            *count = counter.load(Ordering::Relaxed);
+        }
+        stats
+    }
+
+    /// この後に記録するデッドレターを受け取る受信側を返す。
+    ///
+    /// 受信側が満杯のときに記録したデッドレターは、受け取れない。
+    pub async fn subscribe(&self) -> Result<mpsc::Receiver<DeadLetter>, AskError> {
+        self.addr.ask(DeadLetterMessage::Subscribe, TIMEOUT).await
+    }
+
+    pub(super) fn publish(&self, letter: DeadLetter) {
+        self.counts[letter.reason.index()].fetch_add(1, Ordering::Relaxed);
+        // デッドレターがデッドレターにならないように、メールボックスに直接送信する
+        let _ = self
+            .addr
+            .sender
+            .try_send(Envelope::Message(DeadLetterMessage::Deliver(letter), None));
+    }
+}
@@ -0,0 +1,615 @@
+use std::{
+    collections::VecDeque, fmt, panic::AssertUnwindSafe, pin::Pin, sync::Arc, time::Duration,
+};
+
+use async_task::Task;
+use futures::{FutureExt, StreamExt, stream::FuturesUnordered};
+use tokio::sync::{Notify, watch};
+
+use super::{
+    Actor, Addr, CURRENT, Context, DEFAULT_MAILBOX_CAPACITY, ExitReason, Mailbox, Shared, WeakAddr,
+    drain, guarded, mailbox, panic_message, run,
+};
+use crate::runtime::{TaskInfo, spawn_on_tokio, time};
+
+/// 子を実行しているタスク
+///
+/// 子が終了すると、子のインデックスと子、終了した理由を返す。
+type ChildTasks = FuturesUnordered<Task<(usize, Box<dyn Child>, ExitReason), TaskInfo>>;
+
+/// 子が異常終了したときに、どの子を再起動するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// 異常終了した子だけを再起動する。
+    OneForOne,
+    /// すべての子を停止して、再起動する。
+    OneForAll,
+    /// 異常終了した子と、その子より後に追加した子を停止して、再起動する。
+    RestForOne,
+}
+
+/// 子が終了したときに再起動するかどうか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// 常に再起動する。
+    Permanent,
+    /// 異常終了した場合だけ再起動する。
+    Transient,
+    /// 再起動しない。
+    Temporary,
+}
+
+/// 再起動するときに、メールボックスに残っているメッセージをどう扱うか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailboxPolicy {
+    /// 新しいインスタンスに引き継ぐ。
+    Handover,
+    /// 取り出して、デッドレターとして処理する。
+    ///
+    /// 取り出したメッセージは、デッドレター（[`super::dead_letter::DeadLetters`]）に記録した後に、
+    /// [`ChildSpec::with_dead_letter_handler`]で指定した関数に渡す。
+    DeadLetter,
+}
+
+/// 再起動するまでの待機時間
+///
+/// 再起動の回数が増えるごとに待機時間を2倍にして、`max`を上限とする。
+/// 回数は、再起動の回数の上限を数える期間（[`Supervisor::with_intensity`]）の中で数える。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    /// 待機せずに再起動する。
+    pub const fn none() -> Self {
+        Self::new(Duration::ZERO, Duration::ZERO)
+    }
+
+    /// `attempt`回目（1から始まる）の再起動までの待機時間を返す。
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        self.initial.saturating_mul(1 << exponent).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(10), Duration::from_secs(1))
+    }
+}
+
+/// スーパーバイザーが監視するアクターの仕様
+pub struct ChildSpec<A: Actor> {
+    name: String,
+    factory: Box<dyn FnMut() -> A + Send>,
+    capacity: usize,
+    restart: Restart,
+    mailbox_policy: MailboxPolicy,
+    dead_letter: Option<Box<dyn FnMut(A::Message) + Send>>,
+    stall_timeout: Option<Duration>,
+}
+
+impl<A: Actor> ChildSpec<A> {
+    /// アクターを作成する関数を指定して作成する。
+    ///
+    /// アクターを起動または再起動するたびに`factory`を呼び出す。
+    pub fn new<F>(name: impl Into<String>, factory: F) -> Self
+    where
+        F: FnMut() -> A + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            factory: Box::new(factory),
+            capacity: DEFAULT_MAILBOX_CAPACITY,
+            restart: Restart::Transient,
+            mailbox_policy: MailboxPolicy::Handover,
+            dead_letter: None,
+            stall_timeout: None,
+        }
+    }
+
+    /// メールボックスの容量を指定する。
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// 終了したときに再起動するかどうかを指定する（既定は`Transient`）。
+    pub fn with_restart(mut self, restart: Restart) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// 再起動するときのメールボックスの扱いを指定する（既定は`Handover`）。
+    pub fn with_mailbox_policy(mut self, policy: MailboxPolicy) -> Self {
+        self.mailbox_policy = policy;
+        self
+    }
+
+    /// デッドレターとして取り出したメッセージを処理する関数を指定する。
+    ///
+    /// 指定しない場合、デッドレターは破棄される。
+    pub fn with_dead_letter_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(A::Message) + Send + 'static,
+    {
+        self.dead_letter = Some(Box::new(f));
+        self
+    }
+
+    /// 1つのメッセージの処理にかけられる時間を指定する。
+    ///
+    /// この時間を超えた場合は、アクターが停滞しているとみなして異常終了させる。
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// スーパーバイザーが監視する子（アクターまたはスーパーバイザー）
+trait Child: Send + 'static {
+    fn name(&self) -> &str;
+
+    fn restart(&self) -> Restart;
+
+    /// 子を終了するまで実行する。
+    ///
+    /// `shutdown`が通知された場合は、できるだけ早く`ExitReason::Shutdown`で終了する。
+    fn run(&mut self, shutdown: Arc<Notify>) -> BoxFuture<'_, ExitReason>;
+
+    /// 停止の要求に応じない子を直ちに停止させるためのハンドルを返す。
+    ///
+    /// 実行中の子はタスクが所有するため、子を起動するときに取得しておく。
+    /// 直ちに停止させる手段がない子は`None`を返す。
+    fn kill_handle(&self) -> Option<Arc<Shared>>;
+
+    /// 再起動する前に呼び出される。
+    fn before_restart(&mut self);
+
+    /// 再起動しないことが決まったときに呼び出される。
+    fn finish(&mut self);
+}
+
+struct ActorChild<A: Actor> {
+    spec: ChildSpec<A>,
+    receiver: Mailbox<A::Message>,
+    addr: WeakAddr<A>,
+}
+
+impl<A: Actor> ActorChild<A> {
+    fn shared(&self) -> &Arc<Shared> {
+        &self.addr.shared
+    }
+}
+
+impl<A: Actor> Child for ActorChild<A> {
+    fn name(&self) -> &str {
+        &self.spec.name
+    }
+
+    fn restart(&self) -> Restart {
+        self.spec.restart
+    }
+
+    fn run(&mut self, shutdown: Arc<Notify>) -> BoxFuture<'_, ExitReason> {
+        let shared = self.shared().clone();
+        Box::pin(CURRENT.scope(shared, async move {
+            let mut actor = match std::panic::catch_unwind(AssertUnwindSafe(&mut self.spec.factory))
+            {
+                Ok(actor) => actor,
+                Err(payload) => return ExitReason::Panicked(panic_message(payload)),
+            };
+            let mut ctx = Context {
+                addr: self.addr.clone(),
+                stopping: false,
+            };
+            let reason = run(
+                &mut actor,
+                &mut ctx,
+                &mut self.receiver,
+                Some(&shutdown),
+                self.spec.stall_timeout,
+            )
+            .await;
+            if !reason.is_abnormal()
+                && let Err(reason) =
+                    guarded(actor.stopping(&mut ctx), &self.shared().kill, None).await
+            {
+                return reason;
+            }
+            reason
+        }))
+    }
+
+    fn kill_handle(&self) -> Option<Arc<Shared>> {
+        Some(self.shared().clone())
+    }
+
+    fn before_restart(&mut self) {
+        if self.spec.mailbox_policy == MailboxPolicy::Handover {
+            return;
+        }
+        let handler = &mut self.spec.dead_letter;
+        drain(&mut self.receiver, &self.addr.shared, |msg| {
+            if let Some(handler) = handler {
+                handler(msg);
+            }
+        });
+    }
+
+    fn finish(&mut self) {
+        self.receiver.close();
+        // 残っているメッセージを破棄して、応答を待っている側に知らせる
+        drain(&mut self.receiver, &self.addr.shared, drop);
+        self.shared().stopped.send_replace(true);
+    }
+}
+
+impl Child for Supervisor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn restart(&self) -> Restart {
+        self.restart
+    }
+
+    fn run(&mut self, shutdown: Arc<Notify>) -> BoxFuture<'_, ExitReason> {
+        Box::pin(Supervisor::run(self, shutdown))
+    }
+
+    fn kill_handle(&self) -> Option<Arc<Shared>> {
+        // 子のスーパーバイザーは、停止の要求に応じない自分の子を停止させた後に終了する
+        None
+    }
+
+    fn before_restart(&mut self) {}
+
+    fn finish(&mut self) {
+        self.finish_children();
+    }
+}
+
+struct Slot {
+    /// 実行していない子（実行中は子を実行しているタスクが所有する）
+    child: Option<Box<dyn Child>>,
+    shutdown: Arc<Notify>,
+    /// 実行中の子を直ちに停止させるハンドル
+    kill: Option<Arc<Shared>>,
+    running: bool,
+    /// 再起動しないことが決まった
+    done: bool,
+}
+
+/// 子が終了したときに呼び出される関数
+type ExitHook = Arc<dyn Fn(&str, &ExitReason) + Send + Sync>;
+
+/// 子のアクターを監視して、異常終了した子を再起動するスーパーバイザー
+///
+/// 子は追加した順に起動し、その逆順に停止する。
+/// 子のアドレスは再起動しても変わらず、メールボックスに残っているメッセージは[`MailboxPolicy`]に従って扱われる。
+/// 再起動の回数が上限を超えた場合は、すべての子を停止して、親のスーパーバイザーにエスカレーションする。
+pub struct Supervisor {
+    name: String,
+    strategy: Strategy,
+    max_restarts: usize,
+    within: Duration,
+    backoff: Backoff,
+    shutdown_timeout: Duration,
+    restart: Restart,
+    on_child_exit: Option<ExitHook>,
+    slots: Vec<Slot>,
+    /// 他の子の停止を待っている間に終了した子
+    pending: VecDeque<(usize, ExitReason)>,
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("name", &self.name)
+            .field("strategy", &self.strategy)
+            .field("children", &self.slots.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Supervisor {
+    pub fn new(name: impl Into<String>, strategy: Strategy) -> Self {
+        Self {
+            name: name.into(),
+            strategy,
+            max_restarts: 3,
+            within: Duration::from_secs(5),
+            backoff: Backoff::default(),
+            shutdown_timeout: Duration::from_secs(5),
+            restart: Restart::Transient,
+            on_child_exit: None,
+            slots: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// `within`の間に`max_restarts`回を超えて再起動しようとした場合に、エスカレーションする（既定は5秒間に3回）。
+    pub fn with_intensity(mut self, max_restarts: usize, within: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.within = within;
+        self
+    }
+
+    /// 再起動するまでの待機時間を指定する。
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// 子の停止を待つ時間を指定する（既定は5秒）。
+    ///
+    /// この時間内に停止しない子は、直ちに停止させる。
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// 親のスーパーバイザーに追加したときに、このスーパーバイザーを再起動するかどうかを指定する（既定は`Transient`）。
+    pub fn with_restart(mut self, restart: Restart) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// 子が終了するたびに、子の名前と終了した理由を渡して呼び出す関数を指定する。
+    pub fn on_child_exit<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, &ExitReason) + Send + Sync + 'static,
+    {
+        self.on_child_exit = Some(Arc::new(f));
+        self
+    }
+
+    /// アクターを子として追加して、そのアドレスを返す。
+    ///
+    /// アクターはスーパーバイザーを起動したときに起動する。
+    pub fn add<A: Actor>(&mut self, spec: ChildSpec<A>) -> Addr<A> {
+        let (addr, receiver) = mailbox::<A>(spec.capacity);
+        self.push(Box::new(ActorChild {
+            spec,
+            receiver,
+            addr: addr.downgrade(),
+        }));
+        addr
+    }
+
+    /// スーパーバイザーを子として追加する。
+    pub fn add_supervisor(&mut self, supervisor: Supervisor) {
+        self.push(Box::new(supervisor));
+    }
+
+    fn push(&mut self, child: Box<dyn Child>) {
+        self.slots.push(Slot {
+            child: Some(child),
+            shutdown: Arc::new(Notify::new()),
+            kill: None,
+            running: false,
+            done: false,
+        });
+    }
+
+    /// スーパーバイザーを起動する。
+    ///
+    /// Tokioランタイムか[`Simulation`](crate::runtime::sim::Simulation)の中から呼び出さなければならない。
+    /// シミュレーションの中では、再起動の回数を数える期間や待機時間を仮想時間で計る。
+    pub fn start(mut self) -> SupervisorHandle {
+        let shutdown = Arc::new(Notify::new());
+        let (reason_sender, reason) = watch::channel(None);
+        spawn_on_tokio({
+            let shutdown = shutdown.clone();
+            async move {
+                let reason = self.run(shutdown).await;
+                self.finish_children();
+                reason_sender.send_replace(Some(reason));
+            }
+        })
+        .detach();
+        SupervisorHandle { shutdown, reason }
+    }
+
+    async fn run(&mut self, shutdown: Arc<Notify>) -> ExitReason {
+        let mut tasks = ChildTasks::new();
+        let mut restarts = VecDeque::new();
+        self.pending.clear();
+        for i in 0..self.slots.len() {
+            if !self.slots[i].done {
+                self.start_child(&mut tasks, i, Duration::ZERO);
+            }
+        }
+
+        loop {
+            let (i, reason) = match self.pending.pop_front() {
+                Some(exited) => exited,
+                None => tokio::select! {
+                    biased;
+                    _ = shutdown.notified() => {
+                        self.stop_children(&mut tasks, (0..self.slots.len()).rev()).await;
+                        return ExitReason::Shutdown;
+                    }
+                    joined = tasks.next() => match joined {
+                        Some(joined) => self.collect(joined),
+                        // すべての子が終了した
+                        None => return ExitReason::Normal,
+                    },
+                },
+            };
+
+            let restart = match self.slots[i].child.as_ref().unwrap().restart() {
+                Restart::Permanent => true,
+                Restart::Transient => reason.is_abnormal(),
+                Restart::Temporary => false,
+            };
+            if !restart {
+                self.finish_child(i);
+                continue;
+            }
+
+            // 再起動の回数の上限を確認
+            let now = time::now();
+            while restarts
+                .front()
+                .is_some_and(|&restarted: &Duration| now - restarted >= self.within)
+            {
+                restarts.pop_front();
+            }
+            restarts.push_back(now);
+            if restarts.len() > self.max_restarts {
+                self.stop_children(&mut tasks, (0..self.slots.len()).rev())
+                    .await;
+                return ExitReason::Escalated;
+            }
+            let delay = self.backoff.delay(restarts.len());
+
+            let group: Vec<usize> = match self.strategy {
+                Strategy::OneForOne => vec![i],
+                Strategy::OneForAll => (0..self.slots.len()).collect(),
+                Strategy::RestForOne => (i..self.slots.len()).collect(),
+            };
+            self.stop_children(&mut tasks, group.iter().rev().copied())
+                .await;
+            // 再起動する子の終了は、再起動によって処理済みになる
+            self.pending.retain(|(j, _)| !group.contains(j));
+            for j in group {
+                if self.slots[j].done {
+                    continue;
+                }
+                let child = self.slots[j].child.as_mut().unwrap();
+                if j != i && child.restart() == Restart::Temporary {
+                    self.finish_child(j);
+                    continue;
+                }
+                child.before_restart();
+                self.start_child(&mut tasks, j, delay);
+            }
+        }
+    }
+
+    /// 子を実行するタスクを生成する。
+    fn start_child(&mut self, tasks: &mut ChildTasks, i: usize, delay: Duration) {
+        let slot = &mut self.slots[i];
+        let mut child = slot.child.take().unwrap();
+        let shutdown = Arc::new(Notify::new());
+        slot.shutdown = shutdown.clone();
+        slot.kill = child.kill_handle();
+        slot.running = true;
+        tasks.push(spawn_on_tokio(async move {
+            let reason = tokio::select! {
+                biased;
+                _ = shutdown.notified() => ExitReason::Shutdown,
+                _ = time::sleep(delay) => child.run(shutdown.clone()).await,
+            };
+            (i, child, reason)
+        }));
+    }
+
+    /// 終了した子をスロットに戻して、子のインデックスと終了した理由を返す。
+    fn collect(&mut self, joined: (usize, Box<dyn Child>, ExitReason)) -> (usize, ExitReason) {
+        let (i, child, reason) = joined;
+        if let Some(hook) = &self.on_child_exit {
+            hook(child.name(), &reason);
+        }
+        let slot = &mut self.slots[i];
+        slot.child = Some(child);
+        slot.running = false;
+        (i, reason)
+    }
+
+    /// 指定した順に子を1つずつ停止させる。
+    async fn stop_children(
+        &mut self,
+        tasks: &mut ChildTasks,
+        indices: impl Iterator<Item = usize>,
+    ) {
+        for i in indices {
+            if !self.slots[i].running {
+                continue;
+            }
+            self.slots[i].shutdown.notify_one();
+            let mut deadline = time::sleep(self.shutdown_timeout);
+            let mut killed = false;
+            while self.slots[i].running {
+                let joined = if killed {
+                    tasks.next().await
+                } else {
+                    tokio::select! {
+                        joined = tasks.next() => joined,
+                        () = &mut deadline => {
+                            // 停止の要求に応じない子は、起動したときに取得したハンドルで停止させる
+                            self.kill_running(i);
+                            killed = true;
+                            continue;
+                        }
+                    }
+                };
+                let (j, reason) = self.collect(joined.expect("running child has a task"));
+                if j != i {
+                    self.pending.push_back((j, reason));
+                }
+            }
+            if killed && let Some(shared) = &self.slots[i].kill {
+                // 通知が届く前に子が終了していた場合に、再起動した子が直ちに停止しないように取り消す
+                let _ = shared.kill.notified().now_or_never();
+            }
+        }
+    }
+
+    fn kill_running(&self, i: usize) {
+        if let Some(shared) = &self.slots[i].kill {
+            shared.kill.notify_one();
+        }
+    }
+
+    fn finish_child(&mut self, i: usize) {
+        let slot = &mut self.slots[i];
+        slot.done = true;
+        slot.child.as_mut().unwrap().finish();
+    }
+
+    fn finish_children(&mut self) {
+        for i in 0..self.slots.len() {
+            if !self.slots[i].done {
+                self.finish_child(i);
+            }
+        }
+    }
+}
+
+/// 起動したスーパーバイザーを操作するハンドル
+#[derive(Debug, Clone)]
+pub struct SupervisorHandle {
+    shutdown: Arc<Notify>,
+    reason: watch::Receiver<Option<ExitReason>>,
+}
+
+impl SupervisorHandle {
+    /// すべての子を停止させて、スーパーバイザーが終了するまで待機する。
+    pub async fn shutdown(&self) -> ExitReason {
+        self.shutdown.notify_one();
+        self.stopped().await
+    }
+
+    /// スーパーバイザーが終了するまで待機して、終了した理由を返す。
+    ///
+    /// 子の再起動の回数が上限を超えた場合は`ExitReason::Escalated`を返す。
+    pub async fn stopped(&self) -> ExitReason {
+        let mut reason = self.reason.clone();
+        let reason = reason
+            .wait_for(Option::is_some)
+            .await
+            .expect("supervisor task dropped");
+        reason.clone().unwrap()
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    any::{Any, type_name},
+    collections::HashMap,
+    fmt,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use super::{Actor, ActorId, Addr, Shared, dead_letter::DeadLetters};
+use crate::runtime::spawn_on_tokio;
+
+/// レジストリの操作で発生するエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    /// 同じ名前で、停止していないアクターが登録されている。
+    AlreadyRegistered(String),
+    /// その名前で登録されているアクターがない（停止したアクターを含む）。
+    NotFound(String),
+    /// 登録されているアクターの型が、要求した型と異なる。
+    TypeMismatch {
+        name: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyRegistered(name) => write!(f, "actor `{name}` is already registered"),
+            Self::NotFound(name) => write!(f, "actor `{name}` is not registered"),
+            Self::TypeMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(f, "actor `{name}` is a `{actual}`, not a `{expected}`"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// 登録されていたアクターが停止したことを、監視しているアクターに知らせる通知
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Terminated {
+    /// アクターを登録していた名前
+    pub name: String,
+    pub id: ActorId,
+}
+
+/// 監視を識別するID
+///
+/// [`ActorSystem::unwatch`]で監視を解除するときに使用する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+type Notify = Box<dyn FnOnce(Terminated) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+struct Entry {
+    /// 型を消去した`Addr<A>`
+    addr: Box<dyn Any + Send + Sync>,
+    type_name: &'static str,
+    shared: Arc<Shared>,
+    watchers: HashMap<WatchId, Notify>,
+}
+
+impl Entry {
+    fn is_stopped(&self) -> bool {
+        *self.shared.stopped.borrow()
+    }
+
+    /// 監視しているアクターに、停止したことを通知する。
+    fn notify(self, name: &str) {
+        for (_, notify) in self.watchers {
+            spawn_on_tokio(notify(Terminated {
+                name: name.to_string(),
+                id: self.shared.id,
+            }))
+            .detach();
+        }
+    }
+}
+
+struct Inner {
+    name: String,
+    entries: Mutex<HashMap<String, Entry>>,
+    next_watch_id: AtomicU64,
+    dead_letters: OnceLock<DeadLetters>,
+}
+
+/// アクターのアドレスを名前または型で登録して、検索するためのアクターシステム
+///
+/// レジストリはシステムごとに独立しているため、1つのプロセスで複数のシステムを使用できる。
+/// 登録したアドレスは、登録を解除するかアクターが停止するまで、レジストリが保持する。
+/// 停止したアクターはレジストリから削除され、監視しているアクターに[`Terminated`]が送信される。
+///
+/// スーパーバイザーが再起動したアクターは、停止したとはみなさない。
+///
+/// 登録したアクターと、そのアクターが起動したアクターに届かなかったメッセージは、
+/// システムのデッドレター（[`ActorSystem::dead_letters`]）に記録する。
+#[derive(Clone)]
+pub struct ActorSystem {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for ActorSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActorSystem")
+            .field("name", &self.inner.name)
+            .field("registered", &self.inner.entries.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl ActorSystem {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                name: name.into(),
+                entries: Mutex::new(HashMap::new()),
+                next_watch_id: AtomicU64::new(1),
+                dead_letters: OnceLock::new(),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.inner.entries.lock().unwrap()
+    }
+
+    /// システムのデッドレターを返す。
+    ///
+    /// 最初に呼び出したときにデッドレターアクターを起動するため、Tokioランタイムか[`Simulation`](crate::runtime::sim::Simulation)の中から呼び出さなければならない。
+    pub fn dead_letters(&self) -> &DeadLetters {
+        self.inner.dead_letters.get_or_init(DeadLetters::spawn)
+    }
+
+    /// アドレスを名前で登録する。
+    ///
+    /// 同じ名前で登録されていたアクターが停止している場合は、置き換える。
+    /// アクターにデッドレターを指定していない場合は、システムのデッドレターに記録する。
+    /// Tokioランタイムか[`Simulation`](crate::runtime::sim::Simulation)の中から呼び出さなければならない。
+    pub fn register<A: Actor>(
+        &self,
+        name: impl Into<String>,
+        addr: &Addr<A>,
+    ) -> Result<(), RegistryError> {
+        let name = name.into();
+        let mut entries = self.lock();
+        if let Some(entry) = entries.get(&name) {
+            if !entry.is_stopped() {
+                return Err(RegistryError::AlreadyRegistered(name));
+            }
+            entries.remove(&name).unwrap().notify(&name);
+        }
+        entries.insert(
+            name.clone(),
+            Entry {
+                addr: Box::new(addr.clone()),
+                type_name: type_name::<A>(),
+                shared: addr.shared.clone(),
+                watchers: HashMap::new(),
+            },
+        );
+        drop(entries);
+        self.dead_letters().track(addr);
+
+        // アクターが停止したらレジストリから削除して、監視しているアクターに通知する
+        let id = addr.id();
+        let mut stopped = addr.shared.stopped.subscribe();
+        let inner = Arc::downgrade(&self.inner);
+        spawn_on_tokio(async move {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+            drop(stopped);
+            remove_stopped(inner, name, id);
+        })
+        .detach();
+        Ok(())
+    }
+
+    /// アドレスをアクターの型で登録する。
+    ///
+    /// 型で登録できるアドレスは、型ごとに1つだけである。
+    pub fn register_type<A: Actor>(&self, addr: &Addr<A>) -> Result<(), RegistryError> {
+        self.register(type_name::<A>(), addr)
+    }
+
+    /// 名前の登録を解除して、登録されていたアクターのIDを返す。
+    ///
+    /// 監視しているアクターには通知しない。
+    pub fn unregister(&self, name: &str) -> Option<ActorId> {
+        self.lock().remove(name).map(|entry| entry.shared.id)
+    }
+
+    /// 名前で登録されたアドレスを検索する。
+    pub fn lookup<A: Actor>(&self, name: &str) -> Result<Addr<A>, RegistryError> {
+        let entries = self.lock();
+        let entry = entries
+            .get(name)
+            .filter(|entry| !entry.is_stopped())
+            .ok_or_else(|| RegistryError::NotFound(name.to_string()))?;
+        entry
+            .addr
+            .downcast_ref::<Addr<A>>()
+            .cloned()
+            .ok_or_else(|| RegistryError::TypeMismatch {
+                name: name.to_string(),
+                expected: type_name::<A>(),
+                actual: entry.type_name,
+            })
+    }
+
+    /// 型で登録されたアドレスを検索する。
+    pub fn lookup_type<A: Actor>(&self) -> Result<Addr<A>, RegistryError> {
+        self.lookup(type_name::<A>())
+    }
+
+    /// 名前で登録されたアクターを監視する。
+    ///
+    /// 監視しているアクターが停止すると、`f`で[`Terminated`]から作成したメッセージを`watcher`に送信する。
+    /// 監視はアクターが停止したときに1度だけ通知され、`watcher`が停止している場合は通知されない。
+    pub fn watch<W, F>(&self, name: &str, watcher: &Addr<W>, f: F) -> Result<WatchId, RegistryError>
+    where
+        W: Actor,
+        F: FnOnce(Terminated) -> W::Message + Send + 'static,
+    {
+        let mut entries = self.lock();
+        let entry = entries
+            .get_mut(name)
+            .filter(|entry| !entry.is_stopped())
+            .ok_or_else(|| RegistryError::NotFound(name.to_string()))?;
+        let id = WatchId(self.inner.next_watch_id.fetch_add(1, Ordering::Relaxed));
+        let watcher = watcher.downgrade();
+        entry.watchers.insert(
+            id,
+            Box::new(move |terminated| {
+                Box::pin(async move {
+                    if let Some(watcher) = watcher.upgrade() {
+                        let _ = watcher.send(f(terminated)).await;
+                    }
+                })
+            }),
+        );
+        Ok(id)
+    }
+
+    /// 監視を解除する。
+    ///
+    /// 監視が見つからない（通知済みを含む）場合は`false`を返す。
+    pub fn unwatch(&self, id: WatchId) -> bool {
+        self.lock()
+            .values_mut()
+            .any(|entry| entry.watchers.remove(&id).is_some())
+    }
+}
+
+/// 停止したアクターがまだ登録されている場合は、削除して監視しているアクターに通知する。
+fn remove_stopped(inner: Weak<Inner>, name: String, id: ActorId) {
+    let Some(inner) = inner.upgrade() else {
+        return;
+    };
+    let mut entries = inner.entries.lock().unwrap();
+    if entries
+        .get(&name)
+        .is_some_and(|entry| entry.shared.id == id)
+    {
+        let entry = entries.remove(&name).unwrap();
+        drop(entries);
+        entry.notify(&name);
+    }
+}
@@ -1,14 +1,8 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, LazyLock, Mutex},
-};
+use std::{sync::LazyLock, time::Duration};
 
-use tokio::{
-    runtime::{Builder, Runtime},
-    task::JoinHandle,
-};
+use tokio::runtime::{Builder, Runtime};
 
-pub type AddFutMap = LazyLock<Arc<Mutex<HashMap<String, JoinHandle<i32>>>>>;
+use crate::job::{JobError, JobId, JobRegistry};
 
 static TOKIO_RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
     Builder::new_multi_thread()
@@ -17,6 +11,10 @@ static TOKIO_RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
         .expect("Failed to create Tokio runtime")
 });
 
+/// `async_add`のジョブ
+static ADD_JOBS: LazyLock<JobRegistry<i32>> =
+    LazyLock::new(|| JobRegistry::new(TOKIO_RUNTIME.handle().clone()));
+
 async fn async_add(a: i32, b: i32) -> i32 {
     println!("starting async_add for {a} and {b}");
     tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
@@ -24,46 +22,19 @@ async fn async_add(a: i32, b: i32) -> i32 {
     a + b
 }
 
-fn add_handler(
-    a: Option<i32>,
-    b: Option<i32>,
-    id: Option<String>,
-) -> Result<(Option<i32>, Option<String>), String> {
-    static MAP: AddFutMap = LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
-    match (a, b, id) {
-        (Some(a), Some(b), None) => {
-            let handle = TOKIO_RUNTIME.spawn(async_add(a, b));
-            let id = uuid::Uuid::new_v4().to_string();
-            MAP.lock().unwrap().insert(id.clone(), handle);
-            Ok((None, Some(id)))
-        }
-        (None, None, Some(id)) => {
-            let handle = match MAP.lock().unwrap().remove(&id) {
-                Some(handle) => handle,
-                None => return Err("No handle found".to_string()),
-            };
-            let result: i32 = match TOKIO_RUNTIME.block_on(handle) {
-                Ok(result) => result,
-                Err(e) => return Err(e.to_string()),
-            };
-            Ok((Some(result), None))
-        }
-        _ => Err("either a or b need to be provided or a handle_id".to_string()),
-    }
+/// `async_add`のジョブを管理するレジストリを返す。
+///
+/// ジョブの状態の確認やキャンセルに使用する。
+pub fn add_jobs() -> &'static JobRegistry<i32> {
+    &ADD_JOBS
 }
 
-pub fn send_add(a: i32, b: i32) -> Result<String, String> {
-    match add_handler(Some(a), Some(b), None) {
-        Ok((None, Some(id))) => Ok(id),
-        Ok(_) => Err("Something went wrong, please contact author".to_string()),
-        Err(e) => Err(e),
-    }
+/// `a + b`を非同期に計算するジョブを投入する。
+pub fn send_add(a: i32, b: i32) -> JobId {
+    ADD_JOBS.submit(async_add(a, b))
 }
 
-pub fn get_add(id: String) -> Result<i32, String> {
-    match add_handler(None, None, Some(id)) {
-        Ok((Some(result), None)) => Ok(result),
-        Ok(_) => Err("Something went wrong, please contact author".to_string()),
-        Err(e) => Err(e),
-    }
+/// ジョブが完了するまでブロックして、計算結果を回収する。
+pub fn get_add(id: JobId) -> Result<i32, JobError> {
+    ADD_JOBS.wait(id, Duration::MAX)
 }
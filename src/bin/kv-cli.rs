@@ -0,0 +1,162 @@
+//! キーバリューストアサーバーのコマンドラインクライアント
+//!
+//! `kv::remote::RemoteClient`のリクエストを、async_runtimeのエグゼキューターで実行する。
+use std::{io, time::Duration};
+
+use async_runtime::executor::Executor;
+use async_rust::kv::remote::RemoteClient;
+
+const USAGE: &str = "\
+usage: kv-cli [--addr <ADDR>] <COMMAND>
+
+commands:
+    get <KEY>                       キーの値を表示
+    set <KEY> <VALUE> [--ttl <SECS>]
+                                    キーに値を書き込んで、バージョンを表示
+    delete <KEY>                    キーを削除して、バージョンを表示
+    scan [PREFIX] [--limit <N>]     指定した文字列で始まるキーと値を表示 (default limit: 100)
+    promote                         レプリカを昇格して、昇格した時点のバージョンを表示
+
+options:
+    --addr <ADDR>                   接続先のアドレス (default: 127.0.0.1:7879)
+    -h, --help                      このメッセージを表示";
+
+enum Command {
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: String,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        key: String,
+    },
+    Scan {
+        prefix: String,
+        limit: u32,
+    },
+    Promote,
+}
+
+/// コマンドライン引数（プログラム名を除く）から、接続先とコマンドを構築する。
+///
+/// `--help`が指定された場合は`Ok(None)`を返す。
+fn parse_args(args: impl IntoIterator<Item = String>) -> io::Result<Option<(String, Command)>> {
+    let mut addr = String::from("127.0.0.1:7879");
+    let mut ttl = None;
+    let mut limit = 100;
+    let mut positional = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => addr = value(&arg, args.next())?,
+            "--ttl" => {
+                let secs: f64 = parse(&arg, args.next())?;
+                if !secs.is_finite() || secs <= 0.0 {
+                    return Err(invalid_input("--ttl must be greater than 0"));
+                }
+                ttl = Some(Duration::from_secs_f64(secs));
+            }
+            "--limit" => limit = parse(&arg, args.next())?,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with("--") => {
+                return Err(invalid_input(format!("unknown option: {arg}")));
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let command = match positional
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["get", key] => Command::Get {
+            key: key.to_string(),
+        },
+        ["set", key, value] => Command::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl,
+        },
+        ["delete", key] => Command::Delete {
+            key: key.to_string(),
+        },
+        ["scan"] => Command::Scan {
+            prefix: String::new(),
+            limit,
+        },
+        ["scan", prefix] => Command::Scan {
+            prefix: prefix.to_string(),
+            limit,
+        },
+        ["promote"] => Command::Promote,
+        [] => return Err(invalid_input("a command is required")),
+        [command, ..] => return Err(invalid_input(format!("invalid command: {command}"))),
+    };
+    Ok(Some((addr, command)))
+}
+
+fn value(option: &str, value: Option<String>) -> io::Result<String> {
+    value.ok_or_else(|| invalid_input(format!("{option} requires a value")))
+}
+
+fn parse<T: std::str::FromStr>(option: &str, value: Option<String>) -> io::Result<T> {
+    let value = self::value(option, value)?;
+    value
+        .parse()
+        .map_err(|_| invalid_input(format!("invalid value for {option}: {value}")))
+}
+
+fn invalid_input(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
+async fn run(client: RemoteClient, command: Command) -> io::Result<()> {
+    match command {
+        Command::Get { key } => match client.get(key).await? {
+            Some(entry) => println!("{}", String::from_utf8_lossy(&entry.value)),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "key not found")),
+        },
+        Command::Set { key, value, ttl } => {
+            let version = match ttl {
+                Some(ttl) => client.set_with_ttl(key, value, ttl).await?,
+                None => client.set(key, value).await?,
+            };
+            println!("version {version}");
+        }
+        Command::Delete { key } => println!("version {}", client.delete(key).await?),
+        Command::Scan { prefix, limit } => {
+            for entry in client.scan(prefix, limit).await? {
+                println!("{}\t{}", entry.key, String::from_utf8_lossy(&entry.value));
+            }
+        }
+        Command::Promote => println!("promoted at version {}", client.promote().await?),
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let (addr, command) = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return Err(e);
+        }
+    };
+
+    let mut executor = Executor::default();
+    let result = executor.spawn(run(RemoteClient::new(addr), command));
+    while !executor.polling.is_empty() {
+        executor.poll();
+    }
+    result.recv().map_err(io::Error::other)?
+}
@@ -0,0 +1,170 @@
+//! キーバリューストアサーバー
+//!
+//! キーバリューストアのアクターを起動して、async_runtimeのTCPサーバーで公開する。
+//! バインドしたアドレスを標準出力に出力するため、`--addr`にポート番号0を指定して起動できる。
+//! Ctrl-Cを受け取ると、同期を待っている操作を永続化してから終了する。
+//!
+//! `--replication-addr`を指定すると、レプリカの接続を受け付けて操作を送信する。
+//! `--replicate-from`を指定すると、読み取り専用のレプリカとして起動して、プライマリから操作を受け取る。
+//! レプリカは`kv-cli promote`で昇格するまで書き込みを受け付けない（フェイルオーバーは手動で行う）。
+//! レプリカにも`--replication-addr`を指定しておくと、昇格した後に他のレプリカの接続を受け付けられる。
+use std::{io, path::PathBuf, time::Duration};
+
+use async_runtime::trace::{self, StderrSubscriber};
+use async_rust::{
+    actor::system::ActorSystem,
+    kv::{
+        client::Client,
+        key_value::KeyValueActor,
+        replication::{Primary, Replica, ReplicationMode},
+        router::Router,
+        server::Server,
+        writer::WriterActor,
+    },
+};
+
+const USAGE: &str = "\
+usage: kv-server [OPTIONS]
+
+options:
+    --addr <ADDR>       待ち受けるアドレス (default: 127.0.0.1:7879)
+    --dir <DIR>         データを永続化するディレクトリ (default: ./data)
+    --workers <N>       接続を処理するワーカースレッドの数 (default: 3)
+    --replication-addr <ADDR>
+                        レプリカの接続を受け付けるアドレス
+    --replicate-from <ADDR>
+                        指定したプライマリのレプリカとして起動
+    --sync-replicas <N> 書き込みに応答する前に、N個のレプリカの確認応答を待つ (default: 0)
+    --sync-timeout <SECS>
+                        レプリカの確認応答を待つ時間 (default: 3)
+    -h, --help          このメッセージを表示
+
+environment:
+    TRACE_TASKS         設定されている場合は、タスクのライフサイクルイベントを標準エラー出力に出力";
+
+struct Config {
+    addr: String,
+    dir: PathBuf,
+    workers: usize,
+    replication_addr: Option<String>,
+    replicate_from: Option<String>,
+    sync_replicas: usize,
+    sync_timeout: Duration,
+}
+
+impl Config {
+    /// コマンドライン引数（プログラム名を除く）から設定を構築する。
+    ///
+    /// `--help`が指定された場合は`Ok(None)`を返す。
+    fn from_args(args: impl IntoIterator<Item = String>) -> io::Result<Option<Self>> {
+        let mut config = Self {
+            addr: String::from("127.0.0.1:7879"),
+            dir: PathBuf::from("./data"),
+            workers: 3,
+            replication_addr: None,
+            replicate_from: None,
+            sync_replicas: 0,
+            sync_timeout: Duration::from_secs(3),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--addr" => config.addr = value(&arg, args.next())?,
+                "--dir" => config.dir = PathBuf::from(value(&arg, args.next())?),
+                "--workers" => {
+                    config.workers = value(&arg, args.next())?
+                        .parse()
+                        .ok()
+                        .filter(|&workers| workers > 0)
+                        .ok_or_else(|| invalid_input("--workers must be greater than 0"))?;
+                }
+                "--replication-addr" => {
+                    config.replication_addr = Some(value(&arg, args.next())?);
+                }
+                "--replicate-from" => config.replicate_from = Some(value(&arg, args.next())?),
+                "--sync-replicas" => {
+                    config.sync_replicas = value(&arg, args.next())?
+                        .parse()
+                        .map_err(|_| invalid_input("--sync-replicas must be a number"))?;
+                }
+                "--sync-timeout" => {
+                    let secs = value(&arg, args.next())?
+                        .parse()
+                        .ok()
+                        .filter(|secs: &f64| secs.is_finite() && *secs > 0.0)
+                        .ok_or_else(|| invalid_input("--sync-timeout must be greater than 0"))?;
+                    config.sync_timeout = Duration::from_secs_f64(secs);
+                }
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(invalid_input(format!("unknown option: {arg}"))),
+            }
+        }
+        Ok(Some(config))
+    }
+
+    /// 設定したレプリケーションのルーターアクターを作成する。
+    fn router(&self) -> Router {
+        let mode = match self.sync_replicas {
+            0 => ReplicationMode::Async,
+            replicas => ReplicationMode::Sync {
+                replicas,
+                timeout: self.sync_timeout,
+            },
+        };
+        let read_only = self.replicate_from.is_some();
+        Router::new(&self.dir)
+            .with_writer(move |dir| WriterActor::new(dir).with_replication(mode))
+            .with_key_value(move |writer| KeyValueActor::new(writer).with_read_only(read_only))
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> io::Result<String> {
+    value.ok_or_else(|| invalid_input(format!("{option} requires a value")))
+}
+
+fn invalid_input(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
+fn main() -> io::Result<()> {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return Err(e);
+        }
+    };
+    if std::env::var_os("TRACE_TASKS").is_some() {
+        trace::set_subscriber(StderrSubscriber::new());
+    }
+
+    // アクターはTokioランタイムで実行する
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+    let system = ActorSystem::new("kv-server");
+    let client = runtime
+        .block_on(Client::start_with(&system, config.router()))
+        .map_err(io::Error::other)?;
+    let server = Server::bind(&config.addr, client.clone())?.with_workers(config.workers);
+    println!("listening on {}", server.local_addr()?);
+    if let Some(addr) = &config.replication_addr {
+        let primary = runtime.block_on(Primary::bind(addr, client.clone()))?;
+        println!("replication on {}", primary.local_addr()?);
+        runtime.spawn(primary.run());
+    }
+    if let Some(primary) = config.replicate_from {
+        runtime.spawn(Replica::new(primary, client.clone()).run());
+    }
+
+    runtime.spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            client.shutdown().await;
+            std::process::exit(0);
+        }
+    });
+    server.run()
+}
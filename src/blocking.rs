@@ -0,0 +1,117 @@
+use std::{
+    collections::VecDeque,
+    panic::catch_unwind,
+    sync::{Condvar, LazyLock, Mutex},
+    time::Duration,
+};
+
+use async_task::{Runnable, Task};
+
+/// ブロッキング処理用のスレッドの最大数の既定値
+const DEFAULT_MAX_THREADS: usize = 512;
+/// 処理を待っているスレッドが終了するまでの時間の既定値
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+/// ブロッキング処理を実行するスレッドプール
+///
+/// スレッドは処理が投入されたときに必要な分だけ起動し、`max_threads`を超えて起動しない。
+/// `keep_alive`の間に処理が投入されなかったスレッドは終了する。
+struct BlockingPool {
+    state: Mutex<PoolState>,
+    condvar: Condvar,
+    max_threads: usize,
+    keep_alive: Duration,
+}
+
+#[derive(Default)]
+struct PoolState {
+    /// 実行を待っている処理
+    queue: VecDeque<Runnable>,
+    /// 起動しているスレッドの数
+    threads: usize,
+    /// 処理を待っているスレッドの数
+    idle: usize,
+}
+
+static POOL: LazyLock<BlockingPool> = LazyLock::new(|| {
+    let max_threads = std::env::var("MAX_BLOCKING_NUM")
+        .ok()
+        .and_then(|num| num.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_THREADS);
+    let keep_alive = std::env::var("BLOCKING_KEEP_ALIVE_MS")
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_KEEP_ALIVE);
+    BlockingPool {
+        state: Mutex::new(PoolState::default()),
+        condvar: Condvar::new(),
+        max_threads: max_threads.max(1),
+        keep_alive,
+    }
+});
+
+impl BlockingPool {
+    fn schedule(&'static self, runnable: Runnable) {
+        let mut state = self.state.lock().unwrap();
+        state.queue.push_back(runnable);
+        if state.idle > 0 {
+            // 処理を待っているスレッドを起こす
+            self.condvar.notify_one();
+        }
+        // 処理を待っているスレッドだけではキューの処理を引き受けられない場合は、スレッドを増やす
+        if state.queue.len() > state.idle && state.threads < self.max_threads {
+            state.threads += 1;
+            std::thread::Builder::new()
+                .name("blocking".to_string())
+                .spawn(move || self.run())
+                .expect("failed to spawn a blocking thread");
+        }
+        // スレッドの数が上限に達している場合は、いずれかのスレッドが処理を終えるまでキューで待機する
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(runnable) = state.queue.pop_front() {
+                drop(state);
+                let _ = catch_unwind(|| runnable.run());
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state.idle += 1;
+            let (guard, timeout) = self.condvar.wait_timeout(state, self.keep_alive).unwrap();
+            state = guard;
+            state.idle -= 1;
+            if timeout.timed_out() && state.queue.is_empty() {
+                state.threads -= 1;
+                return;
+            }
+        }
+    }
+}
+
+/// ブロッキング処理を、ブロッキング処理用のスレッドプールで実行する。
+///
+/// `std::thread::sleep`や同期的なファイル操作などをワーカーで実行すると、
+/// そのワーカーにキューイングされている他のタスクもすべて待たされる。
+/// この関数で処理をオフロードして、返されるタスクハンドルを待ち合わせる。
+///
+/// スレッドの最大数と、処理がないスレッドが終了するまでの時間は、
+/// [`Runtime`](crate::runtime::Runtime)で設定する。
+pub fn spawn_blocking<F, T>(f: F) -> Task<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let schedule = |runnable| POOL.schedule(runnable);
+    let (runnable, task) = async_task::spawn(async move { f() }, schedule);
+    runnable.schedule();
+    task
+}
+
+/// ブロッキング処理用のスレッドプールで起動しているスレッドの数を返す。
+pub fn thread_count() -> usize {
+    POOL.state.lock().unwrap().threads
+}
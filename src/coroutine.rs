@@ -0,0 +1,82 @@
+pub mod generator;
+pub mod scheduler;
+
+// `native`フィーチャーでは、nightlyのコルーチン（`std::ops::Coroutine`）をそのまま使う。
+// コルーチンリテラル（`#[coroutine] || { yield 1; }`）も、このトレイトを実装したコルーチンとして扱える。
+#[cfg(feature = "native")]
+pub use std::ops::{Coroutine, CoroutineState};
+
+#[cfg(not(feature = "native"))]
+use std::pin::Pin;
+
+/// 再開したときに、値を生成して停止したか、完了したかを表す
+///
+/// `native`フィーチャーでは`std::ops::CoroutineState`である。
+#[cfg(not(feature = "native"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoroutineState<Y, R> {
+    /// 値を生成して停止した
+    Yielded(Y),
+    /// 完了した
+    ///
+    /// 完了したコルーチンを再び再開してはならない。
+    Complete(R),
+}
+
+/// 安定版のRustで使えるコルーチン
+///
+/// nightlyの`std::ops::Coroutine`と同じインターフェイスであるため、
+/// このトレイトを実装したコルーチンは、`native`フィーチャーを有効にしてもそのままコンパイルできる。
+/// 安定版ではコルーチンリテラルを使えないため、構造体に実装するか、[`generator::Generator`]を使う。
+///
+/// ジェネリックパラメーター`R`は、[`Coroutine::resume`]を呼び出すときの引数の型である。
+#[cfg(not(feature = "native"))]
+pub trait Coroutine<R = ()> {
+    /// 停止するときに生成する値の型
+    type Yield;
+    /// 完了したときに返す値の型
+    type Return;
+
+    /// 次に停止するか完了するまで実行する。
+    fn resume(self: Pin<&mut Self>, arg: R) -> CoroutineState<Self::Yield, Self::Return>;
+}
+
+#[cfg(not(feature = "native"))]
+impl<G: ?Sized + Coroutine<R>, R> Coroutine<R> for Pin<&mut G> {
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn resume(mut self: Pin<&mut Self>, arg: R) -> CoroutineState<G::Yield, G::Return> {
+        G::resume((*self).as_mut(), arg)
+    }
+}
+
+#[cfg(not(feature = "native"))]
+impl<G: ?Sized + Coroutine<R> + Unpin, R> Coroutine<R> for &mut G {
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn resume(mut self: Pin<&mut Self>, arg: R) -> CoroutineState<G::Yield, G::Return> {
+        G::resume(Pin::new(&mut **self), arg)
+    }
+}
+
+#[cfg(not(feature = "native"))]
+impl<G: ?Sized + Coroutine<R>, R> Coroutine<R> for Pin<Box<G>> {
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn resume(mut self: Pin<&mut Self>, arg: R) -> CoroutineState<G::Yield, G::Return> {
+        G::resume((*self).as_mut(), arg)
+    }
+}
+
+#[cfg(not(feature = "native"))]
+impl<G: ?Sized + Coroutine<R> + Unpin, R> Coroutine<R> for Box<G> {
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn resume(mut self: Pin<&mut Self>, arg: R) -> CoroutineState<G::Yield, G::Return> {
+        G::resume(Pin::new(&mut **self), arg)
+    }
+}
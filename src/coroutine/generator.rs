@@ -0,0 +1,177 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use super::{Coroutine, CoroutineState};
+
+type Body<'a, R> = Pin<Box<dyn Future<Output = R> + Send + 'a>>;
+type Producer<'a, Y, R, A> = Box<dyn FnOnce(Co<Y, A>, A) -> Body<'a, R> + Send + 'a>;
+
+/// 生成した値と再開したときの引数を受け渡す場所
+struct Slot<Y, A> {
+    yielded: Option<Y>,
+    arg: Option<A>,
+}
+
+/// ジェネレーターの本体から値を生成するハンドル
+///
+/// [`Co::yield_`]を`.await`すると、ジェネレーターは値を生成して停止する。
+pub struct Co<Y, A = ()> {
+    slot: Arc<Mutex<Slot<Y, A>>>,
+}
+
+impl<Y, A> Co<Y, A> {
+    /// `value`を生成して停止し、次に再開されたときの引数を返す。
+    pub fn yield_(&self, value: Y) -> Yield<Y, A> {
+        Yield {
+            slot: self.slot.clone(),
+            value: Some(value),
+        }
+    }
+}
+
+impl<Y, A> fmt::Debug for Co<Y, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Co").finish_non_exhaustive()
+    }
+}
+
+/// [`Co::yield_`]が返すフューチャー
+pub struct Yield<Y, A> {
+    slot: Arc<Mutex<Slot<Y, A>>>,
+    value: Option<Y>,
+}
+
+impl<Y, A> Unpin for Yield<Y, A> {}
+
+impl<Y, A> Future for Yield<Y, A> {
+    type Output = A;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<A> {
+        let this = &mut *self;
+        let mut slot = this.slot.lock().unwrap();
+        // 最初のポーリングで値を渡して停止し、次に再開されたときに引数を受け取る
+        if let Some(value) = this.value.take() {
+            slot.yielded = Some(value);
+            return Poll::Pending;
+        }
+        let arg = slot
+            .arg
+            .take()
+            .expect("generator was polled outside of resume");
+        Poll::Ready(arg)
+    }
+}
+
+enum State<'a, Y, R, A> {
+    /// 最初に再開されるのを待っている
+    Start(Producer<'a, Y, R, A>),
+    Running(Body<'a, R>),
+    Complete,
+}
+
+/// async/awaitで書いた本体を、安定版のRustでコルーチンとして実行する
+///
+/// 本体は、ハンドル[`Co`]と最初に再開されたときの引数を受け取るasyncブロックである。
+/// [`Co::yield_`]を`.await`するたびに値を生成して停止し、本体が終わるとその戻り値で完了する。
+/// コルーチンリテラルの`yield`に相当する。
+///
+/// ```
+/// # #![cfg_attr(feature = "native", feature(coroutine_trait))]
+/// use async_rust::coroutine::{Coroutine, CoroutineState, generator::Generator};
+/// use std::pin::Pin;
+///
+/// let mut counter = Generator::new(|co, ()| async move {
+///     for n in 0..2 {
+///         co.yield_(n).await;
+///     }
+///     "done"
+/// });
+/// assert_eq!(Pin::new(&mut counter).resume(()), CoroutineState::Yielded(0));
+/// assert_eq!(Pin::new(&mut counter).resume(()), CoroutineState::Yielded(1));
+/// assert_eq!(Pin::new(&mut counter).resume(()), CoroutineState::Complete("done"));
+/// ```
+///
+/// 本体は再開されたときにだけポーリングされ、ウェイカーは何もしない。
+/// そのため、本体では[`Co::yield_`]以外の、待機するフューチャーを`.await`してはならない。
+pub struct Generator<'a, Y, R = (), A = ()> {
+    slot: Arc<Mutex<Slot<Y, A>>>,
+    state: State<'a, Y, R, A>,
+}
+
+impl<'a, Y, R, A> Generator<'a, Y, R, A>
+where
+    Y: Send + 'a,
+    A: Send + 'a,
+{
+    pub fn new<F, Fut>(body: F) -> Self
+    where
+        F: FnOnce(Co<Y, A>, A) -> Fut + Send + 'a,
+        Fut: Future<Output = R> + Send + 'a,
+    {
+        Self {
+            slot: Arc::new(Mutex::new(Slot {
+                yielded: None,
+                arg: None,
+            })),
+            state: State::Start(Box::new(move |co, arg| Box::pin(body(co, arg)))),
+        }
+    }
+}
+
+impl<Y, R, A> fmt::Debug for Generator<'_, Y, R, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            State::Start(_) => "Start",
+            State::Running(_) => "Running",
+            State::Complete => "Complete",
+        };
+        f.debug_struct("Generator")
+            .field("state", &state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Y, R, A> Unpin for Generator<'_, Y, R, A> {}
+
+impl<Y, R, A> Coroutine<A> for Generator<'_, Y, R, A> {
+    type Yield = Y;
+    type Return = R;
+
+    /// # Panics
+    ///
+    /// 完了した後に再開した場合と、本体が[`Co::yield_`]以外のフューチャーで待機した場合
+    fn resume(mut self: Pin<&mut Self>, arg: A) -> CoroutineState<Y, R> {
+        let this = &mut *self;
+        match std::mem::replace(&mut this.state, State::Complete) {
+            State::Start(producer) => {
+                let co = Co {
+                    slot: this.slot.clone(),
+                };
+                this.state = State::Running(producer(co, arg));
+            }
+            running @ State::Running(_) => {
+                this.slot.lock().unwrap().arg = Some(arg);
+                this.state = running;
+            }
+            State::Complete => panic!("generator resumed after completion"),
+        }
+        let State::Running(body) = &mut this.state else {
+            unreachable!()
+        };
+        let mut cx = Context::from_waker(Waker::noop());
+        match body.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => {
+                this.state = State::Complete;
+                CoroutineState::Complete(output)
+            }
+            Poll::Pending => match this.slot.lock().unwrap().yielded.take() {
+                Some(value) => CoroutineState::Yielded(value),
+                None => panic!("generator body awaited a future other than `Co::yield_`"),
+            },
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, TryLockError},
+    time::{Duration, Instant},
+};
+
+use super::{Coroutine, CoroutineState};
+use crate::runtime::FutureType;
+
+/// 条件を満たしたかを調べる関数
+type Probe = Box<dyn Fn() -> bool + Send>;
+
+type PinnedCoroutine = Pin<Box<dyn Coroutine<(), Yield = Wait, Return = ()>>>;
+
+/// ロックとチャネルの条件を調べ直す間隔の既定値
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// コルーチンが停止するときに生成する、再開する条件
+///
+/// [`Scheduler`]は、条件を満たすまでコルーチンを再開しない。
+pub enum Wait {
+    /// 他のコルーチンに譲り、すぐに再開できる
+    Yield,
+    /// 指定した時刻まで待機する
+    Until(Instant),
+    /// ミューテックスのロックが解放されるまで待機する
+    Lock(Probe),
+    /// チャネルからメッセージを受信できるか、送信側がすべて破棄されるまで待機する
+    Channel(Probe),
+}
+
+impl Wait {
+    /// 現在から`duration`が経過するまで待機する。
+    pub fn sleep(duration: Duration) -> Self {
+        Self::Until(Instant::now() + duration)
+    }
+
+    /// `mutex`のロックが解放されるまで待機する。
+    ///
+    /// 再開したときに他のスレッドが先にロックを取得している場合もあるため、
+    /// 再開した後は`try_lock`で取得し直す。
+    ///
+    /// ロックの解放は通知されないため、[`Scheduler`]は再開できるコルーチンがない間、
+    /// [`Scheduler::with_poll_interval`]の間隔（既定では1ミリ秒）でスレッドを停止してはロックを調べ直す。
+    /// そのため、ロックが解放されてから再開するまでに最大でその間隔だけ遅れる。
+    pub fn lock<T: Send + 'static>(mutex: &Arc<Mutex<T>>) -> Self {
+        let mutex = mutex.clone();
+        Self::Lock(Box::new(move || {
+            !matches!(mutex.try_lock(), Err(TryLockError::WouldBlock))
+        }))
+    }
+
+    /// `receiver`がメッセージを受信できるか、送信側がすべて破棄されるまで待機する。
+    ///
+    /// [`Wait::lock`]と同じく、[`Scheduler::with_poll_interval`]の間隔で調べ直す。
+    pub fn channel<T: Send + 'static>(receiver: &flume::Receiver<T>) -> Self {
+        let receiver = receiver.clone();
+        Self::Channel(Box::new(move || {
+            !receiver.is_empty() || receiver.is_disconnected()
+        }))
+    }
+
+    fn holds(&self, now: Instant) -> bool {
+        match self {
+            Self::Yield => true,
+            Self::Until(deadline) => now >= *deadline,
+            Self::Lock(probe) | Self::Channel(probe) => probe(),
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        match self {
+            Self::Until(deadline) => Some(*deadline),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Wait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Yield => f.write_str("Yield"),
+            Self::Until(deadline) => f.debug_tuple("Until").field(deadline).finish(),
+            Self::Lock(_) => f.write_str("Lock"),
+            Self::Channel(_) => f.write_str("Channel"),
+        }
+    }
+}
+
+/// スケジューラーに登録したコルーチンを識別するID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// 再開できるのに、閾値より長く再開されなかったコルーチン
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Starvation {
+    pub task: TaskId,
+    pub priority: FutureType,
+    /// 再開できるようになってから経過した時間
+    pub waited: Duration,
+}
+
+impl fmt::Display for Starvation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "task {} ({:?}) has been runnable for {:?} without being resumed",
+            self.task, self.priority, self.waited
+        )
+    }
+}
+
+struct Task {
+    coroutine: PinnedCoroutine,
+    priority: FutureType,
+    /// 再開する条件。再開できるキューに入っている間は`None`
+    wait: Option<Wait>,
+    /// 再開できるキューに入った時刻
+    runnable_since: Instant,
+    /// 現在の待ちで、飢餓状態を報告した
+    reported: bool,
+}
+
+/// 飢餓状態を検出する閾値と、報告を受け取る関数
+struct StarvationHandler {
+    threshold: Duration,
+    handler: Box<dyn FnMut(Starvation)>,
+}
+
+/// 優先度と待機条件を扱う、コルーチンの協調的なスケジューラー
+///
+/// コルーチンは[`Wait`]を生成して停止し、スケジューラーは条件を満たしたコルーチンだけを再開する。
+/// 再開できるコルーチンが複数ある場合は、優先度が高いものから、同じ優先度の中では到着順に再開する。
+/// そのため、優先度が高いコルーチンが停止せずに譲り続けると、優先度が低いコルーチンは再開されない。
+/// [`Scheduler::with_starvation_handler`]を指定すると、そのようなコルーチンを報告する。
+///
+/// 再開できるコルーチンがない場合は、最も早い[`Wait::Until`]の時刻まで現在のスレッドを停止する。
+/// ロックとチャネルの条件は通知を受け取れないため、[`Scheduler::with_poll_interval`]の間隔で調べ直す。
+pub struct Scheduler {
+    tasks: BTreeMap<TaskId, Task>,
+    /// 条件を満たすまで待機しているコルーチン
+    ///
+    /// 再開できるコルーチンを調べるたびに走査するのは、このリストだけである。
+    waiting: Vec<TaskId>,
+    high: VecDeque<TaskId>,
+    low: VecDeque<TaskId>,
+    next_id: u64,
+    poll_interval: Duration,
+    starvation: Option<StarvationHandler>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("tasks", &self.tasks.len())
+            .field("runnable", &(self.high.len() + self.low.len()))
+            .field("poll_interval", &self.poll_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            waiting: Vec::new(),
+            high: VecDeque::new(),
+            low: VecDeque::new(),
+            next_id: 0,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            starvation: None,
+        }
+    }
+
+    /// ロックとチャネルの条件を調べ直す間隔を指定する。
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// 再開できるのに`threshold`より長く再開されなかったコルーチンを、`handler`に報告する。
+    ///
+    /// 報告は、コルーチンが再開できるようになるたびに1回だけである。
+    pub fn with_starvation_handler(
+        mut self,
+        threshold: Duration,
+        handler: impl FnMut(Starvation) + 'static,
+    ) -> Self {
+        self.starvation = Some(StarvationHandler {
+            threshold,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// コルーチンを登録する。
+    ///
+    /// 登録したコルーチンは、すぐに再開できる。
+    pub fn spawn<C>(&mut self, coroutine: C, priority: FutureType) -> TaskId
+    where
+        C: Coroutine<(), Yield = Wait, Return = ()> + 'static,
+    {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        self.tasks.insert(
+            id,
+            Task {
+                coroutine: Box::pin(coroutine),
+                priority,
+                wait: None,
+                runnable_since: Instant::now(),
+                reported: false,
+            },
+        );
+        self.queue(priority).push_back(id);
+        id
+    }
+
+    /// 完了していないコルーチンの数を返す。
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// 条件を満たしたコルーチンのうち、優先度が最も高いものを1回再開する。
+    ///
+    /// 再開できるコルーチンがなかった場合は`false`を返す。
+    pub fn tick(&mut self) -> bool {
+        let now = Instant::now();
+        self.wake(now);
+        self.report_starvation(now);
+        let Some(id) = self.high.pop_front().or_else(|| self.low.pop_front()) else {
+            return false;
+        };
+        let task = self.tasks.get_mut(&id).expect("queued task must exist");
+        match task.coroutine.as_mut().resume(()) {
+            CoroutineState::Yielded(Wait::Yield) => {
+                task.runnable_since = Instant::now();
+                task.reported = false;
+                let priority = task.priority;
+                self.queue(priority).push_back(id);
+            }
+            CoroutineState::Yielded(wait) => {
+                task.wait = Some(wait);
+                self.waiting.push(id);
+            }
+            CoroutineState::Complete(()) => {
+                self.tasks.remove(&id);
+            }
+        }
+        true
+    }
+
+    /// すべてのコルーチンが完了するまで実行する。
+    ///
+    /// ロックやチャネルの条件が満たされないままの場合は、終了しない。
+    pub fn run(&mut self) {
+        while !self.is_empty() {
+            if !self.tick() {
+                std::thread::sleep(self.idle_duration());
+            }
+        }
+    }
+
+    fn queue(&mut self, priority: FutureType) -> &mut VecDeque<TaskId> {
+        match priority {
+            FutureType::High => &mut self.high,
+            FutureType::Low => &mut self.low,
+        }
+    }
+
+    /// 条件を満たした待機中のコルーチンを、再開できるキューに移す。
+    fn wake(&mut self, now: Instant) {
+        let Self {
+            tasks,
+            waiting,
+            high,
+            low,
+            ..
+        } = self;
+        waiting.retain(|id| {
+            let task = tasks.get_mut(id).expect("waiting task must exist");
+            if !task.wait.as_ref().is_some_and(|wait| wait.holds(now)) {
+                return true;
+            }
+            task.wait = None;
+            task.runnable_since = now;
+            task.reported = false;
+            match task.priority {
+                FutureType::High => high.push_back(*id),
+                FutureType::Low => low.push_back(*id),
+            }
+            false
+        });
+    }
+
+    fn report_starvation(&mut self, now: Instant) {
+        let Some(starvation) = self.starvation.as_mut() else {
+            return;
+        };
+        for id in self.high.iter().chain(&self.low) {
+            let task = self.tasks.get_mut(id).expect("queued task must exist");
+            let waited = now.saturating_duration_since(task.runnable_since);
+            if !task.reported && waited >= starvation.threshold {
+                task.reported = true;
+                (starvation.handler)(Starvation {
+                    task: *id,
+                    priority: task.priority,
+                    waited,
+                });
+            }
+        }
+    }
+
+    /// 再開できるコルーチンがないときに、現在のスレッドを停止する時間を返す。
+    ///
+    /// 時刻を待つコルーチンだけの場合は最も早い時刻まで、それ以外は調べ直す間隔だけ停止する。
+    fn idle_duration(&self) -> Duration {
+        let now = Instant::now();
+        self.waiting
+            .iter()
+            .filter_map(|id| self.tasks[id].wait.as_ref())
+            .map(|wait| match wait.deadline() {
+                Some(deadline) => deadline.saturating_duration_since(now),
+                None => self.poll_interval,
+            })
+            .min()
+            .unwrap_or(self.poll_interval)
+    }
+}
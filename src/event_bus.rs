@@ -0,0 +1,353 @@
+pub mod durable;
+pub mod topic;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures::{Stream, StreamExt};
+
+use crate::sync::{Arc, AtomicU64, AtomicUsize, Mutex, MutexGuard, Ordering};
+use topic::TopicPattern;
+
+/// 購読者ごとに格納できるイベントの数の既定値
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// 購読を識別するID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubscriptionId(u64);
+
+impl fmt::Display for SubscriptionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "subscription-{}", self.0)
+    }
+}
+
+/// 購読者のバッファが満杯のときに、イベントをどう扱うか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// 最も古いイベントを破棄して、新しいイベントを格納する。
+    #[default]
+    DropOldest,
+    /// 新しいイベントを破棄する。
+    DropNewest,
+    /// 購読を解除する。
+    ///
+    /// 購読者は、バッファに残っているイベントを受け取った後に終了する。
+    Disconnect,
+}
+
+/// 購読の設定
+#[derive(Debug, Clone)]
+pub struct SubscribeOptions {
+    capacity: usize,
+    lag_policy: LagPolicy,
+    topics: Vec<TopicPattern>,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            lag_policy: LagPolicy::default(),
+            topics: Vec::new(),
+        }
+    }
+}
+
+impl SubscribeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 受け取っていないイベントを格納できる数を指定する。
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// バッファが満杯のときの扱いを指定する（既定は`DropOldest`）。
+    pub fn with_lag_policy(mut self, policy: LagPolicy) -> Self {
+        self.lag_policy = policy;
+        self
+    }
+
+    /// 受け取るイベントのトピックのパターン（[`TopicPattern`]）を追加する。
+    ///
+    /// パターンを指定した購読者は、いずれかのパターンに一致するトピックで送信されたイベントだけを受け取る。
+    /// 指定しない場合は、すべてのイベントを受け取る。
+    pub fn with_topic(mut self, pattern: impl Into<TopicPattern>) -> Self {
+        self.topics.push(pattern.into());
+        self
+    }
+}
+
+/// 購読者が受け取っていないイベント
+struct Queue<T> {
+    events: VecDeque<T>,
+    waker: Option<Waker>,
+    /// バッファが満杯で破棄したイベントの数
+    dropped: u64,
+    /// バスが閉じられたか、購読が解除された
+    closed: bool,
+}
+
+struct Subscriber<T> {
+    options: SubscribeOptions,
+    queue: Mutex<Queue<T>>,
+}
+
+impl<T> Subscriber<T> {
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap()
+    }
+
+    fn accepts(&self, topic: Option<&str>) -> bool {
+        let topics = &self.options.topics;
+        topics.is_empty()
+            || topic.is_some_and(|topic| topics.iter().any(|pattern| pattern.matches(topic)))
+    }
+
+    /// イベントを格納して、購読者を起こす。
+    ///
+    /// 遅れている購読者を解除する場合は`false`を返す。
+    fn push(&self, event: T) -> bool {
+        let mut queue = self.lock();
+        let mut connected = true;
+        if queue.events.len() < self.options.capacity {
+            queue.events.push_back(event);
+        } else {
+            queue.dropped += 1;
+            match self.options.lag_policy {
+                LagPolicy::DropOldest => {
+                    queue.events.pop_front();
+                    queue.events.push_back(event);
+                }
+                LagPolicy::DropNewest => return true,
+                LagPolicy::Disconnect => {
+                    queue.closed = true;
+                    connected = false;
+                }
+            }
+        }
+        let waker = queue.waker.take();
+        drop(queue);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        connected
+    }
+
+    fn close(&self) {
+        let mut queue = self.lock();
+        queue.closed = true;
+        let waker = queue.waker.take();
+        drop(queue);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+struct Inner<T> {
+    subscribers: Mutex<BTreeMap<SubscriptionId, Arc<Subscriber<T>>>>,
+    next_id: AtomicU64,
+    /// バスのハンドルの数
+    senders: AtomicUsize,
+}
+
+impl<T> Inner<T> {
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<SubscriptionId, Arc<Subscriber<T>>>> {
+        self.subscribers.lock().unwrap()
+    }
+}
+
+/// イベントを購読者に配信するバス
+///
+/// 購読（[`Subscription`]）はバスへの参照を持たずに所有できるため、タスクに移動できる。
+/// 購読者はイベントが送信されたときに起こされ、購読を破棄すると直ちに購読が解除される。
+///
+/// ハンドルを複製して、複数のタスクから送信できる。
+/// すべてのハンドルが破棄されると、購読者は受け取っていないイベントを受け取った後に終了する。
+pub struct EventBus<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for EventBus<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for EventBus<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            for subscriber in std::mem::take(&mut *self.inner.lock()).into_values() {
+                subscriber.close();
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for EventBus<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscriber_count())
+            .finish()
+    }
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> EventBus<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                subscribers: Mutex::new(BTreeMap::new()),
+                next_id: AtomicU64::new(1),
+                senders: AtomicUsize::new(1),
+            }),
+        }
+    }
+
+    /// すべてのイベントを既定の設定で受け取る購読を開始する。
+    pub fn subscribe(&self) -> Subscription<T> {
+        self.subscribe_with(SubscribeOptions::default())
+    }
+
+    /// 設定を指定して購読を開始する。
+    pub fn subscribe_with(&self, options: SubscribeOptions) -> Subscription<T> {
+        let id = SubscriptionId(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
+        let subscriber = Arc::new(Subscriber {
+            queue: Mutex::new(Queue {
+                events: VecDeque::with_capacity(options.capacity.min(DEFAULT_CAPACITY)),
+                waker: None,
+                dropped: 0,
+                closed: false,
+            }),
+            options,
+        });
+        self.inner.lock().insert(id, subscriber.clone());
+        Subscription {
+            id,
+            subscriber,
+            bus: self.inner.clone(),
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.inner.lock().len()
+    }
+}
+
+impl<T: Clone> EventBus<T> {
+    /// トピックを指定した購読者を除く、すべての購読者にイベントを送信する。
+    ///
+    /// 配信の対象になった購読者の数を返す。
+    pub fn send(&self, event: T) -> usize {
+        self.deliver(None, event)
+    }
+
+    /// トピックを指定せずに購読している購読者と、`topic`に一致するパターンで購読している購読者にイベントを送信する。
+    ///
+    /// 配信の対象になった購読者の数を返す。
+    pub fn publish(&self, topic: &str, event: T) -> usize {
+        self.deliver(Some(topic), event)
+    }
+
+    fn deliver(&self, topic: Option<&str>, event: T) -> usize {
+        let mut subscribers = self.inner.lock();
+        let mut delivered = 0;
+        subscribers.retain(|_, subscriber| {
+            if !subscriber.accepts(topic) {
+                return true;
+            }
+            delivered += 1;
+            subscriber.push(event.clone())
+        });
+        delivered
+    }
+}
+
+/// イベントバスの購読
+///
+/// イベントを送信された順に受け取る[`Stream`]である。
+/// バスが閉じられるか、遅れたために購読が解除されると、受け取っていないイベントを返した後に終了する。
+pub struct Subscription<T> {
+    id: SubscriptionId,
+    subscriber: Arc<Subscriber<T>>,
+    bus: Arc<Inner<T>>,
+}
+
+impl<T> fmt::Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<T> Subscription<T> {
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// 次のイベントを受け取る。
+    ///
+    /// 購読が終了した場合は`None`を返す。
+    pub async fn recv(&mut self) -> Option<T> {
+        self.next().await
+    }
+
+    /// 待機せずに、受け取っていないイベントを返す。
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.subscriber.lock().events.pop_front()
+    }
+
+    /// バッファが満杯で破棄されたイベントの数を返す。
+    pub fn dropped(&self) -> u64 {
+        self.subscriber.lock().dropped
+    }
+
+    /// バスが閉じられたか、購読が解除された場合に`true`を返す。
+    ///
+    /// 受け取っていないイベントが残っている場合がある。
+    pub fn is_closed(&self) -> bool {
+        self.subscriber.lock().closed
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut queue = self.subscriber.lock();
+        if let Some(event) = queue.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if queue.closed {
+            return Poll::Ready(None);
+        }
+        match &mut queue.waker {
+            Some(waker) => waker.clone_from(cx.waker()),
+            waker @ None => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.bus.lock().remove(&self.id);
+    }
+}
@@ -0,0 +1,559 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::{
+    EventBus, LagPolicy, SubscribeOptions, Subscription, SubscriptionId, topic::TopicPattern,
+};
+
+/// セグメントの大きさの既定値
+///
+/// 書き込んでいるセグメントがこの大きさを超えたら、新しいセグメントに書き込む。
+const DEFAULT_SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+const SEGMENT_EXTENSION: &str = "log";
+
+/// 永続的な購読者が処理したオフセットを保存するディレクトリ
+const OFFSETS_DIR: &str = "offsets";
+
+/// ログに追記したイベント
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record<T> {
+    /// ログの先頭からの通し番号
+    pub offset: u64,
+    /// 追記した時刻（UNIXエポックからのミリ秒）
+    ///
+    /// 時計が戻った場合も、前のイベントより小さくならない。
+    pub timestamp: u64,
+    pub topic: String,
+    pub event: T,
+}
+
+/// ログを構成するファイル
+#[derive(Debug)]
+struct Segment {
+    /// 最初のイベントのオフセット（ファイル名）
+    base: u64,
+    /// 最初のイベントの時刻（空の場合は`None`）
+    first_timestamp: Option<u64>,
+}
+
+/// セグメントに分割した、追記専用のイベントのログ
+///
+/// セグメントには、イベントを1行に1つずつJSONで書き込む。
+struct SegmentLog {
+    dir: PathBuf,
+    segments: Vec<Segment>,
+    /// 書き込んでいるセグメント（最後のセグメント）
+    file: File,
+    len: u64,
+    segment_size: u64,
+    next_offset: u64,
+    last_timestamp: u64,
+}
+
+impl SegmentLog {
+    /// ログを開く。
+    ///
+    /// 最後のセグメントの、書き込み中にクラッシュした行は切り詰める。
+    fn open<T: DeserializeOwned>(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut bases = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION)
+                && let Some(base) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse().ok())
+            {
+                bases.push(base);
+            }
+        }
+        bases.sort_unstable();
+        if bases.is_empty() {
+            bases.push(0);
+        }
+
+        let mut segments = Vec::with_capacity(bases.len());
+        let mut next_offset = 0;
+        let mut last_timestamp = 0;
+        let mut len = 0;
+        for &base in &bases {
+            let (records, valid_len) = read_segment::<T>(&segment_path(dir, base))?;
+            if let Some(last) = records.last() {
+                next_offset = last.offset + 1;
+                last_timestamp = last.timestamp;
+            }
+            segments.push(Segment {
+                base,
+                first_timestamp: records.first().map(|record| record.timestamp),
+            });
+            len = valid_len;
+        }
+        let base = segments.last().map_or(0, |segment| segment.base);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(dir, base))?;
+        if file.metadata()?.len() > len {
+            file.set_len(len)?;
+            file.sync_data()?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segments,
+            file,
+            len,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            next_offset,
+            last_timestamp,
+        })
+    }
+
+    /// イベントを追記する。
+    fn append<T: Serialize>(&mut self, topic: &str, event: T) -> io::Result<Record<T>> {
+        if self.len >= self.segment_size {
+            self.roll()?;
+        }
+        let record = Record {
+            offset: self.next_offset,
+            timestamp: now_millis().max(self.last_timestamp),
+            topic: topic.to_string(),
+            event,
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        self.next_offset += 1;
+        self.last_timestamp = record.timestamp;
+        let segment = self.segments.last_mut().expect("log has a segment");
+        segment.first_timestamp.get_or_insert(record.timestamp);
+        Ok(record)
+    }
+
+    /// 新しいセグメントに書き込みを切り替える。
+    fn roll(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        let base = self.next_offset;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(&self.dir, base))?;
+        self.len = 0;
+        self.segments.push(Segment {
+            base,
+            first_timestamp: None,
+        });
+        Ok(())
+    }
+
+    /// `offset`以降のイベントのうち、`filter`を満たすイベントを読み込む。
+    fn read_from<T, F>(&self, offset: u64, filter: F) -> io::Result<Vec<Record<T>>>
+    where
+        T: DeserializeOwned,
+        F: Fn(&Record<T>) -> bool,
+    {
+        // `offset`を含むセグメントから読み込む
+        let first = self
+            .segments
+            .partition_point(|segment| segment.base <= offset)
+            .saturating_sub(1);
+        self.read_segments(first, |record| record.offset >= offset && filter(record))
+    }
+
+    /// `timestamp`以降に追記されたイベントを読み込む。
+    fn read_since<T: DeserializeOwned>(&self, timestamp: u64) -> io::Result<Vec<Record<T>>> {
+        // 次のセグメントの最初のイベントが`timestamp`より前のセグメントは、読み込む必要がない
+        let first = self.segments.partition_point(|segment| {
+            segment
+                .first_timestamp
+                .is_some_and(|first| first < timestamp)
+        });
+        self.read_segments(first.saturating_sub(1), |record| {
+            record.timestamp >= timestamp
+        })
+    }
+
+    fn read_segments<T, F>(&self, first: usize, filter: F) -> io::Result<Vec<Record<T>>>
+    where
+        T: DeserializeOwned,
+        F: Fn(&Record<T>) -> bool,
+    {
+        let mut records = Vec::new();
+        for segment in &self.segments[first..] {
+            let (segment, _) = read_segment(&segment_path(&self.dir, segment.base))?;
+            records.extend(segment.into_iter().filter(&filter));
+        }
+        Ok(records)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+fn segment_path(dir: &Path, base: u64) -> PathBuf {
+    dir.join(format!("{base:020}.{SEGMENT_EXTENSION}"))
+}
+
+/// セグメントのイベントと、正しく書き込まれている部分の大きさを返す。
+fn read_segment<T: DeserializeOwned>(path: &Path) -> io::Result<(Vec<Record<T>>, u64)> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e),
+    };
+    let mut records = Vec::new();
+    let mut len = 0;
+    for line in contents.split_inclusive(|&byte| byte == b'\n') {
+        // 改行で終わっていない行は、書き込み中にクラッシュした行である
+        let Some(line) = line.strip_suffix(b"\n") else {
+            break;
+        };
+        let Ok(record) = serde_json::from_slice(line) else {
+            break;
+        };
+        records.push(record);
+        len += line.len() as u64 + 1;
+    }
+    Ok((records, len))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// ログから読み込んだイベントと、その後に追記されるイベントの購読
+type CatchUp<T> = (VecDeque<Record<T>>, Subscription<Record<T>>);
+
+struct Shared<T> {
+    dir: PathBuf,
+    log: Mutex<SegmentLog>,
+    bus: EventBus<Record<T>>,
+    /// 購読している永続的な購読者の名前
+    active: Mutex<HashSet<String>>,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, SegmentLog> {
+        self.log.lock().unwrap()
+    }
+}
+
+impl<T: Clone + DeserializeOwned> Shared<T> {
+    /// `offset`以降のイベントを読み込んで、その後に追記されるイベントの購読を開始する。
+    ///
+    /// ログをロックしている間に購読を開始するため、読み込んだイベントと購読したイベントの間に漏れはない。
+    fn catch_up(
+        &self,
+        pattern: &TopicPattern,
+        offset: u64,
+        capacity: usize,
+    ) -> io::Result<CatchUp<T>> {
+        let log = self.lock();
+        let backlog = log.read_from(offset, |record| pattern.matches(&record.topic))?;
+        let live = self.bus.subscribe_with(
+            SubscribeOptions::new()
+                .with_topic(pattern.clone())
+                .with_capacity(capacity)
+                .with_lag_policy(LagPolicy::Disconnect),
+        );
+        Ok((backlog.into(), live))
+    }
+}
+
+/// イベントをディスクのログに追記してから配信するイベントバス
+///
+/// イベントは、[`EventBus`]と同じようにトピックのパターンで購読できる。
+/// 永続的な購読者（[`DurableSubscription`]）は名前で識別され、処理したことを確定したオフセットを保存する。
+/// プロセスを起動し直した後に同じ名前で購読すると、確定したオフセットの続きから受け取る。
+///
+/// 追記したイベントはOSに書き込むが、`fsync`するのは[`DurableEventBus::sync`]を呼び出したときと、
+/// セグメントを切り替えたときだけである。
+pub struct DurableEventBus<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for DurableEventBus<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for DurableEventBus<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DurableEventBus")
+            .field("dir", &self.shared.dir)
+            .field("next_offset", &self.next_offset())
+            .finish()
+    }
+}
+
+impl<T> DurableEventBus<T> {
+    /// 次に追記するイベントのオフセットを返す。
+    pub fn next_offset(&self) -> u64 {
+        self.shared.lock().next_offset
+    }
+
+    /// セグメントを切り替える大きさを指定する。
+    pub fn with_segment_size(self, bytes: u64) -> Self {
+        self.shared.lock().segment_size = bytes.max(1);
+        self
+    }
+
+    /// 追記したイベントをディスクに同期する。
+    pub fn sync(&self) -> io::Result<()> {
+        self.shared.lock().sync()
+    }
+
+    /// 永続的な購読者が処理したことを確定したオフセットの、次のオフセットを返す。
+    ///
+    /// 確定していない場合は0を返す。
+    pub fn committed(&self, name: &str) -> io::Result<u64> {
+        match fs::read_to_string(offset_path(&self.shared.dir, name)?) {
+            Ok(offset) => offset
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<T> DurableEventBus<T>
+where
+    T: Clone + Serialize + DeserializeOwned + Send + 'static,
+{
+    /// `dir`のログを開く。
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        let log = SegmentLog::open::<T>(&dir)?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                dir,
+                log: Mutex::new(log),
+                bus: EventBus::new(),
+                active: Mutex::new(HashSet::new()),
+            }),
+        })
+    }
+
+    /// イベントをログに追記して、トピックに一致する購読者に配信する。
+    ///
+    /// 追記したイベントのオフセットを返す。
+    pub fn publish(&self, topic: &str, event: T) -> io::Result<u64> {
+        let mut log = self.shared.lock();
+        let record = log.append(topic, event)?;
+        let offset = record.offset;
+        // 購読者がオフセットの順に受け取るように、ログをロックしたまま配信する
+        self.shared.bus.publish(topic, record);
+        Ok(offset)
+    }
+
+    /// この後に追記されるイベントの購読を開始する（オフセットは保存しない）。
+    pub fn subscribe_with(&self, options: SubscribeOptions) -> Subscription<Record<T>> {
+        self.shared.bus.subscribe_with(options)
+    }
+
+    /// 永続的な購読者として、確定したオフセットの続きから購読を開始する。
+    ///
+    /// 同じ名前の購読者は、同時に1つだけ購読できる。
+    /// 同じ名前の購読が破棄されていない場合は、`ErrorKind::AlreadyExists`のエラーを返す。
+    pub fn subscribe_durable(
+        &self,
+        name: &str,
+        pattern: impl Into<TopicPattern>,
+    ) -> io::Result<DurableSubscription<T>> {
+        let offset = self.committed(name)?;
+        self.subscribe_from(name, pattern, offset)
+    }
+
+    /// 永続的な購読者として、`offset`から購読を開始する。
+    ///
+    /// [`DurableEventBus::subscribe_durable`]と同じく、同じ名前の購読者は同時に1つだけ購読できる。
+    pub fn subscribe_from(
+        &self,
+        name: &str,
+        pattern: impl Into<TopicPattern>,
+        offset: u64,
+    ) -> io::Result<DurableSubscription<T>> {
+        let offset_path = offset_path(&self.shared.dir, name)?;
+        let pattern = pattern.into();
+        let capacity = super::DEFAULT_CAPACITY;
+        let mut active = self.shared.active.lock().unwrap();
+        if active.contains(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("durable subscriber `{name}` is already subscribed"),
+            ));
+        }
+        let (backlog, live) = self.shared.catch_up(&pattern, offset, capacity)?;
+        active.insert(name.to_string());
+        Ok(DurableSubscription {
+            id: live.id(),
+            name: name.to_string(),
+            pattern,
+            offset_path,
+            shared: Arc::downgrade(&self.shared),
+            backlog,
+            live,
+            next: offset,
+            capacity,
+        })
+    }
+
+    /// `since`以降に追記されたイベントを、オフセットの順に返す。
+    pub fn replay(&self, since: SystemTime) -> io::Result<Vec<Record<T>>> {
+        let timestamp = since
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.shared.lock().read_since(timestamp)
+    }
+}
+
+/// 購読者の名前から、オフセットを保存するファイルのパスを返す。
+fn offset_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid subscriber name `{name}`"),
+        ));
+    }
+    Ok(dir.join(OFFSETS_DIR).join(name))
+}
+
+/// 永続的な購読
+///
+/// ログに追記されたイベントを、オフセットの順に漏れなく受け取る[`Stream`]である。
+/// 受け取るのが遅れてバッファが満杯になった場合は、ログから読み込み直して続きを受け取る。
+/// すべての[`DurableEventBus`]が破棄されると、受け取っていないイベントを返した後に終了する。
+/// 破棄すると、同じ名前で再び購読できるようになる。
+pub struct DurableSubscription<T> {
+    /// 最初の購読のID（ログから読み込み直した後も変わらない）
+    id: SubscriptionId,
+    name: String,
+    pattern: TopicPattern,
+    offset_path: PathBuf,
+    shared: Weak<Shared<T>>,
+    /// ログから読み込んだ、まだ返していないイベント
+    backlog: VecDeque<Record<T>>,
+    live: Subscription<Record<T>>,
+    /// 次に返すイベントのオフセットの下限
+    next: u64,
+    capacity: usize,
+}
+
+impl<T> fmt::Debug for DurableSubscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DurableSubscription")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("pattern", &self.pattern)
+            .finish()
+    }
+}
+
+impl<T> DurableSubscription<T> {
+    /// 購読を開始したときに割り当てられたID
+    ///
+    /// 受け取るのが遅れてログから読み込み直した場合も、同じIDを返す。
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// `offset`までのイベントを処理したことを確定して、ディスクに保存する。
+    ///
+    /// 同じ名前で購読し直すと、`offset`の次のイベントから受け取る。
+    pub fn commit(&self, offset: u64) -> io::Result<()> {
+        let dir = self.offset_path.parent().expect("offset path has a parent");
+        fs::create_dir_all(dir)?;
+        // 書き込み中にクラッシュしても壊れないように、一時ファイルを置き換える
+        let tmp = self.offset_path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all((offset + 1).to_string().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.offset_path)
+    }
+}
+
+impl<T> Drop for DurableSubscription<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.active.lock().unwrap().remove(&self.name);
+        }
+    }
+}
+
+// イベントをピン留めして扱うことはない
+impl<T> Unpin for DurableSubscription<T> {}
+
+impl<T: Clone + DeserializeOwned> DurableSubscription<T> {
+    /// 次のイベントを受け取る。
+    pub async fn recv(&mut self) -> Option<io::Result<Record<T>>> {
+        self.next().await
+    }
+}
+
+impl<T: Clone + DeserializeOwned> Stream for DurableSubscription<T> {
+    type Item = io::Result<Record<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(record) = this.backlog.pop_front() {
+                this.next = record.offset + 1;
+                return Poll::Ready(Some(Ok(record)));
+            }
+            match this.live.poll_next_unpin(cx) {
+                Poll::Ready(Some(record)) => {
+                    // ログから読み込んだイベントは読み飛ばす
+                    if record.offset >= this.next {
+                        this.next = record.offset + 1;
+                        return Poll::Ready(Some(Ok(record)));
+                    }
+                }
+                Poll::Ready(None) => {
+                    let shared = match this.shared.upgrade() {
+                        Some(shared) if this.live.dropped() > 0 => shared,
+                        _ => return Poll::Ready(None),
+                    };
+                    // 遅れて購読が解除されたため、ログから続きを読み込む
+                    // 購読し直しても、`id`は最初の購読のIDのままにする
+                    match shared.catch_up(&this.pattern, this.next, this.capacity) {
+                        Ok((backlog, live)) => {
+                            this.backlog = backlog;
+                            this.live = live;
+                        }
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
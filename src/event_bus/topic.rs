@@ -0,0 +1,68 @@
+use std::fmt;
+
+/// トピックの階層の区切り
+const SEPARATOR: char = '.';
+
+/// 1つの階層に一致するワイルドカード
+const SINGLE: &str = "*";
+
+/// 0個以上の階層に一致するワイルドカード
+const MULTI: &str = "#";
+
+/// `.`で区切った階層的なトピックに一致するパターン
+///
+/// `*`は任意の1つの階層に、`#`は任意の0個以上の階層に一致する。
+/// 例えば、`sensors.*.temperature`は`sensors.kitchen.temperature`に、
+/// `sensors.#`は`sensors`と`sensors.kitchen.humidity`に一致する。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TopicPattern {
+    pattern: String,
+}
+
+impl TopicPattern {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// トピックがパターンに一致する場合に`true`を返す。
+    pub fn matches(&self, topic: &str) -> bool {
+        let pattern: Vec<_> = self.pattern.split(SEPARATOR).collect();
+        let topic: Vec<_> = topic.split(SEPARATOR).collect();
+        matches(&pattern, &topic)
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl From<&str> for TopicPattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
+impl From<String> for TopicPattern {
+    fn from(pattern: String) -> Self {
+        Self::new(pattern)
+    }
+}
+
+fn matches(pattern: &[&str], topic: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => topic.is_empty(),
+        // 残りのパターンが一致するまで、階層を1つずつ読み飛ばす
+        Some((&MULTI, rest)) => (0..=topic.len()).any(|skip| matches(rest, &topic[skip..])),
+        Some((&segment, rest)) => topic.split_first().is_some_and(|(&first, topic_rest)| {
+            (segment == SINGLE || segment == first) && matches(rest, topic_rest)
+        }),
+    }
+}
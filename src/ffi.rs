@@ -0,0 +1,279 @@
+use std::{
+    cell::RefCell,
+    ffi::{CString, c_char, c_void},
+    ptr,
+    time::Duration,
+};
+
+use crate::{
+    async_mod,
+    job::{JobError, JobId, JobStatus},
+};
+
+/// C言語から呼び出す関数の結果
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncRustStatus {
+    /// 成功した。
+    Ok = 0,
+    /// ジョブが完了していない。
+    Pending = 1,
+    /// ジョブが存在しない。
+    NotFound = 2,
+    /// 指定した時間内にジョブが完了しなかった。
+    Timeout = 3,
+    /// ジョブがキャンセルされた。
+    Cancelled = 4,
+    /// ジョブがパニックした。
+    Panicked = 5,
+    /// 引数が不正（NULLポインターなど）。
+    InvalidArgument = 6,
+}
+
+/// ジョブの状態
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncRustJobStatus {
+    Pending = 0,
+    Running = 1,
+    Done = 2,
+    Failed = 3,
+}
+
+/// ジョブを識別するID
+///
+/// 値として受け渡しでき、解放する必要はない。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncRustJobId {
+    pub bytes: [u8; 16],
+}
+
+/// ジョブが完了したときに呼び出されるコールバック
+///
+/// `status`が`Ok`の場合に限り、`result`にジョブの結果が格納される。
+pub type AsyncRustCallback = Option<
+    extern "C" fn(user_data: *mut c_void, id: AsyncRustJobId, status: AsyncRustStatus, result: i32),
+>;
+
+thread_local! {
+    /// 現在のスレッドで最後に発生したエラーのメッセージ
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+impl From<JobId> for AsyncRustJobId {
+    fn from(id: JobId) -> Self {
+        Self {
+            bytes: *id.as_bytes(),
+        }
+    }
+}
+
+impl From<AsyncRustJobId> for JobId {
+    fn from(id: AsyncRustJobId) -> Self {
+        JobId::from_bytes(id.bytes)
+    }
+}
+
+impl From<JobStatus> for AsyncRustJobStatus {
+    fn from(status: JobStatus) -> Self {
+        match status {
+            JobStatus::Pending => Self::Pending,
+            JobStatus::Running => Self::Running,
+            JobStatus::Done => Self::Done,
+            JobStatus::Failed => Self::Failed,
+        }
+    }
+}
+
+/// エラーを現在のスレッドの最後のエラーとして記録し、対応する結果を返す。
+fn set_last_error(e: &JobError) -> AsyncRustStatus {
+    let message = CString::new(e.to_string().replace('\0', "")).unwrap();
+    LAST_ERROR.with_borrow_mut(|last| *last = Some(message));
+    match e {
+        JobError::NotFound(_) => AsyncRustStatus::NotFound,
+        JobError::Timeout(_) => AsyncRustStatus::Timeout,
+        JobError::Cancelled(_) => AsyncRustStatus::Cancelled,
+        JobError::Panicked { .. } => AsyncRustStatus::Panicked,
+    }
+}
+
+/// ジョブの結果を出力引数に書き込む。
+///
+/// # Safety
+///
+/// `out`は、NULLでないi32の書き込み可能なポインターでなければならない。
+unsafe fn write_result(result: Result<i32, JobError>, out: *mut i32) -> AsyncRustStatus {
+    match result {
+        Ok(value) => {
+            unsafe { out.write(value) };
+            AsyncRustStatus::Ok
+        }
+        Err(e) => set_last_error(&e),
+    }
+}
+
+/// ユーザーデータをランタイムのスレッドに移動するためのラッパー
+struct UserData(*mut c_void);
+
+// SAFETY: ユーザーデータを任意のスレッドで使用できることは、呼び出し側が保証する。
+unsafe impl Send for UserData {}
+
+/// `a + b`を非同期に計算するジョブを投入して、IDを`out_id`に書き込む。
+///
+/// # Safety
+///
+/// `out_id`は、NULLまたは書き込み可能な`AsyncRustJobId`へのポインターでなければならない。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn async_rust_add_submit(
+    a: i32,
+    b: i32,
+    out_id: *mut AsyncRustJobId,
+) -> AsyncRustStatus {
+    if out_id.is_null() {
+        return AsyncRustStatus::InvalidArgument;
+    }
+    let id = async_mod::send_add(a, b);
+    unsafe { out_id.write(id.into()) };
+    AsyncRustStatus::Ok
+}
+
+/// ジョブの状態を`out_status`に書き込む。
+///
+/// # Safety
+///
+/// `out_status`は、NULLまたは書き込み可能な`AsyncRustJobStatus`へのポインターでなければならない。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn async_rust_add_status(
+    id: AsyncRustJobId,
+    out_status: *mut AsyncRustJobStatus,
+) -> AsyncRustStatus {
+    if out_status.is_null() {
+        return AsyncRustStatus::InvalidArgument;
+    }
+    match async_mod::add_jobs().status(id.into()) {
+        Ok(status) => {
+            unsafe { out_status.write(status.into()) };
+            AsyncRustStatus::Ok
+        }
+        Err(e) => set_last_error(&e),
+    }
+}
+
+/// ブロックせずにジョブの結果を回収して、`out_result`に書き込む。
+///
+/// ジョブが完了していない場合は`Pending`を返す。
+/// 結果を回収したジョブ（失敗したジョブを含む）は削除される。
+///
+/// # Safety
+///
+/// `out_result`は、NULLまたは書き込み可能なi32へのポインターでなければならない。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn async_rust_add_poll(
+    id: AsyncRustJobId,
+    out_result: *mut i32,
+) -> AsyncRustStatus {
+    if out_result.is_null() {
+        return AsyncRustStatus::InvalidArgument;
+    }
+    match async_mod::add_jobs().poll(id.into()) {
+        Ok(Some(value)) => unsafe { write_result(Ok(value), out_result) },
+        Ok(None) => AsyncRustStatus::Pending,
+        Err(e) => set_last_error(&e),
+    }
+}
+
+/// ジョブが完了するまで最大`timeout_ms`ミリ秒の間ブロックして、結果を`out_result`に書き込む。
+///
+/// # Safety
+///
+/// `out_result`は、NULLまたは書き込み可能なi32へのポインターでなければならない。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn async_rust_add_wait(
+    id: AsyncRustJobId,
+    timeout_ms: u64,
+    out_result: *mut i32,
+) -> AsyncRustStatus {
+    if out_result.is_null() {
+        return AsyncRustStatus::InvalidArgument;
+    }
+    let result = async_mod::add_jobs().wait(id.into(), Duration::from_millis(timeout_ms));
+    unsafe { write_result(result, out_result) }
+}
+
+/// 完了していないジョブをキャンセルする。
+#[unsafe(no_mangle)]
+pub extern "C" fn async_rust_add_cancel(id: AsyncRustJobId) -> AsyncRustStatus {
+    match async_mod::add_jobs().cancel(id.into()) {
+        Ok(()) => AsyncRustStatus::Ok,
+        Err(e) => set_last_error(&e),
+    }
+}
+
+/// ジョブが完了したときに呼び出すコールバックを登録する。
+///
+/// コールバックはランタイムのスレッドで1度だけ呼び出され、結果はコールバックに渡される。
+/// コールバックの中では、このライブラリの関数（`async_rust_last_error_message`を含む）を呼び出せる。
+/// ただし、ランタイムのスレッドをブロックするため、`async_rust_add_wait`を呼び出してはならない。
+///
+/// # Safety
+///
+/// `user_data`は、コールバックが呼び出されるまで有効で、任意のスレッドから使用できなければならない。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn async_rust_add_on_complete(
+    id: AsyncRustJobId,
+    callback: AsyncRustCallback,
+    user_data: *mut c_void,
+) -> AsyncRustStatus {
+    let Some(callback) = callback else {
+        return AsyncRustStatus::InvalidArgument;
+    };
+    let user_data = UserData(user_data);
+    let registered = async_mod::add_jobs().on_complete(id.into(), move |result| {
+        let user_data = user_data;
+        let (status, value) = match result {
+            Ok(value) => (AsyncRustStatus::Ok, value),
+            Err(e) => (set_last_error(&e), 0),
+        };
+        callback(user_data.0, id, status, value);
+    });
+    match registered {
+        Ok(()) => AsyncRustStatus::Ok,
+        Err(e) => set_last_error(&e),
+    }
+}
+
+/// 現在のスレッドで最後に発生したエラーのメッセージを返す。
+///
+/// エラーが発生していない場合はNULLを返す。
+/// 返された文字列は呼び出し側が所有し、`async_rust_string_free`で解放しなければならない。
+#[unsafe(no_mangle)]
+pub extern "C" fn async_rust_last_error_message() -> *mut c_char {
+    LAST_ERROR
+        .with_borrow(|last| last.clone())
+        .map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// ジョブのIDを文字列に変換する。
+///
+/// 返された文字列は呼び出し側が所有し、`async_rust_string_free`で解放しなければならない。
+#[unsafe(no_mangle)]
+pub extern "C" fn async_rust_job_id_to_string(id: AsyncRustJobId) -> *mut c_char {
+    CString::new(JobId::from(id).to_string())
+        .unwrap()
+        .into_raw()
+}
+
+/// このライブラリが返した文字列を解放する。
+///
+/// NULLを渡した場合は何もしない。
+///
+/// # Safety
+///
+/// `s`は、NULLまたはこのライブラリが返した解放していない文字列でなければならない。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn async_rust_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
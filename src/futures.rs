@@ -1,21 +1,48 @@
 use std::{
     pin::Pin,
-    task::{Context, Poll},
+    task::{Context, Poll, ready},
     time::Duration,
 };
 
+use crate::{
+    blocking::spawn_blocking,
+    runtime::time::{self, Sleep},
+};
+
+/// 次のカウントに進むまでの時間
+const INTERVAL: Duration = Duration::from_secs(1);
+
 /// カウンターを持つFuture
 ///
-/// pollが呼ばれるたびにカウンターをインクリメントし、カウンターが3になったら完了する。
-#[derive(Debug, Clone, Copy)]
+/// 最初にpollされたときにカウンターをインクリメントし、その後は1秒経過するたびに次のカウントに進み、
+/// カウンターが`max`になったら完了する。
+/// 待機中に何度pollされても、登録するタイマーは1つだけである。
+#[derive(Debug)]
 pub struct CounterFuture {
     count: u32,
     max: u32,
+    /// 次のカウントに進むまで待機しているタイマー
+    sleep: Option<Sleep>,
 }
 
 impl CounterFuture {
     pub fn new(count: u32, max: u32) -> Self {
-        Self { count, max }
+        Self {
+            count,
+            max,
+            sleep: None,
+        }
+    }
+}
+
+/// 複製したフューチャーは、現在のカウントの待機を始め直す。
+impl Clone for CounterFuture {
+    fn clone(&self) -> Self {
+        Self {
+            count: self.count,
+            max: self.max,
+            sleep: self.sleep.as_ref().map(|_| time::sleep(INTERVAL)),
+        }
     }
 }
 
@@ -23,23 +50,22 @@ impl Future for CounterFuture {
     type Output = u32;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.count += 1;
-        println!("polling with result: {}", self.count);
-
-        // 本来、poll内でブロッキング処理をするのは非推奨
-        std::thread::sleep(Duration::from_secs(1));
-
-        if self.count < self.max {
-            // wakerを呼び出して、再度pollされるように登録
-            cx.waker().wake_by_ref();
-            Poll::Pending
-        } else {
-            Poll::Ready(self.count)
+        loop {
+            if let Some(sleep) = self.sleep.as_mut() {
+                // poll内でブロックせず、期限まではタイマーのスレッドにウェイカーを預ける
+                ready!(Pin::new(sleep).poll(cx));
+                if self.count >= self.max {
+                    return Poll::Ready(self.count);
+                }
+            }
+            self.count += 1;
+            println!("polling with result: {}", self.count);
+            self.sleep = Some(time::sleep(INTERVAL));
         }
     }
 }
 
 pub async fn async_fn() {
-    std::thread::sleep(Duration::from_secs(1));
+    spawn_blocking(|| std::thread::sleep(Duration::from_secs(1))).await;
     println!("async_fn");
 }
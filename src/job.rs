@@ -0,0 +1,393 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    panic::AssertUnwindSafe,
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use futures::FutureExt;
+use tokio::{runtime::Handle, task::AbortHandle};
+use uuid::Uuid;
+
+/// 結果が回収されないジョブを保持する時間の既定値
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// ジョブを識別するID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JobId(Uuid);
+
+impl JobId {
+    fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// IDのバイト列を返す。
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        self.0.as_bytes()
+    }
+
+    /// バイト列からIDを作成する。
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(Uuid::from_bytes(bytes))
+    }
+}
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for JobId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(Self)
+    }
+}
+
+/// ジョブの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// 投入されたが、まだ実行が開始されていない。
+    Pending,
+    /// 実行中
+    Running,
+    /// 完了して、結果を回収できる。
+    Done,
+    /// パニックまたはキャンセルにより失敗した。
+    Failed,
+}
+
+/// ジョブの操作で発生するエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    /// ジョブが存在しない（結果を回収済み、またはTTLを過ぎて破棄された）。
+    NotFound(JobId),
+    /// 指定した時間内にジョブが完了しなかった。
+    Timeout(JobId),
+    /// ジョブがキャンセルされた。
+    Cancelled(JobId),
+    /// ジョブがパニックした。
+    Panicked { id: JobId, message: String },
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "job {id} not found"),
+            Self::Timeout(id) => write!(f, "job {id} timed out"),
+            Self::Cancelled(id) => write!(f, "job {id} was cancelled"),
+            Self::Panicked { id, message } => write!(f, "job {id} panicked: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+/// ジョブが完了したときに呼び出すコールバック
+type Callback<T> = Box<dyn FnOnce(Result<T, JobError>) + Send>;
+
+enum JobState<T> {
+    Pending,
+    Running,
+    Done(T),
+    Failed(JobError),
+}
+
+struct Job<T> {
+    state: JobState<T>,
+    abort: Option<AbortHandle>,
+    /// ジョブが完了または失敗した時刻
+    finished_at: Option<Instant>,
+    callback: Option<Callback<T>>,
+}
+
+impl<T> Job<T> {
+    fn status(&self) -> JobStatus {
+        match self.state {
+            JobState::Pending => JobStatus::Pending,
+            JobState::Running => JobStatus::Running,
+            JobState::Done(_) => JobStatus::Done,
+            JobState::Failed(_) => JobStatus::Failed,
+        }
+    }
+
+    fn finish(&mut self, state: JobState<T>) {
+        self.state = state;
+        self.abort = None;
+        self.finished_at = Some(Instant::now());
+    }
+
+    fn into_result(self) -> Result<T, JobError> {
+        match self.state {
+            JobState::Done(output) => Ok(output),
+            JobState::Failed(e) => Err(e),
+            JobState::Pending | JobState::Running => unreachable!("job is not finished"),
+        }
+    }
+}
+
+/// ジョブと、TTLで破棄する順番
+struct Jobs<T> {
+    map: HashMap<JobId, Job<T>>,
+    /// 完了または失敗したジョブのIDと時刻
+    ///
+    /// ジョブはロックを保持したまま完了させるため、時刻の順に並ぶ。
+    /// 回収済みのジョブのIDも、TTLを過ぎるまで残る。
+    expiry: VecDeque<(Instant, JobId)>,
+}
+
+impl<T> Jobs<T> {
+    /// ジョブを完了させて、TTLで破棄する対象に加える。
+    fn finish(&mut self, id: JobId, state: JobState<T>) -> Option<&mut Job<T>> {
+        let job = self.map.get_mut(&id)?;
+        job.finish(state);
+        self.expiry.push_back((job.finished_at.unwrap(), id));
+        Some(job)
+    }
+
+    /// TTLを過ぎた未回収のジョブを、古いものから破棄する。
+    fn evict(&mut self, ttl: Duration) {
+        let now = Instant::now();
+        while let Some(&(finished_at, id)) = self.expiry.front() {
+            if now.duration_since(finished_at) < ttl {
+                break;
+            }
+            self.expiry.pop_front();
+            self.map.remove(&id);
+        }
+    }
+}
+
+struct Inner<T> {
+    jobs: Mutex<Jobs<T>>,
+    /// ジョブが完了または失敗したことを、`wait`で待機しているスレッドに通知する
+    finished: Condvar,
+    ttl: Duration,
+}
+
+impl<T> Inner<T> {
+    /// ロックを取得して、TTLを過ぎた未回収のジョブを破棄する。
+    ///
+    /// 破棄するのは完了した順に並べたキューの先頭だけなので、ジョブの数によらず償却定数時間で済む。
+    fn lock(&self) -> MutexGuard<'_, Jobs<T>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.evict(self.ttl);
+        jobs
+    }
+
+    fn update(&self, id: JobId, f: impl FnOnce(&mut Job<T>)) {
+        if let Some(job) = self.jobs.lock().unwrap().map.get_mut(&id) {
+            f(job);
+        }
+    }
+
+    /// ジョブを完了させる。
+    ///
+    /// コールバックが登録されている場合は、ジョブを削除してコールバックと結果を返す。
+    /// キャンセルされたジョブの状態は上書きしない。
+    fn complete(
+        &self,
+        id: JobId,
+        state: JobState<T>,
+    ) -> Option<(Callback<T>, Result<T, JobError>)> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.map.get(&id)?.finished_at.is_some() {
+            return None;
+        }
+        let job = jobs.finish(id, state)?;
+        self.finished.notify_all();
+        let callback = job.callback.take()?;
+        let job = jobs.map.remove(&id).unwrap();
+        Some((callback, job.into_result()))
+    }
+}
+
+/// 同期的なコードから非同期処理を投入し、その結果を後から回収するためのレジストリ
+///
+/// ジョブは`Handle`が指すTokioランタイムで実行される。
+/// 完了したジョブの結果は、回収されるかTTLを過ぎるまで保持される。
+///
+/// `wait`は呼び出したスレッドをブロックするため、ランタイムのワーカースレッドから呼び出してはならない。
+pub struct JobRegistry<T> {
+    inner: Arc<Inner<T>>,
+    handle: Handle,
+}
+
+impl<T> Clone for JobRegistry<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> JobRegistry<T> {
+    pub fn new(handle: Handle) -> Self {
+        Self::with_ttl(handle, DEFAULT_TTL)
+    }
+
+    /// 結果が回収されないジョブを保持する時間を指定して作成する。
+    pub fn with_ttl(handle: Handle, ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                jobs: Mutex::new(Jobs {
+                    map: HashMap::new(),
+                    expiry: VecDeque::new(),
+                }),
+                finished: Condvar::new(),
+                ttl,
+            }),
+            handle,
+        }
+    }
+
+    /// フューチャーをジョブとして投入する。
+    pub fn submit<F>(&self, future: F) -> JobId
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let id = JobId::new();
+        // タスクが登録前に完了しないように、ロックを保持したままタスクを生成する
+        let mut jobs = self.inner.lock();
+        let inner = self.inner.clone();
+        let task = self.handle.spawn(async move {
+            inner.update(id, |job| job.state = JobState::Running);
+            let state = match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(output) => JobState::Done(output),
+                Err(payload) => {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    JobState::Failed(JobError::Panicked { id, message })
+                }
+            };
+            if let Some((callback, result)) = inner.complete(id, state) {
+                callback(result);
+            }
+        });
+        jobs.map.insert(
+            id,
+            Job {
+                state: JobState::Pending,
+                abort: Some(task.abort_handle()),
+                finished_at: None,
+                callback: None,
+            },
+        );
+        id
+    }
+
+    /// ジョブの状態を返す。
+    pub fn status(&self, id: JobId) -> Result<JobStatus, JobError> {
+        self.inner
+            .lock()
+            .map
+            .get(&id)
+            .map(Job::status)
+            .ok_or(JobError::NotFound(id))
+    }
+
+    /// ブロックせずにジョブの結果を回収する。
+    ///
+    /// ジョブが完了していない場合は`Ok(None)`を返す。
+    /// 結果を回収したジョブ（失敗したジョブを含む）はレジストリから削除される。
+    pub fn poll(&self, id: JobId) -> Result<Option<T>, JobError> {
+        let mut jobs = self.inner.lock();
+        Self::take(&mut jobs, id)
+    }
+
+    /// ジョブが完了するまで最大`timeout`の間ブロックして、結果を回収する。
+    ///
+    /// 時間内に完了しなかった場合は`JobError::Timeout`を返し、ジョブはレジストリに残る。
+    pub fn wait(&self, id: JobId, timeout: Duration) -> Result<T, JobError> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut jobs = self.inner.lock();
+        loop {
+            if let Some(output) = Self::take(&mut jobs, id)? {
+                return Ok(output);
+            }
+            jobs = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(JobError::Timeout(id));
+                    }
+                    self.inner.finished.wait_timeout(jobs, remaining).unwrap().0
+                }
+                None => self.inner.finished.wait(jobs).unwrap(),
+            };
+        }
+    }
+
+    /// 完了していないジョブをキャンセルする。
+    ///
+    /// キャンセルしたジョブは失敗したジョブとして扱われ、回収すると`JobError::Cancelled`を返す。
+    /// すでに完了したジョブには影響しない。
+    pub fn cancel(&self, id: JobId) -> Result<(), JobError> {
+        let mut jobs = self.inner.lock();
+        let job = jobs.map.get_mut(&id).ok_or(JobError::NotFound(id))?;
+        if let Some(abort) = job.abort.take() {
+            abort.abort();
+            let job = jobs
+                .finish(id, JobState::Failed(JobError::Cancelled(id)))
+                .unwrap();
+            self.inner.finished.notify_all();
+            if let Some(callback) = job.callback.take() {
+                let job = jobs.map.remove(&id).unwrap();
+                self.dispatch(callback, job.into_result());
+            }
+        }
+        Ok(())
+    }
+
+    /// ジョブが完了したときに呼び出すコールバックを登録する。
+    ///
+    /// コールバックは、ジョブの結果（失敗とキャンセルを含む）を受け取って、ランタイムのスレッドで1度だけ呼び出される。
+    /// 結果はコールバックに渡されるため、コールバックを登録したジョブは`poll`や`wait`で回収できない。
+    /// すでに完了しているジョブに登録した場合も、ランタイムのスレッドで呼び出される。
+    /// コールバックを再度登録した場合は、以前のコールバックを置き換える。
+    pub fn on_complete<F>(&self, id: JobId, callback: F) -> Result<(), JobError>
+    where
+        F: FnOnce(Result<T, JobError>) + Send + 'static,
+    {
+        let mut jobs = self.inner.lock();
+        let job = jobs.map.get_mut(&id).ok_or(JobError::NotFound(id))?;
+        if job.finished_at.is_none() {
+            job.callback = Some(Box::new(callback));
+            return Ok(());
+        }
+        let job = jobs.map.remove(&id).unwrap();
+        drop(jobs);
+        self.dispatch(Box::new(callback), job.into_result());
+        Ok(())
+    }
+
+    /// レジストリに残っているジョブの数を返す。
+    pub fn len(&self) -> usize {
+        self.inner.lock().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// ランタイムのスレッドでコールバックを呼び出す。
+    fn dispatch(&self, callback: Callback<T>, result: Result<T, JobError>) {
+        self.handle.spawn(async move { callback(result) });
+    }
+
+    fn take(jobs: &mut Jobs<T>, id: JobId) -> Result<Option<T>, JobError> {
+        let job = jobs.map.get(&id).ok_or(JobError::NotFound(id))?;
+        if job.finished_at.is_none() {
+            return Ok(None);
+        }
+        jobs.map.remove(&id).unwrap().into_result().map(Some)
+    }
+}
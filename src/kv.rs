@@ -0,0 +1,458 @@
+pub mod client;
+pub mod key_value;
+pub mod remote;
+pub mod replication;
+pub mod ring;
+pub mod router;
+pub mod server;
+mod snapshot;
+mod wal;
+pub mod writer;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::actor::AskError;
+use wal::Wal;
+
+/// ログの大きさがこのバイト数を超えたら、スナップショットを作成してログを空にする
+const DEFAULT_SNAPSHOT_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// 現在時刻をUNIXエポックからのミリ秒で返す。
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// 先行書き込みログに記録する操作
+///
+/// 有効期限は絶対時刻で記録するため、ログを再生しても同じデータになる。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Set {
+        key: String,
+        value: Vec<u8>,
+        /// 有効期限（UNIXエポックからのミリ秒）
+        expires_at: Option<u64>,
+    },
+    Delete {
+        key: String,
+    },
+    /// 複数の操作をまとめて適用する。
+    ///
+    /// 1つのレコードとして記録されるため、すべての操作が永続化されるか、どれも永続化されない。
+    Batch(Vec<Operation>),
+}
+
+/// キーに格納されている値
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub value: Vec<u8>,
+    /// 値を書き込んだ操作のシーケンス番号
+    ///
+    /// キーを書き込むたびに大きくなるため、compare-and-swapに使用する。
+    pub version: u64,
+    /// 有効期限（UNIXエポックからのミリ秒）
+    pub expires_at: Option<u64>,
+}
+
+impl Entry {
+    /// `now`の時点で有効期限が切れている場合に`true`を返す。
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// 走査するキーの範囲
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanRange {
+    /// 指定した文字列で始まるキー
+    Prefix(String),
+    /// 指定した範囲のキー
+    Range(Bound<String>, Bound<String>),
+}
+
+impl ScanRange {
+    /// すべてのキー
+    pub fn all() -> Self {
+        Self::Range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// `start`以上`end`未満のキー
+    pub fn between(start: impl Into<String>, end: impl Into<String>) -> Self {
+        Self::Range(Bound::Included(start.into()), Bound::Excluded(end.into()))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        match self {
+            Self::Prefix(prefix) => key.starts_with(prefix.as_str()),
+            Self::Range(start, end) => {
+                let after_start = match start {
+                    Bound::Included(start) => key >= start.as_str(),
+                    Bound::Excluded(start) => key > start.as_str(),
+                    Bound::Unbounded => true,
+                };
+                let before_end = match end {
+                    Bound::Included(end) => key <= end.as_str(),
+                    Bound::Excluded(end) => key < end.as_str(),
+                    Bound::Unbounded => true,
+                };
+                after_start && before_end
+            }
+        }
+    }
+}
+
+/// キーバリューストアの操作で発生するエラー
+#[derive(Debug, Clone)]
+pub enum KvError {
+    /// compare-and-swapで指定したバージョンが、現在のバージョンと一致しなかった。
+    ///
+    /// キーが存在しない場合のバージョンは0である。
+    Conflict {
+        key: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// バッチに含まれるキーが、複数のシャードに割り当てられている。
+    ///
+    /// シャードごとに永続化するため、複数のシャードにまたがる書き込みはまとめて適用できない。
+    CrossShard,
+    /// 指定したシャードが存在しない。
+    UnknownShard(u32),
+    /// 最後のシャードは削除できない。
+    LastShard,
+    /// レプリケーションは、シャードが1つのストアだけで使用できる。
+    MultipleShards,
+    /// 読み取り専用のレプリカは、書き込みを受け付けない。
+    ReadOnly,
+    /// 昇格したストアは、レプリケーションの操作を適用しない。
+    NotReplica,
+    /// レプリケーションの操作のシーケンス番号が連続していない。
+    OutOfSequence { expected: u64, actual: u64 },
+    /// 操作は永続化されたが、指定した時間内に必要な数のレプリカが確認応答しなかった。
+    NotReplicated(u64),
+    /// 操作を永続化できなかった。
+    Io(Arc<io::Error>),
+    /// アクターが応答しなかった。
+    Unavailable(AskError),
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Conflict {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "version conflict on `{key}`: expected {expected}, actual {actual}"
+            ),
+            Self::CrossShard => write!(f, "batch spans multiple shards"),
+            Self::UnknownShard(shard) => write!(f, "shard {shard} does not exist"),
+            Self::LastShard => write!(f, "the last shard cannot be removed"),
+            Self::MultipleShards => write!(f, "replication requires a single shard"),
+            Self::ReadOnly => write!(f, "read-only replica does not accept writes"),
+            Self::NotReplica => write!(f, "store has been promoted and is not a replica"),
+            Self::OutOfSequence { expected, actual } => write!(
+                f,
+                "replicated operation is out of sequence: expected {expected}, actual {actual}"
+            ),
+            Self::NotReplicated(seq) => write!(
+                f,
+                "operation {seq} was persisted but not acknowledged by enough replicas"
+            ),
+            Self::Io(e) => write!(f, "failed to persist the operation: {e}"),
+            Self::Unavailable(e) => write!(f, "key-value store is unavailable: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e.as_ref()),
+            Self::Unavailable(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KvError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(Arc::new(e))
+    }
+}
+
+impl From<AskError> for KvError {
+    fn from(e: AskError) -> Self {
+        Self::Unavailable(e)
+    }
+}
+
+/// 順序付きのキーと値の集合
+///
+/// 操作を適用するたびにシーケンス番号を1つ進めて、書き込んだキーのバージョンにする。
+/// 同じ操作を同じ順序で適用すれば、同じデータになる。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Data {
+    entries: BTreeMap<String, Entry>,
+    /// 有効期限とキーの組
+    expiry: BTreeSet<(u64, String)>,
+    /// 最後に適用した操作のシーケンス番号
+    seq: u64,
+}
+
+impl Data {
+    /// 最後に適用した操作のシーケンス番号を返す。
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// 有効期限が切れたキーを含めた、キーの数を返す。
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 有効期限が切れたキーを含めて、キーの順に返す。
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.entries.iter()
+    }
+
+    /// `now`の時点で有効なキーの値を返す。
+    pub fn get(&self, key: &str, now: u64) -> Option<&Entry> {
+        self.entries.get(key).filter(|entry| !entry.is_expired(now))
+    }
+
+    /// キーの現在のバージョンを返す（存在しない場合は0）。
+    pub fn version(&self, key: &str, now: u64) -> u64 {
+        self.get(key, now).map_or(0, |entry| entry.version)
+    }
+
+    /// 範囲に含まれる有効なキーと値を、キーの順に最大`limit`個返す。
+    pub fn scan(&self, range: &ScanRange, limit: usize, now: u64) -> Vec<(String, Entry)> {
+        let entries: Box<dyn Iterator<Item = (&String, &Entry)>> = match range {
+            ScanRange::Prefix(prefix) => Box::new(
+                self.entries
+                    .range::<String, _>((Bound::Included(prefix), Bound::Unbounded))
+                    .take_while(|(key, _)| key.starts_with(prefix.as_str())),
+            ),
+            ScanRange::Range(start, end) if is_valid_range(start, end) => {
+                Box::new(self.entries.range((start.clone(), end.clone())))
+            }
+            ScanRange::Range(..) => Box::new(std::iter::empty()),
+        };
+        entries
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .take(limit)
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect()
+    }
+
+    /// `now`の時点で有効期限が切れているキーを返す。
+    pub fn expired(&self, now: u64) -> Vec<String> {
+        self.expiry
+            .iter()
+            .take_while(|(expires_at, _)| *expires_at <= now)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// 操作を適用して、その操作のシーケンス番号を返す。
+    pub fn apply(&mut self, operation: Operation) -> u64 {
+        self.replay(self.seq + 1, operation);
+        self.seq
+    }
+
+    /// シーケンス番号が`seq`の操作を適用する。
+    fn replay(&mut self, seq: u64, operation: Operation) {
+        self.apply_at(seq, operation);
+        self.seq = seq;
+    }
+
+    fn apply_at(&mut self, seq: u64, operation: Operation) {
+        match operation {
+            Operation::Set {
+                key,
+                value,
+                expires_at,
+            } => {
+                self.remove(&key);
+                if let Some(expires_at) = expires_at {
+                    self.expiry.insert((expires_at, key.clone()));
+                }
+                let entry = Entry {
+                    value,
+                    version: seq,
+                    expires_at,
+                };
+                self.entries.insert(key, entry);
+            }
+            Operation::Delete { key } => self.remove(&key),
+            Operation::Batch(operations) => {
+                for operation in operations {
+                    self.apply_at(seq, operation);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(Entry {
+            expires_at: Some(expires_at),
+            ..
+        }) = self.entries.remove(key)
+        {
+            self.expiry.remove(&(expires_at, key.to_string()));
+        }
+    }
+
+    fn insert_entry(&mut self, key: String, entry: Entry) {
+        if let Some(expires_at) = entry.expires_at {
+            self.expiry.insert((expires_at, key.clone()));
+        }
+        self.entries.insert(key, entry);
+    }
+}
+
+/// `BTreeMap::range`がパニックしない範囲の場合に`true`を返す。
+fn is_valid_range(start: &Bound<String>, end: &Bound<String>) -> bool {
+    match (start, end) {
+        (Bound::Excluded(start), Bound::Excluded(end)) => start < end,
+        (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) => start <= end,
+        _ => true,
+    }
+}
+
+/// 先行書き込みログとスナップショットで永続化するキーバリューストア
+///
+/// 操作はメモリ上のデータに直ちに反映され、ログのバッファに追記される。
+/// [`Store::sync`]を呼び出すまで、追記した操作はファイルに書き込まれない。
+/// 複数の操作をまとめて同期することで、`fsync`の回数を減らせる。
+///
+/// ログの大きさがしきい値を超えると、同期したときにスナップショットを作成してログを空にする。
+/// スナップショットは一時ファイルに書き込んだ後に名前を変更するため、書き込み中にクラッシュしても壊れない。
+/// 開いたときは、スナップショットを読み込んだ後に、ログに記録されている操作を再生する。
+/// ログの末尾が書き込み途中で壊れている場合は、壊れている部分を切り詰める。
+///
+/// ファイルを同期的に操作するため、非同期コンテキストからはブロッキング処理として呼び出す。
+#[derive(Debug)]
+pub struct Store {
+    dir: PathBuf,
+    data: Data,
+    wal: Wal,
+    snapshot_threshold: u64,
+}
+
+impl Store {
+    /// ディレクトリに保存されているストアを開く。
+    ///
+    /// ディレクトリが存在しない場合は作成する。
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut data = snapshot::read(&dir)?.unwrap_or_default();
+        let (wal, records) = Wal::open(&dir)?;
+        for (seq, operation) in records {
+            // スナップショットを作成した後、ログを空にする前にクラッシュした場合は、
+            // スナップショットに含まれている操作がログに残っている
+            if seq > data.seq {
+                data.replay(seq, operation);
+            }
+        }
+        Ok(Self {
+            dir,
+            data,
+            wal,
+            snapshot_threshold: DEFAULT_SNAPSHOT_THRESHOLD,
+        })
+    }
+
+    /// スナップショットを作成するログの大きさを指定する。
+    pub fn with_snapshot_threshold(mut self, bytes: u64) -> Self {
+        self.snapshot_threshold = bytes;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 有効期限を考慮せずに、キーの値を返す。
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.data.entries.get(key)
+    }
+
+    /// すべてのデータを返す。
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    /// 最後に追記した操作のシーケンス番号を返す。
+    pub fn seq(&self) -> u64 {
+        self.data.seq
+    }
+
+    /// 操作をデータに反映して、ログのバッファに追記し、その操作のシーケンス番号を返す。
+    ///
+    /// 追記した操作は[`Store::sync`]を呼び出すまで永続化されない。
+    pub fn apply(&mut self, operation: Operation) -> u64 {
+        self.wal.append(self.data.seq + 1, &operation);
+        self.data.apply(operation)
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) -> u64 {
+        self.apply(Operation::Set {
+            key: key.into(),
+            value: value.into(),
+            expires_at: None,
+        })
+    }
+
+    pub fn delete(&mut self, key: impl Into<String>) -> u64 {
+        self.apply(Operation::Delete { key: key.into() })
+    }
+
+    /// ログのバッファをファイルに書き込んで、`fsync`する。
+    ///
+    /// ログの大きさがしきい値を超えた場合は、続けてスナップショットを作成する。
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync()?;
+        if self.wal.len() >= self.snapshot_threshold {
+            self.snapshot()?;
+        }
+        Ok(())
+    }
+
+    /// ログを同期した後に、現在のデータのスナップショットを作成して、ログを空にする。
+    pub fn snapshot(&mut self) -> io::Result<()> {
+        self.wal.sync()?;
+        snapshot::write(&self.dir, &self.data)?;
+        self.wal.reset()
+    }
+
+    /// データを置き換えて、そのスナップショットを作成し、ログを空にする。
+    ///
+    /// 同期していない操作は破棄する。レプリカがプライマリのスナップショットを受け取ったときに使用する。
+    pub fn restore(&mut self, data: Data) -> io::Result<()> {
+        snapshot::write(&self.dir, &data)?;
+        self.wal.reset()?;
+        self.data = data;
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::{path::PathBuf, time::Duration};
+
+use tokio::sync::mpsc;
+
+use super::{
+    Data, Entry, KvError, Operation, ScanRange,
+    key_value::{KeyValueMessage, WatchEvent, Write},
+    replication::Subscription,
+    router::{Router, RoutingMessage},
+};
+use crate::actor::{
+    self, Addr, Reply,
+    system::{ActorSystem, RegistryError},
+};
+
+/// アクターの応答を待つ既定の時間
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// シャードの追加と削除を待つ時間
+///
+/// すべてのキーを移行するまで応答しないため、長めにする。
+const MIGRATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// レプリカがスナップショットを受け取ったときに、データの置き換えを待つ時間
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// アクターシステムに登録されたルーターアクターを介して、キーバリューストアを操作するクライアント
+///
+/// 書き込みは、操作が永続化された後に、書き込んだキーのバージョンを返す。
+#[derive(Clone)]
+pub struct Client {
+    router: Addr<Router>,
+    timeout: Duration,
+}
+
+impl Client {
+    /// `dir`に永続化するキーバリューストアを起動して、ルーターアクターをアクターシステムに登録する。
+    pub async fn start(
+        system: &ActorSystem,
+        dir: impl Into<PathBuf>,
+    ) -> Result<Self, RegistryError> {
+        Self::start_with(system, Router::new(dir)).await
+    }
+
+    /// 設定したルーターアクターでキーバリューストアを起動する。
+    ///
+    /// シャードは、ルーターアクターが起動したときに起動する。
+    pub async fn start_with(system: &ActorSystem, router: Router) -> Result<Self, RegistryError> {
+        let router = actor::spawn(router);
+        if let Err(e) = system.register_type(&router) {
+            router.stop().await;
+            router.stopped().await;
+            return Err(e);
+        }
+        Ok(Self {
+            router,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// アクターシステムに登録されているキーバリューストアに接続する。
+    pub fn connect(system: &ActorSystem) -> Result<Self, RegistryError> {
+        Ok(Self {
+            router: system.lookup_type::<Router>()?,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// アクターの応答を待つ時間を指定する。
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn ask<R>(&self, f: impl FnOnce(Reply<R>) -> KeyValueMessage) -> Result<R, KvError> {
+        self.ask_within(self.timeout, f).await
+    }
+
+    async fn ask_within<R>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(Reply<R>) -> KeyValueMessage,
+    ) -> Result<R, KvError> {
+        Ok(self
+            .router
+            .ask(|response| RoutingMessage::KeyValue(f(response)), timeout)
+            .await?)
+    }
+
+    pub async fn get(&self, key: impl Into<String>) -> Result<Option<Entry>, KvError> {
+        let key = key.into();
+        self.ask(|response| KeyValueMessage::Get { key, response })
+            .await
+    }
+
+    pub async fn set(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> Result<u64, KvError> {
+        self.set_inner(key.into(), value.into(), None).await
+    }
+
+    /// `ttl`が経過すると削除されるキーを書き込む。
+    pub async fn set_with_ttl(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+        ttl: Duration,
+    ) -> Result<u64, KvError> {
+        self.set_inner(key.into(), value.into(), Some(ttl)).await
+    }
+
+    async fn set_inner(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<u64, KvError> {
+        self.ask(|response| KeyValueMessage::Set {
+            key,
+            value,
+            ttl,
+            response,
+        })
+        .await?
+    }
+
+    /// キーのバージョンが`expected_version`と一致する場合に書き込む。
+    ///
+    /// キーが存在しないことを期待する場合は、`expected_version`に0を指定する。
+    pub async fn compare_and_swap(
+        &self,
+        key: impl Into<String>,
+        expected_version: u64,
+        value: impl Into<Vec<u8>>,
+    ) -> Result<u64, KvError> {
+        let key = key.into();
+        let value = value.into();
+        self.ask(|response| KeyValueMessage::CompareAndSwap {
+            key,
+            expected_version,
+            value,
+            ttl: None,
+            response,
+        })
+        .await?
+    }
+
+    pub async fn delete(&self, key: impl Into<String>) -> Result<u64, KvError> {
+        let key = key.into();
+        self.ask(|response| KeyValueMessage::Delete { key, response })
+            .await?
+    }
+
+    /// 範囲に含まれるキーと値を、キーの順に最大`limit`個返す。
+    pub async fn scan(
+        &self,
+        range: ScanRange,
+        limit: usize,
+    ) -> Result<Vec<(String, Entry)>, KvError> {
+        self.ask(|response| KeyValueMessage::Scan {
+            range,
+            limit,
+            response,
+        })
+        .await
+    }
+
+    /// 指定した文字列で始まるキーと値を、キーの順にすべて返す。
+    pub async fn scan_prefix(
+        &self,
+        prefix: impl Into<String>,
+    ) -> Result<Vec<(String, Entry)>, KvError> {
+        self.scan(ScanRange::Prefix(prefix.into()), usize::MAX)
+            .await
+    }
+
+    /// 複数の書き込みを、まとめて1つの操作として適用する。
+    pub async fn batch(&self, writes: Vec<Write>) -> Result<u64, KvError> {
+        self.ask(|response| KeyValueMessage::Batch { writes, response })
+            .await?
+    }
+
+    /// 指定した文字列で始まるキーの変更を監視する。
+    ///
+    /// 受信側を破棄すると監視を止める。
+    pub async fn watch(
+        &self,
+        prefix: impl Into<String>,
+    ) -> Result<mpsc::UnboundedReceiver<WatchEvent>, KvError> {
+        let prefix = prefix.into();
+        self.ask(|response| KeyValueMessage::Watch { prefix, response })
+            .await
+    }
+
+    /// シャードを追加して、追加したシャードに割り当てられるキーを移行する。
+    ///
+    /// 追加したシャードのIDを返す。
+    pub async fn add_shard(&self) -> Result<u32, KvError> {
+        self.router
+            .ask(RoutingMessage::AddShard, MIGRATION_TIMEOUT)
+            .await?
+    }
+
+    /// シャードのキーを他のシャードに移行して、シャードを削除する。
+    pub async fn remove_shard(&self, shard: u32) -> Result<(), KvError> {
+        self.router
+            .ask(
+                |response| RoutingMessage::RemoveShard { shard, response },
+                MIGRATION_TIMEOUT,
+            )
+            .await?
+    }
+
+    /// シャードのIDを昇順に返す。
+    pub async fn shards(&self) -> Result<Vec<u32>, KvError> {
+        Ok(self
+            .router
+            .ask(RoutingMessage::Shards, self.timeout)
+            .await?)
+    }
+
+    /// レプリカを昇格して、書き込みを受け付けるようにする。
+    ///
+    /// 昇格した時点のバージョン（最後に適用した操作のシーケンス番号）を返す。
+    /// 昇格したストアは、プライマリから操作を受け取らなくなる。
+    pub async fn promote(&self) -> Result<u64, KvError> {
+        self.ask(KeyValueMessage::Promote).await?
+    }
+
+    pub(super) async fn subscribe(
+        &self,
+        history: Option<u64>,
+        seq: u64,
+    ) -> Result<Subscription, KvError> {
+        self.ask(|response| KeyValueMessage::Subscribe {
+            history,
+            seq,
+            response,
+        })
+        .await?
+    }
+
+    pub(super) async fn replicate(&self, entries: Vec<(u64, Operation)>) -> Result<u64, KvError> {
+        self.ask(|response| KeyValueMessage::Replicate { entries, response })
+            .await?
+    }
+
+    pub(super) async fn restore(&self, data: Data) -> Result<(), KvError> {
+        self.ask_within(RESTORE_TIMEOUT, |response| KeyValueMessage::Restore {
+            data,
+            response,
+        })
+        .await?
+    }
+
+    /// ルーターアクター、キーバリューストアアクター、永続用ファイルライタアクターの順に停止させて、
+    /// 同期を待っている操作を永続化する。
+    pub async fn shutdown(&self) {
+        self.router.stop().await;
+        self.router.stopped().await;
+    }
+}
@@ -0,0 +1,487 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use super::{
+    Data, Entry, KvError, Operation, ScanRange, now_millis,
+    replication::Subscription,
+    writer::{Ack, WriterActor, WriterLogMessage},
+};
+use crate::actor::{Actor, Addr, AskError, Context, Reply};
+
+/// 有効期限が切れたキーを削除する既定の間隔
+const DEFAULT_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 永続化されているデータの読み込みを待つ時間
+const LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// [`KeyValueActor`]が受け取るメッセージ
+///
+/// 書き込みの応答は、操作が永続化された後に送信され、操作によって書き込まれたキーのバージョンを返す。
+pub enum KeyValueMessage {
+    Get {
+        key: String,
+        response: Reply<Option<Entry>>,
+    },
+    /// 範囲に含まれるキーと値を、キーの順に最大`limit`個返す。
+    Scan {
+        range: ScanRange,
+        limit: usize,
+        response: Reply<Vec<(String, Entry)>>,
+    },
+    /// キーに値を書き込む。
+    ///
+    /// `ttl`を指定すると、その時間が経過した後にキーを削除する。
+    Set {
+        key: String,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+        response: Reply<Result<u64, KvError>>,
+    },
+    /// キーのバージョンが`expected_version`と一致する場合に、キーに値を書き込む。
+    ///
+    /// キーが存在しないことを期待する場合は、`expected_version`に0を指定する。
+    CompareAndSwap {
+        key: String,
+        expected_version: u64,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+        response: Reply<Result<u64, KvError>>,
+    },
+    Delete {
+        key: String,
+        response: Reply<Result<u64, KvError>>,
+    },
+    /// 複数の書き込みを、まとめて1つの操作として適用する。
+    Batch {
+        writes: Vec<Write>,
+        response: Reply<Result<u64, KvError>>,
+    },
+    /// 指定した文字列で始まるキーの変更を通知する受信側を返す。
+    Watch {
+        prefix: String,
+        response: Reply<mpsc::UnboundedReceiver<WatchEvent>>,
+    },
+    /// 有効期限が切れたキーを削除する。
+    Expire,
+    /// 永続化した操作をレプリカに送信する購読を開始する。
+    Subscribe {
+        history: Option<u64>,
+        seq: u64,
+        response: Reply<Result<Subscription, KvError>>,
+    },
+    /// プライマリから受け取った操作を、シーケンス番号の順に適用する。
+    ///
+    /// すべての操作を永続化した後に、最後の操作のシーケンス番号を返す。
+    Replicate {
+        entries: Vec<(u64, Operation)>,
+        response: Reply<Result<u64, KvError>>,
+    },
+    /// プライマリから受け取ったデータで置き換える。
+    ///
+    /// 置き換えたキーの変更は通知しない。
+    Restore {
+        data: Data,
+        response: Reply<Result<(), KvError>>,
+    },
+    /// レプリカを昇格して書き込みを受け付けるようにし、最後に適用した操作のシーケンス番号を返す。
+    Promote(Reply<Result<u64, KvError>>),
+}
+
+impl KeyValueMessage {
+    /// 1つのキーを操作するメッセージの場合に、そのキーを返す。
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Self::Get { key, .. }
+            | Self::Set { key, .. }
+            | Self::CompareAndSwap { key, .. }
+            | Self::Delete { key, .. } => Some(key),
+            _ => None,
+        }
+    }
+}
+
+/// バッチに含める書き込み
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Write {
+    Set {
+        key: String,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        key: String,
+    },
+}
+
+impl Write {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Set { key, .. } | Self::Delete { key } => key,
+        }
+    }
+
+    fn into_operation(self, now: u64) -> Operation {
+        match self {
+            Self::Set { key, value, ttl } => Operation::Set {
+                key,
+                value,
+                expires_at: expires_at(now, ttl),
+            },
+            Self::Delete { key } => Operation::Delete { key },
+        }
+    }
+}
+
+/// 監視しているキーの変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    Set {
+        key: String,
+        value: Vec<u8>,
+        version: u64,
+    },
+    Deleted {
+        key: String,
+        version: u64,
+    },
+    /// 有効期限が切れたため削除された。
+    Expired {
+        key: String,
+        version: u64,
+    },
+}
+
+impl WatchEvent {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Set { key, .. } | Self::Deleted { key, .. } | Self::Expired { key, .. } => key,
+        }
+    }
+}
+
+/// レプリケーションのメッセージに、エラーを応答する。
+pub(super) fn reject_replication(message: KeyValueMessage, error: KvError) {
+    match message {
+        KeyValueMessage::Subscribe { response, .. } => {
+            let _ = response.send(Err(error));
+        }
+        KeyValueMessage::Replicate { response, .. } => {
+            let _ = response.send(Err(error));
+        }
+        KeyValueMessage::Restore { response, .. } => {
+            let _ = response.send(Err(error));
+        }
+        KeyValueMessage::Promote(response) => {
+            let _ = response.send(Err(error));
+        }
+        _ => {}
+    }
+}
+
+fn expires_at(now: u64, ttl: Option<Duration>) -> Option<u64> {
+    ttl.map(|ttl| now.saturating_add(ttl.as_millis() as u64))
+}
+
+/// キーバリューストアアクター
+///
+/// 起動したときに永続用ファイルライタアクターからデータを読み込み、以降は同じ操作をメモリ上のデータと
+/// 永続用ファイルライタアクターに適用する。
+/// 両者のシーケンス番号が一致するため、永続用ファイルライタアクターが応答したシーケンス番号が、
+/// 書き込んだキーのバージョンになる。
+///
+/// 操作は永続用ファイルライタアクターが受け付けた後にメモリ上のデータに適用し、監視している受信側に変更を通知する。
+/// 永続用ファイルライタアクターが停止した場合は、このアクターも停止する（停止した後に届いた書き込みにはエラーを応答する）。
+/// 停止するときは、永続用ファイルライタアクターを停止させて、同期を待っている操作を永続化する。
+///
+/// 永続用ファイルライタアクターは、同期に失敗すると異常終了して、同期していない操作を破棄する。
+/// スーパーバイザーで監視する場合は、永続用ファイルライタアクターを先に追加して`RestForOne`または`OneForAll`戦略を使い、
+/// 永続用ファイルライタアクターと一緒に再起動して、永続化されているデータを読み込み直す。
+///
+/// 読み取り専用で起動したレプリカは、プライマリから受け取った操作だけを適用する。
+/// 有効期限が切れたキーも、プライマリが削除するまで削除しない（読み込むときは存在しないものとして扱う）。
+pub struct KeyValueActor {
+    data: Data,
+    writer: Addr<WriterActor>,
+    expiry_interval: Duration,
+    watchers: Vec<(String, mpsc::UnboundedSender<WatchEvent>)>,
+    read_only: bool,
+}
+
+impl KeyValueActor {
+    pub fn new(writer: Addr<WriterActor>) -> Self {
+        Self {
+            data: Data::default(),
+            writer,
+            expiry_interval: DEFAULT_EXPIRY_INTERVAL,
+            watchers: Vec::new(),
+            read_only: false,
+        }
+    }
+
+    /// 有効期限が切れたキーを削除する間隔を指定する。
+    pub fn with_expiry_interval(mut self, interval: Duration) -> Self {
+        self.expiry_interval = interval;
+        self
+    }
+
+    /// レプリカとして、昇格するまで書き込みを受け付けない読み取り専用で起動するかどうかを指定する。
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// 書き込みを受け付けられる場合に、操作を適用する。
+    async fn write(&mut self, operation: Operation, response: Ack, ctx: &mut Context<Self>) {
+        if self.read_only {
+            let _ = response.send(Err(KvError::ReadOnly));
+            return;
+        }
+        self.apply(operation, Some(response), false, ctx).await;
+    }
+
+    /// 操作を永続用ファイルライタアクターに送信して、受け付けられた後にメモリ上のデータに適用する。
+    ///
+    /// `expired`が`true`の場合、削除したキーを有効期限切れとして通知する。
+    /// 永続用ファイルライタアクターが停止していた場合は、`response`にエラーを応答して停止し、`false`を返す。
+    async fn apply(
+        &mut self,
+        operation: Operation,
+        response: Option<Ack>,
+        expired: bool,
+        ctx: &mut Context<Self>,
+    ) -> bool {
+        let events = self.events(&operation, self.data.seq() + 1, expired);
+        // 永続用ファイルライタアクターが、操作をログに同期した後に応答する
+        let message = WriterLogMessage::Append {
+            operation: operation.clone(),
+            ack: response,
+        };
+        if let Err(e) = self.writer.send(message).await {
+            self.writer_stopped(e.into_inner(), ctx);
+            return false;
+        }
+        self.data.apply(operation);
+        self.notify(events);
+        true
+    }
+
+    /// 永続用ファイルライタアクターに届かなかったメッセージの応答先にエラーを応答して、停止する。
+    ///
+    /// 永続化できない書き込みを受け付け続けないように、このアクターも停止する。
+    fn writer_stopped(&mut self, message: WriterLogMessage, ctx: &mut Context<Self>) {
+        let error = KvError::Unavailable(AskError::Closed);
+        match message {
+            WriterLogMessage::Append { ack: Some(ack), .. } => {
+                let _ = ack.send(Err(error));
+            }
+            WriterLogMessage::Subscribe { response, .. } => {
+                let _ = response.send(Err(error));
+            }
+            WriterLogMessage::Restore { response, .. } => {
+                let _ = response.send(Err(error));
+            }
+            _ => {}
+        }
+        ctx.stop();
+    }
+
+    /// 操作を適用したときに通知する変更を返す。
+    fn events(&self, operation: &Operation, version: u64, expired: bool) -> Vec<WatchEvent> {
+        match operation {
+            Operation::Set { key, value, .. } => vec![WatchEvent::Set {
+                key: key.clone(),
+                value: value.clone(),
+                version,
+            }],
+            // 存在しないキーの削除は通知しない
+            Operation::Delete { key } if !self.data.entries.contains_key(key) => vec![],
+            Operation::Delete { key } if expired => vec![WatchEvent::Expired {
+                key: key.clone(),
+                version,
+            }],
+            Operation::Delete { key } => vec![WatchEvent::Deleted {
+                key: key.clone(),
+                version,
+            }],
+            Operation::Batch(operations) => operations
+                .iter()
+                .flat_map(|operation| self.events(operation, version, expired))
+                .collect(),
+        }
+    }
+
+    /// 変更を監視している受信側に通知して、閉じられた受信側を取り除く。
+    fn notify(&mut self, events: Vec<WatchEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        self.watchers.retain(|(prefix, sender)| {
+            events
+                .iter()
+                .filter(|event| event.key().starts_with(prefix.as_str()))
+                .all(|event| sender.send(event.clone()).is_ok())
+                && !sender.is_closed()
+        });
+    }
+}
+
+impl Actor for KeyValueActor {
+    type Message = KeyValueMessage;
+
+    async fn started(&mut self, ctx: &mut Context<Self>) {
+        self.data = self
+            .writer
+            .ask(WriterLogMessage::Get, LOAD_TIMEOUT)
+            .await
+            .expect("failed to load the data from the writer");
+        ctx.send_interval(self.expiry_interval, || KeyValueMessage::Expire);
+        // 永続用ファイルライタアクターが停止したら、同期されていない操作を含むデータを返し続けないように停止する
+        let writer = self.writer.clone();
+        let weak = ctx.weak_addr();
+        tokio::spawn(async move {
+            writer.stopped().await;
+            if let Some(addr) = weak.upgrade() {
+                addr.kill();
+            }
+        });
+    }
+
+    async fn handle(&mut self, message: KeyValueMessage, ctx: &mut Context<Self>) {
+        let now = now_millis();
+        match message {
+            KeyValueMessage::Get { key, response } => {
+                let _ = response.send(self.data.get(&key, now).cloned());
+            }
+            KeyValueMessage::Scan {
+                range,
+                limit,
+                response,
+            } => {
+                let _ = response.send(self.data.scan(&range, limit, now));
+            }
+            KeyValueMessage::Set {
+                key,
+                value,
+                ttl,
+                response,
+            } => {
+                let operation = Write::Set { key, value, ttl }.into_operation(now);
+                self.write(operation, response, ctx).await;
+            }
+            KeyValueMessage::CompareAndSwap {
+                key,
+                expected_version,
+                value,
+                ttl,
+                response,
+            } => {
+                let actual = self.data.version(&key, now);
+                if actual != expected_version && !self.read_only {
+                    let _ = response.send(Err(KvError::Conflict {
+                        key,
+                        expected: expected_version,
+                        actual,
+                    }));
+                    return;
+                }
+                let operation = Write::Set { key, value, ttl }.into_operation(now);
+                self.write(operation, response, ctx).await;
+            }
+            KeyValueMessage::Delete { key, response } => {
+                self.write(Operation::Delete { key }, response, ctx).await;
+            }
+            KeyValueMessage::Batch { writes, response } => {
+                let operations = writes
+                    .into_iter()
+                    .map(|write| write.into_operation(now))
+                    .collect();
+                self.write(Operation::Batch(operations), response, ctx)
+                    .await;
+            }
+            KeyValueMessage::Watch { prefix, response } => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                if response.send(receiver).is_ok() {
+                    self.watchers.push((prefix, sender));
+                }
+            }
+            KeyValueMessage::Expire if self.read_only => {}
+            KeyValueMessage::Expire => {
+                let operations: Vec<_> = self
+                    .data
+                    .expired(now)
+                    .into_iter()
+                    .map(|key| Operation::Delete { key })
+                    .collect();
+                if !operations.is_empty() {
+                    // 削除も永続化して、開き直したときに期限切れのキーが残らないようにする
+                    self.apply(Operation::Batch(operations), None, true, ctx)
+                        .await;
+                }
+            }
+            KeyValueMessage::Subscribe {
+                history,
+                seq,
+                response,
+            } => {
+                // 送信した操作をすべて適用した後に、永続用ファイルライタアクターが購読を開始する
+                let message = WriterLogMessage::Subscribe {
+                    history,
+                    seq,
+                    response,
+                };
+                if let Err(e) = self.writer.send(message).await {
+                    self.writer_stopped(e.into_inner(), ctx);
+                }
+            }
+            KeyValueMessage::Replicate { .. } | KeyValueMessage::Restore { .. }
+                if !self.read_only =>
+            {
+                reject_replication(message, KvError::NotReplica);
+            }
+            KeyValueMessage::Replicate { entries, response } => {
+                let Some(last) = entries.len().checked_sub(1) else {
+                    let _ = response.send(Ok(self.data.seq()));
+                    return;
+                };
+                let mut response = Some(response);
+                for (i, (seq, operation)) in entries.into_iter().enumerate() {
+                    let expected = self.data.seq() + 1;
+                    if seq != expected {
+                        if let Some(response) = response {
+                            let _ = response.send(Err(KvError::OutOfSequence {
+                                expected,
+                                actual: seq,
+                            }));
+                        }
+                        return;
+                    }
+                    // 最後の操作を永続化したときに応答する
+                    let ack = if i == last { response.take() } else { None };
+                    if !self.apply(operation, ack, false, ctx).await {
+                        return;
+                    }
+                }
+            }
+            KeyValueMessage::Restore { data, response } => {
+                let message = WriterLogMessage::Restore {
+                    data: data.clone(),
+                    response,
+                };
+                match self.writer.send(message).await {
+                    Ok(()) => self.data = data,
+                    Err(e) => self.writer_stopped(e.into_inner(), ctx),
+                }
+            }
+            KeyValueMessage::Promote(response) => {
+                self.read_only = false;
+                let _ = response.send(Ok(self.data.seq()));
+            }
+        }
+    }
+
+    async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+        self.writer.stop().await;
+        self.writer.stopped().await;
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    io,
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_runtime::{receiver::TcpReceiver, sender::TcpSender};
+use data_layer::kv::{Entry, Request, Response};
+
+use super::server::decode;
+
+/// [`Server`](super::server::Server)に接続するクライアント
+///
+/// リクエストごとに接続して、リクエストを送信した後に書き込み側を閉じ、
+/// サーバーが接続を閉じるまでレスポンスを読み込む。
+/// async_runtimeのTCPフューチャーを使用するため、どのエグゼキューターでも実行できる。
+#[derive(Debug, Clone)]
+pub struct RemoteClient {
+    addr: String,
+}
+
+impl RemoteClient {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into() }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    async fn request(&self, request: Request) -> io::Result<Response> {
+        let stream = Arc::new(Mutex::new(TcpStream::connect(&self.addr)?));
+        TcpSender {
+            stream: stream.clone(),
+            buffer: request.serialize()?,
+        }
+        .await?;
+        // 書き込み側を閉じて、リクエストの終わりをサーバーに知らせる
+        stream
+            .lock()
+            .map_err(|_| io::Error::other("stream lock is poisoned"))?
+            .shutdown(Shutdown::Write)?;
+        let response = TcpReceiver {
+            stream,
+            buffer: vec![],
+        }
+        .await?;
+        match decode(&response, Response::deserialize)? {
+            Response::Error(message) => Err(io::Error::other(message)),
+            response => Ok(response),
+        }
+    }
+
+    pub async fn get(&self, key: impl Into<String>) -> io::Result<Option<Entry>> {
+        match self.request(Request::Get { key: key.into() }).await? {
+            Response::Value(entry) => Ok(entry),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// キーに値を書き込んで、書き込んだバージョンを返す。
+    pub async fn set(&self, key: impl Into<String>, value: impl Into<Vec<u8>>) -> io::Result<u64> {
+        self.write(Request::Set {
+            key: key.into(),
+            value: value.into(),
+            ttl_millis: None,
+        })
+        .await
+    }
+
+    /// `ttl`が経過すると削除されるキーを書き込む。
+    pub async fn set_with_ttl(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+        ttl: Duration,
+    ) -> io::Result<u64> {
+        self.write(Request::Set {
+            key: key.into(),
+            value: value.into(),
+            // 0は有効期限がないことを表すため、1ミリ秒以上にする
+            ttl_millis: Some((ttl.as_millis() as u64).max(1)),
+        })
+        .await
+    }
+
+    pub async fn delete(&self, key: impl Into<String>) -> io::Result<u64> {
+        self.write(Request::Delete { key: key.into() }).await
+    }
+
+    /// 指定した文字列で始まるキーを、キーの順に最大`limit`個返す。
+    pub async fn scan(&self, prefix: impl Into<String>, limit: u32) -> io::Result<Vec<Entry>> {
+        let request = Request::Scan {
+            prefix: prefix.into(),
+            limit,
+        };
+        match self.request(request).await? {
+            Response::Entries(entries) => Ok(entries),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// レプリカを昇格して、昇格した時点のバージョンを返す。
+    pub async fn promote(&self) -> io::Result<u64> {
+        self.write(Request::Promote).await
+    }
+
+    async fn write(&self, request: Request) -> io::Result<u64> {
+        match self.request(request).await? {
+            Response::Written { version } => Ok(version),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected response: {response:?}"),
+    )
+}
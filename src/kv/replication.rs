@@ -0,0 +1,580 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    net::SocketAddr,
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    time::Instant,
+};
+use tokio_util::task::AbortOnDropHandle;
+
+use super::{
+    Data, KvError, Operation,
+    client::Client,
+    snapshot,
+    wal::{Decoder, crc32, decode_operation, encode_operation, put_bytes, put_u32, put_u64},
+    writer::{Ack, WriterActor, WriterLogMessage},
+};
+use crate::actor::WeakAddr;
+
+/// 接続し直したレプリカに続きから送信するために保持する、同期済みの操作の数
+const LOG_TAIL: usize = 10_000;
+
+/// レプリカに送信していない操作を保持する数
+///
+/// 送信が追いつかないレプリカは切断して、接続し直させる。
+const SUBSCRIBER_CAPACITY: usize = 4_096;
+
+/// レプリカが1回にまとめて適用する操作の最大数
+const MAX_BATCH: usize = 256;
+
+/// レプリカがプライマリに接続し直すまで待つ既定の時間
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 受け付けるフレームの最大の大きさ
+const MAX_FRAME_LEN: usize = 1 << 30;
+
+const TAG_HELLO: u8 = 1;
+const TAG_SNAPSHOT: u8 = 2;
+const TAG_CONTINUE: u8 = 3;
+const TAG_ENTRY: u8 = 4;
+const TAG_ACK: u8 = 5;
+
+/// 書き込みに応答するまでに、レプリカの確認応答を待つかどうか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplicationMode {
+    /// ローカルのログを同期した後に応答して、レプリカには非同期に送信する。
+    #[default]
+    Async,
+    /// `replicas`個のレプリカが永続化を確認応答した後に応答する。
+    ///
+    /// `timeout`以内に確認応答がなかった場合は、[`KvError::NotReplicated`]を返す。
+    /// 操作はプライマリに永続化されているため、取り消されない。
+    Sync { replicas: usize, timeout: Duration },
+}
+
+/// レプリカへの送信を開始した購読
+///
+/// 送信する最初のデータと、その後に同期した操作を受け取る受信側を持つ。
+pub struct Subscription {
+    id: u64,
+    history: u64,
+    start: Start,
+    entries: mpsc::Receiver<(u64, Operation)>,
+    writer: WeakAddr<WriterActor>,
+}
+
+/// 購読を開始したときに送信するデータ
+enum Start {
+    /// レプリカが適用した操作の続きを保持していない場合に送信する、すべてのデータ
+    Snapshot(Data),
+    /// レプリカが適用した操作の続き
+    Tail(Vec<(u64, Operation)>),
+}
+
+struct Subscriber {
+    sender: mpsc::Sender<(u64, Operation)>,
+    /// 永続化を確認応答した操作のシーケンス番号
+    acked: u64,
+}
+
+/// 永続用ファイルライタアクターが、同期した操作をレプリカに送信するための状態
+///
+/// 履歴は、操作の列を識別するランダムな値である。
+/// データを置き換えると、それまでの操作の続きではなくなるため、新しい履歴にする。
+/// プロセスを起動し直した場合も新しい履歴になり、レプリカにはスナップショットを送信する。
+pub(super) struct ReplicationLog {
+    mode: ReplicationMode,
+    history: u64,
+    /// 同期した直近の操作
+    tail: VecDeque<(u64, Operation)>,
+    /// 追記したが、まだ同期していない操作
+    staged: Vec<(u64, Operation)>,
+    subscribers: BTreeMap<u64, Subscriber>,
+    next_id: u64,
+    /// レプリカの確認応答を待っている操作のシーケンス番号、応答先、期限
+    waiting: VecDeque<(u64, Ack, Instant)>,
+}
+
+impl ReplicationLog {
+    pub(super) fn new(mode: ReplicationMode) -> Self {
+        Self {
+            mode,
+            history: new_history(),
+            tail: VecDeque::new(),
+            staged: Vec::new(),
+            subscribers: BTreeMap::new(),
+            next_id: 0,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// ログに追記した操作を、同期した後に送信するために保持する。
+    pub(super) fn stage(&mut self, seq: u64, operation: Operation) {
+        self.staged.push((seq, operation));
+    }
+
+    /// 同期した操作をレプリカに送信する。
+    ///
+    /// 送信が追いつかないレプリカと、切断したレプリカは取り除く。
+    pub(super) fn ship(&mut self) {
+        for (seq, operation) in self.staged.drain(..) {
+            self.subscribers.retain(|_, subscriber| {
+                subscriber.sender.try_send((seq, operation.clone())).is_ok()
+            });
+            if self.tail.len() == LOG_TAIL {
+                self.tail.pop_front();
+            }
+            self.tail.push_back((seq, operation));
+        }
+    }
+
+    /// レプリカの購読を開始する。
+    ///
+    /// レプリカが同じ履歴の`seq`までの操作を適用していて、その続きを保持している場合は続きを、
+    /// そうでない場合は`data`を送信する。`data`には、同期したすべての操作を適用したデータを渡す。
+    pub(super) fn subscribe(
+        &mut self,
+        history: Option<u64>,
+        seq: u64,
+        data: &Data,
+        writer: WeakAddr<WriterActor>,
+    ) -> Subscription {
+        let (start, acked) = if history == Some(self.history) && self.has_tail(seq, data.seq()) {
+            let tail = self
+                .tail
+                .iter()
+                .filter(|(entry, _)| *entry > seq)
+                .cloned()
+                .collect();
+            (Start::Tail(tail), seq)
+        } else {
+            (Start::Snapshot(data.clone()), 0)
+        };
+        let (sender, entries) = mpsc::channel(SUBSCRIBER_CAPACITY);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.insert(id, Subscriber { sender, acked });
+        Subscription {
+            id,
+            history: self.history,
+            start,
+            entries,
+            writer,
+        }
+    }
+
+    /// `seq`の次から`current`までの操作を保持している場合に`true`を返す。
+    fn has_tail(&self, seq: u64, current: u64) -> bool {
+        seq == current
+            || seq < current
+                && self
+                    .tail
+                    .front()
+                    .is_some_and(|(first, _)| *first <= seq + 1)
+    }
+
+    /// 同期した操作に応答する。
+    ///
+    /// 同期レプリケーションでは、必要な数のレプリカが確認応答するまで応答を待たせる。
+    pub(super) fn wait(&mut self, seq: u64, ack: Ack, now: Instant) {
+        match self.mode {
+            ReplicationMode::Sync { replicas, timeout } if replicas > 0 => {
+                self.waiting.push_back((seq, ack, now + timeout));
+                self.release();
+            }
+            _ => {
+                let _ = ack.send(Ok(seq));
+            }
+        }
+    }
+
+    pub(super) fn acknowledge(&mut self, replica: u64, seq: u64) {
+        if let Some(subscriber) = self.subscribers.get_mut(&replica) {
+            subscriber.acked = subscriber.acked.max(seq);
+        }
+        self.release();
+    }
+
+    /// 必要な数のレプリカが確認応答した操作に応答する。
+    fn release(&mut self) {
+        let ReplicationMode::Sync { replicas, .. } = self.mode else {
+            return;
+        };
+        while let Some(&(seq, ..)) = self.waiting.front() {
+            let acked = self
+                .subscribers
+                .values()
+                .filter(|subscriber| !subscriber.sender.is_closed() && subscriber.acked >= seq)
+                .count();
+            if acked < replicas {
+                break;
+            }
+            if let Some((seq, ack, _)) = self.waiting.pop_front() {
+                let _ = ack.send(Ok(seq));
+            }
+        }
+    }
+
+    /// 確認応答を待っている最も古い操作の期限を返す。
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        self.waiting.front().map(|&(_, _, deadline)| deadline)
+    }
+
+    /// 期限が過ぎた操作に、確認応答がなかったことを応答する。
+    pub(super) fn expire(&mut self, now: Instant) {
+        while self
+            .waiting
+            .front()
+            .is_some_and(|&(_, _, deadline)| deadline <= now)
+        {
+            if let Some((seq, ack, _)) = self.waiting.pop_front() {
+                let _ = ack.send(Err(KvError::NotReplicated(seq)));
+            }
+        }
+    }
+
+    /// データを置き換えたときに、新しい履歴にして、購読しているレプリカを切断する。
+    pub(super) fn reset(&mut self) {
+        self.history = new_history();
+        self.tail.clear();
+        self.staged.clear();
+        self.subscribers.clear();
+    }
+}
+
+fn new_history() -> u64 {
+    // 0は、レプリカが履歴を持っていないことを表す
+    rand::random::<u64>().max(1)
+}
+
+/// プライマリとレプリカの間で送受信するフレーム
+///
+/// フレームは、ペイロードの長さ（u32）、ペイロードのCRC32（u32）、ペイロードの順に並ぶ。
+#[derive(Debug)]
+enum Frame {
+    /// レプリカが最後に適用した操作の履歴とシーケンス番号
+    Hello {
+        history: Option<u64>,
+        seq: u64,
+    },
+    /// すべてのデータ。続けて、その後に同期した操作を送信する。
+    Snapshot {
+        history: u64,
+        data: Data,
+    },
+    /// レプリカが適用した操作の続きを送信する。
+    Continue {
+        history: u64,
+    },
+    Entry {
+        seq: u64,
+        operation: Operation,
+    },
+    /// レプリカが`seq`までの操作を永続化した。
+    Ack {
+        seq: u64,
+    },
+}
+
+impl Frame {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Hello { history, seq } => {
+                buf.push(TAG_HELLO);
+                put_u64(&mut buf, history.unwrap_or(0));
+                put_u64(&mut buf, *seq);
+            }
+            Self::Snapshot { history, data } => {
+                buf.push(TAG_SNAPSHOT);
+                put_u64(&mut buf, *history);
+                put_bytes(&mut buf, &snapshot::encode(data));
+            }
+            Self::Continue { history } => {
+                buf.push(TAG_CONTINUE);
+                put_u64(&mut buf, *history);
+            }
+            Self::Entry { seq, operation } => {
+                buf.push(TAG_ENTRY);
+                put_u64(&mut buf, *seq);
+                encode_operation(&mut buf, operation);
+            }
+            Self::Ack { seq } => {
+                buf.push(TAG_ACK);
+                put_u64(&mut buf, *seq);
+            }
+        }
+        buf
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        let mut decoder = Decoder::new(payload);
+        let frame = match decoder.u8()? {
+            TAG_HELLO => Self::Hello {
+                history: Some(decoder.u64()?).filter(|&history| history != 0),
+                seq: decoder.u64()?,
+            },
+            TAG_SNAPSHOT => Self::Snapshot {
+                history: decoder.u64()?,
+                data: snapshot::decode(decoder.bytes()?)?,
+            },
+            TAG_CONTINUE => Self::Continue {
+                history: decoder.u64()?,
+            },
+            TAG_ENTRY => Self::Entry {
+                seq: decoder.u64()?,
+                operation: decode_operation(&mut decoder)?,
+            },
+            TAG_ACK => Self::Ack {
+                seq: decoder.u64()?,
+            },
+            _ => return None,
+        };
+        decoder.is_empty().then_some(frame)
+    }
+}
+
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Frame> {
+    let len = reader.read_u32_le().await? as usize;
+    let crc = reader.read_u32_le().await?;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid_data("replication frame is too large"));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    if crc32(&payload) != crc {
+        return Err(invalid_data("replication frame is corrupted"));
+    }
+    Frame::decode(&payload).ok_or_else(|| invalid_data("invalid replication frame"))
+}
+
+async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), frame: &Frame) -> io::Result<()> {
+    let payload = frame.encode();
+    let mut buf = Vec::with_capacity(8 + payload.len());
+    put_u32(&mut buf, payload.len() as u32);
+    put_u32(&mut buf, crc32(&payload));
+    buf.extend_from_slice(&payload);
+    writer.write_all(&buf).await
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// レプリカの接続を受け付けて、ストアの操作を送信するプライマリ
+///
+/// レプリカは、最後に適用した操作の履歴とシーケンス番号を送信する。
+/// 同じ履歴の続きを保持している場合はその続きを、そうでない場合はスナップショットを送信した後に、
+/// 永続用ファイルライタアクターがログを同期した操作を順に送信する。
+///
+/// レプリケーションは、シャードが1つのストアだけで使用できる。
+pub struct Primary {
+    listener: TcpListener,
+    client: Client,
+}
+
+impl Primary {
+    /// アドレスにバインドしたプライマリを作成する。
+    pub async fn bind(addr: impl ToSocketAddrs, client: Client) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            client,
+        })
+    }
+
+    /// バインドしたアドレスを返す。
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// レプリカの接続を受け付けて、接続ごとのタスクで操作を送信する。
+    pub async fn run(self) -> io::Result<()> {
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("Connection failed: {e}");
+                    continue;
+                }
+            };
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                match serve(stream, client).await {
+                    // レプリカが切断した
+                    Ok(()) | Err(KvError::Io(_)) => {}
+                    Err(e) => eprintln!("Replication to {peer} stopped: {e}"),
+                }
+            });
+        }
+    }
+}
+
+/// レプリカに、購読を開始した時点のデータとその後の操作を送信する。
+async fn serve(stream: TcpStream, client: Client) -> Result<(), KvError> {
+    stream.set_nodelay(true)?;
+    let (mut reader, writer) = stream.into_split();
+    let mut writer = BufWriter::new(writer);
+    let Frame::Hello { history, seq } = read_frame(&mut reader).await? else {
+        return Err(invalid_data("expected a hello frame").into());
+    };
+    let Subscription {
+        id,
+        history,
+        start,
+        mut entries,
+        writer: log,
+    } = client.subscribe(history, seq).await?;
+    match start {
+        Start::Snapshot(data) => {
+            write_frame(&mut writer, &Frame::Snapshot { history, data }).await?
+        }
+        Start::Tail(tail) => {
+            write_frame(&mut writer, &Frame::Continue { history }).await?;
+            for (seq, operation) in tail {
+                write_frame(&mut writer, &Frame::Entry { seq, operation }).await?;
+            }
+        }
+    }
+    writer.flush().await?;
+
+    // 確認応答は別のタスクで読み込んで、永続用ファイルライタアクターに送信する
+    let mut acks = AbortOnDropHandle::new(tokio::spawn(async move {
+        loop {
+            let Frame::Ack { seq } = read_frame(&mut reader).await? else {
+                return Err(invalid_data("expected an ack frame"));
+            };
+            let Some(addr) = log.upgrade() else {
+                return Ok(());
+            };
+            let message = WriterLogMessage::Acknowledge { replica: id, seq };
+            if addr.send(message).await.is_err() {
+                return Ok(());
+            }
+        }
+    }));
+    loop {
+        tokio::select! {
+            entry = entries.recv() => {
+                // 送信が追いつかなかった場合と、データを置き換えた場合は、切断して接続し直させる
+                let Some((seq, operation)) = entry else {
+                    return Ok(());
+                };
+                write_frame(&mut writer, &Frame::Entry { seq, operation }).await?;
+                while let Ok((seq, operation)) = entries.try_recv() {
+                    write_frame(&mut writer, &Frame::Entry { seq, operation }).await?;
+                }
+                writer.flush().await?;
+            }
+            result = &mut acks => return Ok(result.map_err(io::Error::other)??),
+        }
+    }
+}
+
+/// プライマリに接続して、ストアに操作を適用するレプリカ
+///
+/// ストアは、読み取り専用のキーバリューストアアクター
+/// （[`KeyValueActor::with_read_only`](super::key_value::KeyValueActor::with_read_only)）で起動する。
+/// 受け取った操作はまとめて適用して、永続化した後にプライマリに確認応答する。
+/// 接続が切れた場合は、間隔をあけて接続し直し、最後に適用した操作の続きから受け取る。
+///
+/// ストアを昇格すると、レプリケーションを終了する。
+pub struct Replica {
+    primary: String,
+    client: Client,
+    retry_interval: Duration,
+}
+
+impl Replica {
+    pub fn new(primary: impl Into<String>, client: Client) -> Self {
+        Self {
+            primary: primary.into(),
+            client,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+        }
+    }
+
+    /// 接続が切れてから接続し直すまで待つ時間を指定する。
+    pub fn with_retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// ストアが昇格するまで、プライマリから受け取った操作を適用する。
+    pub async fn run(self) {
+        // 最後に適用した操作の履歴とシーケンス番号
+        let mut position = None;
+        loop {
+            match self.follow(&mut position).await {
+                Ok(()) | Err(KvError::Io(_)) => {}
+                Err(KvError::NotReplica) => return,
+                Err(e) => {
+                    // 適用した操作がわからなくなったため、スナップショットから受け取り直す
+                    position = None;
+                    eprintln!("Replication from {} failed: {e}", self.primary);
+                }
+            }
+            tokio::time::sleep(self.retry_interval).await;
+        }
+    }
+
+    async fn follow(&self, position: &mut Option<(u64, u64)>) -> Result<(), KvError> {
+        let stream = TcpStream::connect(&self.primary).await?;
+        stream.set_nodelay(true)?;
+        let (mut reader, mut writer) = stream.into_split();
+        let hello = Frame::Hello {
+            history: position.map(|(history, _)| history),
+            seq: position.map_or(0, |(_, seq)| seq),
+        };
+        write_frame(&mut writer, &hello).await?;
+        let history = match read_frame(&mut reader).await? {
+            Frame::Snapshot { history, data } => {
+                let seq = data.seq();
+                self.client.restore(data).await?;
+                *position = Some((history, seq));
+                write_frame(&mut writer, &Frame::Ack { seq }).await?;
+                history
+            }
+            Frame::Continue { history } if hello_matches(position, history) => history,
+            _ => return Err(invalid_data("expected a snapshot or continue frame").into()),
+        };
+
+        // 適用を待つ間に届いた操作をまとめて適用するため、別のタスクで読み込む
+        let (sender, mut received) = mpsc::channel(MAX_BATCH);
+        let _reading = AbortOnDropHandle::new(tokio::spawn(async move {
+            loop {
+                let entry = match read_frame(&mut reader).await {
+                    Ok(Frame::Entry { seq, operation }) => Ok((seq, operation)),
+                    Ok(_) => Err(invalid_data("expected an entry frame")),
+                    Err(e) => Err(e),
+                };
+                let failed = entry.is_err();
+                if sender.send(entry).await.is_err() || failed {
+                    return;
+                }
+            }
+        }));
+        while let Some(entry) = received.recv().await {
+            let mut entries = vec![entry?];
+            while entries.len() < MAX_BATCH
+                && let Ok(entry) = received.try_recv()
+            {
+                entries.push(entry?);
+            }
+            let seq = entries.last().map_or(0, |(seq, _)| *seq);
+            self.client.replicate(entries).await?;
+            *position = Some((history, seq));
+            write_frame(&mut writer, &Frame::Ack { seq }).await?;
+        }
+        Ok(())
+    }
+}
+
+fn hello_matches(position: &Option<(u64, u64)>, history: u64) -> bool {
+    position.is_some_and(|(current, _)| current == history)
+}
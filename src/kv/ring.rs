@@ -0,0 +1,98 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// 1つのシャードをリングに配置する点の数
+const VIRTUAL_NODES: u32 = 128;
+
+/// キーをシャードに割り当てるコンシステントハッシュのリング
+///
+/// シャードごとに複数の仮想ノードをリングに配置して、キーのハッシュ値から時計回りに最初に見つかった
+/// 仮想ノードのシャードにキーを割り当てる。
+/// シャードを追加または削除したときに、割り当てが変わるキーは追加または削除したシャードのキーだけである。
+///
+/// ハッシュ値はプロセスやビルドに依存しないため、再起動しても同じ割り当てになる。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HashRing {
+    points: BTreeMap<u64, u32>,
+    shards: BTreeSet<u32>,
+}
+
+impl HashRing {
+    pub fn new(shards: impl IntoIterator<Item = u32>) -> Self {
+        let mut ring = Self::default();
+        for shard in shards {
+            ring.add(shard);
+        }
+        ring
+    }
+
+    pub fn add(&mut self, shard: u32) {
+        if !self.shards.insert(shard) {
+            return;
+        }
+        for node in 0..VIRTUAL_NODES {
+            // 点が衝突した場合は、IDが小さいシャードを優先して、追加する順序に依存しないようにする
+            self.points
+                .entry(virtual_node(shard, node))
+                .and_modify(|owner| *owner = (*owner).min(shard))
+                .or_insert(shard);
+        }
+    }
+
+    pub fn remove(&mut self, shard: u32) {
+        if self.shards.contains(&shard) {
+            // 衝突して上書きしていた点を戻すため、残りのシャードで作り直す
+            let shards: Vec<_> = self.shards().filter(|&s| s != shard).collect();
+            *self = Self::new(shards);
+        }
+    }
+
+    pub fn contains(&self, shard: u32) -> bool {
+        self.shards.contains(&shard)
+    }
+
+    /// シャードのIDを昇順に返す。
+    pub fn shards(&self) -> impl Iterator<Item = u32> + '_ {
+        self.shards.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// キーを割り当てるシャードを返す（シャードがない場合は`None`）。
+    pub fn owner(&self, key: &str) -> Option<u32> {
+        let hash = hash(key.as_bytes());
+        self.points
+            .range(hash..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, &shard)| shard)
+    }
+}
+
+fn virtual_node(shard: u32, node: u32) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&shard.to_le_bytes());
+    bytes[4..].copy_from_slice(&node.to_le_bytes());
+    hash(&bytes)
+}
+
+/// FNV-1aでハッシュ値を計算した後に、ビットを攪拌する。
+///
+/// FNV-1aだけでは、末尾だけが異なる短いキーのハッシュ値が偏るため、SplitMix64の最終処理で攪拌する。
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
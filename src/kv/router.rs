@@ -0,0 +1,533 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use futures::future;
+use tokio::{fs, sync::mpsc};
+
+use super::{
+    Entry, KvError, ScanRange,
+    key_value::{KeyValueActor, KeyValueMessage, WatchEvent, Write, reject_replication},
+    now_millis,
+    ring::HashRing,
+    writer::WriterActor,
+};
+use crate::actor::{self, Actor, Addr, Context, Reply};
+
+/// シャードの構成を保存したファイルがない場合に起動するシャードの数
+const DEFAULT_SHARDS: usize = 1;
+
+const SHARDS_FILE: &str = "shards";
+const SHARDS_TEMP_FILE: &str = "shards.tmp";
+
+/// シャードへの問い合わせを待つ時間
+///
+/// シャードの起動やキーの移行では、すべてのデータを読み込むため長めにする。
+const SHARD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// [`Router`]が受け取るメッセージ
+pub enum RoutingMessage {
+    KeyValue(KeyValueMessage),
+    /// シャードを追加して、追加したシャードに割り当てられるキーを移行する。
+    ///
+    /// 追加したシャードのIDを返す。
+    AddShard(Reply<Result<u32, KvError>>),
+    /// シャードのキーを他のシャードに移行して、シャードを削除する。
+    RemoveShard {
+        shard: u32,
+        response: Reply<Result<(), KvError>>,
+    },
+    /// シャードのIDを昇順に返す。
+    Shards(Reply<Vec<u32>>),
+}
+
+type WriterFactory = Box<dyn Fn(PathBuf) -> WriterActor + Send + Sync>;
+type KeyValueFactory = Box<dyn Fn(Addr<WriterActor>) -> KeyValueActor + Send + Sync>;
+
+/// キーをコンシステントハッシュでシャードに振り分けるルーターアクター
+///
+/// シャードは、キーバリューストアアクターと永続用ファイルライタアクターの組であり、
+/// `dir`の下のシャードごとのディレクトリに永続化する。
+/// キーを指定するメッセージはキーを割り当てたシャードに転送し、走査と監視はすべてのシャードの結果をまとめる。
+/// バッチは、すべてのキーが同じシャードに割り当てられている場合だけ適用できる。
+///
+/// シャードを追加または削除するときは、キーを新しいシャードにコピーして、シャードの構成を保存した後に、
+/// 元のシャードから削除する。
+/// 移行している間に届いたメッセージは、移行が終わった後に処理する。
+/// 移行の途中で停止した場合は、起動したときに、割り当てられていないシャードに残っているキーを削除する。
+/// 移行したキーのバージョンは、移行先のシャードで新しく割り当てられる。
+///
+/// 停止するときは、すべてのシャードを停止させて、その停止を待つ。
+pub struct Router {
+    dir: PathBuf,
+    initial_shards: usize,
+    writer: WriterFactory,
+    key_value: KeyValueFactory,
+    shards: BTreeMap<u32, Addr<KeyValueActor>>,
+    /// 監視している変更を転送するタスクと共有するリング
+    ring: Arc<RwLock<HashRing>>,
+    watchers: Vec<(String, mpsc::UnboundedSender<WatchEvent>)>,
+}
+
+impl Router {
+    /// `dir`の下にシャードを永続化するルーターアクターを作成する。
+    ///
+    /// シャードはアクターが起動したときに起動する。
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            initial_shards: DEFAULT_SHARDS,
+            writer: Box::new(WriterActor::new),
+            key_value: Box::new(KeyValueActor::new),
+            shards: BTreeMap::new(),
+            ring: Arc::default(),
+            watchers: Vec::new(),
+        }
+    }
+
+    /// シャードの構成が保存されていない場合に起動するシャードの数を指定する。
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        self.initial_shards = shards.max(1);
+        self
+    }
+
+    /// シャードのディレクトリから、永続用ファイルライタアクターを作成する関数を指定する。
+    pub fn with_writer<F>(mut self, f: F) -> Self
+    where
+        F: Fn(PathBuf) -> WriterActor + Send + Sync + 'static,
+    {
+        self.writer = Box::new(f);
+        self
+    }
+
+    /// 永続用ファイルライタアクターのアドレスから、キーバリューストアアクターを作成する関数を指定する。
+    pub fn with_key_value<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Addr<WriterActor>) -> KeyValueActor + Send + Sync + 'static,
+    {
+        self.key_value = Box::new(f);
+        self
+    }
+
+    fn shard_dir(&self, shard: u32) -> PathBuf {
+        self.dir.join(format!("shard-{shard}"))
+    }
+
+    fn spawn_shard(&self, shard: u32) -> Addr<KeyValueActor> {
+        let writer = actor::spawn((self.writer)(self.shard_dir(shard)));
+        actor::spawn((self.key_value)(writer))
+    }
+
+    fn ring(&self) -> HashRing {
+        self.ring.read().expect("ring lock is poisoned").clone()
+    }
+
+    fn set_ring(&self, ring: HashRing) {
+        *self.ring.write().expect("ring lock is poisoned") = ring;
+    }
+
+    /// キーを割り当てたシャードを返す。
+    fn owner(&self, key: &str) -> &Addr<KeyValueActor> {
+        let shard = self
+            .ring()
+            .owner(key)
+            .expect("router has at least one shard");
+        &self.shards[&shard]
+    }
+
+    async fn add_shard(&mut self) -> Result<u32, KvError> {
+        let shard = self.shards.keys().next_back().map_or(0, |&last| last + 1);
+        // 移行の途中で停止したときに残ったデータを使わない
+        remove_dir(&self.shard_dir(shard)).await?;
+        let addr = self.spawn_shard(shard);
+        let mut ring = self.ring();
+        ring.add(shard);
+
+        if let Err(e) = self.copy_to(shard, &addr, &ring).await {
+            stop(&addr).await;
+            let _ = remove_dir(&self.shard_dir(shard)).await;
+            return Err(e);
+        }
+
+        self.set_ring(ring);
+        self.shards.insert(shard, addr.clone());
+        self.watchers.retain(|(_, sender)| !sender.is_closed());
+        for (prefix, sender) in &self.watchers {
+            if let Ok(events) = watch(&addr, prefix.clone()).await {
+                forward(shard, events, sender.clone(), self.ring.clone());
+            }
+        }
+        // 削除に失敗したキーは、次に起動したときに削除する
+        let ring = self.ring();
+        for (&source, addr) in &self.shards {
+            let _ = remove_unowned(source, addr, &ring).await;
+        }
+        Ok(shard)
+    }
+
+    /// 追加するシャードに割り当てられるキーを既存のシャードからコピーして、シャードの構成を保存する。
+    async fn copy_to(
+        &self,
+        shard: u32,
+        addr: &Addr<KeyValueActor>,
+        ring: &HashRing,
+    ) -> Result<(), KvError> {
+        for source in self.shards.values() {
+            let writes = migrated(scan_all(source).await?, |key| {
+                ring.owner(key) == Some(shard)
+            });
+            batch(addr, writes).await?;
+        }
+        write_shards(&self.dir, ring.shards()).await
+    }
+
+    /// 削除するシャードのキーを新しく割り当てられるシャードにコピーして、シャードの構成を保存する。
+    ///
+    /// `targets`には、コピーしたシャードを追加する。
+    async fn copy_from(
+        &self,
+        source: &Addr<KeyValueActor>,
+        ring: &HashRing,
+        targets: &mut BTreeSet<u32>,
+    ) -> Result<(), KvError> {
+        let mut writes: BTreeMap<u32, Vec<Write>> = BTreeMap::new();
+        for write in migrated(scan_all(source).await?, |_| true) {
+            let target = ring.owner(write.key()).expect("ring has other shards");
+            writes.entry(target).or_default().push(write);
+        }
+        for (target, writes) in writes {
+            targets.insert(target);
+            batch(&self.shards[&target], writes).await?;
+        }
+        write_shards(&self.dir, ring.shards()).await
+    }
+
+    async fn remove_shard(&mut self, shard: u32) -> Result<(), KvError> {
+        let Some(source) = self.shards.get(&shard) else {
+            return Err(KvError::UnknownShard(shard));
+        };
+        if self.shards.len() == 1 {
+            return Err(KvError::LastShard);
+        }
+        let mut ring = self.ring();
+        ring.remove(shard);
+
+        let mut targets = BTreeSet::new();
+        if let Err(e) = self.copy_from(source, &ring, &mut targets).await {
+            // コピーしたキーを、構成を変更していないリングに従って削除する
+            let current = self.ring();
+            for target in targets {
+                let _ = remove_unowned(target, &self.shards[&target], &current).await;
+            }
+            return Err(e);
+        }
+
+        self.set_ring(ring);
+        if let Some(source) = self.shards.remove(&shard) {
+            stop(&source).await;
+        }
+        // 構成は保存済みのため、削除に失敗したディレクトリは使われない
+        let _ = remove_dir(&self.shard_dir(shard)).await;
+        Ok(())
+    }
+
+    async fn route(&mut self, message: KeyValueMessage) {
+        match message {
+            KeyValueMessage::Scan {
+                range,
+                limit,
+                response,
+            } => self.scan(range, limit, response),
+            KeyValueMessage::Watch { prefix, response } => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                for (&shard, addr) in &self.shards {
+                    if let Ok(events) = watch(addr, prefix.clone()).await {
+                        forward(shard, events, sender.clone(), self.ring.clone());
+                    }
+                }
+                if response.send(receiver).is_ok() {
+                    self.watchers.push((prefix, sender));
+                }
+            }
+            KeyValueMessage::Expire => {
+                for addr in self.shards.values() {
+                    let _ = addr.send(KeyValueMessage::Expire).await;
+                }
+            }
+            KeyValueMessage::Batch { writes, response } => {
+                let ring = self.ring();
+                let mut owners = writes.iter().map(|write| ring.owner(write.key()));
+                let owner = owners.next().flatten().or_else(|| ring.shards().next());
+                if owners.any(|other| other != owner) {
+                    let _ = response.send(Err(KvError::CrossShard));
+                    return;
+                }
+                let owner = owner.expect("router has at least one shard");
+                let message = KeyValueMessage::Batch { writes, response };
+                let _ = self.shards[&owner].send(message).await;
+            }
+            message @ (KeyValueMessage::Subscribe { .. }
+            | KeyValueMessage::Replicate { .. }
+            | KeyValueMessage::Restore { .. }
+            | KeyValueMessage::Promote(_)) => {
+                // シーケンス番号はシャードごとに割り当てるため、複数のシャードはレプリケーションできない
+                match self.shards.values().collect::<Vec<_>>().as_slice() {
+                    [shard] => {
+                        let _ = shard.send(message).await;
+                    }
+                    _ => reject_replication(message, KvError::MultipleShards),
+                }
+            }
+            message => {
+                let key = message.key().expect("remaining messages have a key");
+                let owner = self.owner(key).clone();
+                let _ = owner.send(message).await;
+            }
+        }
+    }
+
+    /// すべてのシャードを走査して、キーの順にまとめた結果を応答する。
+    ///
+    /// シャードの応答を待つ間もメッセージを処理できるように、別のタスクで待機する。
+    fn scan(&self, range: ScanRange, limit: usize, response: Reply<Vec<(String, Entry)>>) {
+        let shards: Vec<_> = self
+            .shards
+            .iter()
+            .map(|(&shard, addr)| (shard, addr.clone()))
+            .collect();
+        let ring = self.ring();
+        tokio::spawn(async move {
+            let scans = shards.iter().map(|(_, addr)| {
+                let range = range.clone();
+                addr.ask(
+                    move |response| KeyValueMessage::Scan {
+                        range,
+                        limit,
+                        response,
+                    },
+                    SHARD_TIMEOUT,
+                )
+            });
+            // 応答しないシャードがある場合は、応答先を破棄して知らせる
+            let Ok(results) = future::join_all(scans)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+            else {
+                return;
+            };
+            let mut entries: Vec<_> = shards
+                .iter()
+                .zip(results)
+                .flat_map(|(&(shard, _), entries)| {
+                    // 移行の途中で残っている、割り当てられていないキーを除く
+                    let ring = &ring;
+                    entries
+                        .into_iter()
+                        .filter(move |(key, _)| ring.owner(key) == Some(shard))
+                })
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries.truncate(limit);
+            let _ = response.send(entries);
+        });
+    }
+}
+
+impl Actor for Router {
+    type Message = RoutingMessage;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        let shards = match read_shards(&self.dir).await {
+            Ok(Some(shards)) => shards,
+            Ok(None) => {
+                let shards: Vec<_> = (0..self.initial_shards as u32).collect();
+                if let Err(e) = write_shards(&self.dir, shards.iter().copied()).await {
+                    panic!("failed to save the shards in {}: {e}", self.dir.display());
+                }
+                shards
+            }
+            Err(e) => panic!("failed to read the shards in {}: {e}", self.dir.display()),
+        };
+        for &shard in &shards {
+            self.shards.insert(shard, self.spawn_shard(shard));
+        }
+        let ring = HashRing::new(shards);
+        // 移行の途中で停止した場合に残っている、割り当てられていないキーを削除する
+        for (&shard, addr) in &self.shards {
+            if let Err(e) = remove_unowned(shard, addr, &ring).await {
+                panic!("failed to clean up shard {shard}: {e}");
+            }
+        }
+        self.set_ring(ring);
+    }
+
+    async fn handle(&mut self, message: RoutingMessage, _ctx: &mut Context<Self>) {
+        match message {
+            RoutingMessage::KeyValue(message) => self.route(message).await,
+            RoutingMessage::AddShard(response) => {
+                let _ = response.send(self.add_shard().await);
+            }
+            RoutingMessage::RemoveShard { shard, response } => {
+                let _ = response.send(self.remove_shard(shard).await);
+            }
+            RoutingMessage::Shards(response) => {
+                let _ = response.send(self.shards.keys().copied().collect());
+            }
+        }
+    }
+
+    async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+        for addr in self.shards.values() {
+            addr.stop().await;
+        }
+        for addr in self.shards.values() {
+            addr.stopped().await;
+        }
+    }
+}
+
+async fn stop(addr: &Addr<KeyValueActor>) {
+    addr.stop().await;
+    addr.stopped().await;
+}
+
+async fn scan_all(shard: &Addr<KeyValueActor>) -> Result<Vec<(String, Entry)>, KvError> {
+    let entries = shard
+        .ask(
+            |response| KeyValueMessage::Scan {
+                range: ScanRange::all(),
+                limit: usize::MAX,
+                response,
+            },
+            SHARD_TIMEOUT,
+        )
+        .await?;
+    Ok(entries)
+}
+
+async fn batch(shard: &Addr<KeyValueActor>, writes: Vec<Write>) -> Result<u64, KvError> {
+    if writes.is_empty() {
+        return Ok(0);
+    }
+    shard
+        .ask(
+            |response| KeyValueMessage::Batch { writes, response },
+            SHARD_TIMEOUT,
+        )
+        .await?
+}
+
+async fn watch(
+    shard: &Addr<KeyValueActor>,
+    prefix: String,
+) -> Result<mpsc::UnboundedReceiver<WatchEvent>, KvError> {
+    let events = shard
+        .ask(
+            |response| KeyValueMessage::Watch { prefix, response },
+            SHARD_TIMEOUT,
+        )
+        .await?;
+    Ok(events)
+}
+
+/// シャードの変更のうち、そのシャードに割り当てられているキーの変更だけを転送する。
+///
+/// 移行のためにコピーまたは削除したキーの変更は通知しない。
+fn forward(
+    shard: u32,
+    mut events: mpsc::UnboundedReceiver<WatchEvent>,
+    sender: mpsc::UnboundedSender<WatchEvent>,
+    ring: Arc<RwLock<HashRing>>,
+) {
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let owner = ring
+                .read()
+                .expect("ring lock is poisoned")
+                .owner(event.key());
+            if owner == Some(shard) && sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// 走査したキーのうち、`moved`が`true`を返すキーを書き込む操作を返す。
+///
+/// 有効期限は、残りの時間として引き継ぐ。
+fn migrated(entries: Vec<(String, Entry)>, moved: impl Fn(&str) -> bool) -> Vec<Write> {
+    let now = now_millis();
+    entries
+        .into_iter()
+        .filter(|(key, entry)| moved(key) && !entry.is_expired(now))
+        .map(|(key, entry)| Write::Set {
+            key,
+            value: entry.value,
+            ttl: entry
+                .expires_at
+                .map(|expires_at| Duration::from_millis(expires_at - now)),
+        })
+        .collect()
+}
+
+/// シャードに割り当てられていないキーを削除する。
+async fn remove_unowned(
+    shard: u32,
+    addr: &Addr<KeyValueActor>,
+    ring: &HashRing,
+) -> Result<(), KvError> {
+    let writes = scan_all(addr)
+        .await?
+        .into_iter()
+        .filter(|(key, _)| ring.owner(key) != Some(shard))
+        .map(|(key, _)| Write::Delete { key })
+        .collect();
+    batch(addr, writes).await.map(|_| ())
+}
+
+async fn remove_dir(dir: &Path) -> Result<(), KvError> {
+    match fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// 保存されているシャードのIDを読み込む。
+///
+/// 保存されていない場合は`None`を返す。
+async fn read_shards(dir: &Path) -> io::Result<Option<Vec<u32>>> {
+    let contents = match fs::read_to_string(dir.join(SHARDS_FILE)).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let shards = contents
+        .lines()
+        .map(|line| line.trim().parse())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if shards.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no shards"));
+    }
+    Ok(Some(shards))
+}
+
+/// シャードのIDを一時ファイルに書き込んだ後に、名前を変更して置き換える。
+async fn write_shards(dir: &Path, shards: impl Iterator<Item = u32>) -> Result<(), KvError> {
+    let contents: String = shards.map(|shard| format!("{shard}\n")).collect();
+    fs::create_dir_all(dir).await?;
+    let temp = dir.join(SHARDS_TEMP_FILE);
+    let mut file = fs::File::create(&temp).await?;
+    tokio::io::AsyncWriteExt::write_all(&mut file, contents.as_bytes()).await?;
+    file.sync_all().await?;
+    drop(file);
+    fs::rename(&temp, dir.join(SHARDS_FILE)).await?;
+    // 名前の変更を永続化する
+    fs::File::open(dir).await?.sync_all().await?;
+    Ok(())
+}
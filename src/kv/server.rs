@@ -0,0 +1,196 @@
+use std::{
+    io::{self, Cursor},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use async_runtime::{executor::Executor, receiver::TcpReceiver, sender::TcpSender, trace};
+use data_layer::kv::{self as protocol, Request, Response};
+use tokio::runtime::Handle;
+
+use super::{Entry, ScanRange, client::Client};
+
+/// 接続を処理するワーカースレッドの既定の数
+const DEFAULT_WORKERS: usize = 3;
+
+/// キーバリューストアをTCPで公開するサーバー
+///
+/// 受け付けた接続を、async_runtimeのエグゼキューターを実行するワーカースレッドに順番に振り分ける。
+/// 1つの接続で1つのリクエストを処理する。
+/// クライアントがリクエストを送信して書き込み側を閉じると、レスポンスを送信して接続を閉じる。
+///
+/// アクターへの問い合わせはTokioのタイマーを使用するため、ワーカースレッドはTokioランタイムのコンテキストに入る。
+pub struct Server {
+    listener: TcpListener,
+    client: Client,
+    runtime: Handle,
+    workers: usize,
+}
+
+impl Server {
+    /// アドレスにバインドしたサーバーを作成する。
+    ///
+    /// Tokioランタイムの中から呼び出さなければならない。
+    pub fn bind(addr: impl ToSocketAddrs, client: Client) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            client,
+            runtime: Handle::current(),
+            workers: DEFAULT_WORKERS,
+        })
+    }
+
+    /// ワーカースレッドの数を指定する。
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// バインドしたアドレスを返す。
+    ///
+    /// ポート番号に0を指定した場合に、割り当てられたポート番号を確認できる。
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// 接続を受け付けて、ワーカースレッドに振り分ける。
+    ///
+    /// 呼び出したスレッドをブロックする。
+    pub fn run(self) -> io::Result<()> {
+        let workers: Vec<_> = (0..self.workers)
+            .map(|id| spawn_worker(id, self.client.clone(), self.runtime.clone()))
+            .collect();
+        let mut index = 0;
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if workers[index].send(stream).is_err() {
+                        return Err(io::Error::other(format!("worker {index} stopped")));
+                    }
+                    index = (index + 1) % workers.len();
+                }
+                Err(e) => {
+                    eprintln!("Connection failed: {e}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ワーカースレッドを起動して、接続を送信する送信側を返す。
+fn spawn_worker(id: usize, client: Client, runtime: Handle) -> mpsc::Sender<TcpStream> {
+    let (tx, rx) = mpsc::channel::<TcpStream>();
+    thread::spawn(move || {
+        trace::set_worker_id(id);
+        let _guard = runtime.enter();
+        let mut executor = Executor::default();
+        loop {
+            // 処理中の接続がなければ、次の接続を受け取るまで待機する
+            let stream = if executor.polling.is_empty() {
+                match rx.recv() {
+                    Ok(stream) => Some(stream),
+                    Err(_) => break,
+                }
+            } else {
+                rx.try_recv().ok()
+            };
+            if let Some(stream) = stream {
+                let client = client.clone();
+                executor.spawn(async move {
+                    if let Err(e) = handle_connection(stream, &client).await {
+                        eprintln!("Failed to handle connection: {e}");
+                    }
+                });
+            }
+            executor.poll();
+        }
+    });
+    tx
+}
+
+async fn handle_connection(stream: TcpStream, client: &Client) -> io::Result<()> {
+    let stream = Arc::new(Mutex::new(stream));
+    // クライアントが書き込み側を閉じるまで読み込む
+    let request = TcpReceiver {
+        stream: stream.clone(),
+        buffer: vec![],
+    }
+    .await?;
+    let response = match decode(&request, Request::deserialize) {
+        Ok(request) => execute(client, request).await,
+        Err(e) => Response::Error(format!("invalid request: {e}")),
+    };
+    TcpSender {
+        stream,
+        buffer: response.serialize()?,
+    }
+    .await
+}
+
+/// リクエストをキーバリューストアに対して実行する。
+async fn execute(client: &Client, request: Request) -> Response {
+    let result = match request {
+        Request::Get { key } => client
+            .get(key.clone())
+            .await
+            .map(|entry| Response::Value(entry.map(|entry| to_protocol(key, entry)))),
+        Request::Set {
+            key,
+            value,
+            ttl_millis: Some(ttl),
+        } => client
+            .set_with_ttl(key, value, Duration::from_millis(ttl))
+            .await
+            .map(|version| Response::Written { version }),
+        Request::Set { key, value, .. } => client
+            .set(key, value)
+            .await
+            .map(|version| Response::Written { version }),
+        Request::Delete { key } => client
+            .delete(key)
+            .await
+            .map(|version| Response::Written { version }),
+        Request::Scan { prefix, limit } => client
+            .scan(ScanRange::Prefix(prefix), limit as usize)
+            .await
+            .map(|entries| {
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, entry)| to_protocol(key, entry))
+                    .collect();
+                Response::Entries(entries)
+            }),
+        Request::Promote => client
+            .promote()
+            .await
+            .map(|version| Response::Written { version }),
+    };
+    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+fn to_protocol(key: String, entry: Entry) -> protocol::Entry {
+    protocol::Entry {
+        key,
+        value: entry.value,
+        version: entry.version,
+    }
+}
+
+/// バイト列全体をメッセージとしてデコードする。
+pub(super) fn decode<T>(
+    bytes: &[u8],
+    deserialize: fn(&mut Cursor<&[u8]>) -> io::Result<T>,
+) -> io::Result<T> {
+    let mut cursor = Cursor::new(bytes);
+    let message = deserialize(&mut cursor)?;
+    if cursor.position() != bytes.len() as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "trailing bytes after the message",
+        ));
+    }
+    Ok(message)
+}
@@ -0,0 +1,86 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use super::{
+    Data, Entry,
+    wal::{Decoder, crc32, put_bytes, put_u32, put_u64},
+};
+
+const SNAPSHOT_FILE: &str = "snapshot";
+const TEMP_FILE: &str = "snapshot.tmp";
+
+const MAGIC: &[u8; 4] = b"AKVS";
+const VERSION: u32 = 2;
+
+/// スナップショットを読み込んで、データを返す。
+///
+/// スナップショットが存在しない場合は`None`を返す。
+pub(super) fn read(dir: &Path) -> io::Result<Option<Data>> {
+    let contents = match fs::read(dir.join(SNAPSHOT_FILE)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    decode(&contents)
+        .map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "snapshot is corrupted"))
+}
+
+/// スナップショットを一時ファイルに書き込んだ後に、名前を変更して置き換える。
+pub(super) fn write(dir: &Path, data: &Data) -> io::Result<()> {
+    let temp = dir.join(TEMP_FILE);
+    let mut file = File::create(&temp)?;
+    file.write_all(&encode(data))?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp, dir.join(SNAPSHOT_FILE))?;
+    // 名前の変更を永続化する
+    File::open(dir)?.sync_all()
+}
+
+pub(super) fn encode(data: &Data) -> Vec<u8> {
+    let mut buf = MAGIC.to_vec();
+    put_u32(&mut buf, VERSION);
+    put_u64(&mut buf, data.seq);
+    put_u64(&mut buf, data.len() as u64);
+    for (key, entry) in data.iter() {
+        put_bytes(&mut buf, key.as_bytes());
+        put_bytes(&mut buf, &entry.value);
+        put_u64(&mut buf, entry.version);
+        // 有効期限がない場合は0を書き込む
+        put_u64(&mut buf, entry.expires_at.unwrap_or(0));
+    }
+    let crc = crc32(&buf);
+    put_u32(&mut buf, crc);
+    buf
+}
+
+pub(super) fn decode(contents: &[u8]) -> Option<Data> {
+    let (body, crc) = contents.split_last_chunk::<4>()?;
+    if crc32(body) != u32::from_le_bytes(*crc) {
+        return None;
+    }
+    let body = body.strip_prefix(MAGIC)?;
+    let mut decoder = Decoder::new(body);
+    if decoder.u32()? != VERSION {
+        return None;
+    }
+    let mut data = Data {
+        seq: decoder.u64()?,
+        ..Data::default()
+    };
+    let count = decoder.u64()?;
+    for _ in 0..count {
+        let key = decoder.string()?;
+        let entry = Entry {
+            value: decoder.bytes()?.to_vec(),
+            version: decoder.u64()?,
+            expires_at: Some(decoder.u64()?).filter(|&expires_at| expires_at != 0),
+        };
+        data.insert_entry(key, entry);
+    }
+    decoder.is_empty().then_some(data)
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use super::Operation;
+
+const WAL_FILE: &str = "wal.log";
+
+/// レコードのヘッダの大きさ（ペイロードの長さとCRC32）
+const HEADER_LEN: usize = 8;
+
+const TAG_SET: u8 = 1;
+const TAG_DELETE: u8 = 2;
+const TAG_BATCH: u8 = 3;
+
+/// 先行書き込みログ
+///
+/// レコードは、ペイロードの長さ（u32）、ペイロードのCRC32（u32）、ペイロードの順に並ぶ。
+/// ペイロードには、シーケンス番号と操作を格納する。
+#[derive(Debug)]
+pub(super) struct Wal {
+    file: File,
+    /// まだファイルに書き込んでいないレコード
+    buffer: Vec<u8>,
+    /// ファイルに書き込んだバイト数
+    len: u64,
+}
+
+impl Wal {
+    /// ログを開いて、記録されているレコードを返す。
+    ///
+    /// 末尾の壊れているレコードは切り詰める。
+    pub(super) fn open(dir: &Path) -> io::Result<(Self, Vec<(u64, Operation)>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(WAL_FILE))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while let Some((record, len)) = decode_record(&contents[offset..]) {
+            records.push(record);
+            offset += len;
+        }
+        if offset < contents.len() {
+            // 書き込み中にクラッシュしたレコードの後ろに追記しないように切り詰める
+            file.set_len(offset as u64)?;
+            file.sync_data()?;
+        }
+        let wal = Self {
+            file,
+            buffer: Vec::new(),
+            len: offset as u64,
+        };
+        Ok((wal, records))
+    }
+
+    /// ファイルに書き込んだバイト数を返す。
+    pub(super) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// レコードをバッファに追記する。
+    pub(super) fn append(&mut self, seq: u64, operation: &Operation) {
+        let mut payload = Vec::new();
+        put_u64(&mut payload, seq);
+        encode_operation(&mut payload, operation);
+        put_u32(&mut self.buffer, payload.len() as u32);
+        put_u32(&mut self.buffer, crc32(&payload));
+        self.buffer.extend_from_slice(&payload);
+    }
+
+    /// バッファをファイルに書き込んで、`fsync`する。
+    ///
+    /// 失敗した場合は、途中まで書き込んだレコードを切り詰めて、バッファを残す。
+    pub(super) fn sync(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self
+            .file
+            .write_all(&self.buffer)
+            .and_then(|_| self.file.sync_data())
+        {
+            let _ = self.file.set_len(self.len);
+            return Err(e);
+        }
+        self.len += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// 同期していないレコードを含めて、ログを空にする。
+    pub(super) fn reset(&mut self) -> io::Result<()> {
+        self.buffer.clear();
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn decode_record(bytes: &[u8]) -> Option<((u64, Operation), usize)> {
+    let mut header = Decoder::new(bytes.get(..HEADER_LEN)?);
+    let len = header.u32()? as usize;
+    let crc = header.u32()?;
+    let payload = bytes.get(HEADER_LEN..HEADER_LEN + len)?;
+    if crc32(payload) != crc {
+        return None;
+    }
+    let mut decoder = Decoder::new(payload);
+    let seq = decoder.u64()?;
+    let operation = decode_operation(&mut decoder)?;
+    decoder
+        .is_empty()
+        .then_some(((seq, operation), HEADER_LEN + len))
+}
+
+pub(super) fn encode_operation(buf: &mut Vec<u8>, operation: &Operation) {
+    match operation {
+        Operation::Set {
+            key,
+            value,
+            expires_at,
+        } => {
+            buf.push(TAG_SET);
+            put_bytes(buf, key.as_bytes());
+            put_bytes(buf, value);
+            // 有効期限がない場合は0を書き込む
+            put_u64(buf, expires_at.unwrap_or(0));
+        }
+        Operation::Delete { key } => {
+            buf.push(TAG_DELETE);
+            put_bytes(buf, key.as_bytes());
+        }
+        Operation::Batch(operations) => {
+            buf.push(TAG_BATCH);
+            put_u32(buf, operations.len() as u32);
+            for operation in operations {
+                encode_operation(buf, operation);
+            }
+        }
+    }
+}
+
+pub(super) fn decode_operation(decoder: &mut Decoder<'_>) -> Option<Operation> {
+    match decoder.u8()? {
+        TAG_SET => Some(Operation::Set {
+            key: decoder.string()?,
+            value: decoder.bytes()?.to_vec(),
+            expires_at: Some(decoder.u64()?).filter(|&expires_at| expires_at != 0),
+        }),
+        TAG_DELETE => Some(Operation::Delete {
+            key: decoder.string()?,
+        }),
+        TAG_BATCH => {
+            let count = decoder.u32()?;
+            (0..count)
+                .map(|_| decode_operation(decoder))
+                .collect::<Option<_>>()
+                .map(Operation::Batch)
+        }
+        _ => None,
+    }
+}
+
+pub(super) fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(super) fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// 長さ（u32）を前置したバイト列を書き込む。
+pub(super) fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(buf, bytes.len() as u32);
+    buf.extend_from_slice(bytes);
+}
+
+/// バイト列から値を読み込む。
+///
+/// バイト列が足りない場合は`None`を返す。
+pub(super) struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (head, tail) = self.0.split_at_checked(len)?;
+        self.0 = tail;
+        Some(head)
+    }
+
+    pub(super) fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(super) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(super) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub(super) fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+}
+
+/// CRC-32（IEEE 802.3）を計算する。
+pub(super) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
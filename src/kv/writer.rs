@@ -0,0 +1,304 @@
+use std::{io, path::PathBuf, time::Duration};
+
+use tokio::time::Instant;
+
+use super::{
+    Data, KvError, Operation, Store,
+    replication::{ReplicationLog, ReplicationMode, Subscription},
+};
+use crate::actor::{Actor, Context, Reply};
+
+/// 同期を待っている操作がこの数に達したら、直ちに同期する
+const DEFAULT_MAX_BATCH: usize = 256;
+
+/// 操作を永続化した後に、操作のシーケンス番号または同期のエラーを受け取る応答先
+pub type Ack = Reply<Result<u64, KvError>>;
+
+/// [`WriterActor`]が受け取るメッセージ
+pub enum WriterLogMessage {
+    /// 操作をログに追記する。
+    ///
+    /// `ack`には、操作を含むログを同期した後に応答する。
+    Append {
+        operation: Operation,
+        ack: Option<Ack>,
+    },
+    /// すべてのデータを返す。
+    Get(Reply<Data>),
+    /// 追記した操作を同期した後に、スナップショットを作成する。
+    Snapshot(Reply<io::Result<()>>),
+    /// 追記した操作を同期する。
+    Flush,
+    /// 同期した操作をレプリカに送信する購読を開始する。
+    ///
+    /// `history`と`seq`には、レプリカが最後に適用した操作の履歴とシーケンス番号を指定する。
+    Subscribe {
+        history: Option<u64>,
+        seq: u64,
+        response: Reply<Result<Subscription, KvError>>,
+    },
+    /// レプリカが`seq`までの操作を永続化した。
+    Acknowledge { replica: u64, seq: u64 },
+    /// 同期を待っている操作を破棄して、データを置き換える。
+    Restore {
+        data: Data,
+        response: Reply<Result<(), KvError>>,
+    },
+    /// レプリカの確認応答を待っている操作のうち、時間切れになった操作に応答する。
+    CheckReplication,
+}
+
+/// ストアを永続化するアクター
+///
+/// 追記された操作は、メールボックスに届いている操作と合わせて1回の`fsync`で同期する（グループコミット）。
+/// 同期を待つ時間を指定すると、その間に届いた操作もまとめて同期する。
+///
+/// 同期した操作は、購読しているレプリカに送信する。
+/// 同期レプリケーションでは、必要な数のレプリカが確認応答した後に応答する。
+///
+/// ストアを開けなかった場合、またはログを同期できなかった場合はパニックする。
+/// スーパーバイザーが再起動すると、ディスクに永続化されている状態からストアを開き直す。
+pub struct WriterActor {
+    dir: PathBuf,
+    store: Option<Store>,
+    snapshot_threshold: Option<u64>,
+    sync_delay: Duration,
+    max_batch: usize,
+    /// 同期を待っている操作のシーケンス番号と応答先
+    pending: Vec<(u64, Option<Ack>)>,
+    flush_scheduled: bool,
+    /// 最初のレプリカが購読するか、レプリケーションを設定するまでは`None`
+    replication: Option<ReplicationLog>,
+    check_scheduled: bool,
+}
+
+impl WriterActor {
+    /// `dir`に永続化するアクターを作成する。
+    ///
+    /// ストアはアクターが起動したときに開く。
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            store: None,
+            snapshot_threshold: None,
+            sync_delay: Duration::ZERO,
+            max_batch: DEFAULT_MAX_BATCH,
+            pending: Vec::new(),
+            flush_scheduled: false,
+            replication: None,
+            check_scheduled: false,
+        }
+    }
+
+    /// スナップショットを作成するログの大きさを指定する。
+    pub fn with_snapshot_threshold(mut self, bytes: u64) -> Self {
+        self.snapshot_threshold = Some(bytes);
+        self
+    }
+
+    /// 最初の操作が追記されてから同期するまで待つ時間を指定する（既定は待たない）。
+    pub fn with_sync_delay(mut self, delay: Duration) -> Self {
+        self.sync_delay = delay;
+        self
+    }
+
+    /// 1回の同期にまとめる操作の最大数を指定する。
+    pub fn with_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch.max(1);
+        self
+    }
+
+    /// レプリケーションの確認応答を待つかどうかを指定する（既定は待たない）。
+    pub fn with_replication(mut self, mode: ReplicationMode) -> Self {
+        self.replication = Some(ReplicationLog::new(mode));
+        self
+    }
+
+    fn store(&mut self) -> &mut Store {
+        self.store.as_mut().expect("store is opened in started")
+    }
+
+    /// ブロッキング処理用のスレッドでストアを操作する。
+    async fn blocking<R, F>(&mut self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Store) -> R + Send + 'static,
+    {
+        let mut store = self.store.take().expect("store is opened in started");
+        let (store, output) = tokio::task::spawn_blocking(move || {
+            let output = f(&mut store);
+            (store, output)
+        })
+        .await
+        .expect("blocking store operation panicked");
+        self.store = Some(store);
+        output
+    }
+
+    /// 同期を待っている操作を同期して、応答先に結果を送信する。
+    async fn flush(&mut self) {
+        self.flush_scheduled = false;
+        if self.pending.is_empty() {
+            return;
+        }
+        let result = self.blocking(Store::sync).await.map_err(KvError::from);
+        if let Err(e) = result {
+            for (_, ack) in self.pending.drain(..) {
+                if let Some(ack) = ack {
+                    let _ = ack.send(Err(e.clone()));
+                }
+            }
+            // メモリ上のデータとディスクの内容が一致しなくなったため、開き直す
+            panic!("failed to sync the write-ahead log: {e}");
+        }
+        let Some(log) = &mut self.replication else {
+            for (seq, ack) in self.pending.drain(..) {
+                if let Some(ack) = ack {
+                    let _ = ack.send(Ok(seq));
+                }
+            }
+            return;
+        };
+        log.ship();
+        let now = Instant::now();
+        for (seq, ack) in self.pending.drain(..) {
+            if let Some(ack) = ack {
+                log.wait(seq, ack, now);
+            }
+        }
+    }
+
+    /// レプリカの確認応答を待っている最も古い操作が時間切れになったときに、`CheckReplication`を送信する。
+    fn schedule_check(&mut self, ctx: &Context<Self>) {
+        let Some(deadline) = self
+            .replication
+            .as_ref()
+            .and_then(ReplicationLog::next_deadline)
+        else {
+            return;
+        };
+        if self.check_scheduled {
+            return;
+        }
+        self.check_scheduled = true;
+        let addr = ctx.weak_addr();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline).await;
+            if let Some(addr) = addr.upgrade() {
+                let _ = addr.send(WriterLogMessage::CheckReplication).await;
+            }
+        });
+    }
+
+    /// メールボックスに届いている操作を処理した後に同期するように、`Flush`を送信する。
+    fn schedule_flush(&mut self, ctx: &Context<Self>) {
+        if self.flush_scheduled {
+            return;
+        }
+        self.flush_scheduled = true;
+        let addr = ctx.weak_addr();
+        let delay = self.sync_delay;
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            if let Some(addr) = addr.upgrade() {
+                let _ = addr.send(WriterLogMessage::Flush).await;
+            }
+        });
+    }
+}
+
+impl Actor for WriterActor {
+    type Message = WriterLogMessage;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        let dir = self.dir.clone();
+        let threshold = self.snapshot_threshold;
+        let store = tokio::task::spawn_blocking(move || {
+            let store = Store::open(&dir)?;
+            Ok::<_, io::Error>(match threshold {
+                Some(threshold) => store.with_snapshot_threshold(threshold),
+                None => store,
+            })
+        })
+        .await
+        .expect("opening the store panicked");
+        match store {
+            Ok(store) => self.store = Some(store),
+            Err(e) => panic!("failed to open the store in {}: {e}", self.dir.display()),
+        }
+    }
+
+    async fn handle(&mut self, message: WriterLogMessage, ctx: &mut Context<Self>) {
+        match message {
+            WriterLogMessage::Append { operation, ack } => {
+                let staged = self.replication.is_some().then(|| operation.clone());
+                let seq = self.store().apply(operation);
+                if let (Some(log), Some(operation)) = (&mut self.replication, staged) {
+                    log.stage(seq, operation);
+                }
+                self.pending.push((seq, ack));
+                if self.pending.len() >= self.max_batch {
+                    self.flush().await;
+                    self.schedule_check(ctx);
+                } else {
+                    self.schedule_flush(ctx);
+                }
+            }
+            WriterLogMessage::Get(response) => {
+                let _ = response.send(self.store().data().clone());
+            }
+            WriterLogMessage::Snapshot(response) => {
+                self.flush().await;
+                let _ = response.send(self.blocking(Store::snapshot).await);
+            }
+            WriterLogMessage::Flush => {
+                self.flush().await;
+                self.schedule_check(ctx);
+            }
+            WriterLogMessage::Subscribe {
+                history,
+                seq,
+                response,
+            } => {
+                // 購読を開始する時点のデータに、同期していない操作が含まれないようにする
+                self.flush().await;
+                let data = self
+                    .store
+                    .as_ref()
+                    .expect("store is opened in started")
+                    .data();
+                let subscription = self
+                    .replication
+                    .get_or_insert_with(|| ReplicationLog::new(ReplicationMode::Async))
+                    .subscribe(history, seq, data, ctx.weak_addr());
+                let _ = response.send(Ok(subscription));
+            }
+            WriterLogMessage::Acknowledge { replica, seq } => {
+                if let Some(log) = &mut self.replication {
+                    log.acknowledge(replica, seq);
+                }
+            }
+            WriterLogMessage::Restore { data, response } => {
+                self.flush().await;
+                if let Err(e) = self.blocking(move |store| store.restore(data)).await {
+                    panic!("failed to restore the store: {e}");
+                }
+                if let Some(log) = &mut self.replication {
+                    log.reset();
+                }
+                let _ = response.send(Ok(()));
+            }
+            WriterLogMessage::CheckReplication => {
+                self.check_scheduled = false;
+                if let Some(log) = &mut self.replication {
+                    log.expire(Instant::now());
+                }
+                self.schedule_check(ctx);
+            }
+        }
+    }
+
+    async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+        self.flush().await;
+    }
+}
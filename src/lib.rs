@@ -1,6 +1,23 @@
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
+
+pub mod actor;
 pub mod async_mod;
+pub mod blocking;
+pub mod coroutine;
+pub mod event_bus;
+pub mod ffi;
 pub mod futures;
+pub mod job;
+pub mod kv;
+pub mod per_thread;
+pub mod pipeline;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reactive;
 pub mod runtime;
+mod sync;
+pub mod task_local;
+pub mod trace;
 
 /// spawn_taskを呼び出すマクロ
 ///
@@ -0,0 +1,97 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+thread_local! {
+    /// 現在のスレッドが所有する`PerThread`の値
+    ///
+    /// キーは`PerThread`の静的変数のアドレス。
+    static SLOTS: RefCell<HashMap<usize, Arc<dyn Any + Send + Sync>>> = RefCell::new(HashMap::new());
+}
+
+/// スレッドごとに独立した値を持ち、すべてのスレッドの値を安全に集計できる変数
+///
+/// `thread_local!`に`UnsafeCell`を格納して、集計するときに各スレッドで値を取り出す方法の代わりに使用する。
+/// 各スレッドは自分の値をロックの競合なしに更新でき、任意のスレッドから全スレッドの値を集計できる。
+/// スレッドが終了しても、そのスレッドの値は集計の対象として残る。
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use async_rust::per_thread::PerThread;
+///
+/// static COUNTER: PerThread<HashMap<u32, u32>> = PerThread::new(HashMap::new);
+///
+/// COUNTER.with(|counter| *counter.entry(1).or_default() += 1);
+/// let total = COUNTER.fold(0, |total, counter| total + counter.values().sum::<u32>());
+/// assert_eq!(total, 1);
+/// ```
+pub struct PerThread<T: 'static> {
+    init: fn() -> T,
+    slots: Mutex<Vec<Arc<Mutex<T>>>>,
+}
+
+impl<T: 'static> fmt::Debug for PerThread<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PerThread").finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> PerThread<T> {
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            init,
+            slots: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 現在のスレッドの値への可変参照をクロージャーに渡す。
+    ///
+    /// スレッドで初めてアクセスしたときに、初期値で初期化して集計の対象に登録する。
+    /// クロージャーの中から同じ変数の`with`や`fold`を呼び出すとデッドロックする。
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let key = self as *const Self as usize;
+        let slot = SLOTS.with_borrow_mut(|slots| {
+            slots
+                .entry(key)
+                .or_insert_with(|| {
+                    let slot = Arc::new(Mutex::new((self.init)()));
+                    self.slots.lock().unwrap().push(slot.clone());
+                    slot
+                })
+                .clone()
+        });
+        let slot = slot.downcast::<Mutex<T>>().unwrap();
+        let mut value = slot.lock().unwrap();
+        f(&mut value)
+    }
+
+    /// すべてのスレッドの値を畳み込む。
+    pub fn fold<A, F>(&self, init: A, mut f: F) -> A
+    where
+        F: FnMut(A, &T) -> A,
+    {
+        let slots = self.slots.lock().unwrap().clone();
+        slots
+            .iter()
+            .fold(init, |acc, slot| f(acc, &slot.lock().unwrap()))
+    }
+
+    /// すべてのスレッドの値のコピーを返す。
+    pub fn snapshot(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.fold(Vec::new(), |mut values, value| {
+            values.push(value.clone());
+            values
+        })
+    }
+}
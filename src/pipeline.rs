@@ -0,0 +1,503 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures::{Stream, StreamExt, executor::block_on};
+
+use crate::coroutine::{Coroutine, CoroutineState};
+
+/// 値を生成するコルーチンを、パイプラインの入口として包む。
+///
+/// コルーチンが完了すると、パイプラインの入力は終端に達する。
+/// 変換やシンクに値を渡すために、`Unpin`でないコルーチンは[`Box::pin`]で包む。
+pub fn source<C: Coroutine<(), Return = ()>>(coroutine: C) -> Source<C> {
+    Source { coroutine }
+}
+
+/// イテレーターの要素を順に生成するソースを作成する。
+pub fn from_iter<I: IntoIterator>(iter: I) -> Source<FromIter<I::IntoIter>> {
+    source(FromIter {
+        iter: iter.into_iter(),
+    })
+}
+
+/// ストリームの要素を順に生成するソースを作成する。
+///
+/// 次の要素が届くまで、`resume`を呼び出したスレッドをブロックする。
+/// そのため、非同期タスクの中で再開してはならない。
+pub fn from_stream<S: Stream + Unpin>(stream: S) -> Source<FromStream<S>> {
+    source(FromStream { stream })
+}
+
+/// フューチャーを、ポーリングが`Pending`を返すたびに`()`を生成するコルーチンに変換する。
+///
+/// フューチャーが完了すると、その出力でコルーチンも完了する。
+/// ウェイカーは何もしないため、`resume`を呼び出す側が繰り返し再開する必要がある。
+pub fn from_future<F: Future>(future: F) -> FromFuture<F> {
+    FromFuture {
+        future: Box::pin(future),
+    }
+}
+
+/// `()`を生成するコルーチンを、完了するとその戻り値を出力するフューチャーに変換する。
+///
+/// コルーチンが値を生成するたびに自身を起こして`Pending`を返すため、
+/// 他のタスクと交互に実行される。
+pub fn into_future<C: Coroutine<(), Yield = ()> + Unpin>(coroutine: C) -> CoroutineFuture<C> {
+    CoroutineFuture { coroutine }
+}
+
+/// 入力ごとに`f`を呼び出して、その戻り値を生成するステージを作成する。
+pub fn map<F>(f: F) -> Map<F> {
+    Map { f }
+}
+
+/// 入力ごとに`f`を呼び出すシンクを作成する。
+pub fn for_each<F>(f: F) -> ForEach<F> {
+    ForEach { f }
+}
+
+/// 入力ごとに`f`を呼び出し、最初にエラーを返したところで完了するシンクを作成する。
+///
+/// 入力が終端に達した場合は`Ok(())`で完了する。
+pub fn try_for_each<F>(f: F) -> TryForEach<F> {
+    TryForEach { f }
+}
+
+/// 入力を[`Vec`]に集めて、終端に達したときに返すシンクを作成する。
+pub fn collect<T>() -> Collect<T> {
+    Collect { items: Vec::new() }
+}
+
+/// パイプラインの入口
+///
+/// ソースのコルーチンに、[`Source::stage`]で変換を合成していき、
+/// [`Source::sink`]でシンクに接続すると[`Pipeline`]になる。
+///
+/// エラーはパイプラインを止めずに、`Result`などの値として後段に渡す。
+/// エラーを受け取ったときに処理を止めるかは、後段のステージやシンクが決める。
+#[derive(Debug)]
+pub struct Source<C> {
+    coroutine: C,
+}
+
+impl<C> Source<C>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+{
+    /// 生成する値を`stage`で変換する。
+    ///
+    /// ステージは入力ごとに1つの値を生成する。ステージが完了すると、入力は終端に達する。
+    pub fn stage<S>(self, stage: S) -> Source<Then<C, S>>
+    where
+        S: Coroutine<C::Yield, Return = ()> + Unpin,
+    {
+        source(Then {
+            source: self.coroutine,
+            stage,
+        })
+    }
+
+    /// 生成する値を`f`で変換する。
+    pub fn map<F, U>(self, f: F) -> Source<Then<C, Map<F>>>
+    where
+        F: FnMut(C::Yield) -> U,
+    {
+        self.stage(map(f))
+    }
+
+    /// `predicate`が`true`を返す値だけを生成する。
+    pub fn filter<P>(self, predicate: P) -> Source<Filter<C, P>>
+    where
+        P: FnMut(&C::Yield) -> bool + Unpin,
+    {
+        source(Filter {
+            source: self.coroutine,
+            predicate,
+        })
+    }
+
+    /// シンクに接続する。
+    ///
+    /// シンクには生成した値を`Some`で渡し、入力が終端に達したときに`None`を渡す。
+    /// シンクは`None`を受け取ったら完了しなければならない。終端に達する前に完了した場合は、
+    /// 残りの値を生成せずにパイプラインが完了する。
+    pub fn sink<K>(self, sink: K) -> Pipeline<C, K>
+    where
+        K: Coroutine<Option<C::Yield>, Yield = ()> + Unpin,
+    {
+        Pipeline {
+            source: self.coroutine,
+            sink,
+        }
+    }
+
+    /// 生成する値を返すストリームに変換する。
+    ///
+    /// ポーリングするたびにソースを1回再開するため、`Pending`は返さない。
+    pub fn into_stream(self) -> CoroutineStream<C> {
+        CoroutineStream {
+            coroutine: Some(self.coroutine),
+        }
+    }
+}
+
+impl<C> Coroutine<()> for Source<C>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+{
+    type Yield = C::Yield;
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, arg: ()) -> CoroutineState<Self::Yield, ()> {
+        Pin::new(&mut self.coroutine).resume(arg)
+    }
+}
+
+impl<C> IntoIterator for Source<C>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+{
+    type Item = C::Yield;
+    type IntoIter = Iter<C>;
+
+    fn into_iter(self) -> Iter<C> {
+        Iter {
+            coroutine: Some(self.coroutine),
+        }
+    }
+}
+
+/// ソースをシンクに接続したパイプライン
+///
+/// [`Pipeline::run`]で終わるまで実行するか、コルーチンとして1つずつ値を流す。
+/// [`IntoFuture`]を実装しているため、非同期タスクの中では`.await`で実行できる。
+#[derive(Debug)]
+pub struct Pipeline<C, K> {
+    source: C,
+    sink: K,
+}
+
+impl<C, K> Pipeline<C, K>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+    K: Coroutine<Option<C::Yield>, Yield = ()> + Unpin,
+{
+    /// シンクが完了するまで値を流して、シンクの戻り値を返す。
+    ///
+    /// # Panics
+    ///
+    /// 入力が終端に達した後に、シンクが完了しなかった場合
+    pub fn run(mut self) -> K::Return {
+        loop {
+            if let CoroutineState::Complete(output) = Pin::new(&mut self).resume(()) {
+                return output;
+            }
+        }
+    }
+}
+
+impl<C, K> Coroutine<()> for Pipeline<C, K>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+    K: Coroutine<Option<C::Yield>, Yield = ()> + Unpin,
+{
+    type Yield = ();
+    type Return = K::Return;
+
+    /// ソースを1回再開して、生成した値をシンクに渡す。
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<(), K::Return> {
+        let input = match Pin::new(&mut self.source).resume(()) {
+            CoroutineState::Yielded(value) => Some(value),
+            CoroutineState::Complete(()) => None,
+        };
+        let end = input.is_none();
+        match Pin::new(&mut self.sink).resume(input) {
+            CoroutineState::Yielded(()) => {
+                assert!(!end, "sink must complete at the end of input");
+                CoroutineState::Yielded(())
+            }
+            CoroutineState::Complete(output) => CoroutineState::Complete(output),
+        }
+    }
+}
+
+impl<C, K> IntoFuture for Pipeline<C, K>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+    K: Coroutine<Option<C::Yield>, Yield = ()> + Unpin,
+{
+    type Output = K::Return;
+    type IntoFuture = CoroutineFuture<Self>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        into_future(self)
+    }
+}
+
+/// [`Source::stage`]で合成したコルーチン
+#[derive(Debug)]
+pub struct Then<C, S> {
+    source: C,
+    stage: S,
+}
+
+impl<C, S> Coroutine<()> for Then<C, S>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+    S: Coroutine<C::Yield, Return = ()> + Unpin,
+{
+    type Yield = S::Yield;
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<Self::Yield, ()> {
+        match Pin::new(&mut self.source).resume(()) {
+            CoroutineState::Yielded(value) => Pin::new(&mut self.stage).resume(value),
+            CoroutineState::Complete(()) => CoroutineState::Complete(()),
+        }
+    }
+}
+
+/// [`Source::filter`]で合成したコルーチン
+#[derive(Debug)]
+pub struct Filter<C, P> {
+    source: C,
+    predicate: P,
+}
+
+impl<C, P> Coroutine<()> for Filter<C, P>
+where
+    C: Coroutine<(), Return = ()> + Unpin,
+    P: FnMut(&C::Yield) -> bool + Unpin,
+{
+    type Yield = C::Yield;
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<Self::Yield, ()> {
+        loop {
+            match Pin::new(&mut self.source).resume(()) {
+                CoroutineState::Yielded(value) if (self.predicate)(&value) => {
+                    return CoroutineState::Yielded(value);
+                }
+                CoroutineState::Yielded(_) => {}
+                CoroutineState::Complete(()) => return CoroutineState::Complete(()),
+            }
+        }
+    }
+}
+
+/// [`map`]が返すステージ
+#[derive(Debug)]
+pub struct Map<F> {
+    f: F,
+}
+
+impl<F> Unpin for Map<F> {}
+
+impl<T, U, F> Coroutine<T> for Map<F>
+where
+    F: FnMut(T) -> U,
+{
+    type Yield = U;
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, arg: T) -> CoroutineState<U, ()> {
+        CoroutineState::Yielded((self.f)(arg))
+    }
+}
+
+/// [`for_each`]が返すシンク
+#[derive(Debug)]
+pub struct ForEach<F> {
+    f: F,
+}
+
+impl<F> Unpin for ForEach<F> {}
+
+impl<T, F> Coroutine<Option<T>> for ForEach<F>
+where
+    F: FnMut(T),
+{
+    type Yield = ();
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, arg: Option<T>) -> CoroutineState<(), ()> {
+        match arg {
+            Some(value) => {
+                (self.f)(value);
+                CoroutineState::Yielded(())
+            }
+            None => CoroutineState::Complete(()),
+        }
+    }
+}
+
+/// [`try_for_each`]が返すシンク
+#[derive(Debug)]
+pub struct TryForEach<F> {
+    f: F,
+}
+
+impl<F> Unpin for TryForEach<F> {}
+
+impl<T, E, F> Coroutine<Option<T>> for TryForEach<F>
+where
+    F: FnMut(T) -> Result<(), E>,
+{
+    type Yield = ();
+    type Return = Result<(), E>;
+
+    fn resume(mut self: Pin<&mut Self>, arg: Option<T>) -> CoroutineState<(), Result<(), E>> {
+        match arg.map(&mut self.f) {
+            Some(Ok(())) => CoroutineState::Yielded(()),
+            Some(Err(e)) => CoroutineState::Complete(Err(e)),
+            None => CoroutineState::Complete(Ok(())),
+        }
+    }
+}
+
+/// [`collect`]が返すシンク
+#[derive(Debug)]
+pub struct Collect<T> {
+    items: Vec<T>,
+}
+
+impl<T> Unpin for Collect<T> {}
+
+impl<T> Coroutine<Option<T>> for Collect<T> {
+    type Yield = ();
+    type Return = Vec<T>;
+
+    fn resume(mut self: Pin<&mut Self>, arg: Option<T>) -> CoroutineState<(), Vec<T>> {
+        match arg {
+            Some(value) => {
+                self.items.push(value);
+                CoroutineState::Yielded(())
+            }
+            None => CoroutineState::Complete(std::mem::take(&mut self.items)),
+        }
+    }
+}
+
+/// [`from_iter`]が返すコルーチン
+#[derive(Debug)]
+pub struct FromIter<I> {
+    iter: I,
+}
+
+impl<I> Unpin for FromIter<I> {}
+
+impl<I: Iterator> Coroutine<()> for FromIter<I> {
+    type Yield = I::Item;
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<I::Item, ()> {
+        match self.iter.next() {
+            Some(item) => CoroutineState::Yielded(item),
+            None => CoroutineState::Complete(()),
+        }
+    }
+}
+
+/// [`from_stream`]が返すコルーチン
+#[derive(Debug)]
+pub struct FromStream<S> {
+    stream: S,
+}
+
+impl<S: Stream + Unpin> Coroutine<()> for FromStream<S> {
+    type Yield = S::Item;
+    type Return = ();
+
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<S::Item, ()> {
+        match block_on(self.stream.next()) {
+            Some(item) => CoroutineState::Yielded(item),
+            None => CoroutineState::Complete(()),
+        }
+    }
+}
+
+/// [`from_future`]が返すコルーチン
+#[derive(Debug)]
+pub struct FromFuture<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Coroutine<()> for FromFuture<F> {
+    type Yield = ();
+    type Return = F::Output;
+
+    fn resume(mut self: Pin<&mut Self>, _arg: ()) -> CoroutineState<(), F::Output> {
+        let mut cx = Context::from_waker(Waker::noop());
+        match self.future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => CoroutineState::Complete(output),
+            Poll::Pending => CoroutineState::Yielded(()),
+        }
+    }
+}
+
+/// [`into_future`]が返すフューチャー
+#[derive(Debug)]
+pub struct CoroutineFuture<C> {
+    coroutine: C,
+}
+
+impl<C: Coroutine<(), Yield = ()> + Unpin> Future for CoroutineFuture<C> {
+    type Output = C::Return;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<C::Return> {
+        match Pin::new(&mut self.coroutine).resume(()) {
+            CoroutineState::Yielded(()) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            CoroutineState::Complete(output) => Poll::Ready(output),
+        }
+    }
+}
+
+/// ソースを変換したイテレーター
+///
+/// ソースが完了した後は、再開せずに`None`を返し続ける。
+#[derive(Debug)]
+pub struct Iter<C> {
+    coroutine: Option<C>,
+}
+
+impl<C: Coroutine<(), Return = ()> + Unpin> Iterator for Iter<C> {
+    type Item = C::Yield;
+
+    fn next(&mut self) -> Option<C::Yield> {
+        match Pin::new(self.coroutine.as_mut()?).resume(()) {
+            CoroutineState::Yielded(value) => Some(value),
+            CoroutineState::Complete(()) => {
+                self.coroutine = None;
+                None
+            }
+        }
+    }
+}
+
+/// [`Source::into_stream`]が返すストリーム
+#[derive(Debug)]
+pub struct CoroutineStream<C> {
+    coroutine: Option<C>,
+}
+
+impl<C: Coroutine<(), Return = ()> + Unpin> Stream for CoroutineStream<C> {
+    type Item = C::Yield;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<C::Yield>> {
+        let Some(coroutine) = self.coroutine.as_mut() else {
+            return Poll::Ready(None);
+        };
+        match Pin::new(coroutine).resume(()) {
+            CoroutineState::Yielded(value) => Poll::Ready(Some(value)),
+            CoroutineState::Complete(()) => {
+                self.coroutine = None;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
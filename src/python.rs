@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyKeyError, PyTimeoutError, PyValueError},
+    prelude::*,
+};
+
+use crate::{
+    async_mod,
+    job::{JobError, JobId, JobStatus},
+};
+
+create_exception!(
+    async_rust,
+    JobCancelledError,
+    PyException,
+    "ジョブがキャンセルされた。"
+);
+create_exception!(
+    async_rust,
+    JobPanickedError,
+    PyException,
+    "ジョブがパニックした。"
+);
+
+impl From<JobError> for PyErr {
+    fn from(e: JobError) -> Self {
+        let message = e.to_string();
+        match e {
+            JobError::NotFound(_) => PyKeyError::new_err(message),
+            JobError::Timeout(_) => PyTimeoutError::new_err(message),
+            JobError::Cancelled(_) => JobCancelledError::new_err(message),
+            JobError::Panicked { .. } => JobPanickedError::new_err(message),
+        }
+    }
+}
+
+fn parse_id(job_id: &str) -> PyResult<JobId> {
+    job_id
+        .parse()
+        .map_err(|e| PyValueError::new_err(format!("invalid job id {job_id:?}: {e}")))
+}
+
+/// `a + b`を非同期に計算するジョブを投入して、ジョブのIDを返す。
+#[pyfunction]
+fn submit_add(a: i32, b: i32) -> String {
+    async_mod::send_add(a, b).to_string()
+}
+
+/// ジョブの状態を`"pending"`、`"running"`、`"done"`、`"failed"`のいずれかで返す。
+#[pyfunction]
+fn status(job_id: &str) -> PyResult<&'static str> {
+    let status = async_mod::add_jobs().status(parse_id(job_id)?)?;
+    Ok(match status {
+        JobStatus::Pending => "pending",
+        JobStatus::Running => "running",
+        JobStatus::Done => "done",
+        JobStatus::Failed => "failed",
+    })
+}
+
+/// ブロックせずにジョブの結果を回収する。
+///
+/// ジョブが完了していない場合は`None`を返す。
+#[pyfunction]
+fn poll(job_id: &str) -> PyResult<Option<i32>> {
+    Ok(async_mod::add_jobs().poll(parse_id(job_id)?)?)
+}
+
+/// ジョブが完了するまでブロックして、結果を回収する。
+///
+/// `timeout`（秒）を指定した場合、時間内に完了しなければ`TimeoutError`を送出する。
+/// 待機している間はGILを解放するため、他のPythonスレッドは実行を続けられる。
+#[pyfunction]
+#[pyo3(signature = (job_id, timeout=None))]
+fn get_result(py: Python<'_>, job_id: &str, timeout: Option<f64>) -> PyResult<i32> {
+    let id = parse_id(job_id)?;
+    let timeout = match timeout {
+        Some(secs) => Duration::try_from_secs_f64(secs)
+            .map_err(|e| PyValueError::new_err(format!("invalid timeout {secs}: {e}")))?,
+        None => Duration::MAX,
+    };
+    Ok(py.detach(|| async_mod::add_jobs().wait(id, timeout))?)
+}
+
+/// 完了していないジョブをキャンセルする。
+#[pyfunction]
+fn cancel(job_id: &str) -> PyResult<()> {
+    Ok(async_mod::add_jobs().cancel(parse_id(job_id)?)?)
+}
+
+/// ジョブの結果を受け取る`asyncio.Future`を返す。
+///
+/// 実行中のイベントループから呼び出し、`await`して結果を受け取る。
+/// 結果はフューチャーに渡されるため、このジョブを`poll`や`get_result`で回収することはできない。
+#[pyfunction]
+fn result_async<'py>(py: Python<'py>, job_id: &str) -> PyResult<Bound<'py, PyAny>> {
+    let id = parse_id(job_id)?;
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+
+    let (event_loop_ref, future_ref) = (event_loop.unbind(), future.clone().unbind());
+    async_mod::add_jobs().on_complete(id, move |result| {
+        // ランタイムのスレッドで呼び出されるため、イベントループのスレッドで結果を設定する
+        Python::attach(|py| {
+            let (ok, value) = match result {
+                Ok(value) => (true, value.into_pyobject(py).unwrap().into_any().unbind()),
+                Err(e) => (false, PyErr::from(e).into_value(py).into_any()),
+            };
+            let resolve = wrap_pyfunction!(resolve_future, py).unwrap();
+            // イベントループが閉じられている場合は、結果を受け取る相手がいないため無視する
+            let _ = event_loop_ref.call_method1(
+                py,
+                "call_soon_threadsafe",
+                (resolve, future_ref, ok, value),
+            );
+        });
+    })?;
+    Ok(future)
+}
+
+/// イベントループのスレッドで、フューチャーに結果または例外を設定する。
+#[pyfunction]
+fn resolve_future(future: &Bound<'_, PyAny>, ok: bool, value: Bound<'_, PyAny>) -> PyResult<()> {
+    // 待機している側がキャンセルしたフューチャーには設定できない
+    if future.call_method0("done")?.is_truthy()? {
+        return Ok(());
+    }
+    if ok {
+        future.call_method1("set_result", (value,))?;
+    } else {
+        future.call_method1("set_exception", (value,))?;
+    }
+    Ok(())
+}
+
+#[pymodule]
+fn async_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("JobCancelledError", m.py().get_type::<JobCancelledError>())?;
+    m.add("JobPanickedError", m.py().get_type::<JobPanickedError>())?;
+    m.add_function(wrap_pyfunction!(submit_add, m)?)?;
+    m.add_function(wrap_pyfunction!(status, m)?)?;
+    m.add_function(wrap_pyfunction!(poll, m)?)?;
+    m.add_function(wrap_pyfunction!(get_result, m)?)?;
+    m.add_function(wrap_pyfunction!(cancel, m)?)?;
+    m.add_function(wrap_pyfunction!(result_async, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,244 @@
+pub mod clock;
+pub mod thermostat;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures::{Stream, StreamExt};
+
+use crate::sync::{Arc, AtomicUsize, Mutex, MutexGuard, Ordering};
+
+struct State<T> {
+    value: T,
+    /// 値が変わるたびに増やす番号
+    version: u64,
+    /// 値が変わるのを待っている監視者
+    watchers: BTreeMap<u64, Waker>,
+    next_watcher: u64,
+    /// すべてのシグナルのハンドルが破棄された
+    closed: bool,
+}
+
+struct Inner<T> {
+    state: Mutex<State<T>>,
+    /// シグナルのハンドルの数
+    handles: AtomicUsize,
+}
+
+impl<T> Inner<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// 値が変わったときに監視者を起こす、観測可能なセル
+///
+/// 監視者（[`Watcher`]）は、値が変わるまでポーリングされない。
+/// 同じ値を設定しても、監視者は起こされない。
+///
+/// ハンドルを複製して、複数のタスクから値を変更できる。
+/// すべてのハンドルが破棄されると、監視者は終了する。
+pub struct Signal<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Signal<T> {
+    fn clone(&self) -> Self {
+        self.inner.handles.fetch_add(1, Ordering::Relaxed);
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Signal<T> {
+    fn drop(&mut self) {
+        if self.inner.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let mut state = self.inner.lock();
+            state.closed = true;
+            let watchers = std::mem::take(&mut state.watchers);
+            drop(state);
+            watchers.into_values().for_each(Waker::wake);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Signal<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.lock();
+        f.debug_struct("Signal")
+            .field("value", &state.value)
+            .field("version", &state.version)
+            .finish()
+    }
+}
+
+impl<T: Default> Default for Signal<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Signal<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    value,
+                    version: 0,
+                    watchers: BTreeMap::new(),
+                    next_watcher: 1,
+                    closed: false,
+                }),
+                handles: AtomicUsize::new(1),
+            }),
+        }
+    }
+
+    /// 現在の値を参照して、`f`の結果を返す。
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.inner.lock().value)
+    }
+
+    /// 値が変わった回数を返す。
+    pub fn version(&self) -> u64 {
+        self.inner.lock().version
+    }
+
+    /// 値の変化を監視する。
+    ///
+    /// 監視者は、監視を開始した後に値が変わったときに起こされる。
+    pub fn watch(&self) -> Watcher<T> {
+        let mut state = self.inner.lock();
+        let id = state.next_watcher;
+        state.next_watcher += 1;
+        Watcher {
+            id,
+            seen: state.version,
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Signal<T> {
+    pub fn get(&self) -> T {
+        self.inner.lock().value.clone()
+    }
+}
+
+impl<T: Clone + PartialEq> Signal<T> {
+    /// 値を設定する。
+    ///
+    /// 値が変わった場合は、監視者を起こして`true`を返す。
+    pub fn set(&self, value: T) -> bool {
+        self.update(|current| *current = value)
+    }
+
+    /// `f`で値を変更する。
+    ///
+    /// 値が変わった場合は、監視者を起こして`true`を返す。
+    pub fn update(&self, f: impl FnOnce(&mut T)) -> bool {
+        let mut state = self.inner.lock();
+        let previous = state.value.clone();
+        f(&mut state.value);
+        if state.value == previous {
+            return false;
+        }
+        state.version += 1;
+        let watchers = std::mem::take(&mut state.watchers);
+        drop(state);
+        watchers.into_values().for_each(Waker::wake);
+        true
+    }
+}
+
+/// シグナルの値の変化の監視
+///
+/// 値が変わるたびに最新の値を返す[`Stream`]である。
+/// 受け取る前に何度も値が変わった場合は、最新の値だけを返す。
+pub struct Watcher<T> {
+    id: u64,
+    /// 最後に受け取った値の番号
+    seen: u64,
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> fmt::Debug for Watcher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher").field("seen", &self.seen).finish()
+    }
+}
+
+impl<T> Watcher<T> {
+    /// 最後に受け取った後に値が変わった場合に`true`を返す。
+    pub fn has_changed(&self) -> bool {
+        self.inner.lock().version != self.seen
+    }
+}
+
+impl<T: Clone> Watcher<T> {
+    /// 現在の値を返して、受け取ったことにする。
+    pub fn borrow_and_update(&mut self) -> T {
+        let state = self.inner.lock();
+        self.seen = state.version;
+        state.value.clone()
+    }
+
+    /// 値が変わるまで待機して、最新の値を返す。
+    ///
+    /// すべてのシグナルのハンドルが破棄された場合は`None`を返す。
+    pub async fn changed(&mut self) -> Option<T> {
+        self.next().await
+    }
+
+    /// 値が`predicate`を満たすまで待機して、その値を返す。
+    ///
+    /// 現在の値が`predicate`を満たす場合は、待機せずに返す。
+    pub async fn wait_for(&mut self, mut predicate: impl FnMut(&T) -> bool) -> Option<T> {
+        let value = self.borrow_and_update();
+        if predicate(&value) {
+            return Some(value);
+        }
+        while let Some(value) = self.changed().await {
+            if predicate(&value) {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+impl<T: Clone> Stream for Watcher<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.inner.lock();
+        if state.version != self.seen {
+            let value = state.value.clone();
+            let version = state.version;
+            drop(state);
+            self.seen = version;
+            return Poll::Ready(Some(value));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        match state.watchers.get_mut(&self.id) {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => {
+                state.watchers.insert(self.id, cx.waker().clone());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Watcher<T> {
+    fn drop(&mut self) {
+        self.inner.lock().watchers.remove(&self.id);
+    }
+}
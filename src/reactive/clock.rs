@@ -0,0 +1,116 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+/// シミュレーションの時計
+///
+/// シミュレーションの時間は、実際の時間の`speed`倍の速さで進む。
+/// 例えば、速さを1000にすると、シミュレーションの3秒は実際の3ミリ秒になるため、テストを短時間で実行できる。
+///
+/// 複製した時計は、同じ時刻を指す。
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    origin: Instant,
+    speed: f64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    /// 実際の時間と同じ速さで進む時計を作成する。
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            speed: 1.0,
+        }
+    }
+
+    /// 実際の時間の何倍の速さで進むかを指定する。
+    ///
+    /// # Panics
+    ///
+    /// `speed`が正の有限な値でない場合
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(
+            speed.is_finite() && speed > 0.0,
+            "clock speed must be positive"
+        );
+        self.speed = speed;
+        self
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// 時計を作成してから経過した、シミュレーションの時間を返す。
+    pub fn elapsed(&self) -> Duration {
+        self.origin.elapsed().mul_f64(self.speed)
+    }
+
+    /// シミュレーションの時間で`duration`だけ待機する。
+    pub async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(self.real_duration(duration)).await;
+    }
+
+    /// シミュレーションの時間で`period`ごとに刻む[`Ticker`]を作成する。
+    ///
+    /// 最初の刻みは、`period`が経過したときである。
+    pub fn ticker(&self, period: Duration) -> Ticker {
+        // 周期が0のタイマーは作成できない
+        let period = self.real_duration(period).max(Duration::from_nanos(1));
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        // 遅れた刻みをまとめて返さずに、遅れた分だけ後にずらす
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Ticker {
+            clock: *self,
+            interval,
+        }
+    }
+
+    fn real_duration(&self, duration: Duration) -> Duration {
+        duration.div_f64(self.speed)
+    }
+}
+
+/// 一定の周期で刻む時計
+///
+/// 刻むたびに、シミュレーションの時刻（[`Clock::elapsed`]）を返す[`Stream`]である。
+/// 次の刻みまではタイマーで待機するため、ポーリングし続けることはない。
+#[derive(Debug)]
+pub struct Ticker {
+    clock: Clock,
+    interval: Interval,
+}
+
+impl Ticker {
+    /// 次の刻みまで待機する。
+    pub async fn tick(&mut self) -> Duration {
+        self.interval.tick().await;
+        self.clock.elapsed()
+    }
+
+    /// 次の刻みを、現在から1周期後にする。
+    pub fn reset(&mut self) {
+        self.interval.reset();
+    }
+}
+
+impl Stream for Ticker {
+    type Item = Duration;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Duration>> {
+        self.interval
+            .poll_tick(cx)
+            .map(|_| Some(self.clock.elapsed()))
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use tokio_util::task::AbortOnDropHandle;
+
+use super::{Signal, clock::Clock};
+
+/// 温度（1/100度単位）
+pub type Centidegrees = i16;
+
+/// 温度の変化の設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    /// 1周期あたりの温度の変化の大きさ
+    pub step: Centidegrees,
+    /// シミュレーションの時間での周期
+    pub period: Duration,
+}
+
+impl Rate {
+    pub const fn new(step: Centidegrees, period: Duration) -> Self {
+        Self { step, period }
+    }
+}
+
+/// ヒーターで部屋の温度を設定温度に保つサーモスタット
+///
+/// 現在の温度、設定温度、ヒーターの状態を[`Signal`]として公開するため、
+/// 表示や入力を行うタスクは、値が変わったときだけ処理できる。
+/// サーモスタットごとに状態を持つため、1つのプロセスで複数のサーモスタットを動かせる。
+///
+/// ヒーターは、現在の温度が設定温度より低いときにONにし、高いときにOFFにする。
+/// ONの間は周期ごとに温度が上がり、ヒーターの状態によらず周期ごとに熱が失われる。
+#[derive(Debug, Clone)]
+pub struct Thermostat {
+    temperature: Signal<Centidegrees>,
+    desired: Signal<Centidegrees>,
+    heater_on: Signal<bool>,
+    heating: Rate,
+    heat_loss: Rate,
+}
+
+impl Thermostat {
+    /// ヒーターで3秒ごとに0.03度上がり、3秒ごとに0.01度下がるサーモスタットを作成する。
+    pub fn new(temperature: Centidegrees, desired: Centidegrees) -> Self {
+        Self {
+            temperature: Signal::new(temperature),
+            desired: Signal::new(desired),
+            heater_on: Signal::new(false),
+            heating: Rate::new(3, Duration::from_secs(3)),
+            heat_loss: Rate::new(1, Duration::from_secs(3)),
+        }
+    }
+
+    /// ヒーターをONにしている間の温度の上がり方を指定する。
+    pub fn with_heating(mut self, heating: Rate) -> Self {
+        self.heating = heating;
+        self
+    }
+
+    /// 熱が失われて温度が下がる速さを指定する。
+    pub fn with_heat_loss(mut self, heat_loss: Rate) -> Self {
+        self.heat_loss = heat_loss;
+        self
+    }
+
+    /// 現在の温度
+    pub fn temperature(&self) -> &Signal<Centidegrees> {
+        &self.temperature
+    }
+
+    /// 設定温度（変更すると、直ちにヒーターの状態に反映される）
+    pub fn desired(&self) -> &Signal<Centidegrees> {
+        &self.desired
+    }
+
+    /// ヒーターがONかどうか
+    pub fn heater_on(&self) -> &Signal<bool> {
+        &self.heater_on
+    }
+
+    /// サーモスタットを動かすタスクを起動する。
+    ///
+    /// 返したハンドルを破棄すると、タスクを中止する。
+    pub fn spawn(&self, clock: Clock) -> AbortOnDropHandle<()> {
+        AbortOnDropHandle::new(tokio::spawn(self.clone().run(clock)))
+    }
+
+    /// サーモスタットを動かす。
+    ///
+    /// すべてのループは値の変化かタイマーを待機するため、ポーリングし続けることはない。
+    pub async fn run(self, clock: Clock) {
+        tokio::join!(self.control(), self.heat(clock), self.lose_heat(clock));
+    }
+
+    /// 温度または設定温度が変わるたびに、ヒーターをONにするかOFFにするかを決める。
+    async fn control(&self) {
+        let mut temperature = self.temperature.watch();
+        let mut desired = self.desired.watch();
+        loop {
+            let current = temperature.borrow_and_update();
+            let target = desired.borrow_and_update();
+            if current < target {
+                self.heater_on.set(true);
+            } else if current > target {
+                self.heater_on.set(false);
+            }
+            tokio::select! {
+                _ = temperature.changed() => {}
+                _ = desired.changed() => {}
+            }
+        }
+    }
+
+    /// ヒーターがONの間、周期ごとに温度を上げる。
+    async fn heat(&self, clock: Clock) {
+        let mut heater_on = self.heater_on.watch();
+        loop {
+            heater_on.wait_for(|&on| on).await;
+            // ONになってから1周期が経過するまでに、OFFになった場合は温度を上げない
+            tokio::select! {
+                _ = clock.sleep(self.heating.period) => {
+                    self.temperature.update(|t| *t = t.saturating_add(self.heating.step));
+                }
+                _ = heater_on.wait_for(|&on| !on) => {}
+            }
+        }
+    }
+
+    /// 周期ごとに温度を下げる。
+    async fn lose_heat(&self, clock: Clock) {
+        let mut ticker = clock.ticker(self.heat_loss.period);
+        loop {
+            ticker.tick().await;
+            self.temperature
+                .update(|t| *t = t.saturating_sub(self.heat_loss.step));
+        }
+    }
+}
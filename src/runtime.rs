@@ -1,104 +1,417 @@
-use std::{panic::catch_unwind, sync::LazyLock, time::Duration};
+pub mod sim;
+pub mod time;
+
+use std::{
+    cell::Cell,
+    panic::catch_unwind,
+    pin::Pin,
+    sync::{LazyLock, Mutex},
+    task::{Context, Poll},
+    thread::ThreadId,
+    time::Duration,
+};
 
 use async_task::{Runnable, Task};
 use flume::{Receiver, Sender};
 
-use crate::{join, spawn_task};
+use crate::{
+    join, spawn_task, task_local,
+    trace::{self, TaskEventKind},
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FutureType {
     High,
     Low,
 }
 
-pub fn spawn_task_function<F, T>(future: F, order: FutureType) -> Task<T>
-where
-    F: Future<Output = T> + Send + 'static,
-    T: Send + 'static,
-{
-    // 優先度が高いタスクキュー
-    static HIGH_CHANNEL: LazyLock<(Sender<Runnable>, Receiver<Runnable>)> =
-        LazyLock::new(flume::unbounded::<Runnable>);
-    // 優先度が低いタスクキュー
-    static LOW_CHANNEL: LazyLock<(Sender<Runnable>, Receiver<Runnable>)> =
-        LazyLock::new(flume::unbounded::<Runnable>);
-
-    // 優先度が高いキューを優先的に処理するスレッドを2つ起動し、HIGH_CHANNELへのSenderを返す
-    static HIGH_QUEUE: LazyLock<flume::Sender<Runnable>> = LazyLock::new(|| {
-        let high_num = std::env::var("HIGH_NUM").unwrap().parse::<usize>().unwrap();
-        for _ in 0..high_num {
-            let high_receiver = HIGH_CHANNEL.1.clone();
-            let low_receiver = LOW_CHANNEL.1.clone();
-            std::thread::spawn(move || {
-                loop {
-                    match high_receiver.try_recv() {
-                        Ok(runnable) => {
-                            let _ = catch_unwind(|| runnable.run());
-                        }
-                        Err(_) => match low_receiver.try_recv() {
-                            Ok(runnable) => {
-                                let _ = catch_unwind(|| runnable.run());
-                            }
-                            Err(_) => {
-                                std::thread::sleep(Duration::from_millis(100));
-                            }
-                        },
-                    }
-                }
-            });
+/// タスクに付与するメタデータ
+///
+/// タスクを識別するIDと、タスクを生成したときに指定された優先度を保持する。
+#[derive(Debug, Clone, Copy)]
+pub struct TaskInfo {
+    id: u64,
+    priority: FutureType,
+}
+
+impl TaskInfo {
+    fn new(priority: FutureType) -> Self {
+        Self {
+            id: async_runtime::trace::next_task_id(),
+            priority,
         }
-        HIGH_CHANNEL.0.clone()
-    });
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn priority(&self) -> FutureType {
+        self.priority
+    }
+}
+
+/// タスクが完了せずに破棄されたことを検出するガード
+///
+/// タスクのフューチャーに所有させ、フューチャーが完了する前に破棄された場合に、
+/// キャンセルイベントを通知する。
+struct CancelGuard {
+    info: TaskInfo,
+    completed: bool,
+}
+
+impl CancelGuard {
+    /// 完了イベントは、ワーカーがポーリングの終了を通知した後に通知する。
+    fn complete(&mut self) {
+        self.completed = true;
+        COMPLETED.with(|completed| completed.set(true));
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        // パニックによる破棄は、ワーカーがパニックイベントとして通知する
+        if !self.completed && !std::thread::panicking() {
+            trace::emit(TaskEventKind::Cancelled, self.info);
+        }
+    }
+}
+
+/// タスクキューの送信側と受信側
+type Channel = (Sender<Runnable<TaskInfo>>, Receiver<Runnable<TaskInfo>>);
+
+// 優先度が高いタスクキュー
+static HIGH_CHANNEL: LazyLock<Channel> = LazyLock::new(flume::unbounded::<Runnable<TaskInfo>>);
+// 優先度が低いタスクキュー
+static LOW_CHANNEL: LazyLock<Channel> = LazyLock::new(flume::unbounded::<Runnable<TaskInfo>>);
 
-    // 優先度が低いキューを処理するスレッドを起動し、LOW_CHANNELのSenderを返す
-    static LOW_QUEUE: LazyLock<flume::Sender<Runnable>> = LazyLock::new(|| {
-        let low_num = std::env::var("LOW_NUM").unwrap().parse::<usize>().unwrap();
-        for _ in 0..low_num {
-            let low_receiver = LOW_CHANNEL.1.clone();
-            let high_receiver = HIGH_CHANNEL.1.clone();
-            std::thread::spawn(move || {
-                loop {
-                    match low_receiver.try_recv() {
-                        Ok(runnable) => {
-                            let _ = catch_unwind(|| runnable.run());
-                        }
-                        Err(_) => match high_receiver.try_recv() {
-                            Ok(runnable) => {
-                                let _ = catch_unwind(|| runnable.run());
-                            }
-                            Err(_) => {
-                                std::thread::sleep(Duration::from_millis(100));
-                            }
-                        },
-                    }
-                }
-            });
+// 優先度が高いキューを優先的に処理するスレッドを起動し、HIGH_CHANNELへのSenderを返す
+// 初期化の競合はloomでモデル検査していない（crate::syncを参照）
+static HIGH_QUEUE: LazyLock<Sender<Runnable<TaskInfo>>> = LazyLock::new(|| {
+    let high_num = std::env::var("HIGH_NUM").unwrap().parse::<usize>().unwrap();
+    for _ in 0..high_num {
+        spawn_worker(FutureType::High);
+    }
+    HIGH_CHANNEL.0.clone()
+});
+
+// 優先度が低いキューを優先的に処理するスレッドを起動し、LOW_CHANNELのSenderを返す
+static LOW_QUEUE: LazyLock<Sender<Runnable<TaskInfo>>> = LazyLock::new(|| {
+    let low_num = std::env::var("LOW_NUM").unwrap().parse::<usize>().unwrap();
+    for _ in 0..low_num {
+        spawn_worker(FutureType::Low);
+    }
+    LOW_CHANNEL.0.clone()
+});
+
+/// ワーカーの優先度と、そのワーカーだけが処理するタスクキューの送信側
+struct Worker {
+    priority: FutureType,
+    local: Sender<Runnable<TaskInfo>>,
+}
+
+/// 起動したワーカー
+///
+/// ワーカーのIDは、このベクタのインデックスと一致する。
+static WORKERS: Mutex<Vec<Worker>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// 現在のスレッドがワーカーの場合、そのワーカーのID
+    ///
+    /// トレースのワーカーIDは他のエグゼキューターのスレッドにも設定されるため、
+    /// [`spawn_local`]の振り分けにはこちらを使う。
+    static WORKER_ID: Cell<Option<usize>> = const { Cell::new(None) };
+    /// 現在のスレッドでポーリングしたタスクが完了した
+    static COMPLETED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// ワーカースレッドを起動する。
+///
+/// ワーカーは、自分の優先度のキュー、自分専用のキュー、もう一方の優先度のキューの順にタスクを取り出す。
+fn spawn_worker(priority: FutureType) {
+    let (primary, secondary) = match priority {
+        FutureType::High => (HIGH_CHANNEL.1.clone(), LOW_CHANNEL.1.clone()),
+        FutureType::Low => (LOW_CHANNEL.1.clone(), HIGH_CHANNEL.1.clone()),
+    };
+    let (local_sender, local) = flume::unbounded();
+    let worker_id = {
+        let mut workers = WORKERS.lock().unwrap();
+        workers.push(Worker {
+            priority,
+            local: local_sender,
+        });
+        workers.len() - 1
+    };
+    std::thread::spawn(move || {
+        WORKER_ID.with(|id| id.set(Some(worker_id)));
+        trace::set_worker_id(worker_id);
+        loop {
+            if let Ok(runnable) = primary.try_recv() {
+                run_task(runnable);
+            } else if let Ok(runnable) = local.try_recv() {
+                run_task(runnable);
+            } else if let Ok(runnable) = secondary.try_recv() {
+                run_task(runnable);
+            } else {
+                std::thread::sleep(Duration::from_millis(100));
+            }
         }
-        LOW_CHANNEL.0.clone()
     });
+}
+
+/// 起動しているワーカーの数を返す。
+///
+/// ワーカーが起動していない場合は起動する。
+/// [`spawn_pinned`]には、0からこの数未満のインデックスを指定できる。
+pub fn worker_count() -> usize {
+    LazyLock::force(&HIGH_QUEUE);
+    LazyLock::force(&LOW_QUEUE);
+    WORKERS.lock().unwrap().len()
+}
 
-    // 優先度が高いキューにタスクを送信するクロージャー
-    let schedule_high = |runnable| HIGH_QUEUE.send(runnable).unwrap();
-    // 優先度が低いキューにタスクを送信するクロージャー
-    let schedule_low = |runnable| LOW_QUEUE.send(runnable).unwrap();
+/// タスクを1回ポーリングする。
+///
+/// タスク内で発生したパニックはワーカーに伝搬させない。
+fn run_task(runnable: Runnable<TaskInfo>) {
+    let info = *runnable.metadata();
+    trace::emit(TaskEventKind::PollStarted, info);
+    let result = catch_unwind(|| runnable.run());
+    let completed = COMPLETED.with(|completed| completed.replace(false));
+    match result {
+        Ok(_) => {
+            trace::emit(TaskEventKind::PollEnded, info);
+            if completed {
+                trace::emit(TaskEventKind::Completed, info);
+            }
+        }
+        Err(_) => trace::emit(TaskEventKind::Panicked, info),
+    }
+}
+
+/// タスクを生成して、`queue`に投入する。
+///
+/// タスクが起床されたときも`queue`に投入される。
+fn spawn_with<F, Q>(future: F, info: TaskInfo, queue: Q) -> Task<F::Output, TaskInfo>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+    Q: Fn(Runnable<TaskInfo>) + Clone + Send + Sync + 'static,
+{
+    // タスクが起床されたときに呼び出されるスケジューラ
+    let schedule = {
+        let queue = queue.clone();
+        move |runnable: Runnable<TaskInfo>| {
+            trace::emit(TaskEventKind::Woken, *runnable.metadata());
+            queue(runnable);
+        }
+    };
 
-    // 引数で渡された優先度によってスケジューラを切り替える
-    let schedule = match order {
-        FutureType::High => schedule_high,
-        FutureType::Low => schedule_low,
+    // タスクの完了を通知するようにフューチャーをラップ
+    let mut guard = CancelGuard {
+        info,
+        completed: false,
     };
+    // タスクローカル変数の格納場所をタスクに持たせる
+    let future = task_local::scope(async move {
+        let output = future.await;
+        guard.complete();
+        output
+    });
 
     // タスクを生成
-    let (runnable, task) = async_task::spawn(future, schedule);
+    let (runnable, task) = async_task::Builder::new()
+        .metadata(info)
+        .spawn(move |_| future, schedule);
+    trace::emit(TaskEventKind::Spawned, info);
     // タスクをスケジューリング（エグゼキューターのキューに投入）
-    runnable.schedule();
+    queue(runnable);
     // block_onで待ち合わせ可能なタスクハンドルを返す
     task
 }
 
+pub fn spawn_task_function<F, T>(future: F, order: FutureType) -> Task<T, TaskInfo>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    // 優先度が高いキューにタスクを送信する関数
+    fn queue_high(runnable: Runnable<TaskInfo>) {
+        HIGH_QUEUE.send(runnable).unwrap();
+    }
+    // 優先度が低いキューにタスクを送信する関数
+    fn queue_low(runnable: Runnable<TaskInfo>) {
+        LOW_QUEUE.send(runnable).unwrap();
+    }
+
+    // シミュレーションの中では、シミュレーションのキューに投入する
+    if let Some(queue) = sim::scheduler() {
+        return spawn_with(future, TaskInfo::new(order), queue);
+    }
+    // 引数で渡された優先度によってキューを切り替える
+    let queue: fn(Runnable<TaskInfo>) = match order {
+        FutureType::High => queue_high,
+        FutureType::Low => queue_low,
+    };
+    spawn_with(future, TaskInfo::new(order), queue)
+}
+
+/// 起床されるたびに、呼び出したスレッドのTokioランタイムでポーリングするタスクを生成する。
+///
+/// アクターのようにTokioランタイムで実行するタスクも、[`sim::Simulation`]の中ではシミュレーションのキューに投入して、
+/// 仮想時間と決定的な順序で実行できるようにする。
+/// タスクハンドルを破棄するとタスクは中止されるため、待ち合わせない場合は`detach`する。
+///
+/// # Panics
+///
+/// シミュレーションの外で、Tokioランタイムの外から呼び出した場合。
+pub(crate) fn spawn_on_tokio<F>(future: F) -> Task<F::Output, TaskInfo>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let info = TaskInfo::new(FutureType::Low);
+    if let Some(queue) = sim::scheduler() {
+        return spawn_with(future, info, queue);
+    }
+    let handle = tokio::runtime::Handle::current();
+    spawn_with(future, info, move |runnable| {
+        handle.spawn(async move { run_task(runnable) });
+    })
+}
+
+/// 特定のワーカーでのみ生成、ポーリング、破棄されるフューチャー
+///
+/// `Send`でないフューチャーを、ワーカー間で移動しないタスクとして実行するために使用する。
+/// フューチャーを生成するクロージャーは`Send`であるため、どのスレッドでも保持できる。
+/// フューチャーを生成した後は、そのフューチャーを生成したスレッド以外でポーリングするとパニックし、
+/// 破棄しようとした場合は破棄せずにリークさせる。
+struct Pinned<C, F> {
+    create: Option<C>,
+    future: Option<(Pin<Box<F>>, ThreadId)>,
+}
+
+// SAFETY: `Send`でないフューチャーは、それを生成したスレッドでのみポーリングおよび破棄する。
+unsafe impl<C: Send, F> Send for Pinned<C, F> {}
+
+// クロージャーはピン留めせずに取り出して呼び出し、フューチャーはボックスでピン留めしている。
+impl<C, F> Unpin for Pinned<C, F> {}
+
+impl<C, F> Pinned<C, F>
+where
+    C: FnOnce() -> F,
+    F: Future,
+{
+    fn new(create: C) -> Self {
+        Self {
+            create: Some(create),
+            future: None,
+        }
+    }
+
+    /// 現在のスレッドで生成されたフューチャーから作成する。
+    fn local(future: F) -> Self {
+        Self {
+            create: None,
+            future: Some((Box::pin(future), std::thread::current().id())),
+        }
+    }
+}
+
+impl<C, F> Future for Pinned<C, F>
+where
+    C: FnOnce() -> F,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(create) = this.create.take() {
+            this.future = Some((Box::pin(create()), std::thread::current().id()));
+        }
+        let (future, thread) = this.future.as_mut().expect("polled after completion");
+        assert_eq!(
+            *thread,
+            std::thread::current().id(),
+            "pinned task polled by a thread that didn't create it"
+        );
+        future.as_mut().poll(cx)
+    }
+}
+
+impl<C, F> Drop for Pinned<C, F> {
+    fn drop(&mut self) {
+        if let Some((future, thread)) = self.future.take()
+            && thread != std::thread::current().id()
+        {
+            // 別のスレッドで破棄するとメモリ安全性を保証できないため、リークさせる
+            std::mem::forget(future);
+        }
+    }
+}
+
+/// `Send`でないフューチャーを、現在のワーカーに固定されたタスクとして生成する。
+///
+/// タスクは現在のワーカーだけでポーリングされるため、`Rc`などの`Send`でない値を保持できる。
+/// 返されるタスクハンドルは、他のワーカーで実行されているタスクからも待ち合わせできる。
+///
+/// # Panics
+///
+/// ランタイムのワーカー以外のスレッドから呼び出した場合（[`sim::Simulation`]の中を除く）。
+pub fn spawn_local<F>(future: F) -> Task<F::Output, TaskInfo>
+where
+    F: Future + 'static,
+    F::Output: Send + 'static,
+{
+    if let Some(queue) = sim::scheduler() {
+        let future = Pinned::<fn() -> F, F>::local(future);
+        return spawn_with(future, TaskInfo::new(FutureType::Low), queue);
+    }
+    let worker_id = WORKER_ID
+        .with(Cell::get)
+        .expect("spawn_local must be called from a runtime worker");
+    spawn_on_worker(worker_id, Pinned::<fn() -> F, F>::local(future))
+}
+
+/// クロージャーで生成したフューチャーを、指定したワーカーに固定されたタスクとして生成する。
+///
+/// フューチャーはワーカー上でクロージャーを呼び出して生成するため、`Send`である必要はない。
+/// ワーカーのインデックスは0から[`worker_count`]未満で指定する。
+///
+/// # Panics
+///
+/// 指定したインデックスのワーカーが存在しない場合。
+pub fn spawn_pinned<C, F>(idx: usize, create: C) -> Task<F::Output, TaskInfo>
+where
+    C: FnOnce() -> F + Send + 'static,
+    F: Future + 'static,
+    F::Output: Send + 'static,
+{
+    let count = worker_count();
+    assert!(idx < count, "worker index {idx} out of range (0..{count})");
+    spawn_on_worker(idx, Pinned::new(create))
+}
+
+fn spawn_on_worker<C, F>(worker_id: usize, future: Pinned<C, F>) -> Task<F::Output, TaskInfo>
+where
+    C: FnOnce() -> F + Send + 'static,
+    F: Future + 'static,
+    F::Output: Send + 'static,
+{
+    let (priority, local) = {
+        let workers = WORKERS.lock().unwrap();
+        let worker = &workers[worker_id];
+        (worker.priority, worker.local.clone())
+    };
+    let queue = move |runnable| local.send(runnable).unwrap();
+    spawn_with(future, TaskInfo::new(priority), queue)
+}
+
 pub struct Runtime {
     high_num: usize,
     low_num: usize,
+    max_blocking_num: Option<usize>,
+    blocking_keep_alive: Option<Duration>,
 }
 
 impl Runtime {
@@ -106,8 +419,10 @@ impl Runtime {
     pub fn new() -> Self {
         let num_cores = std::thread::available_parallelism().unwrap().get();
         Self {
-            high_num: num_cores - 2,
+            high_num: num_cores.saturating_sub(2).max(1),
             low_num: 1,
+            max_blocking_num: None,
+            blocking_keep_alive: None,
         }
     }
 
@@ -119,10 +434,27 @@ impl Runtime {
         self.low_num = num;
         self
     }
+    /// [`spawn_blocking`](crate::blocking::spawn_blocking)で使用するスレッドの最大数を設定する。
+    pub fn with_max_blocking_num(mut self, num: usize) -> Self {
+        self.max_blocking_num = Some(num);
+        self
+    }
+    /// [`spawn_blocking`](crate::blocking::spawn_blocking)で使用するスレッドが、
+    /// 処理がない状態で終了するまでの時間を設定する。
+    pub fn with_blocking_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.blocking_keep_alive = Some(keep_alive);
+        self
+    }
     pub fn run(&self) {
         unsafe {
             std::env::set_var("HIGH_NUM", self.high_num.to_string());
             std::env::set_var("LOW_NUM", self.low_num.to_string());
+            if let Some(num) = self.max_blocking_num {
+                std::env::set_var("MAX_BLOCKING_NUM", num.to_string());
+            }
+            if let Some(keep_alive) = self.blocking_keep_alive {
+                std::env::set_var("BLOCKING_KEEP_ALIVE_MS", keep_alive.as_millis().to_string());
+            }
         }
 
         let high = spawn_task!(async {}, FutureType::High);
@@ -0,0 +1,266 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    pin::pin,
+    sync::{
+        Arc, Mutex, MutexGuard, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use async_task::Runnable;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use super::{TaskInfo, run_task};
+
+/// 再現するシミュレーションのシードを指定する環境変数
+pub const SEED_ENV: &str = "ASYNC_RUST_SIM_SEED";
+
+/// 仮想時間のタイマーを識別するID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct TimerId(u64);
+
+#[derive(Default)]
+struct State {
+    /// シミュレーションを開始してから経過した仮想時間
+    now: Duration,
+    /// ポーリングできるタスク
+    ready: Vec<Runnable<TaskInfo>>,
+    timers: BTreeMap<TimerId, (Duration, Waker)>,
+    next_timer: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// 最も早いタイマーの時刻まで仮想時間を進めて、その時刻のタイマーを起こす。
+    ///
+    /// タイマーがない場合は`false`を返す。
+    fn advance(&self) -> bool {
+        let mut state = self.lock();
+        let Some(deadline) = state.timers.values().map(|&(deadline, _)| deadline).min() else {
+            return false;
+        };
+        state.now = state.now.max(deadline);
+        let now = state.now;
+        let expired: Vec<_> = state
+            .timers
+            .extract_if(.., |_, (deadline, _)| *deadline <= now)
+            .map(|(_, (_, waker))| waker)
+            .collect();
+        drop(state);
+        expired.into_iter().for_each(Waker::wake);
+        true
+    }
+
+    /// 残っているタスクとタイマーを破棄する。
+    fn clear(&self) {
+        let mut state = self.lock();
+        let ready = std::mem::take(&mut state.ready);
+        let timers = std::mem::take(&mut state.timers);
+        // タスクを破棄するとタイマーが解除されるため、ロックを解放してから破棄する
+        drop(state);
+        drop(ready);
+        drop(timers);
+    }
+}
+
+thread_local! {
+    /// 現在のスレッドで実行しているシミュレーション
+    static CURRENT: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+}
+
+fn with_current<R>(f: impl FnOnce(&Shared) -> R) -> Option<R> {
+    CURRENT.with_borrow(|current| current.as_deref().map(f))
+}
+
+/// シミュレーションを実行している間、現在のスレッドにシミュレーションを設定するガード
+struct Enter;
+
+impl Enter {
+    fn new(shared: Arc<Shared>) -> Self {
+        CURRENT.with_borrow_mut(|current| {
+            assert!(current.is_none(), "simulations cannot be nested");
+            *current = Some(shared);
+        });
+        Self
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        CURRENT.set(None);
+    }
+}
+
+/// 仮想時間の現在時刻（シミュレーションを開始してからの経過時間）を返す。
+///
+/// シミュレーションの外では`None`を返す。
+pub fn now() -> Option<Duration> {
+    with_current(|shared| shared.lock().now)
+}
+
+pub(crate) fn register_timer(deadline: Duration, waker: Waker) -> Option<TimerId> {
+    with_current(|shared| {
+        let mut state = shared.lock();
+        let id = TimerId(state.next_timer);
+        state.next_timer += 1;
+        state.timers.insert(id, (deadline, waker));
+        id
+    })
+}
+
+pub(crate) fn update_timer(id: TimerId, waker: &Waker) {
+    with_current(|shared| {
+        if let Some((_, current)) = shared.lock().timers.get_mut(&id) {
+            current.clone_from(waker);
+        }
+    });
+}
+
+pub(crate) fn cancel_timer(id: TimerId) {
+    let waker = with_current(|shared| shared.lock().timers.remove(&id)).flatten();
+    drop(waker);
+}
+
+/// 現在のスレッドでシミュレーションを実行している場合は、タスクをシミュレーションに投入する関数を返す。
+pub(super) fn scheduler() -> Option<impl Fn(Runnable<TaskInfo>) + Clone + Send + Sync + 'static> {
+    CURRENT.with_borrow(|current| {
+        let shared: Weak<Shared> = Arc::downgrade(current.as_ref()?);
+        Some(move |runnable| {
+            // シミュレーションが終了した後に起こされたタスクは破棄する
+            if let Some(shared) = shared.upgrade() {
+                shared.lock().ready.push(runnable);
+            }
+        })
+    })
+}
+
+/// メインのフューチャーが起こされたかを記録するウェイカー
+#[derive(Default)]
+struct MainWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for MainWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+}
+
+/// 仮想時間で、タスクを決定的に実行するテスト用のエグゼキューター
+///
+/// [`Simulation::block_on`]で実行している間、[`spawn_task!`](crate::spawn_task)と
+/// [`spawn_local`](super::spawn_local)で生成したタスクは、ワーカーではなく現在のスレッドで実行される。
+/// [`crate::actor`]のアクターとスーパーバイザーも、シミュレーションの中で起動するとシミュレーションのタスクとして実行される。
+/// ポーリングできるタスクが複数ある場合は、シードから生成した乱数で次にポーリングするタスクを選ぶため、
+/// 同じシードで実行すると、同じ順序でタスクがポーリングされる。
+///
+/// すべてのタスクが待機している場合は、最も早いタイマーの時刻まで仮想時間を直ちに進める。
+/// [`super::time::sleep`]と[`super::time::timeout`]は仮想時間で待機するため、実際には待機しない。
+/// アクターの応答のタイムアウトや停滞の検出、スーパーバイザーの再起動の待機時間も仮想時間で計る。
+///
+/// シミュレーションがパニックした場合は、シードを標準エラー出力に出力する。
+/// 環境変数[`SEED_ENV`]にシードを指定して[`Simulation::from_env`]で実行すると、同じ実行順序を再現できる。
+///
+/// 他のスレッドで実行される処理（[`spawn_blocking`](crate::blocking::spawn_blocking)やTokioのタスク）は、
+/// シミュレーションでは制御できない。
+#[derive(Debug, Clone, Copy)]
+pub struct Simulation {
+    seed: u64,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// 環境変数[`SEED_ENV`]のシードで作成する。
+    ///
+    /// 環境変数がない場合は、ランダムなシードを使用する。
+    ///
+    /// # Panics
+    ///
+    /// 環境変数が整数でない場合
+    pub fn from_env() -> Self {
+        let seed = match std::env::var(SEED_ENV) {
+            Ok(seed) => seed
+                .parse()
+                .unwrap_or_else(|_| panic!("{SEED_ENV} must be an integer, got `{seed}`")),
+            Err(_) => rand::random(),
+        };
+        Self::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// フューチャーが完了するまでシミュレーションを実行して、その出力を返す。
+    ///
+    /// フューチャーが完了したときに残っているタスクは破棄する。
+    ///
+    /// # Panics
+    ///
+    /// フューチャーがパニックした場合と、タイマーがないのにすべてのタスクが待機している（デッドロックした）場合
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let shared = Arc::new(Shared::default());
+        let enter = Enter::new(shared.clone());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.run(&shared, future)));
+        shared.clear();
+        drop(enter);
+        result.unwrap_or_else(|payload| {
+            eprintln!(
+                "simulation failed with seed {seed}; rerun with {SEED_ENV}={seed} to replay it",
+                seed = self.seed
+            );
+            panic::resume_unwind(payload)
+        })
+    }
+
+    fn run<F: Future>(&self, shared: &Shared, future: F) -> F::Output {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let main = Arc::new(MainWaker {
+            woken: AtomicBool::new(true),
+        });
+        let waker = Waker::from(main.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            let ready = shared.lock().ready.len();
+            let candidates = ready + usize::from(main.woken.load(Ordering::SeqCst));
+            if candidates == 0 {
+                assert!(
+                    shared.advance(),
+                    "simulation deadlocked: every task is waiting and no timer is set"
+                );
+                continue;
+            }
+            // メインのフューチャーは、最後の候補とする
+            let pick = rng.random_range(0..candidates);
+            if pick == ready {
+                main.woken.store(false, Ordering::SeqCst);
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            } else {
+                let runnable = shared.lock().ready.swap_remove(pick);
+                run_task(runnable);
+            }
+        }
+    }
+}
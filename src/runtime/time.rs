@@ -0,0 +1,229 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    pin::Pin,
+    sync::{Condvar, LazyLock, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use super::sim::{self, TimerId};
+
+/// 実時間のタイマーを管理し、期限を過ぎたタイマーのウェイカーを起こすスレッド
+///
+/// タイマーは期限の順に並べ、最も早い期限まで条件変数で待機する。
+struct TimerThread {
+    state: Mutex<TimerState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct TimerState {
+    /// 期限と識別子をキーにした、登録されているタイマー
+    timers: BTreeMap<(Instant, u64), Waker>,
+    next_timer: u64,
+}
+
+static TIMER: LazyLock<TimerThread> = LazyLock::new(|| {
+    std::thread::Builder::new()
+        .name("timer".to_string())
+        .spawn(|| TIMER.run())
+        .expect("failed to spawn the timer thread");
+    TimerThread {
+        state: Mutex::default(),
+        condvar: Condvar::new(),
+    }
+});
+
+impl TimerThread {
+    fn lock(&self) -> MutexGuard<'_, TimerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn run(&self) {
+        let mut state = self.lock();
+        loop {
+            let now = Instant::now();
+            let pending = state.timers.split_off(&(now, u64::MAX));
+            let expired = std::mem::replace(&mut state.timers, pending);
+            if !expired.is_empty() {
+                drop(state);
+                expired.into_values().for_each(Waker::wake);
+                state = self.lock();
+                continue;
+            }
+            state = match state.timers.first_key_value() {
+                Some((&(deadline, _), _)) => {
+                    self.condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.condvar.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+
+    fn register(&self, deadline: Instant, waker: Waker) -> (Instant, u64) {
+        let mut state = self.lock();
+        let key = (deadline, state.next_timer);
+        state.next_timer += 1;
+        state.timers.insert(key, waker);
+        // 最も早い期限が変わった場合だけ、スレッドに待機時間を計算し直させる
+        if state
+            .timers
+            .first_key_value()
+            .is_some_and(|(&first, _)| first == key)
+        {
+            self.condvar.notify_one();
+        }
+        key
+    }
+
+    fn update(&self, key: (Instant, u64), waker: &Waker) {
+        if let Some(current) = self.lock().timers.get_mut(&key) {
+            current.clone_from(waker);
+        }
+    }
+
+    fn cancel(&self, key: (Instant, u64)) {
+        let waker = self.lock().timers.remove(&key);
+        drop(waker);
+    }
+}
+
+/// 単調に増加する現在時刻を、基準の時刻からの経過時間として返す。
+///
+/// [`sim::Simulation`]の中では仮想時間の現在時刻（[`sim::now`]）を返す。
+/// それ以外では、最初に呼び出したときを基準とする実時間を返す。
+pub fn now() -> Duration {
+    static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+    sim::now().unwrap_or_else(|| START.elapsed())
+}
+
+/// `duration`が経過すると完了するフューチャーを返す。
+///
+/// [`sim::Simulation`]の中では仮想時間で待機する。
+/// それ以外では、実時間のタイマーを管理する1本のスレッドに期限を登録して待機する。
+/// 完了する前に破棄すると、登録したタイマーを解除する。
+pub fn sleep(duration: Duration) -> Sleep {
+    let state = match sim::now() {
+        Some(now) => State::Virtual {
+            deadline: now + duration,
+            timer: None,
+        },
+        None => State::Real {
+            deadline: Instant::now() + duration,
+            timer: None,
+        },
+    };
+    Sleep { state }
+}
+
+enum State {
+    Virtual {
+        deadline: Duration,
+        /// ポーリングされたときに登録したタイマー
+        timer: Option<TimerId>,
+    },
+    Real {
+        deadline: Instant,
+        /// ポーリングされたときに登録したタイマー
+        timer: Option<(Instant, u64)>,
+    },
+}
+
+/// [`sleep`]が返すフューチャー
+pub struct Sleep {
+    state: State,
+}
+
+impl fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.state {
+            State::Virtual { deadline, .. } => {
+                f.debug_struct("Sleep").field("deadline", deadline).finish()
+            }
+            State::Real { deadline, .. } => {
+                f.debug_struct("Sleep").field("deadline", deadline).finish()
+            }
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.state {
+            State::Virtual { deadline, timer } => {
+                if sim::now().is_none_or(|now| now >= *deadline) {
+                    if let Some(timer) = timer.take() {
+                        sim::cancel_timer(timer);
+                    }
+                    return Poll::Ready(());
+                }
+                match timer {
+                    Some(timer) => sim::update_timer(*timer, cx.waker()),
+                    None => *timer = sim::register_timer(*deadline, cx.waker().clone()),
+                }
+                Poll::Pending
+            }
+            State::Real { deadline, timer } => {
+                if Instant::now() >= *deadline {
+                    if let Some(timer) = timer.take() {
+                        TIMER.cancel(timer);
+                    }
+                    return Poll::Ready(());
+                }
+                match timer {
+                    Some(timer) => TIMER.update(*timer, cx.waker()),
+                    None => *timer = Some(TIMER.register(*deadline, cx.waker().clone())),
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        match self.state {
+            State::Virtual {
+                timer: Some(timer), ..
+            } => sim::cancel_timer(timer),
+            State::Real {
+                timer: Some(timer), ..
+            } => TIMER.cancel(timer),
+            _ => {}
+        }
+    }
+}
+
+/// タイムアウトした
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+/// `future`が`duration`以内に完了しなかった場合に、[`Elapsed`]を返す。
+///
+/// [`sleep`]と同じく、[`sim::Simulation`]の中では仮想時間で計る。
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    let mut future = std::pin::pin!(future);
+    let mut sleep = sleep(duration);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut sleep).poll(cx).map(|()| Err(Elapsed(())))
+    })
+    .await
+}
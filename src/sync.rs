@@ -0,0 +1,19 @@
+// loomでモデル検査するときは、イベントバスとシグナルが使う同期プリミティブをloomのものに置き換える。
+// loomのプリミティブは`loom::model`の外では使えないため、`loom`フィーチャーではモデル検査のテストだけを実行する。
+//
+// ランタイムのタスクキュー（`runtime`の`HIGH_QUEUE`と`LOW_QUEUE`）はこのファサードを使わず、モデル検査していない。
+// キューに使うflumeとasync-taskはloomで計装できず、loomは`LazyLock`も提供しないため、
+// `spawn_task_function`を複数のスレッドから同時に初めて呼び出したときの初期化の競合は、
+// `LazyLock`がワーカーの起動を1回だけ実行することに依存しており、モデルでは確認していない。
+
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::sync::{
+    Arc, Mutex, MutexGuard,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "loom")]
+pub(crate) use loom::sync::{
+    Arc, Mutex, MutexGuard,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
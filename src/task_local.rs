@@ -0,0 +1,186 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    pin::Pin,
+    ptr,
+    task::{Context, Poll},
+};
+
+/// タスクローカル変数を宣言するマクロ
+///
+/// `thread_local!`と同じ構文で宣言し、`with`メソッドでアクセスする。
+/// 値はタスクごとに独立して初期化され、タスクがどのワーカーでポーリングされても同じ値を参照する。
+///
+/// ```
+/// use std::cell::Cell;
+///
+/// async_rust::task_local! {
+///     static COUNTER: Cell<u32> = Cell::new(0);
+/// }
+///
+/// # futures_lite::future::block_on(async_rust::task_local::scope(async {
+/// COUNTER.with(|counter| counter.set(counter.get() + 1));
+/// # }));
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])* $vis static $name: $crate::task_local::LocalKey<$t> =
+            $crate::task_local::LocalKey::new(|| $init);
+        $crate::task_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])* $vis static $name: $crate::task_local::LocalKey<$t> =
+            $crate::task_local::LocalKey::new(|| $init);
+    };
+}
+
+/// タスクが所有するタスクローカル変数の値
+///
+/// キーは`LocalKey`の静的変数のアドレス。
+/// 値をボックスに格納することで、マップに要素を追加しても値のアドレスが変わらないようにしている。
+#[derive(Default)]
+struct TaskLocals {
+    values: RefCell<HashMap<usize, Box<dyn Any + Send>>>,
+}
+
+thread_local! {
+    /// 現在のスレッドでポーリングしているタスクのタスクローカル変数
+    static CURRENT: Cell<*const TaskLocals> = const { Cell::new(ptr::null()) };
+}
+
+/// タスクローカル変数にアクセスするキー
+///
+/// [`task_local!`](crate::task_local!)マクロで宣言する。
+pub struct LocalKey<T: 'static> {
+    init: fn() -> T,
+}
+
+impl<T: 'static> fmt::Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+/// タスクの外からタスクローカル変数にアクセスしたときのエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError;
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("task-local value accessed outside of a task")
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+impl<T: Send + 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self { init }
+    }
+
+    /// 現在のタスクの値への参照をクロージャーに渡す。
+    ///
+    /// タスクで初めてアクセスしたときに、宣言した初期値で初期化する。
+    ///
+    /// # Panics
+    ///
+    /// タスクの外（[`spawn_task!`](crate::spawn_task!)で生成したタスクや[`scope`]の外）から呼び出した場合。
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("cannot access a task-local value outside of a task")
+    }
+
+    /// 現在のタスクの値への参照をクロージャーに渡す。
+    ///
+    /// タスクの外から呼び出した場合は`AccessError`を返す。
+    pub fn try_with<F, R>(&'static self, f: F) -> Result<R, AccessError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let locals = CURRENT.with(Cell::get);
+        if locals.is_null() {
+            return Err(AccessError);
+        }
+        // SAFETY: CURRENTはタスクをポーリングしている間だけ、そのタスクが所有する
+        // TaskLocalsを指し、ポーリングが終わると元に戻される。
+        let locals = unsafe { &*locals };
+        let key = self as *const Self as usize;
+
+        let existing = locals
+            .values
+            .borrow()
+            .get(&key)
+            .map(|value| value.downcast_ref::<T>().unwrap() as *const T);
+        let value = match existing {
+            Some(value) => value,
+            None => {
+                // 初期化式から他のタスクローカル変数にアクセスできるように、借用せずに初期化する
+                let value: Box<dyn Any + Send> = Box::new((self.init)());
+                let ptr = value.downcast_ref::<T>().unwrap() as *const T;
+                let previous = locals.values.borrow_mut().insert(key, value);
+                assert!(
+                    previous.is_none(),
+                    "task-local value initialized recursively"
+                );
+                ptr
+            }
+        };
+        // SAFETY: 値はボックスに格納されており、タスクが破棄されるまでマップから削除されない。
+        Ok(f(unsafe { &*value }))
+    }
+}
+
+impl<T: Send + Clone + 'static> LocalKey<T> {
+    /// 現在のタスクの値のコピーを返す。
+    pub fn get(&'static self) -> T {
+        self.with(Clone::clone)
+    }
+}
+
+/// タスクローカル変数の格納場所を持つフューチャー
+///
+/// [`scope`]で生成する。
+pub struct Scope<F> {
+    locals: TaskLocals,
+    future: F,
+}
+
+/// フューチャーに独自のタスクローカル変数の格納場所を与える。
+///
+/// [`spawn_task!`](crate::spawn_task!)で生成したタスクには自動的に適用されるため、
+/// `block_on`などでランタイムの外からフューチャーを実行する場合に使用する。
+pub fn scope<F: Future>(future: F) -> Scope<F> {
+    Scope {
+        locals: TaskLocals::default(),
+        future,
+    }
+}
+
+impl<F: Future> Future for Scope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// ポーリングが終わったとき（パニックした場合も含む）に、CURRENTを元に戻すガード
+        struct Reset(*const TaskLocals);
+
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+
+        // SAFETY: futureをムーブしないため、ピン留めを維持できる。
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let _reset = Reset(CURRENT.with(|current| current.replace(&this.locals)));
+        future.poll(cx)
+    }
+}
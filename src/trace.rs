@@ -0,0 +1,23 @@
+pub use async_runtime::trace::{
+    ConnectionEvent, ConnectionEventKind, MemorySubscriber, Priority, StderrSubscriber, Subscriber,
+    TaskEvent, TaskEventKind, clear_subscriber, current_worker_id, set_subscriber, set_worker_id,
+};
+
+use crate::runtime::{FutureType, TaskInfo};
+
+// ランタイムのイベントは、async_runtimeのサブスクライバーにそのまま通知する。
+// そのため、kv-serverのようにasync_runtimeのエグゼキューターと併用しても、
+// 1つのサブスクライバーで両方のタスクのイベントを受け取れる。
+
+impl From<FutureType> for Priority {
+    fn from(priority: FutureType) -> Self {
+        match priority {
+            FutureType::High => Self::High,
+            FutureType::Low => Self::Low,
+        }
+    }
+}
+
+pub(crate) fn emit(kind: TaskEventKind, info: TaskInfo) {
+    async_runtime::trace::emit(kind, info.id(), Some(info.priority().into()));
+}
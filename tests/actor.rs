@@ -0,0 +1,195 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_rust::actor::{self, Actor, AskError, Context, Reply, SendError};
+
+enum CounterMessage {
+    Add(i64),
+    Get(Reply<i64>),
+    /// 応答せずに`Reply`を破棄する
+    Ignore(Reply<i64>),
+    Sleep(Duration),
+}
+
+struct Counter {
+    total: i64,
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl Counter {
+    fn new(events: &Arc<Mutex<Vec<String>>>) -> Self {
+        Self {
+            total: 0,
+            events: events.clone(),
+        }
+    }
+}
+
+impl Actor for Counter {
+    type Message = CounterMessage;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        self.events.lock().unwrap().push("started".to_string());
+    }
+
+    async fn handle(&mut self, msg: CounterMessage, _ctx: &mut Context<Self>) {
+        match msg {
+            CounterMessage::Add(value) => self.total += value,
+            CounterMessage::Get(reply) => {
+                let _ = reply.send(self.total);
+            }
+            CounterMessage::Ignore(reply) => drop(reply),
+            CounterMessage::Sleep(duration) => tokio::time::sleep(duration).await,
+        }
+    }
+
+    async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("stopping {}", self.total));
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn send_and_ask() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let addr = actor::spawn(Counter::new(&events));
+
+    for i in 1..=10 {
+        addr.send(CounterMessage::Add(i)).await.unwrap();
+    }
+    assert_eq!(addr.ask(CounterMessage::Get, TIMEOUT).await, Ok(55));
+    assert_eq!(
+        addr.ask(CounterMessage::Ignore, TIMEOUT).await,
+        Err(AskError::NoReply)
+    );
+
+    addr.send(CounterMessage::Sleep(Duration::from_millis(200)))
+        .await
+        .unwrap();
+    assert_eq!(
+        addr.ask(CounterMessage::Get, Duration::from_millis(10))
+            .await,
+        Err(AskError::Timeout)
+    );
+}
+
+#[tokio::test]
+async fn lifecycle_hooks() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let addr = actor::spawn(Counter::new(&events));
+
+    addr.send(CounterMessage::Add(1)).await.unwrap();
+    addr.stop().await;
+    addr.stopped().await;
+    assert!(addr.is_stopped());
+    assert_eq!(*events.lock().unwrap(), ["started", "stopping 1"]);
+
+    assert!(matches!(
+        addr.send(CounterMessage::Add(1)).await,
+        Err(SendError::Closed(CounterMessage::Add(1)))
+    ));
+    assert_eq!(
+        addr.ask(CounterMessage::Get, TIMEOUT).await,
+        Err(AskError::Closed)
+    );
+}
+
+#[tokio::test]
+async fn stops_when_all_addresses_are_dropped() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let addr = actor::spawn(Counter::new(&events));
+    let weak = addr.downgrade();
+    addr.send(CounterMessage::Add(2)).await.unwrap();
+    drop(addr);
+
+    // 残っているメッセージを処理してから停止する
+    while events.lock().unwrap().len() < 2 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert_eq!(*events.lock().unwrap(), ["started", "stopping 2"]);
+    assert!(weak.upgrade().is_none());
+}
+
+#[tokio::test]
+async fn bounded_mailbox() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let addr = actor::spawn_with_capacity(Counter::new(&events), 1);
+
+    addr.send(CounterMessage::Sleep(Duration::from_millis(200)))
+        .await
+        .unwrap();
+    // アクターがメッセージを取り出すまで待つ
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    addr.try_send(CounterMessage::Add(1)).unwrap();
+    assert!(matches!(
+        addr.try_send(CounterMessage::Add(1)),
+        Err(SendError::Full(_))
+    ));
+}
+
+#[tokio::test]
+async fn kill_stops_immediately() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let addr = actor::spawn(Counter::new(&events));
+    addr.ask(CounterMessage::Get, TIMEOUT).await.unwrap();
+
+    addr.send(CounterMessage::Sleep(Duration::from_secs(60)))
+        .await
+        .unwrap();
+    addr.kill();
+    tokio::time::timeout(TIMEOUT, addr.stopped()).await.unwrap();
+    // 強制的に停止した場合は`stopping`は呼び出されない
+    assert_eq!(*events.lock().unwrap(), ["started"]);
+}
+
+enum TickerMessage {
+    Tick,
+    Count(Reply<u32>),
+}
+
+#[derive(Default)]
+struct Ticker {
+    ticks: u32,
+}
+
+impl Actor for Ticker {
+    type Message = TickerMessage;
+
+    async fn started(&mut self, ctx: &mut Context<Self>) {
+        ctx.send_interval(Duration::from_millis(10), || TickerMessage::Tick);
+    }
+
+    async fn handle(&mut self, msg: TickerMessage, _ctx: &mut Context<Self>) {
+        match msg {
+            TickerMessage::Tick => self.ticks += 1,
+            TickerMessage::Count(reply) => {
+                let _ = reply.send(self.ticks);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn send_interval() {
+    let addr = actor::spawn(Ticker::default());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let ticks = addr.ask(TickerMessage::Count, TIMEOUT).await.unwrap();
+    assert!(ticks >= 3, "ticks: {ticks}");
+
+    // 定期的な送信はアクターを停止させない
+    let weak = addr.downgrade();
+    drop(addr);
+    tokio::time::timeout(TIMEOUT, async {
+        while weak.upgrade().is_some() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use async_rust::actor::{
+    self, Actor, Context, Reply,
+    system::{ActorSystem, RegistryError, Terminated},
+};
+use tokio::sync::mpsc;
+
+enum EchoMessage {
+    Echo(String, Reply<String>),
+}
+
+struct Echo;
+
+impl Actor for Echo {
+    type Message = EchoMessage;
+
+    async fn handle(&mut self, msg: EchoMessage, _ctx: &mut Context<Self>) {
+        let EchoMessage::Echo(text, reply) = msg;
+        let _ = reply.send(text);
+    }
+}
+
+/// 受け取った`Terminated`をテストに転送するアクター
+struct Watcher(mpsc::UnboundedSender<Terminated>);
+
+impl Actor for Watcher {
+    type Message = Terminated;
+
+    async fn handle(&mut self, msg: Terminated, _ctx: &mut Context<Self>) {
+        let _ = self.0.send(msg);
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn register_and_lookup() {
+    let system = ActorSystem::new("test");
+    let echo = actor::spawn(Echo);
+    system.register("echo", &echo).unwrap();
+    system.register_type(&echo).unwrap();
+    // 登録したアドレスをすべて破棄しても、レジストリがアクターを保持する
+    drop(echo);
+
+    let by_name = system.lookup::<Echo>("echo").unwrap();
+    let by_type = system.lookup_type::<Echo>().unwrap();
+    assert_eq!(by_name.id(), by_type.id());
+    let reply = by_name
+        .ask(
+            |reply| EchoMessage::Echo("hello".to_string(), reply),
+            TIMEOUT,
+        )
+        .await
+        .unwrap();
+    assert_eq!(reply, "hello");
+
+    assert_eq!(
+        system.register("echo", &actor::spawn(Echo)),
+        Err(RegistryError::AlreadyRegistered("echo".to_string()))
+    );
+    assert!(matches!(
+        system.lookup::<Watcher>("echo"),
+        Err(RegistryError::TypeMismatch { .. })
+    ));
+    assert_eq!(
+        system.lookup::<Echo>("missing").unwrap_err(),
+        RegistryError::NotFound("missing".to_string())
+    );
+
+    assert_eq!(system.unregister("echo"), Some(by_name.id()));
+    assert!(system.lookup::<Echo>("echo").is_err());
+}
+
+#[tokio::test]
+async fn systems_are_isolated() {
+    let first = ActorSystem::new("first");
+    let second = ActorSystem::new("second");
+    first.register("echo", &actor::spawn(Echo)).unwrap();
+    second.register("echo", &actor::spawn(Echo)).unwrap();
+
+    let first_echo = first.lookup::<Echo>("echo").unwrap();
+    let second_echo = second.lookup::<Echo>("echo").unwrap();
+    assert_ne!(first_echo.id(), second_echo.id());
+    assert!(ActorSystem::new("third").lookup::<Echo>("echo").is_err());
+}
+
+#[tokio::test]
+async fn watchers_are_notified_when_actor_stops() {
+    let system = ActorSystem::new("test");
+    let echo = actor::spawn(Echo);
+    system.register("echo", &echo).unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watcher = actor::spawn(Watcher(tx.clone()));
+    let unwatched = actor::spawn(Watcher(tx));
+    system.watch("echo", &watcher, |t| t).unwrap();
+    let id = system.watch("echo", &unwatched, |t| t).unwrap();
+    assert!(system.unwatch(id));
+
+    echo.stop().await;
+    let terminated = rx.recv().await.unwrap();
+    assert_eq!(
+        terminated,
+        Terminated {
+            name: "echo".to_string(),
+            id: echo.id(),
+        }
+    );
+    // 解除した監視には通知されない
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(rx.try_recv().is_err());
+    assert!(!system.unwatch(id));
+
+    // 停止したアクターはレジストリから削除され、同じ名前で登録し直せる
+    assert!(system.lookup::<Echo>("echo").is_err());
+    assert!(system.watch("echo", &watcher, |t| t).is_err());
+    system.register("echo", &actor::spawn(Echo)).unwrap();
+}
@@ -0,0 +1,77 @@
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use async_rust::{
+    blocking::{self, spawn_blocking},
+    runtime::{FutureType, Runtime},
+    spawn_task,
+};
+
+/// 条件が満たされるまで待機する。
+///
+/// 5秒経っても満たされない場合は`false`を返す。
+fn eventually(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+#[test]
+fn blocking_low_task_does_not_delay_high_tasks() {
+    // 両方の優先度のキューを処理するワーカーを1つだけ起動する
+    Runtime::new()
+        .with_high_num(0)
+        .with_low_num(1)
+        .with_max_blocking_num(2)
+        .with_blocking_keep_alive(Duration::from_millis(200))
+        .run();
+
+    // 優先度の高いタスクが完了するまでブロックし続ける、優先度の低いタスク
+    let (release, gate) = mpsc::channel::<()>();
+    let low = spawn_task!(
+        async move {
+            spawn_blocking(move || gate.recv().unwrap()).await;
+        },
+        FutureType::Low
+    );
+    assert!(eventually(|| blocking::thread_count() == 1));
+
+    // ブロッキング処理がワーカーを占有していないため、優先度の高いタスクは同じワーカーで完了する
+    let (done, finished) = mpsc::channel();
+    spawn_task!(async move { done.send(1).unwrap() }, FutureType::High).detach();
+    assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(1));
+    release.send(()).unwrap();
+    futures_lite::future::block_on(low);
+
+    // スレッドの数は上限を超えず、残りの処理はキューで待機する
+    let (release, gate) = mpsc::channel::<()>();
+    let gate = std::sync::Arc::new(std::sync::Mutex::new(gate));
+    let tasks: Vec<_> = (0..4)
+        .map(|i| {
+            let gate = gate.clone();
+            spawn_blocking(move || {
+                gate.lock().unwrap().recv().unwrap();
+                i
+            })
+        })
+        .collect();
+    assert_eq!(blocking::thread_count(), 2);
+    for _ in 0..4 {
+        release.send(()).unwrap();
+    }
+    let results: Vec<_> = tasks
+        .into_iter()
+        .map(futures_lite::future::block_on)
+        .collect();
+    assert_eq!(results, [0, 1, 2, 3]);
+
+    // 処理がないスレッドは終了する
+    assert!(eventually(|| blocking::thread_count() == 0));
+}
@@ -0,0 +1,129 @@
+#include <pthread.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "async_rust.h"
+
+#define CHECK(cond)                                                       \
+  do {                                                                    \
+    if (!(cond)) {                                                        \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+      return 1;                                                           \
+    }                                                                     \
+  } while (0)
+
+/* コールバックの結果をメインスレッドに渡すための状態 */
+typedef struct Completion {
+  pthread_mutex_t mutex;
+  pthread_cond_t cond;
+  int called;
+  AsyncRustStatus status;
+  int32_t result;
+  pthread_t thread;
+  char *message;
+} Completion;
+
+static void on_complete(void *user_data, AsyncRustJobId id, AsyncRustStatus status,
+                        int32_t result) {
+  (void)id;
+  Completion *completion = user_data;
+  pthread_mutex_lock(&completion->mutex);
+  completion->called++;
+  completion->status = status;
+  completion->result = result;
+  completion->thread = pthread_self();
+  if (status != ASYNC_RUST_STATUS_OK) {
+    completion->message = async_rust_last_error_message();
+  }
+  pthread_cond_signal(&completion->cond);
+  pthread_mutex_unlock(&completion->mutex);
+}
+
+static void completion_init(Completion *completion) {
+  memset(completion, 0, sizeof(*completion));
+  pthread_mutex_init(&completion->mutex, NULL);
+  pthread_cond_init(&completion->cond, NULL);
+}
+
+static void completion_wait(Completion *completion) {
+  pthread_mutex_lock(&completion->mutex);
+  while (!completion->called) {
+    pthread_cond_wait(&completion->cond, &completion->mutex);
+  }
+  pthread_mutex_unlock(&completion->mutex);
+}
+
+int main(void) {
+  AsyncRustJobId waited = {0}, polled = {0}, callback = {0}, cancelled = {0};
+  int32_t result = 0;
+
+  /* 引数の検証 */
+  CHECK(async_rust_add_submit(1, 2, NULL) == ASYNC_RUST_STATUS_INVALID_ARGUMENT);
+  CHECK(async_rust_add_on_complete(waited, NULL, NULL) ==
+        ASYNC_RUST_STATUS_INVALID_ARGUMENT);
+
+  CHECK(async_rust_add_submit(1, 2, &waited) == ASYNC_RUST_STATUS_OK);
+  CHECK(async_rust_add_submit(3, 4, &polled) == ASYNC_RUST_STATUS_OK);
+  CHECK(async_rust_add_submit(5, 6, &callback) == ASYNC_RUST_STATUS_OK);
+  CHECK(async_rust_add_submit(7, 8, &cancelled) == ASYNC_RUST_STATUS_OK);
+
+  /* IDの文字列は呼び出し側が解放する */
+  char *id = async_rust_job_id_to_string(waited);
+  CHECK(id != NULL && strlen(id) == 36);
+  async_rust_string_free(id);
+
+  /* 完了を待ち合わせる */
+  CHECK(async_rust_add_wait(waited, 10, &result) == ASYNC_RUST_STATUS_TIMEOUT);
+  char *message = async_rust_last_error_message();
+  CHECK(message != NULL && strstr(message, "timed out") != NULL);
+  async_rust_string_free(message);
+  CHECK(async_rust_add_wait(waited, 10000, &result) == ASYNC_RUST_STATUS_OK);
+  CHECK(result == 3);
+  /* 回収したジョブは削除される */
+  CHECK(async_rust_add_poll(waited, &result) == ASYNC_RUST_STATUS_NOT_FOUND);
+
+  /* ブロックせずに回収する */
+  AsyncRustJobStatus status;
+  CHECK(async_rust_add_status(polled, &status) == ASYNC_RUST_STATUS_OK);
+  CHECK(status != ASYNC_RUST_JOB_STATUS_FAILED);
+  AsyncRustStatus polling;
+  while ((polling = async_rust_add_poll(polled, &result)) == ASYNC_RUST_STATUS_PENDING) {
+    usleep(1000);
+  }
+  CHECK(polling == ASYNC_RUST_STATUS_OK);
+  CHECK(result == 7);
+
+  /* コールバックはランタイムのスレッドで呼び出される */
+  Completion done;
+  completion_init(&done);
+  CHECK(async_rust_add_on_complete(callback, on_complete, &done) == ASYNC_RUST_STATUS_OK);
+  completion_wait(&done);
+  CHECK(done.called == 1);
+  CHECK(done.status == ASYNC_RUST_STATUS_OK);
+  CHECK(done.result == 11);
+  CHECK(!pthread_equal(done.thread, pthread_self()));
+
+  /* キャンセルしたジョブのコールバック */
+  AsyncRustJobId pending;
+  CHECK(async_rust_add_submit(9, 10, &pending) == ASYNC_RUST_STATUS_OK);
+  Completion aborted;
+  completion_init(&aborted);
+  CHECK(async_rust_add_on_complete(pending, on_complete, &aborted) == ASYNC_RUST_STATUS_OK);
+  CHECK(async_rust_add_poll(pending, &result) == ASYNC_RUST_STATUS_PENDING);
+  CHECK(async_rust_add_cancel(pending) == ASYNC_RUST_STATUS_OK);
+  completion_wait(&aborted);
+  CHECK(aborted.status == ASYNC_RUST_STATUS_CANCELLED);
+  CHECK(aborted.message != NULL && strstr(aborted.message, "cancelled") != NULL);
+  async_rust_string_free(aborted.message);
+
+  /* 完了したジョブにコールバックを登録した場合も呼び出される */
+  Completion late;
+  completion_init(&late);
+  CHECK(async_rust_add_on_complete(cancelled, on_complete, &late) == ASYNC_RUST_STATUS_OK);
+  completion_wait(&late);
+  CHECK(late.status == ASYNC_RUST_STATUS_OK);
+  CHECK(late.result == 15);
+
+  return 0;
+}
@@ -0,0 +1,96 @@
+// 各テストの実行ファイルは、この中の一部の関数だけを使う
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+};
+
+use async_rust::kv::remote::RemoteClient;
+
+/// テストスイート`suite`のテスト`name`が使う、空の一時ディレクトリのパスを返す。
+///
+/// 前回の実行で残ったディレクトリは削除する。
+pub fn temp_dir(suite: &str, name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(suite)
+        .join(name);
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// テストが終わったときに停止させるサーバーのプロセス
+pub struct ServerProcess {
+    child: Child,
+    addr: String,
+    /// レプリカの接続を受け付けるアドレス
+    replication_addr: Option<String>,
+}
+
+impl ServerProcess {
+    /// エフェメラルポートでサーバーを起動して、バインドしたアドレスを読み込む。
+    ///
+    /// `args`は、`--addr`と`--dir`の後にそのまま渡す。
+    pub fn start(dir: &Path, args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_kv-server"))
+            .args(["--addr", "127.0.0.1:0", "--dir"])
+            .arg(dir)
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut read_addr = |prefix: &str| {
+            let mut line = String::new();
+            stdout.read_line(&mut line).unwrap();
+            line.trim()
+                .strip_prefix(prefix)
+                .unwrap_or_else(|| panic!("unexpected output: {line:?}"))
+                .to_string()
+        };
+        let addr = read_addr("listening on ");
+        let replication_addr = args
+            .contains(&"--replication-addr")
+            .then(|| read_addr("replication on "));
+        Self {
+            child,
+            addr,
+            replication_addr,
+        }
+    }
+
+    /// レプリカの接続を受け付けるプライマリを起動する。
+    pub fn primary(dir: &Path, args: &[&str]) -> Self {
+        let args = [&["--replication-addr", "127.0.0.1:0"], args].concat();
+        Self::start(dir, &args)
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn replication_addr(&self) -> &str {
+        self.replication_addr.as_deref().unwrap()
+    }
+
+    pub fn client(&self) -> RemoteClient {
+        RemoteClient::new(&self.addr)
+    }
+
+    pub fn cli(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_kv-cli"))
+            .args(["--addr", &self.addr])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,87 @@
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use async_rust::coroutine::{Coroutine, CoroutineState, generator::Generator};
+
+fn resume<C: Coroutine<A> + Unpin, A>(
+    coroutine: &mut C,
+    arg: A,
+) -> CoroutineState<C::Yield, C::Return> {
+    Pin::new(coroutine).resume(arg)
+}
+
+#[test]
+fn generator_receives_resume_arguments() {
+    // 受け取った数値の合計を生成し、負の数を受け取ったら合計を返して完了する
+    let mut sum = Generator::new(|co, mut number: i32| async move {
+        let mut total = 0;
+        while number >= 0 {
+            total += number;
+            number = co.yield_(total).await;
+        }
+        total
+    });
+    assert_eq!(resume(&mut sum, 1), CoroutineState::Yielded(1));
+    assert_eq!(resume(&mut sum, 2), CoroutineState::Yielded(3));
+    assert_eq!(resume(&mut sum, 3), CoroutineState::Yielded(6));
+    assert_eq!(resume(&mut sum, -1), CoroutineState::Complete(6));
+}
+
+#[test]
+fn generators_run_as_boxed_coroutines_in_a_queue() {
+    let handle = Arc::new(Mutex::new(0));
+    let mut coroutines: VecDeque<Pin<Box<dyn Coroutine<Yield = (), Return = ()>>>> =
+        VecDeque::new();
+    for _ in 0..2 {
+        let handle = handle.clone();
+        // MutexCoroutineと同じく、ロックを取得できるまで停止する
+        coroutines.push_back(Box::pin(Generator::new(|co, ()| async move {
+            let mut remaining = 2;
+            while remaining > 0 {
+                if let Ok(mut value) = handle.try_lock() {
+                    *value += 1;
+                    remaining -= 1;
+                }
+                co.yield_(()).await;
+            }
+        })));
+    }
+
+    let lock = handle.lock().unwrap();
+    for coroutine in &mut coroutines {
+        assert_eq!(coroutine.as_mut().resume(()), CoroutineState::Yielded(()));
+    }
+    drop(lock);
+
+    let mut resumed = 0;
+    while let Some(mut coroutine) = coroutines.pop_front() {
+        resumed += 1;
+        if let CoroutineState::Yielded(()) = coroutine.as_mut().resume(()) {
+            coroutines.push_back(coroutine);
+        }
+    }
+    assert_eq!(*handle.lock().unwrap(), 4);
+    assert_eq!(resumed, 6);
+}
+
+#[test]
+#[should_panic(expected = "generator resumed after completion")]
+fn resuming_a_completed_generator_panics() {
+    let mut generator = Generator::<(), _>::new(|_co, ()| async {});
+    assert_eq!(resume(&mut generator, ()), CoroutineState::Complete(()));
+    resume(&mut generator, ());
+}
+
+#[test]
+#[should_panic(expected = "awaited a future other than `Co::yield_`")]
+fn awaiting_other_futures_in_a_generator_panics() {
+    let mut generator = Generator::<(), _>::new(|_co, ()| async {
+        futures::future::pending::<()>().await;
+    });
+    resume(&mut generator, ());
+}
@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use async_rust::actor::{
+    self, Actor, Addr, AskError, Context, Reply, SendError,
+    dead_letter::{DeadLetter, DeadLetterReason, DeadLetters},
+    supervisor::{ChildSpec, MailboxPolicy, Strategy, Supervisor},
+    system::ActorSystem,
+};
+use tokio::sync::mpsc;
+
+enum CounterMessage {
+    Add(i64),
+    Get(Reply<i64>),
+    /// 応答せずに`Reply`を破棄する
+    Ignore(Reply<i64>),
+    Sleep(Duration),
+    Panic,
+}
+
+#[derive(Default)]
+struct Counter {
+    total: i64,
+}
+
+impl Actor for Counter {
+    type Message = CounterMessage;
+
+    async fn handle(&mut self, msg: CounterMessage, _ctx: &mut Context<Self>) {
+        match msg {
+            CounterMessage::Add(value) => self.total += value,
+            CounterMessage::Get(reply) => {
+                let _ = reply.send(self.total);
+            }
+            CounterMessage::Ignore(reply) => drop(reply),
+            CounterMessage::Sleep(duration) => tokio::time::sleep(duration).await,
+            CounterMessage::Panic => panic!("counter panicked"),
+        }
+    }
+}
+
+/// 要求を受け取ると、カウンターにメッセージを送信するアクター
+struct Relay {
+    counter: Addr<Counter>,
+}
+
+impl Actor for Relay {
+    type Message = Reply<()>;
+
+    async fn handle(&mut self, reply: Reply<()>, _ctx: &mut Context<Self>) {
+        for value in 1..=3 {
+            let _ = self.counter.send(CounterMessage::Add(value)).await;
+        }
+        let _ = reply.send(());
+    }
+}
+
+/// 起動したときに子のカウンターを起動するアクター
+#[derive(Default)]
+struct Parent {
+    child: Option<Addr<Counter>>,
+}
+
+impl Actor for Parent {
+    type Message = Reply<Addr<Counter>>;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        self.child = Some(actor::spawn(Counter::default()));
+    }
+
+    async fn handle(&mut self, reply: Reply<Addr<Counter>>, _ctx: &mut Context<Self>) {
+        let _ = reply.send(self.child.clone().unwrap());
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+async fn recv(letters: &mut mpsc::Receiver<DeadLetter>) -> DeadLetter {
+    tokio::time::timeout(TIMEOUT, letters.recv())
+        .await
+        .expect("dead letter was not delivered")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn undelivered_messages_are_recorded() {
+    let dead_letters = DeadLetters::spawn();
+    let mut letters = dead_letters.subscribe().await.unwrap();
+    let addr = actor::spawn_with_capacity(Counter::default(), 1);
+    assert!(dead_letters.track(&addr));
+
+    addr.send(CounterMessage::Sleep(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    addr.try_send(CounterMessage::Add(1)).unwrap();
+    assert!(matches!(
+        addr.try_send(CounterMessage::Add(2)),
+        Err(SendError::Full(CounterMessage::Add(2)))
+    ));
+    let letter = recv(&mut letters).await;
+    assert_eq!(letter.reason, DeadLetterReason::MailboxFull);
+    assert_eq!(letter.sender, None);
+    assert_eq!(letter.target, addr.id());
+    assert!(letter.message_type.ends_with("CounterMessage"));
+
+    addr.stop().await;
+    addr.stopped().await;
+    assert!(matches!(
+        addr.send(CounterMessage::Add(3)).await,
+        Err(SendError::Closed(CounterMessage::Add(3)))
+    ));
+    assert_eq!(
+        addr.ask(CounterMessage::Get, TIMEOUT).await,
+        Err(AskError::Closed)
+    );
+    assert_eq!(recv(&mut letters).await.reason, DeadLetterReason::Stopped);
+    // 送信できなかった要求の`Reply`は、応答を破棄したとはみなさない
+    assert_eq!(recv(&mut letters).await.reason, DeadLetterReason::Stopped);
+
+    let stats = dead_letters.stats();
+    assert_eq!(stats.count(DeadLetterReason::MailboxFull), 1);
+    assert_eq!(stats.count(DeadLetterReason::Stopped), 2);
+    assert_eq!(stats.total(), 3);
+}
+
+#[tokio::test]
+async fn dropped_and_unclaimed_replies_are_recorded() {
+    let dead_letters = DeadLetters::spawn();
+    let mut letters = dead_letters.subscribe().await.unwrap();
+    let addr = actor::spawn(Counter::default());
+    dead_letters.track(&addr);
+
+    assert_eq!(
+        addr.ask(CounterMessage::Ignore, TIMEOUT).await,
+        Err(AskError::NoReply)
+    );
+    let letter = recv(&mut letters).await;
+    assert_eq!(letter.reason, DeadLetterReason::ReplyDropped);
+    assert_eq!(letter.target, addr.id());
+    assert_eq!(letter.message_type, "i64");
+
+    addr.send(CounterMessage::Sleep(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    assert_eq!(
+        addr.ask(CounterMessage::Get, Duration::from_millis(10))
+            .await,
+        Err(AskError::Timeout)
+    );
+    assert_eq!(
+        recv(&mut letters).await.reason,
+        DeadLetterReason::ReplyUnclaimed
+    );
+    assert_eq!(dead_letters.stats().total(), 2);
+}
+
+#[tokio::test]
+async fn unprocessed_messages_record_the_sending_actor() {
+    let dead_letters = DeadLetters::spawn();
+    let mut letters = dead_letters.subscribe().await.unwrap();
+    let counter = actor::spawn(Counter::default());
+    dead_letters.track(&counter);
+    let relay = actor::spawn(Relay {
+        counter: counter.clone(),
+    });
+
+    counter
+        .send(CounterMessage::Sleep(Duration::from_secs(10)))
+        .await
+        .unwrap();
+    relay.ask(|reply| reply, TIMEOUT).await.unwrap();
+    counter.kill();
+
+    for _ in 1..=3 {
+        let letter = recv(&mut letters).await;
+        assert_eq!(letter.reason, DeadLetterReason::Unprocessed);
+        assert_eq!(letter.sender, Some(relay.id()));
+        assert_eq!(letter.target, counter.id());
+    }
+}
+
+#[tokio::test]
+async fn supervisor_records_messages_discarded_on_restart() {
+    let dead_letters = DeadLetters::spawn();
+    let mut letters = dead_letters.subscribe().await.unwrap();
+    let mut sup = Supervisor::new("test", Strategy::OneForOne);
+    let addr = sup.add(
+        ChildSpec::new("counter", Counter::default).with_mailbox_policy(MailboxPolicy::DeadLetter),
+    );
+    dead_letters.track(&addr);
+    let handle = sup.start();
+
+    addr.send(CounterMessage::Sleep(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    addr.send(CounterMessage::Panic).await.unwrap();
+    addr.send(CounterMessage::Add(1)).await.unwrap();
+    let letter = recv(&mut letters).await;
+    assert_eq!(letter.reason, DeadLetterReason::Unprocessed);
+    assert_eq!(letter.target, addr.id());
+    assert_eq!(addr.ask(CounterMessage::Get, TIMEOUT).await, Ok(0));
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn actor_system_records_dead_letters_of_registered_actors_and_their_children() {
+    let system = ActorSystem::new("test");
+    let mut letters = system.dead_letters().subscribe().await.unwrap();
+    let parent = actor::spawn(Parent::default());
+    system.register("parent", &parent).unwrap();
+
+    let child = parent.ask(|reply| reply, TIMEOUT).await.unwrap();
+    child.stop().await;
+    child.stopped().await;
+    assert!(child.send(CounterMessage::Add(1)).await.is_err());
+
+    let letter = recv(&mut letters).await;
+    assert_eq!(letter.reason, DeadLetterReason::Stopped);
+    assert_eq!(letter.target, child.id());
+    assert_eq!(
+        letter.to_string(),
+        format!(
+            "{} to {}: actor has stopped",
+            letter.message_type,
+            child.id()
+        )
+    );
+    assert_eq!(system.dead_letters().stats().total(), 1);
+}
@@ -0,0 +1,132 @@
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use async_rust::event_bus::durable::{DurableEventBus, Record};
+use futures::StreamExt;
+
+mod common;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+async fn next(
+    subscription: &mut (impl StreamExt<Item = std::io::Result<Record<u32>>> + Unpin),
+) -> Record<u32> {
+    tokio::time::timeout(TIMEOUT, subscription.next())
+        .await
+        .expect("record was not delivered")
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn durable_subscribers_resume_from_the_committed_offset() {
+    let dir = common::temp_dir("durable_event_bus", "resume");
+    {
+        let bus = DurableEventBus::open(&dir).unwrap().with_segment_size(64);
+        let mut subscription = bus
+            .subscribe_durable("kitchen", "sensors.kitchen.*")
+            .unwrap();
+        for (i, room) in ["kitchen", "bedroom", "kitchen", "kitchen"]
+            .into_iter()
+            .enumerate()
+        {
+            bus.publish(&format!("sensors.{room}.temperature"), i as u32)
+                .unwrap();
+        }
+        let record = next(&mut subscription).await;
+        assert_eq!((record.offset, record.event), (0, 0));
+        let record = next(&mut subscription).await;
+        assert_eq!((record.offset, record.event), (2, 2));
+        subscription.commit(record.offset).unwrap();
+        bus.sync().unwrap();
+    }
+    // 小さいセグメントに分割されている
+    assert!(fs::read_dir(&dir).unwrap().count() > 2);
+
+    let bus = DurableEventBus::<u32>::open(&dir).unwrap();
+    assert_eq!(bus.next_offset(), 4);
+    assert_eq!(bus.committed("kitchen").unwrap(), 3);
+    let mut subscription = bus
+        .subscribe_durable("kitchen", "sensors.kitchen.*")
+        .unwrap();
+    let record = next(&mut subscription).await;
+    assert_eq!(
+        record,
+        Record {
+            offset: 3,
+            timestamp: record.timestamp,
+            topic: "sensors.kitchen.temperature".to_string(),
+            event: 3,
+        }
+    );
+    bus.publish("sensors.kitchen.humidity", 4).unwrap();
+    assert_eq!(next(&mut subscription).await.offset, 4);
+
+    // 購読者の名前はファイル名に使える文字に限られる
+    assert!(bus.subscribe_durable("../escape", "#").is_err());
+
+    // 同じ名前の購読者は同時に1つだけ購読でき、破棄すると購読し直せる
+    let error = bus.subscribe_from("kitchen", "#", 0).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    drop(subscription);
+    assert!(bus.subscribe_durable("kitchen", "#").is_ok());
+}
+
+#[tokio::test]
+async fn lagging_durable_subscribers_catch_up_from_the_log() {
+    let dir = common::temp_dir("durable_event_bus", "lag");
+    let bus = DurableEventBus::open(&dir).unwrap();
+    let mut subscription = bus.subscribe_durable("audit", "#").unwrap();
+    let id = subscription.id();
+    // バッファの容量を超えて追記する
+    for i in 0..500 {
+        bus.publish("events", i).unwrap();
+    }
+    for i in 0..500 {
+        assert_eq!(next(&mut subscription).await.event, i);
+    }
+    assert_eq!(subscription.id(), id);
+
+    drop(bus);
+    assert!(subscription.next().await.is_none());
+}
+
+#[tokio::test]
+async fn replay_returns_events_since_a_timestamp() {
+    let dir = common::temp_dir("durable_event_bus", "replay");
+    let bus = DurableEventBus::open(&dir).unwrap().with_segment_size(1);
+    bus.publish("before", 0).unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let since = SystemTime::now();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    bus.publish("after", 1).unwrap();
+    bus.publish("after", 2).unwrap();
+
+    let records = bus.replay(since).unwrap();
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| record.event)
+            .collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert_eq!(bus.replay(SystemTime::UNIX_EPOCH).unwrap().len(), 3);
+    drop(bus);
+
+    // 書き込み中にクラッシュした行は、開き直すときに切り詰める
+    let last = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .max()
+        .unwrap();
+    let mut contents = fs::read(&last).unwrap();
+    contents.extend_from_slice(b"{\"offset\":3,");
+    fs::write(&last, contents).unwrap();
+    let bus = DurableEventBus::<u32>::open(&dir).unwrap();
+    assert_eq!(bus.next_offset(), 3);
+    assert_eq!(bus.publish("after", 3).unwrap(), 3);
+    assert_eq!(bus.replay(since).unwrap().len(), 3);
+}
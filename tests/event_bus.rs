@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use async_rust::event_bus::{EventBus, LagPolicy, SubscribeOptions, topic::TopicPattern};
+use futures::StreamExt;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn subscribers_are_woken_by_send() {
+    let bus = EventBus::new();
+    let subscriptions = [bus.subscribe(), bus.subscribe()];
+    let consumers: Vec<_> = subscriptions
+        .into_iter()
+        .map(|subscription| {
+            tokio::spawn(async move {
+                subscription
+                    .take_while(|event| std::future::ready(*event != 3.0))
+                    .collect::<Vec<f32>>()
+                    .await
+            })
+        })
+        .collect();
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    for event in [1.0, 2.0, 3.0] {
+        assert_eq!(bus.send(event), 2);
+    }
+    for consumer in consumers {
+        let events = tokio::time::timeout(TIMEOUT, consumer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(events, [1.0, 2.0]);
+    }
+}
+
+#[tokio::test]
+async fn dropping_a_subscription_unsubscribes_immediately() {
+    let bus = EventBus::new();
+    let first = bus.subscribe();
+    let mut second = bus.subscribe();
+    assert_ne!(first.id(), second.id());
+    assert_eq!(bus.subscriber_count(), 2);
+
+    drop(first);
+    assert_eq!(bus.subscriber_count(), 1);
+    assert_eq!(bus.send("event"), 1);
+    assert_eq!(second.recv().await, Some("event"));
+}
+
+#[tokio::test]
+async fn subscriptions_end_when_the_bus_is_dropped() {
+    let bus = EventBus::new();
+    let mut subscription = bus.subscribe();
+    let sender = bus.clone();
+    drop(bus);
+    sender.send(1);
+    assert!(!subscription.is_closed());
+
+    drop(sender);
+    assert!(subscription.is_closed());
+    assert_eq!(subscription.recv().await, Some(1));
+    assert_eq!(subscription.recv().await, None);
+}
+
+#[tokio::test]
+async fn lag_policies_bound_the_buffer() {
+    let bus = EventBus::new();
+    let options = SubscribeOptions::new().with_capacity(2);
+    let mut oldest = bus.subscribe_with(options.clone());
+    let mut newest = bus.subscribe_with(options.clone().with_lag_policy(LagPolicy::DropNewest));
+    let disconnect = bus.subscribe_with(options.with_lag_policy(LagPolicy::Disconnect));
+
+    for event in 1..=4 {
+        bus.send(event);
+    }
+    assert_eq!(bus.subscriber_count(), 2);
+    assert_eq!(oldest.by_ref().take(2).collect::<Vec<_>>().await, [3, 4]);
+    assert_eq!(oldest.dropped(), 2);
+    assert_eq!(newest.by_ref().take(2).collect::<Vec<_>>().await, [1, 2]);
+    assert_eq!(newest.dropped(), 2);
+
+    // 解除された購読者は、残っているイベントを受け取った後に終了する
+    assert!(disconnect.is_closed());
+    assert_eq!(disconnect.dropped(), 1);
+    assert_eq!(disconnect.collect::<Vec<_>>().await, [1, 2]);
+    assert_eq!(newest.try_recv(), None);
+}
+
+#[tokio::test]
+async fn topic_subscribers_receive_only_their_topics() {
+    let bus = EventBus::new();
+    let mut all = bus.subscribe();
+    let mut temperature = bus.subscribe_with(SubscribeOptions::new().with_topic("temperature"));
+    let mut climate = bus.subscribe_with(
+        SubscribeOptions::new()
+            .with_topic("temperature")
+            .with_topic("humidity"),
+    );
+
+    assert_eq!(bus.publish("temperature", 21.5), 3);
+    assert_eq!(bus.publish("humidity", 40.0), 2);
+    assert_eq!(bus.publish("pressure", 1013.0), 1);
+    assert_eq!(bus.send(0.0), 1);
+
+    assert_eq!(
+        all.by_ref().take(4).collect::<Vec<_>>().await,
+        [21.5, 40.0, 1013.0, 0.0]
+    );
+    assert_eq!(temperature.try_recv(), Some(21.5));
+    assert_eq!(temperature.try_recv(), None);
+    assert_eq!(
+        climate.by_ref().take(2).collect::<Vec<_>>().await,
+        [21.5, 40.0]
+    );
+    assert_eq!(climate.try_recv(), None);
+}
+
+#[tokio::test]
+async fn wildcard_patterns_match_hierarchical_topics() {
+    let pattern = TopicPattern::new("sensors.*.temperature");
+    assert!(pattern.matches("sensors.kitchen.temperature"));
+    assert!(!pattern.matches("sensors.kitchen.humidity"));
+    assert!(!pattern.matches("sensors.kitchen.oven.temperature"));
+    assert!(!pattern.matches("sensors.temperature"));
+    let pattern = TopicPattern::new("sensors.#");
+    assert!(pattern.matches("sensors"));
+    assert!(pattern.matches("sensors.kitchen.oven.temperature"));
+    assert!(!pattern.matches("alerts.kitchen"));
+    assert!(TopicPattern::new("#.temperature").matches("sensors.kitchen.temperature"));
+
+    let bus = EventBus::new();
+    let mut temperatures =
+        bus.subscribe_with(SubscribeOptions::new().with_topic("sensors.*.temperature"));
+    let mut kitchen = bus.subscribe_with(SubscribeOptions::new().with_topic("sensors.kitchen.#"));
+
+    assert_eq!(bus.publish("sensors.kitchen.temperature", 21.5), 2);
+    assert_eq!(bus.publish("sensors.bedroom.temperature", 18.0), 1);
+    assert_eq!(bus.publish("sensors.kitchen.humidity", 40.0), 1);
+    assert_eq!(bus.publish("alerts.kitchen", 1.0), 0);
+
+    assert_eq!(
+        temperatures.by_ref().take(2).collect::<Vec<_>>().await,
+        [21.5, 18.0]
+    );
+    assert_eq!(temperatures.try_recv(), None);
+    assert_eq!(
+        kitchen.by_ref().take(2).collect::<Vec<_>>().await,
+        [21.5, 40.0]
+    );
+    assert_eq!(kitchen.try_recv(), None);
+}
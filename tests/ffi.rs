@@ -0,0 +1,58 @@
+use std::{path::Path, process::Command};
+
+/// C言語で書いたテストを、ビルドした共有ライブラリにリンクして実行する。
+#[test]
+fn c_integration() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // 共有ライブラリは、テストの実行ファイルと同じディレクトリに出力される
+    let lib_dir = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let exe = lib_dir.join("ffi_c_test");
+
+    let compiler = cc::Build::new()
+        .target(env!("BUILD_TARGET"))
+        .host(env!("BUILD_TARGET"))
+        .opt_level(0)
+        .cargo_metadata(false)
+        .get_compiler();
+    let mut command = compiler.to_command();
+    if cfg!(feature = "python") {
+        // 拡張モジュールはPythonのシンボルをインタープリターから解決するため、未定義のまま残る
+        command.arg("-Wl,--allow-shlib-undefined");
+    }
+    let status = command
+        .arg(root.join("tests/c/ffi.c"))
+        .arg("-I")
+        .arg(env!("HEADER_DIR"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .args(["-lasync_rust", "-lpthread", "-o"])
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to compile tests/c/ffi.c");
+
+    let output = Command::new(&exe).output().unwrap();
+    assert!(
+        output.status.success(),
+        "C test failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// コミットしているヘッダーが、ビルドで生成したヘッダーと一致する。
+#[test]
+fn committed_header_is_up_to_date() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let committed = std::fs::read_to_string(root.join("include/async_rust.h")).unwrap();
+    let generated =
+        std::fs::read_to_string(Path::new(env!("HEADER_DIR")).join("async_rust.h")).unwrap();
+    assert!(
+        committed == generated,
+        "include/async_rust.h is out of date; regenerate it with `ASYNC_RUST_UPDATE_HEADER=1 cargo build`"
+    );
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use async_rust::job::{JobError, JobRegistry, JobStatus};
+use tokio::runtime::Runtime;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_time()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn submit_and_collect() {
+    let runtime = runtime();
+    let jobs = JobRegistry::new(runtime.handle().clone());
+
+    let id = jobs.submit(async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        1 + 2
+    });
+    assert_eq!(jobs.poll(id), Ok(None));
+    assert_eq!(
+        jobs.wait(id, Duration::from_millis(10)),
+        Err(JobError::Timeout(id))
+    );
+    assert_eq!(jobs.wait(id, Duration::from_secs(5)), Ok(3));
+    // 回収したジョブは削除される
+    assert_eq!(jobs.status(id), Err(JobError::NotFound(id)));
+    assert!(jobs.is_empty());
+}
+
+#[test]
+fn status_transitions() {
+    let runtime = runtime();
+    let jobs = JobRegistry::new(runtime.handle().clone());
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let id = jobs.submit(async move {
+        rx.await.unwrap();
+    });
+    while jobs.status(id) == Ok(JobStatus::Pending) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(jobs.status(id), Ok(JobStatus::Running));
+    tx.send(()).unwrap();
+    while jobs.status(id) == Ok(JobStatus::Running) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(jobs.status(id), Ok(JobStatus::Done));
+    assert_eq!(jobs.poll(id), Ok(Some(())));
+}
+
+#[test]
+fn cancel_and_panic() {
+    let runtime = runtime();
+    let jobs = JobRegistry::new(runtime.handle().clone());
+
+    let id = jobs.submit(std::future::pending::<()>());
+    jobs.cancel(id).unwrap();
+    assert_eq!(jobs.status(id), Ok(JobStatus::Failed));
+    assert_eq!(
+        jobs.wait(id, Duration::from_secs(5)),
+        Err(JobError::Cancelled(id))
+    );
+
+    let id = jobs.submit(async { panic!("boom") });
+    assert_eq!(
+        jobs.wait(id, Duration::from_secs(5)),
+        Err(JobError::Panicked {
+            id,
+            message: "boom".to_string()
+        })
+    );
+}
+
+#[test]
+fn uncollected_results_expire() {
+    let runtime = runtime();
+    let jobs = JobRegistry::with_ttl(runtime.handle().clone(), Duration::from_millis(50));
+
+    let id = jobs.submit(async { 1 });
+    while jobs.status(id) != Ok(JobStatus::Done) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(jobs.poll(id), Err(JobError::NotFound(id)));
+}
@@ -0,0 +1,300 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use async_rust::{
+    actor,
+    actor::AskError,
+    kv::{
+        Data, KvError, Operation, Store,
+        key_value::{KeyValueActor, KeyValueMessage},
+        writer::{WriterActor, WriterLogMessage},
+    },
+};
+use rand::Rng;
+
+mod common;
+
+/// クラッシュさせる子プロセスに、ストアのディレクトリを渡す環境変数
+const CRASH_DIR_ENV: &str = "ASYNC_RUST_KV_CRASH_DIR";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn wal_path(dir: &Path) -> PathBuf {
+    dir.join("wal.log")
+}
+
+/// シーケンス番号から、決まった操作を作成する。
+fn operation(seq: u64) -> Operation {
+    let key = format!("key{}", seq % 37);
+    if seq.is_multiple_of(5) {
+        Operation::Delete { key }
+    } else {
+        Operation::Set {
+            key,
+            value: seq.to_le_bytes().repeat(seq as usize % 50 + 1),
+            expires_at: None,
+        }
+    }
+}
+
+/// シーケンス番号が`seq`までの操作を適用したデータを返す。
+fn expected(seq: u64) -> Data {
+    let mut data = Data::default();
+    for seq in 1..=seq {
+        data.apply(operation(seq));
+    }
+    data
+}
+
+fn value<'a>(store: &'a Store, key: &str) -> Option<&'a [u8]> {
+    store.get(key).map(|entry| entry.value.as_slice())
+}
+
+#[test]
+fn reopen_replays_synced_log() {
+    let dir = common::temp_dir("kv", "replay");
+    let mut store = Store::open(&dir).unwrap();
+    for seq in 1..=20 {
+        store.apply(operation(seq));
+    }
+    store.sync().unwrap();
+    // 同期していない操作は永続化されない
+    store.set("unsynced", "value");
+    drop(store);
+
+    let store = Store::open(&dir).unwrap();
+    assert_eq!(store.seq(), 20);
+    assert_eq!(store.data(), &expected(20));
+    assert_eq!(value(&store, "unsynced"), None);
+}
+
+#[test]
+fn torn_tail_is_truncated() {
+    let dir = common::temp_dir("kv", "torn");
+    let mut store = Store::open(&dir).unwrap();
+    store.set("a", "1");
+    store.set("b", "2");
+    store.sync().unwrap();
+    drop(store);
+
+    // 書き込み途中でクラッシュしたレコードを模倣する
+    let valid_len = fs::metadata(wal_path(&dir)).unwrap().len();
+    let mut wal = fs::OpenOptions::new()
+        .append(true)
+        .open(wal_path(&dir))
+        .unwrap();
+    wal.write_all(&[40, 0, 0, 0, 1, 2, 3]).unwrap();
+    drop(wal);
+
+    let mut store = Store::open(&dir).unwrap();
+    assert_eq!(fs::metadata(wal_path(&dir)).unwrap().len(), valid_len);
+    assert_eq!(value(&store, "b"), Some(&b"2"[..]));
+    store.set("c", "3");
+    store.sync().unwrap();
+    drop(store);
+
+    let store = Store::open(&dir).unwrap();
+    assert_eq!(store.seq(), 3);
+    assert_eq!(value(&store, "c"), Some(&b"3"[..]));
+}
+
+#[test]
+fn snapshot_compacts_log() {
+    let dir = common::temp_dir("kv", "snapshot");
+    let mut store = Store::open(&dir).unwrap().with_snapshot_threshold(1024);
+    for seq in 1..=200 {
+        store.apply(operation(seq));
+        store.sync().unwrap();
+        assert!(fs::metadata(wal_path(&dir)).unwrap().len() < 1024 + 512);
+    }
+    assert!(dir.join("snapshot").exists());
+    assert!(!dir.join("snapshot.tmp").exists());
+    drop(store);
+
+    let store = Store::open(&dir).unwrap();
+    assert_eq!(store.seq(), 200);
+    assert_eq!(store.data(), &expected(200));
+}
+
+#[test]
+fn log_left_after_snapshot_is_not_replayed_twice() {
+    let dir = common::temp_dir("kv", "stale_log");
+    let mut store = Store::open(&dir).unwrap();
+    for seq in 1..=30 {
+        store.apply(operation(seq));
+    }
+    store.sync().unwrap();
+    let log = fs::read(wal_path(&dir)).unwrap();
+    store.snapshot().unwrap();
+    assert_eq!(fs::metadata(wal_path(&dir)).unwrap().len(), 0);
+    drop(store);
+
+    // スナップショットを作成した後、ログを空にする前にクラッシュした状態を模倣する
+    fs::write(wal_path(&dir), log).unwrap();
+    let mut store = Store::open(&dir).unwrap();
+    assert_eq!(store.seq(), 30);
+    assert_eq!(store.data(), &expected(30));
+    store.apply(operation(31));
+    store.sync().unwrap();
+    drop(store);
+
+    assert_eq!(Store::open(&dir).unwrap().data(), &expected(31));
+}
+
+#[test]
+fn batches_and_expiry_survive_log_and_snapshot() {
+    let dir = common::temp_dir("kv", "batch");
+    let mut store = Store::open(&dir).unwrap();
+    store.set("a", "1");
+    let seq = store.apply(Operation::Batch(vec![
+        Operation::Set {
+            key: String::from("b"),
+            value: b"2".to_vec(),
+            expires_at: Some(1_000),
+        },
+        Operation::Delete {
+            key: String::from("a"),
+        },
+    ]));
+    // バッチは1つの操作として、同じバージョンを書き込む
+    assert_eq!(seq, 2);
+    assert_eq!(store.get("b").unwrap().version, 2);
+    store.sync().unwrap();
+    drop(store);
+
+    let mut store = Store::open(&dir).unwrap();
+    assert_eq!(value(&store, "a"), None);
+    assert_eq!(store.get("b").unwrap().expires_at, Some(1_000));
+    assert_eq!(store.data().expired(999), Vec::<String>::new());
+    assert_eq!(store.data().expired(1_000), vec![String::from("b")]);
+    store.snapshot().unwrap();
+    let data = store.data().clone();
+    drop(store);
+
+    assert_eq!(Store::open(&dir).unwrap().data(), &data);
+}
+
+#[tokio::test]
+async fn writer_actor_acknowledges_after_sync() {
+    let dir = common::temp_dir("kv", "writer");
+    let writer = actor::spawn(WriterActor::new(&dir).with_sync_delay(Duration::from_millis(5)));
+    let acks = (1..=100).map(|seq| {
+        writer.ask(
+            move |ack| WriterLogMessage::Append {
+                operation: operation(seq),
+                ack: Some(ack),
+            },
+            TIMEOUT,
+        )
+    });
+    for ack in futures::future::join_all(acks).await {
+        ack.unwrap().unwrap();
+    }
+    // 応答を受け取った操作は、ストアを開き直しても残っている
+    assert_eq!(Store::open(&dir).unwrap().data(), &expected(100));
+
+    writer
+        .send(WriterLogMessage::Append {
+            operation: operation(101),
+            ack: None,
+        })
+        .await
+        .unwrap();
+    writer.stop().await;
+    writer.stopped().await;
+    assert_eq!(Store::open(&dir).unwrap().data(), &expected(101));
+}
+
+#[tokio::test]
+async fn key_value_actor_stops_when_the_writer_is_gone() {
+    let dir = common::temp_dir("kv", "writer_gone");
+    let writer = actor::spawn(WriterActor::new(&dir));
+    let key_value = actor::spawn(KeyValueActor::new(writer.clone()));
+    let set = |key: &'static str| {
+        key_value.ask(
+            move |response| KeyValueMessage::Set {
+                key: key.to_string(),
+                value: b"v".to_vec(),
+                ttl: None,
+                response,
+            },
+            TIMEOUT,
+        )
+    };
+    assert_eq!(set("a").await.unwrap().unwrap(), 1);
+
+    writer.stop().await;
+    writer.stopped().await;
+    // 永続化できない書き込みを受け付けず、キーバリューストアアクターも停止する
+    assert!(matches!(
+        set("b").await,
+        Err(AskError::Closed | AskError::NoReply) | Ok(Err(KvError::Unavailable(AskError::Closed)))
+    ));
+    tokio::time::timeout(TIMEOUT, key_value.stopped())
+        .await
+        .unwrap();
+    assert_eq!(Store::open(&dir).unwrap().seq(), 1);
+}
+
+/// クラッシュさせる子プロセスとして、操作を追記し続ける。
+///
+/// 環境変数が設定されていない場合は何もしない。
+#[test]
+fn crash_child() {
+    let Some(dir) = env::var_os(CRASH_DIR_ENV) else {
+        return;
+    };
+    let mut store = Store::open(dir).unwrap().with_snapshot_threshold(16 * 1024);
+    let mut stdout = io::stdout().lock();
+    loop {
+        // 同期する操作の数を変えて、さまざまな位置でクラッシュさせる
+        for _ in 0..store.seq() % 7 + 1 {
+            store.apply(operation(store.seq() + 1));
+        }
+        store.sync().unwrap();
+        writeln!(stdout, "acked {}", store.seq()).unwrap();
+        stdout.flush().unwrap();
+    }
+}
+
+#[test]
+fn recovers_after_crash_at_random_points() {
+    let dir = common::temp_dir("kv", "crash");
+    let mut rng = rand::rng();
+    for _ in 0..15 {
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["crash_child", "--exact", "--nocapture", "--test-threads=1"])
+            .env(CRASH_DIR_ENV, &dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(rng.random_range(20..150)));
+        child.kill().unwrap();
+        let mut output = String::new();
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        child.wait().unwrap();
+
+        let acked = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("acked ")?.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0);
+        let store = Store::open(&dir).unwrap();
+        // 応答した操作はすべて残っていて、途中の操作が欠けていない
+        assert!(store.seq() >= acked, "seq {} < acked {acked}", store.seq());
+        assert_eq!(store.data(), &expected(store.seq()));
+    }
+    assert!(Store::open(&dir).unwrap().seq() > 0);
+}
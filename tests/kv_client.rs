@@ -0,0 +1,178 @@
+use std::{ops::Bound, path::Path, time::Duration};
+
+use async_rust::{
+    actor::system::ActorSystem,
+    kv::{
+        Entry, KvError, ScanRange,
+        client::Client,
+        key_value::{KeyValueActor, WatchEvent, Write},
+        router::Router,
+    },
+};
+
+mod common;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 有効期限が切れたキーを短い間隔で削除するキーバリューストアを起動する。
+async fn start(system: &ActorSystem, dir: &Path) -> Client {
+    let router = Router::new(dir).with_key_value(|writer| {
+        KeyValueActor::new(writer).with_expiry_interval(Duration::from_millis(20))
+    });
+    Client::start_with(system, router).await.unwrap()
+}
+
+fn keys(entries: &[(String, Entry)]) -> Vec<&str> {
+    entries.iter().map(|(key, _)| key.as_str()).collect()
+}
+
+#[tokio::test]
+async fn versions_and_compare_and_swap() {
+    let system = ActorSystem::new("kv");
+    let client = start(&system, &common::temp_dir("kv_client", "cas")).await;
+
+    // 存在しないキーのバージョンは0
+    let v1 = client.compare_and_swap("counter", 0, "1").await.unwrap();
+    assert!(matches!(
+        client.compare_and_swap("counter", 0, "1").await,
+        Err(KvError::Conflict { actual, .. }) if actual == v1
+    ));
+    let v2 = client.compare_and_swap("counter", v1, "2").await.unwrap();
+    assert!(v2 > v1);
+    let entry = client.get("counter").await.unwrap().unwrap();
+    assert_eq!((entry.value.as_slice(), entry.version), (&b"2"[..], v2));
+
+    // 別のキーへの書き込みでもバージョンが進むため、古いバージョンでは書き込めない
+    client.set("other", "x").await.unwrap();
+    client.delete("counter").await.unwrap();
+    assert!(client.compare_and_swap("counter", v2, "3").await.is_err());
+    assert!(client.compare_and_swap("counter", 0, "3").await.is_ok());
+    client.shutdown().await;
+}
+
+#[tokio::test]
+async fn scans_are_ordered_and_bounded() {
+    let system = ActorSystem::new("kv");
+    let client = start(&system, &common::temp_dir("kv_client", "scan")).await;
+    for key in ["user/3", "user/1", "order/1", "user/2", "users"] {
+        client.set(key, key).await.unwrap();
+    }
+
+    let users = client.scan_prefix("user/").await.unwrap();
+    assert_eq!(keys(&users), ["user/1", "user/2", "user/3"]);
+    let range = client
+        .scan(ScanRange::between("order/", "user/3"), 10)
+        .await
+        .unwrap();
+    assert_eq!(keys(&range), ["order/1", "user/1", "user/2"]);
+    let limited = client.scan(ScanRange::all(), 2).await.unwrap();
+    assert_eq!(keys(&limited), ["order/1", "user/1"]);
+    // 逆転した範囲は空になる
+    let reversed = ScanRange::Range(
+        Bound::Excluded(String::from("b")),
+        Bound::Excluded(String::from("a")),
+    );
+    assert!(client.scan(reversed, 10).await.unwrap().is_empty());
+    client.shutdown().await;
+}
+
+#[tokio::test]
+async fn keys_expire_and_notify_watchers() {
+    let system = ActorSystem::new("kv");
+    let client = start(&system, &common::temp_dir("kv_client", "ttl")).await;
+    let mut events = client.watch("session/").await.unwrap();
+
+    let version = client
+        .set_with_ttl("session/a", "token", Duration::from_millis(100))
+        .await
+        .unwrap();
+    client.set("unwatched", "x").await.unwrap();
+    assert!(client.get("session/a").await.unwrap().is_some());
+
+    let set = tokio::time::timeout(TIMEOUT, events.recv()).await.unwrap();
+    assert_eq!(
+        set,
+        Some(WatchEvent::Set {
+            key: String::from("session/a"),
+            value: b"token".to_vec(),
+            version,
+        })
+    );
+    let expired = tokio::time::timeout(TIMEOUT, events.recv()).await.unwrap();
+    assert!(matches!(
+        expired,
+        Some(WatchEvent::Expired { key, .. }) if key == "session/a"
+    ));
+    assert_eq!(client.get("session/a").await.unwrap(), None);
+    assert!(client.scan_prefix("session/").await.unwrap().is_empty());
+    client.shutdown().await;
+}
+
+#[tokio::test]
+async fn batches_are_applied_and_persisted_atomically() {
+    let system = ActorSystem::new("kv");
+    let dir = common::temp_dir("kv_client", "batch");
+    let client = start(&system, &dir).await;
+    client.set("from", "10").await.unwrap();
+    let mut events = client.watch("").await.unwrap();
+
+    let version = client
+        .batch(vec![
+            Write::Delete {
+                key: String::from("from"),
+            },
+            Write::Set {
+                key: String::from("to"),
+                value: b"10".to_vec(),
+                ttl: None,
+            },
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        events.recv().await,
+        Some(WatchEvent::Deleted {
+            key: String::from("from"),
+            version,
+        })
+    );
+    assert!(
+        matches!(events.recv().await, Some(WatchEvent::Set { version: v, .. }) if v == version)
+    );
+    client.shutdown().await;
+
+    // 開き直しても、バッチのすべての書き込みが残っている
+    let system = ActorSystem::new("kv");
+    let client = start(&system, &dir).await;
+    assert_eq!(client.get("from").await.unwrap(), None);
+    let to = client.get("to").await.unwrap().unwrap();
+    assert_eq!((to.value.as_slice(), to.version), (&b"10"[..], version));
+    // 監視していた受信側は、停止したときに閉じられる
+    assert_eq!(events.recv().await, None);
+    client.shutdown().await;
+}
+
+#[tokio::test]
+async fn client_connects_through_the_actor_system() {
+    let system = ActorSystem::new("kv");
+    let dir = common::temp_dir("kv_client", "connect");
+    let client = start(&system, &dir).await;
+    // 同じアクターシステムには1つしか登録できない
+    assert!(
+        Client::start(&system, common::temp_dir("kv_client", "connect_twice"))
+            .await
+            .is_err()
+    );
+
+    let connected = Client::connect(&system).unwrap();
+    connected.set("hello", "world").await.unwrap();
+    assert_eq!(
+        client.get("hello").await.unwrap().unwrap().value,
+        b"world".to_vec()
+    );
+    client.shutdown().await;
+    assert!(matches!(
+        connected.get("hello").await,
+        Err(KvError::Unavailable(_))
+    ));
+}
@@ -0,0 +1,214 @@
+use std::{
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::executor::block_on;
+
+mod common;
+
+use common::ServerProcess;
+
+fn set(server: &ServerProcess, key: &str, value: &str) -> u64 {
+    block_on(server.client().set(key, value)).unwrap()
+}
+
+fn get(server: &ServerProcess, key: &str) -> Option<Vec<u8>> {
+    block_on(server.client().get(key))
+        .unwrap()
+        .map(|entry| entry.value)
+}
+
+/// サーバーのキーが値になるまで待つ。
+fn wait_for(server: &ServerProcess, key: &str, value: &str) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while get(server, key).as_deref() != Some(value.as_bytes()) {
+        assert!(Instant::now() < deadline, "`{key}` was not replicated");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn keys(server: &ServerProcess) -> Vec<(String, Vec<u8>, u64)> {
+    block_on(server.client().scan("", 1_000))
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.key, entry.value, entry.version))
+        .collect()
+}
+
+/// 接続を切断できる、レプリカとプライマリの間のプロキシ
+struct Proxy {
+    addr: String,
+    paused: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Proxy {
+    fn start(upstream: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let paused = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let upstream = upstream.to_string();
+        let (accepting, tracked) = (paused.clone(), connections.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(downstream) = stream else { continue };
+                // 切断している間に接続されたら、直ちに閉じる
+                if accepting.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let Ok(upstream) = TcpStream::connect(&upstream) else {
+                    continue;
+                };
+                let mut tracked = tracked.lock().unwrap();
+                tracked.push(downstream.try_clone().unwrap());
+                tracked.push(upstream.try_clone().unwrap());
+                pipe(
+                    downstream.try_clone().unwrap(),
+                    upstream.try_clone().unwrap(),
+                );
+                pipe(upstream, downstream);
+            }
+        });
+        Self {
+            addr,
+            paused,
+            connections,
+        }
+    }
+
+    /// 接続をすべて切断して、再開するまで接続を受け付けない。
+    fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        for stream in self.connections.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+}
+
+fn pipe(mut from: TcpStream, mut to: TcpStream) {
+    thread::spawn(move || {
+        let _ = std::io::copy(&mut from, &mut to);
+        let _ = to.shutdown(Shutdown::Both);
+    });
+}
+
+#[test]
+fn replica_receives_a_snapshot_and_follows_the_primary() {
+    let primary =
+        ServerProcess::primary(&common::temp_dir("kv_replication", "follow/primary"), &[]);
+    set(&primary, "user/1", "alice");
+    set(&primary, "user/2", "bob");
+
+    let replica = ServerProcess::start(
+        &common::temp_dir("kv_replication", "follow/replica"),
+        &["--replicate-from", primary.replication_addr()],
+    );
+    wait_for(&replica, "user/2", "bob");
+    set(&primary, "user/3", "carol");
+    block_on(primary.client().delete("user/1")).unwrap();
+    wait_for(&replica, "user/3", "carol");
+    assert_eq!(get(&replica, "user/1"), None);
+    // バージョンもプライマリと一致する
+    assert_eq!(keys(&replica), keys(&primary));
+
+    let error = block_on(replica.client().set("user/4", "dave")).unwrap_err();
+    assert!(error.to_string().contains("read-only"), "{error}");
+}
+
+#[test]
+fn sync_writes_wait_for_replica_acknowledgements() {
+    let primary = ServerProcess::primary(
+        &common::temp_dir("kv_replication", "sync/primary"),
+        &["--sync-replicas", "1", "--sync-timeout", "0.2"],
+    );
+    // レプリカがいない場合は、永続化したことを確認できない
+    let error = block_on(primary.client().set("before", "replica")).unwrap_err();
+    assert!(error.to_string().contains("not acknowledged"), "{error}");
+    assert_eq!(get(&primary, "before").as_deref(), Some(&b"replica"[..]));
+
+    let replica = ServerProcess::start(
+        &common::temp_dir("kv_replication", "sync/replica"),
+        &["--replicate-from", primary.replication_addr()],
+    );
+    wait_for(&replica, "before", "replica");
+    for i in 0..20 {
+        let value = i.to_string();
+        set(&primary, "counter", &value);
+        // 応答した時点で、レプリカに適用されている
+        assert_eq!(get(&replica, "counter"), Some(value.into_bytes()));
+    }
+}
+
+#[test]
+fn replica_catches_up_from_the_log_tail_after_reconnecting() {
+    let primary = ServerProcess::primary(
+        &common::temp_dir("kv_replication", "reconnect/primary"),
+        &[],
+    );
+    let proxy = Proxy::start(primary.replication_addr());
+    let replica = ServerProcess::start(
+        &common::temp_dir("kv_replication", "reconnect/replica"),
+        &["--replicate-from", &proxy.addr],
+    );
+    set(&primary, "key/0", "0");
+    wait_for(&replica, "key/0", "0");
+
+    proxy.pause();
+    for i in 1..50 {
+        set(&primary, &format!("key/{i}"), &i.to_string());
+    }
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(get(&replica, "key/49"), None);
+
+    proxy.resume();
+    wait_for(&replica, "key/49", "49");
+    assert_eq!(keys(&replica), keys(&primary));
+}
+
+#[test]
+fn manual_failover_promotes_a_replica() {
+    let primary =
+        ServerProcess::primary(&common::temp_dir("kv_replication", "failover/primary"), &[]);
+    let standby = ServerProcess::primary(
+        &common::temp_dir("kv_replication", "failover/standby"),
+        &["--replicate-from", primary.replication_addr()],
+    );
+    let replica_dir = common::temp_dir("kv_replication", "failover/replica");
+    let replica = ServerProcess::start(
+        &replica_dir,
+        &["--replicate-from", primary.replication_addr()],
+    );
+    let version = set(&primary, "config", "v1");
+    wait_for(&standby, "config", "v1");
+    wait_for(&replica, "config", "v1");
+
+    // プライマリが停止したら、レプリカを昇格して、他のレプリカの接続先を変更する
+    drop(primary);
+    let output = standby.cli(&["promote"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        std::str::from_utf8(&output.stdout).unwrap(),
+        format!("promoted at version {version}\n")
+    );
+    assert_eq!(set(&standby, "config", "v2"), version + 1);
+
+    drop(replica);
+    let replica = ServerProcess::start(
+        &replica_dir,
+        &["--replicate-from", standby.replication_addr()],
+    );
+    wait_for(&replica, "config", "v2");
+    assert_eq!(keys(&replica), keys(&standby));
+    assert!(block_on(replica.client().set("config", "v3")).is_err());
+}
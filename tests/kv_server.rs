@@ -0,0 +1,91 @@
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, TcpStream},
+    process::Output,
+    time::Duration,
+};
+
+use data_layer::kv::Response;
+use futures::executor::block_on;
+
+mod common;
+
+use common::ServerProcess;
+
+fn stdout(output: &Output) -> &str {
+    assert!(output.status.success(), "{output:?}");
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+#[test]
+fn client_library_reads_and_writes_over_tcp() {
+    let server = ServerProcess::start(&common::temp_dir("kv_server", "library"), &[]);
+    let client = server.client();
+    block_on(async {
+        let v1 = client.set("user/1", "alice").await.unwrap();
+        let v2 = client.set("user/2", "bob").await.unwrap();
+        client.set("order/1", "book").await.unwrap();
+        assert!(v2 > v1);
+
+        let entry = client.get("user/1").await.unwrap().unwrap();
+        assert_eq!((entry.value.as_slice(), entry.version), (&b"alice"[..], v1));
+        let users: Vec<_> = client
+            .scan("user/", 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        assert_eq!(users, ["user/1", "user/2"]);
+        assert_eq!(client.scan("", 1).await.unwrap().len(), 1);
+
+        client.delete("user/1").await.unwrap();
+        assert_eq!(client.get("user/1").await.unwrap(), None);
+
+        client
+            .set_with_ttl("session", "token", Duration::from_millis(50))
+            .await
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(client.get("session").await.unwrap(), None);
+    });
+}
+
+#[test]
+fn cli_commands_and_restart_persistence() {
+    let dir = common::temp_dir("kv_server", "cli");
+    let server = ServerProcess::start(&dir, &[]);
+    assert_eq!(
+        stdout(&server.cli(&["set", "hello", "world"])),
+        "version 1\n"
+    );
+    assert_eq!(stdout(&server.cli(&["set", "help", "me"])), "version 2\n");
+    assert_eq!(stdout(&server.cli(&["get", "hello"])), "world\n");
+    assert_eq!(
+        stdout(&server.cli(&["scan", "hel"])),
+        "hello\tworld\nhelp\tme\n"
+    );
+    assert_eq!(stdout(&server.cli(&["delete", "help"])), "version 3\n");
+    assert!(!server.cli(&["get", "help"]).status.success());
+    assert!(!server.cli(&["unknown"]).status.success());
+
+    // 応答した書き込みは、サーバーを強制終了しても残っている
+    drop(server);
+    let server = ServerProcess::start(&dir, &[]);
+    assert_eq!(stdout(&server.cli(&["scan"])), "hello\tworld\n");
+}
+
+#[test]
+fn invalid_request_gets_an_error_response() {
+    let server = ServerProcess::start(&common::temp_dir("kv_server", "invalid"), &[]);
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream.write_all(&[0xff, 1, 2, 3]).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+    let response = Response::deserialize(&mut std::io::Cursor::new(response.as_slice())).unwrap();
+    assert!(matches!(response, Response::Error(message) if message.contains("invalid request")));
+
+    // サーバーは引き続きリクエストを処理する
+    block_on(server.client().set("key", "value")).unwrap();
+}
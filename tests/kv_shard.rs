@@ -0,0 +1,193 @@
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use async_rust::{
+    actor::system::ActorSystem,
+    kv::{
+        KvError, ScanRange, Store, client::Client, key_value::Write, ring::HashRing, router::Router,
+    },
+};
+
+mod common;
+
+async fn start(dir: &Path, shards: usize) -> Client {
+    let system = ActorSystem::new("kv");
+    Client::start_with(&system, Router::new(dir).with_shards(shards))
+        .await
+        .unwrap()
+}
+
+fn key(i: usize) -> String {
+    format!("key{i:04}")
+}
+
+/// シャードのストアに永続化されているキーを返す。
+fn persisted_keys(dir: &Path, shard: u32) -> Vec<String> {
+    let store = Store::open(dir.join(format!("shard-{shard}"))).unwrap();
+    store.data().iter().map(|(key, _)| key.clone()).collect()
+}
+
+#[test]
+fn ring_moves_only_the_keys_of_the_changed_shard() {
+    let keys: Vec<_> = (0..10_000).map(key).collect();
+    let ring = HashRing::new([0, 1, 2, 3]);
+    assert_eq!(ring, HashRing::new([3, 1, 0, 2]));
+
+    let mut counts = BTreeMap::new();
+    for key in &keys {
+        *counts.entry(ring.owner(key).unwrap()).or_insert(0) += 1;
+    }
+    // 仮想ノードによって、キーがおおむね均等に割り当てられる
+    for (shard, count) in counts {
+        assert!((1_500..3_500).contains(&count), "shard {shard}: {count}");
+    }
+
+    let mut added = ring.clone();
+    added.add(4);
+    for key in &keys {
+        let before = ring.owner(key).unwrap();
+        let after = added.owner(key).unwrap();
+        assert!(after == before || after == 4);
+    }
+    added.remove(4);
+    assert_eq!(added, ring);
+    assert_eq!(HashRing::default().owner("key"), None);
+}
+
+#[tokio::test]
+async fn keys_are_spread_over_shards_and_scans_are_merged() {
+    let dir = common::temp_dir("kv_shard", "spread");
+    let client = start(&dir, 4).await;
+    for i in 0..200 {
+        client.set(key(i), i.to_string()).await.unwrap();
+    }
+    let all = client.scan(ScanRange::all(), usize::MAX).await.unwrap();
+    let keys: Vec<_> = all.iter().map(|(key, _)| key.clone()).collect();
+    assert_eq!(keys, (0..200).map(key).collect::<Vec<_>>());
+    let range = client
+        .scan(ScanRange::between(key(10), key(100)), 5)
+        .await
+        .unwrap();
+    assert_eq!(range.first().unwrap().0, key(10));
+    assert_eq!(range.len(), 5);
+    client.shutdown().await;
+
+    // 各キーは、割り当てられたシャードのファイルだけに永続化されている
+    let ring = HashRing::new([0, 1, 2, 3]);
+    for shard in 0..4 {
+        let keys = persisted_keys(&dir, shard);
+        assert!(!keys.is_empty());
+        assert!(keys.iter().all(|key| ring.owner(key) == Some(shard)));
+    }
+}
+
+#[tokio::test]
+async fn adding_and_removing_shards_migrates_keys() {
+    let dir = common::temp_dir("kv_shard", "migrate");
+    let client = start(&dir, 2).await;
+    for i in 0..300 {
+        client.set(key(i), i.to_string()).await.unwrap();
+    }
+    client
+        .set_with_ttl("session", "token", Duration::from_secs(60))
+        .await
+        .unwrap();
+    let mut events = client.watch("key").await.unwrap();
+
+    assert_eq!(client.add_shard().await.unwrap(), 2);
+    assert_eq!(client.shards().await.unwrap(), [0, 1, 2]);
+    assert!(!persisted_keys(&dir, 2).is_empty());
+    client.set(key(0), "updated").await.unwrap();
+
+    client.remove_shard(0).await.unwrap();
+    assert_eq!(client.shards().await.unwrap(), [1, 2]);
+    assert!(!dir.join("shard-0").exists());
+    for i in 1..300 {
+        let entry = client.get(key(i)).await.unwrap().unwrap();
+        assert_eq!(entry.value, i.to_string().into_bytes());
+    }
+    assert_eq!(client.get(key(0)).await.unwrap().unwrap().value, b"updated");
+    // 有効期限は移行先のシャードに引き継がれる
+    let session = client.get("session").await.unwrap().unwrap();
+    assert!(session.expires_at.is_some());
+
+    // 移行のためのコピーと削除は通知されない
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.key(), key(0));
+    assert!(events.try_recv().is_err());
+    client.shutdown().await;
+
+    // シャードの構成は永続化され、指定したシャードの数より優先される
+    let client = start(&dir, 5).await;
+    assert_eq!(client.shards().await.unwrap(), [1, 2]);
+    assert_eq!(client.scan_prefix("key").await.unwrap().len(), 300);
+    client.shutdown().await;
+}
+
+#[tokio::test]
+async fn keys_left_by_an_interrupted_migration_are_removed() {
+    let dir = common::temp_dir("kv_shard", "interrupted");
+    let client = start(&dir, 2).await;
+    let ring = HashRing::new([0, 1]);
+    let owned_by_0 = (0..)
+        .map(key)
+        .find(|key| ring.owner(key) == Some(0))
+        .unwrap();
+    client.set(owned_by_0.clone(), "current").await.unwrap();
+    client.shutdown().await;
+
+    // 移行の途中で停止して、割り当てられていないシャードにコピーが残った状態を模倣する
+    let mut store = Store::open(dir.join("shard-1")).unwrap();
+    store.set(owned_by_0.clone(), "stale");
+    store.sync().unwrap();
+    drop(store);
+
+    let client = start(&dir, 2).await;
+    let entries = client.scan_prefix("key").await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].1.value, b"current");
+    client.shutdown().await;
+    assert!(persisted_keys(&dir, 1).is_empty());
+}
+
+#[tokio::test]
+async fn batches_must_stay_within_one_shard() {
+    let dir = common::temp_dir("kv_shard", "batch");
+    let client = start(&dir, 4).await;
+    let ring = HashRing::new([0, 1, 2, 3]);
+    let keys: Vec<_> = (0..).map(key).take(50).collect();
+    let same: Vec<_> = keys
+        .iter()
+        .filter(|key| ring.owner(key) == Some(0))
+        .take(2)
+        .collect();
+    let other = keys.iter().find(|key| ring.owner(key) == Some(1)).unwrap();
+
+    let set = |key: &String| Write::Set {
+        key: key.clone(),
+        value: b"value".to_vec(),
+        ttl: None,
+    };
+    client
+        .batch(vec![set(same[0]), set(same[1])])
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.batch(vec![set(same[0]), set(other)]).await,
+        Err(KvError::CrossShard)
+    ));
+    assert_eq!(client.get(other.clone()).await.unwrap(), None);
+
+    assert!(matches!(
+        client.remove_shard(9).await,
+        Err(KvError::UnknownShard(9))
+    ));
+    for shard in [0, 1, 2] {
+        client.remove_shard(shard).await.unwrap();
+    }
+    assert!(matches!(
+        client.remove_shard(3).await,
+        Err(KvError::LastShard)
+    ));
+    assert_eq!(client.scan_prefix("key").await.unwrap().len(), 2);
+    client.shutdown().await;
+}
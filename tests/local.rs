@@ -0,0 +1,64 @@
+use std::{cell::RefCell, rc::Rc, thread::ThreadId, time::Duration};
+
+use async_rust::{
+    runtime::{FutureType, Runtime, spawn_local, spawn_pinned, worker_count},
+    spawn_task,
+};
+
+/// スレッドをブロックせずに、ワーカーに1回制御を返す。
+async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+#[test]
+fn pinned_and_local_tasks_stay_on_their_worker() {
+    Runtime::new().with_high_num(2).with_low_num(1).run();
+    assert_eq!(worker_count(), 3);
+
+    for idx in 0..worker_count() {
+        // Rcを保持するフューチャーを、指定したワーカーで実行する
+        let pinned = spawn_pinned(idx, || async {
+            let threads: Rc<RefCell<Vec<ThreadId>>> = Rc::default();
+            for _ in 0..5 {
+                threads.borrow_mut().push(std::thread::current().id());
+                yield_now().await;
+            }
+
+            // 同じワーカーに、Sendでないタスクを生成する
+            let local_threads = threads.clone();
+            let local = spawn_local(async move {
+                local_threads.borrow_mut().push(std::thread::current().id());
+                yield_now().await;
+                local_threads.borrow().len()
+            });
+            let len = local.await;
+            (len, threads.take())
+        });
+
+        // 別のタスクからタスクハンドルを待ち合わせる
+        let waiter = spawn_task!(
+            async move {
+                let (len, threads) = pinned.await;
+                len == 6 && threads.iter().all(|thread| *thread == threads[0])
+            },
+            FutureType::High
+        );
+        assert!(futures_lite::future::block_on(waiter));
+    }
+}
+
+#[test]
+#[should_panic(expected = "spawn_local must be called from a runtime worker")]
+fn spawn_local_outside_worker_panics() {
+    drop(spawn_local(async { std::thread::sleep(Duration::ZERO) }));
+}
@@ -0,0 +1,56 @@
+//! `cargo test --release --features loom --test loom`で実行する。
+#![cfg(feature = "loom")]
+
+use async_rust::{event_bus::EventBus, reactive::Signal};
+use loom::{future::block_on, thread};
+
+#[test]
+fn subscription_is_woken_by_a_concurrent_send() {
+    loom::model(|| {
+        let bus = EventBus::new();
+        let mut subscription = bus.subscribe();
+        let sender = thread::spawn(move || {
+            bus.send(1);
+        });
+        // 送信と待機がどの順序で実行されても、ウェイカーの登録が失われない
+        assert_eq!(block_on(subscription.recv()), Some(1));
+        assert_eq!(block_on(subscription.recv()), None);
+        sender.join().unwrap();
+    });
+}
+
+#[test]
+fn subscription_ends_when_the_bus_is_dropped_concurrently() {
+    loom::model(|| {
+        let bus = EventBus::<i32>::new();
+        let mut subscription = bus.subscribe();
+        let dropper = thread::spawn(move || drop(bus));
+        assert_eq!(block_on(subscription.recv()), None);
+        dropper.join().unwrap();
+    });
+}
+
+#[test]
+fn unsubscribing_races_with_send() {
+    loom::model(|| {
+        let bus = EventBus::new();
+        let subscription = bus.subscribe();
+        let unsubscriber = thread::spawn(move || drop(subscription));
+        assert!(bus.send(1) <= 1);
+        unsubscriber.join().unwrap();
+        assert_eq!(bus.subscriber_count(), 0);
+    });
+}
+
+#[test]
+fn watcher_observes_a_concurrent_set() {
+    loom::model(|| {
+        let signal = Signal::new(0);
+        let mut watcher = signal.watch();
+        let setter = thread::spawn(move || {
+            signal.set(1);
+        });
+        assert_eq!(block_on(watcher.wait_for(|&value| value == 1)), Some(1));
+        setter.join().unwrap();
+    });
+}
@@ -0,0 +1,120 @@
+#![cfg_attr(feature = "native", feature(coroutine_trait))]
+
+use std::{num::ParseIntError, pin::Pin};
+
+use async_rust::{
+    coroutine::{Coroutine, CoroutineState, generator::Generator},
+    pipeline,
+};
+use futures::StreamExt;
+
+#[test]
+fn stages_transform_values_in_order() {
+    let doubled = pipeline::from_iter(1..=5)
+        .map(|number| number * 2)
+        .filter(|number| number % 3 != 0)
+        .sink(pipeline::collect())
+        .run();
+    assert_eq!(doubled, [2, 4, 8, 10]);
+}
+
+#[test]
+fn generators_compose_as_source_and_stage() {
+    let source = Generator::new(|co, ()| async move {
+        for line in ["1", "2", "3"] {
+            co.yield_(line).await;
+        }
+    });
+    // 直前の入力との差を生成するステージ
+    let stage = Generator::new(|co, mut number: i32| async move {
+        let mut previous = 0;
+        loop {
+            let delta = number - previous;
+            previous = number;
+            number = co.yield_(delta).await;
+        }
+    });
+    let deltas = pipeline::source(source)
+        .map(|line| line.parse::<i32>().unwrap() * 10)
+        .stage(stage)
+        .sink(pipeline::collect())
+        .run();
+    assert_eq!(deltas, [10, 10, 10]);
+}
+
+#[test]
+fn errors_flow_through_the_pipeline_as_values() {
+    let results = pipeline::from_iter(["1", "x", "3"])
+        .map(str::parse::<i32>)
+        .sink(pipeline::collect())
+        .run();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0], Ok(1));
+    assert!(results[1].is_err());
+    assert_eq!(results[2], Ok(3));
+
+    // シンクがエラーで止めた場合は、残りの値を生成しない
+    let mut written = vec![];
+    let result: Result<(), ParseIntError> = pipeline::from_iter(["1", "x", "3"])
+        .map(str::parse::<i32>)
+        .sink(pipeline::try_for_each(|number: Result<i32, _>| {
+            written.push(number?);
+            Ok(())
+        }))
+        .run();
+    assert!(result.is_err());
+    assert_eq!(written, [1]);
+}
+
+#[test]
+fn pipeline_resumes_one_value_at_a_time() {
+    let mut pipeline = pipeline::from_iter(0..2).sink(pipeline::collect());
+    assert!(matches!(
+        Pin::new(&mut pipeline).resume(()),
+        CoroutineState::Yielded(())
+    ));
+    assert!(matches!(
+        Pin::new(&mut pipeline).resume(()),
+        CoroutineState::Yielded(())
+    ));
+    match Pin::new(&mut pipeline).resume(()) {
+        CoroutineState::Complete(numbers) => assert_eq!(numbers, [0, 1]),
+        CoroutineState::Yielded(()) => panic!("the pipeline should have completed"),
+    }
+}
+
+#[test]
+fn adapters_convert_to_and_from_iterators_streams_and_futures() {
+    let squares: Vec<_> = pipeline::from_iter(1..=3)
+        .map(|n| n * n)
+        .into_iter()
+        .collect();
+    assert_eq!(squares, [1, 4, 9]);
+
+    // from_streamは現在のスレッドをブロックするため、エグゼキューターの外で再開する
+    let streamed: Vec<_> = pipeline::from_stream(futures::stream::iter(["a", "b"]))
+        .into_iter()
+        .collect();
+    assert_eq!(streamed, ["a", "b"]);
+    let streamed = futures::executor::block_on(
+        pipeline::from_iter(["a", "b"])
+            .into_stream()
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(streamed, ["a", "b"]);
+
+    let mut ready = pipeline::from_future(async { 42 });
+    assert!(matches!(
+        Pin::new(&mut ready).resume(()),
+        CoroutineState::Complete(42)
+    ));
+
+    let total = futures::executor::block_on(async {
+        let mut total = 0;
+        pipeline::from_iter([1, 2, 3])
+            .sink(pipeline::for_each(|n| total += n))
+            .await;
+        total
+    });
+    assert_eq!(total, 6);
+}
@@ -0,0 +1,36 @@
+#![cfg(feature = "python")]
+
+use std::{path::Path, process::Command};
+
+/// 拡張モジュールを`async_rust`としてインポートできるように配置して、Pythonのテストを実行する。
+///
+/// インタープリターは環境変数`PYTHON`で指定でき、指定しない場合は`python3`を使用する。
+#[test]
+fn python_integration() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // 共有ライブラリは、テストの実行ファイルと同じディレクトリに出力される
+    let lib_dir = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let module_dir = lib_dir.join("python");
+    std::fs::create_dir_all(&module_dir).unwrap();
+    std::fs::copy(
+        lib_dir.join("libasync_rust.so"),
+        module_dir.join("async_rust.so"),
+    )
+    .unwrap();
+
+    let python = std::env::var("PYTHON").unwrap_or_else(|_| "python3".to_string());
+    let output = Command::new(python)
+        .arg(root.join("tests/python/test_async_rust.py"))
+        .env("PYTHONPATH", &module_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "Python test failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
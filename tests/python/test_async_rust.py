@@ -0,0 +1,76 @@
+import asyncio
+import threading
+import time
+import unittest
+
+import async_rust
+
+
+class JobTest(unittest.TestCase):
+    def test_submit_and_get_result(self):
+        job_id = async_rust.submit_add(1, 2)
+        self.assertIn(async_rust.status(job_id), ("pending", "running"))
+        self.assertIsNone(async_rust.poll(job_id))
+        with self.assertRaises(TimeoutError):
+            async_rust.get_result(job_id, timeout=0.01)
+        self.assertEqual(async_rust.get_result(job_id, timeout=10), 3)
+        # 回収したジョブは削除される
+        with self.assertRaises(KeyError):
+            async_rust.status(job_id)
+
+    def test_get_result_releases_gil(self):
+        job_id = async_rust.submit_add(3, 4)
+        ticks = 0
+        stop = threading.Event()
+
+        def count():
+            nonlocal ticks
+            while not stop.is_set():
+                ticks += 1
+                time.sleep(0.01)
+
+        counter = threading.Thread(target=count)
+        counter.start()
+        try:
+            self.assertEqual(async_rust.get_result(job_id), 7)
+        finally:
+            stop.set()
+            counter.join()
+        # GILを保持したまま待機していれば、カウンターのスレッドは進まない
+        self.assertGreater(ticks, 10)
+
+    def test_cancel(self):
+        job_id = async_rust.submit_add(5, 6)
+        async_rust.cancel(job_id)
+        self.assertEqual(async_rust.status(job_id), "failed")
+        with self.assertRaises(async_rust.JobCancelledError):
+            async_rust.get_result(job_id, timeout=1)
+
+    def test_invalid_arguments(self):
+        with self.assertRaises(ValueError):
+            async_rust.status("not-a-job-id")
+        with self.assertRaises(ValueError):
+            async_rust.get_result(async_rust.submit_add(0, 0), timeout=-1)
+
+
+class AsyncioTest(unittest.TestCase):
+    def test_await_results(self):
+        async def main():
+            jobs = [async_rust.submit_add(i, i) for i in range(3)]
+            return await asyncio.gather(*(async_rust.result_async(job) for job in jobs))
+
+        self.assertEqual(asyncio.run(main()), [0, 2, 4])
+
+    def test_await_cancelled_job(self):
+        async def main():
+            job_id = async_rust.submit_add(1, 1)
+            future = async_rust.result_async(job_id)
+            async_rust.cancel(job_id)
+            await future
+
+        with self.assertRaises(async_rust.JobCancelledError):
+            asyncio.run(main())
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,129 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use async_rust::reactive::{
+    Signal,
+    clock::Clock,
+    thermostat::{Rate, Thermostat},
+};
+use futures::{FutureExt, StreamExt};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 起こされた回数を数えるウェイカー
+#[derive(Default)]
+struct CountingWaker {
+    wakes: AtomicUsize,
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn watchers_are_woken_only_when_the_value_changes() {
+    let signal = Signal::new(20);
+    let mut watcher = signal.watch();
+    let counter = Arc::new(CountingWaker::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    assert_eq!(watcher.poll_next_unpin(&mut cx), Poll::Pending);
+    assert!(!signal.set(20));
+    assert!(!signal.update(|value| *value += 0));
+    assert_eq!(counter.wakes.load(Ordering::SeqCst), 0);
+    assert_eq!(watcher.poll_next_unpin(&mut cx), Poll::Pending);
+
+    // 受け取る前に何度も変わった場合は、最新の値だけを受け取る
+    assert!(signal.set(21));
+    assert!(signal.update(|value| *value += 1));
+    assert_eq!(counter.wakes.load(Ordering::SeqCst), 1);
+    assert_eq!(watcher.poll_next_unpin(&mut cx), Poll::Ready(Some(22)));
+    assert_eq!(watcher.poll_next_unpin(&mut cx), Poll::Pending);
+    assert_eq!(signal.version(), 2);
+
+    drop(signal);
+    assert_eq!(counter.wakes.load(Ordering::SeqCst), 2);
+    assert_eq!(watcher.poll_next_unpin(&mut cx), Poll::Ready(None));
+}
+
+#[tokio::test]
+async fn wait_for_resolves_when_the_predicate_holds() {
+    let signal = Signal::new(false);
+    let mut watcher = signal.watch();
+    assert!(watcher.wait_for(|&on| !on).now_or_never().is_some());
+
+    let setter = signal.clone();
+    let handle = tokio::spawn(async move { watcher.wait_for(|&on| on).await });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    setter.set(true);
+    let value = tokio::time::timeout(TIMEOUT, handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(value, Some(true));
+}
+
+#[tokio::test]
+async fn accelerated_clock_ticks_in_simulated_time() {
+    let clock = Clock::new().with_speed(1_000.0);
+    let mut ticker = clock.ticker(Duration::from_secs(1));
+    let ticks: Vec<_> = tokio::time::timeout(TIMEOUT, ticker.by_ref().take(5).collect())
+        .await
+        .unwrap();
+    assert_eq!(ticks.len(), 5);
+    assert!(ticks.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(ticks[4] >= Duration::from_secs(5));
+
+    let start = clock.elapsed();
+    clock.sleep(Duration::from_secs(10)).await;
+    assert!(clock.elapsed() - start >= Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn independent_thermostats_reach_their_desired_temperatures() {
+    let clock = Clock::new().with_speed(1_000.0);
+    let thermostats: Vec<_> = (0..8)
+        .map(|i| {
+            Thermostat::new(2000, 2010 + i * 5)
+                .with_heating(Rate::new(3, Duration::from_secs(1)))
+                .with_heat_loss(Rate::new(1, Duration::from_secs(1)))
+        })
+        .collect();
+    let _handles: Vec<_> = thermostats
+        .iter()
+        .map(|thermostat| thermostat.spawn(clock))
+        .collect();
+
+    for thermostat in &thermostats {
+        let desired = thermostat.desired().get();
+        let mut temperature = thermostat.temperature().watch();
+        let reached = tokio::time::timeout(TIMEOUT, temperature.wait_for(|&t| t >= desired))
+            .await
+            .unwrap();
+        assert!(reached.unwrap() >= desired);
+    }
+
+    // 設定温度を下げると、ヒーターをOFFにして温度が下がる
+    let thermostat = &thermostats[0];
+    let mut heater_on = thermostat.heater_on().watch();
+    thermostat.desired().set(1990);
+    tokio::time::timeout(TIMEOUT, heater_on.wait_for(|&on| !on))
+        .await
+        .unwrap();
+    let mut temperature = thermostat.temperature().watch();
+    tokio::time::timeout(TIMEOUT, temperature.wait_for(|&t| t < 1990))
+        .await
+        .unwrap();
+    tokio::time::timeout(TIMEOUT, heater_on.wait_for(|&on| on))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,159 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_rust::{
+    coroutine::{
+        generator::Generator,
+        scheduler::{Scheduler, Starvation, Wait},
+    },
+    runtime::FutureType,
+};
+
+/// 再開されるたびに`name`を記録し、`waits`を順に生成するコルーチン
+fn recorder(
+    log: &Arc<Mutex<Vec<&'static str>>>,
+    name: &'static str,
+    waits: Vec<Wait>,
+) -> Generator<'static, Wait> {
+    let log = log.clone();
+    Generator::new(move |co, ()| async move {
+        for wait in waits {
+            log.lock().unwrap().push(name);
+            co.yield_(wait).await;
+        }
+        log.lock().unwrap().push(name);
+    })
+}
+
+#[test]
+fn high_priority_coroutines_are_resumed_first() {
+    let log = Arc::new(Mutex::new(vec![]));
+    let mut scheduler = Scheduler::new();
+    scheduler.spawn(recorder(&log, "low", vec![Wait::Yield]), FutureType::Low);
+    scheduler.spawn(
+        recorder(&log, "high", vec![Wait::Yield, Wait::Yield]),
+        FutureType::High,
+    );
+    scheduler.run();
+    assert_eq!(*log.lock().unwrap(), ["high", "high", "high", "low", "low"]);
+}
+
+#[test]
+fn sleeping_coroutines_wake_in_deadline_order() {
+    let log = Arc::new(Mutex::new(vec![]));
+    let mut scheduler = Scheduler::new();
+    scheduler.spawn(
+        recorder(&log, "slow", vec![Wait::sleep(Duration::from_millis(40))]),
+        FutureType::High,
+    );
+    scheduler.spawn(
+        recorder(&log, "fast", vec![Wait::sleep(Duration::from_millis(10))]),
+        FutureType::Low,
+    );
+    let start = Instant::now();
+    scheduler.run();
+    assert!(start.elapsed() >= Duration::from_millis(40));
+    assert_eq!(*log.lock().unwrap(), ["slow", "fast", "fast", "slow"]);
+}
+
+#[test]
+fn lock_waiters_are_not_resumed_while_the_lock_is_held() {
+    let handle = Arc::new(Mutex::new(0));
+    let resumed = Arc::new(Mutex::new(0));
+    let mut scheduler = Scheduler::new();
+    scheduler.spawn(
+        Generator::new({
+            let handle = handle.clone();
+            let resumed = resumed.clone();
+            move |co, ()| async move {
+                loop {
+                    *resumed.lock().unwrap() += 1;
+                    if let Ok(mut value) = handle.try_lock() {
+                        *value += 1;
+                        break;
+                    }
+                    co.yield_(Wait::lock(&handle)).await;
+                }
+            }
+        }),
+        FutureType::Low,
+    );
+
+    let lock = handle.lock().unwrap();
+    for _ in 0..10 {
+        scheduler.tick();
+    }
+    assert_eq!(*resumed.lock().unwrap(), 1);
+    drop(lock);
+
+    scheduler.run();
+    assert_eq!(*resumed.lock().unwrap(), 2);
+    assert_eq!(*handle.lock().unwrap(), 1);
+}
+
+#[test]
+fn channel_waiters_are_resumed_when_a_message_arrives() {
+    let (sender, receiver) = flume::unbounded();
+    let received = Arc::new(Mutex::new(vec![]));
+    let mut scheduler = Scheduler::new();
+    scheduler.spawn(
+        Generator::new({
+            let received = received.clone();
+            move |co, ()| async move {
+                loop {
+                    match receiver.try_recv() {
+                        Ok(message) => received.lock().unwrap().push(message),
+                        Err(flume::TryRecvError::Empty) => {
+                            co.yield_(Wait::channel(&receiver)).await;
+                        }
+                        Err(flume::TryRecvError::Disconnected) => break,
+                    }
+                }
+            }
+        }),
+        FutureType::Low,
+    );
+
+    assert!(scheduler.tick());
+    assert!(!scheduler.tick());
+    sender.send(1).unwrap();
+    sender.send(2).unwrap();
+    assert!(scheduler.tick());
+    assert_eq!(*received.lock().unwrap(), [1, 2]);
+
+    drop(sender);
+    scheduler.run();
+    assert!(scheduler.is_empty());
+}
+
+#[test]
+fn starved_coroutines_are_reported_once() {
+    let reports = Rc::new(RefCell::new(vec![]));
+    let mut scheduler = Scheduler::new().with_starvation_handler(Duration::from_millis(20), {
+        let reports = reports.clone();
+        move |starvation: Starvation| reports.borrow_mut().push(starvation)
+    });
+    let log = Arc::new(Mutex::new(vec![]));
+    let low = scheduler.spawn(recorder(&log, "low", vec![]), FutureType::Low);
+    // 優先度が高いコルーチンが譲り続けるため、優先度が低いコルーチンは再開されない
+    let deadline = Instant::now() + Duration::from_millis(50);
+    scheduler.spawn(
+        Generator::new(move |co, ()| async move {
+            while Instant::now() < deadline {
+                co.yield_(Wait::Yield).await;
+            }
+        }),
+        FutureType::High,
+    );
+    scheduler.run();
+
+    let reports = reports.borrow();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].task, low);
+    assert_eq!(reports[0].priority, FutureType::Low);
+    assert!(reports[0].waited >= Duration::from_millis(20));
+}
@@ -0,0 +1,243 @@
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_rust::{
+    actor::{
+        Actor, AskError, Context, ExitReason, Reply,
+        supervisor::{Backoff, ChildSpec, Strategy, Supervisor},
+    },
+    blocking,
+    runtime::{
+        self, FutureType,
+        sim::{self, Simulation},
+        time,
+    },
+    spawn_task,
+};
+use futures_lite::future;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// 3つのタスクが交互に記録した順序を返す。
+fn interleaving(seed: u64) -> Vec<usize> {
+    Simulation::new(seed).block_on(async {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let tasks: Vec<_> = (0..3)
+            .map(|id| {
+                let order = order.clone();
+                spawn_task!(async move {
+                    for _ in 0..4 {
+                        order.lock().unwrap().push(id);
+                        future::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await;
+        }
+        Arc::try_unwrap(order).unwrap().into_inner().unwrap()
+    })
+}
+
+#[test]
+fn sleeping_tasks_advance_virtual_time_without_waiting() {
+    let started = Instant::now();
+    let (finished, now) = Simulation::new(1).block_on(async {
+        let long = spawn_task!(async {
+            time::sleep(2 * HOUR).await;
+            sim::now().unwrap()
+        });
+        let short = spawn_task!(
+            async {
+                time::sleep(HOUR).await;
+                sim::now().unwrap()
+            },
+            FutureType::High
+        );
+        ((short.await, long.await), sim::now().unwrap())
+    });
+    assert_eq!(finished, (HOUR, 2 * HOUR));
+    assert_eq!(now, 2 * HOUR);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(sim::now(), None);
+}
+
+#[test]
+fn timeouts_use_the_virtual_clock() {
+    Simulation::new(7).block_on(async {
+        let expired = time::timeout(
+            Duration::from_millis(200),
+            time::sleep(Duration::from_millis(700)),
+        )
+        .await;
+        assert_eq!(expired.unwrap_err().to_string(), "deadline has elapsed");
+        assert_eq!(sim::now(), Some(Duration::from_millis(200)));
+
+        let completed = time::timeout(Duration::from_millis(700), async {
+            time::sleep(Duration::from_millis(200)).await;
+            42
+        })
+        .await;
+        assert_eq!(completed, Ok(42));
+        assert_eq!(sim::now(), Some(Duration::from_millis(400)));
+    });
+}
+
+#[test]
+fn the_same_seed_replays_the_same_interleaving() {
+    let first = interleaving(42);
+    assert_eq!(first.len(), 12);
+    assert_eq!(interleaving(42), first);
+    // シードを変えると、異なる順序でポーリングされる
+    assert!((0..16).any(|seed| interleaving(seed) != first));
+}
+
+#[test]
+fn local_tasks_run_on_the_simulation_thread() {
+    let value = Simulation::new(3).block_on(async {
+        let shared = Rc::new(5);
+        let task = runtime::spawn_local({
+            let shared = shared.clone();
+            async move {
+                time::sleep(Duration::from_secs(1)).await;
+                *shared * 2
+            }
+        });
+        task.await
+    });
+    assert_eq!(value, 10);
+}
+
+#[test]
+#[should_panic(expected = "simulation deadlocked")]
+fn waiting_forever_is_reported_as_a_deadlock() {
+    Simulation::new(0).block_on(async {
+        let (_sender, receiver) = flume::bounded::<()>(1);
+        let _ = receiver.recv_async().await;
+    });
+}
+
+#[test]
+fn sleep_outside_a_simulation_waits_in_real_time() {
+    let started = Instant::now();
+    let result = future::block_on(time::timeout(
+        Duration::from_secs(5),
+        time::sleep(Duration::from_millis(20)),
+    ));
+    assert_eq!(result, Ok(()));
+    assert!(started.elapsed() >= Duration::from_millis(20));
+
+    // 実時間のタイマーはタイマー用のスレッドで待機し、ブロッキング処理用のスレッドを使わない
+    let sleeps: Vec<_> = (0..100).map(|_| time::sleep(HOUR)).collect();
+    let result = future::block_on(time::timeout(
+        Duration::from_millis(20),
+        futures::future::join_all(sleeps),
+    ));
+    assert!(result.is_err());
+    assert_eq!(blocking::thread_count(), 0);
+}
+
+enum WorkerMessage {
+    /// 指定した時間の後に応答する
+    Slow(Duration, Reply<()>),
+    /// 処理を終えない
+    Hang,
+    Ping(Reply<usize>),
+}
+
+/// 起動した回数を応答するアクター
+struct Worker {
+    starts: Arc<Mutex<usize>>,
+}
+
+impl Actor for Worker {
+    type Message = WorkerMessage;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        *self.starts.lock().unwrap() += 1;
+    }
+
+    async fn handle(&mut self, msg: WorkerMessage, _ctx: &mut Context<Self>) {
+        match msg {
+            WorkerMessage::Slow(duration, reply) => {
+                time::sleep(duration).await;
+                let _ = reply.send(());
+            }
+            WorkerMessage::Hang => std::future::pending().await,
+            WorkerMessage::Ping(reply) => {
+                let _ = reply.send(*self.starts.lock().unwrap());
+            }
+        }
+    }
+}
+
+/// スーパーバイザーが停滞したアクターを再起動するまでの、仮想時間の経過を記録する。
+fn supervise_stalled_worker(simulation: Simulation) -> Vec<(String, Duration)> {
+    simulation.block_on(async {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut supervisor = Supervisor::new("root", Strategy::OneForOne)
+            .with_backoff(Backoff::new(MINUTE, HOUR))
+            .on_child_exit({
+                let events = events.clone();
+                move |name, reason| {
+                    let event = format!("{name} {reason}");
+                    events.lock().unwrap().push((event, sim::now().unwrap()));
+                }
+            });
+        let starts = Arc::new(Mutex::new(0));
+        let addr = supervisor.add(
+            ChildSpec::new("worker", move || Worker {
+                starts: starts.clone(),
+            })
+            .with_stall_timeout(5 * MINUTE),
+        );
+        let handle = supervisor.start();
+        let record = |event: &str| {
+            let now = sim::now().unwrap();
+            events.lock().unwrap().push((event.to_string(), now));
+        };
+
+        // 応答が遅いと、要求した側はタイムアウトする
+        let result = addr
+            .ask(|reply| WorkerMessage::Slow(2 * MINUTE, reply), MINUTE)
+            .await;
+        assert_eq!(result, Err(AskError::Timeout));
+        record("ask timed out");
+
+        // 処理を終えないアクターは停滞したとみなされ、待機時間の後に再起動される
+        addr.send(WorkerMessage::Hang).await.unwrap();
+        let starts = addr.ask(WorkerMessage::Ping, HOUR).await.unwrap();
+        assert_eq!(starts, 2);
+        record("restarted");
+
+        assert_eq!(handle.shutdown().await, ExitReason::Shutdown);
+        Arc::try_unwrap(events).unwrap().into_inner().unwrap()
+    })
+}
+
+#[test]
+fn supervisors_and_actors_use_the_virtual_clock() {
+    let started = Instant::now();
+    let seeds = match std::env::var(sim::SEED_ENV) {
+        Ok(_) => vec![Simulation::from_env()],
+        Err(_) => (0..8).map(Simulation::new).collect(),
+    };
+    for simulation in seeds {
+        let events = supervise_stalled_worker(simulation);
+        assert_eq!(
+            events,
+            [
+                ("ask timed out".to_string(), MINUTE),
+                ("worker stalled".to_string(), 7 * MINUTE),
+                ("restarted".to_string(), 8 * MINUTE),
+                ("worker shutdown".to_string(), 8 * MINUTE),
+            ]
+        );
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
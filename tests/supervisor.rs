@@ -0,0 +1,319 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_rust::actor::{
+    Actor, Addr, Context, ExitReason, Reply,
+    supervisor::{Backoff, ChildSpec, MailboxPolicy, Restart, Strategy, Supervisor},
+};
+
+type Events = Arc<Mutex<Vec<String>>>;
+
+enum WorkerMessage {
+    Add(i64),
+    Get(Reply<i64>),
+    Panic,
+    Sleep(Duration),
+}
+
+struct Worker {
+    name: &'static str,
+    total: i64,
+    events: Events,
+}
+
+impl Actor for Worker {
+    type Message = WorkerMessage;
+
+    async fn started(&mut self, _ctx: &mut Context<Self>) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("{} started", self.name));
+    }
+
+    async fn handle(&mut self, msg: WorkerMessage, _ctx: &mut Context<Self>) {
+        match msg {
+            WorkerMessage::Add(value) => self.total += value,
+            WorkerMessage::Get(reply) => {
+                let _ = reply.send(self.total);
+            }
+            WorkerMessage::Panic => panic!("{} failed", self.name),
+            WorkerMessage::Sleep(duration) => tokio::time::sleep(duration).await,
+        }
+    }
+
+    async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("{} stopping", self.name));
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn worker(name: &'static str, events: &Events) -> ChildSpec<Worker> {
+    let events = events.clone();
+    ChildSpec::new(name, move || Worker {
+        name,
+        total: 0,
+        events: events.clone(),
+    })
+}
+
+fn supervisor(strategy: Strategy) -> Supervisor {
+    Supervisor::new("test", strategy).with_backoff(Backoff::none())
+}
+
+async fn get(addr: &Addr<Worker>) -> i64 {
+    addr.ask(WorkerMessage::Get, TIMEOUT).await.unwrap()
+}
+
+fn count(events: &Events, event: &str) -> usize {
+    events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|e| *e == event)
+        .count()
+}
+
+/// 各戦略で、異常終了した子に応じて再起動される子を確認する。
+#[tokio::test]
+async fn restart_strategies() {
+    for (strategy, expected) in [
+        (Strategy::OneForOne, [1, 2, 1]),
+        (Strategy::OneForAll, [2, 2, 2]),
+        (Strategy::RestForOne, [1, 2, 2]),
+    ] {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut sup = supervisor(strategy);
+        let a = sup.add(worker("a", &events));
+        let b = sup.add(worker("b", &events));
+        let c = sup.add(worker("c", &events));
+        let handle = sup.start();
+
+        b.send(WorkerMessage::Panic).await.unwrap();
+        for addr in [&b, &a, &c] {
+            assert_eq!(get(addr).await, 0);
+        }
+        let starts = ["a", "b", "c"].map(|name| count(&events, &format!("{name} started")));
+        assert_eq!(starts, expected, "{strategy:?}");
+        // 異常終了した子の`stopping`は呼び出されず、停止させられた子の`stopping`は呼び出される
+        assert_eq!(count(&events, "b stopping"), 0, "{strategy:?}");
+        assert_eq!(
+            count(&events, "c stopping"),
+            expected[2] - 1,
+            "{strategy:?}"
+        );
+
+        assert_eq!(handle.shutdown().await, ExitReason::Shutdown);
+        assert!(a.is_stopped() && b.is_stopped() && c.is_stopped());
+    }
+}
+
+#[tokio::test]
+async fn shutdown_stops_children_in_reverse_order() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne);
+    let a = sup.add(worker("a", &events));
+    let b = sup.add(worker("b", &events));
+    let handle = sup.start();
+    assert_eq!(get(&a).await + get(&b).await, 0);
+
+    assert_eq!(handle.shutdown().await, ExitReason::Shutdown);
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["a started", "b started", "b stopping", "a stopping"]
+    );
+}
+
+#[tokio::test]
+async fn restart_policies() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne);
+    let permanent = sup.add(worker("permanent", &events).with_restart(Restart::Permanent));
+    let transient = sup.add(worker("transient", &events));
+    let temporary = sup.add(worker("temporary", &events).with_restart(Restart::Temporary));
+    let handle = sup.start();
+
+    permanent.stop().await;
+    transient.stop().await;
+    temporary.send(WorkerMessage::Panic).await.unwrap();
+    temporary.stopped().await;
+    transient.stopped().await;
+
+    // 正常終了した`Permanent`の子は再起動され、`Transient`と`Temporary`の子は再起動されない
+    assert_eq!(get(&permanent).await, 0);
+    assert_eq!(count(&events, "permanent started"), 2);
+    assert_eq!(count(&events, "transient started"), 1);
+    assert_eq!(count(&events, "temporary started"), 1);
+    assert!(!permanent.is_stopped());
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn escalates_when_restart_intensity_is_exceeded() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne).with_intensity(2, Duration::from_secs(10));
+    let a = sup.add(worker("a", &events));
+    let b = sup.add(worker("b", &events));
+    let handle = sup.start();
+
+    for _ in 0..3 {
+        a.send(WorkerMessage::Panic).await.unwrap();
+    }
+    assert_eq!(handle.stopped().await, ExitReason::Escalated);
+    assert_eq!(count(&events, "a started"), 3);
+    assert!(a.is_stopped() && b.is_stopped());
+    assert_eq!(count(&events, "b stopping"), 1);
+}
+
+#[tokio::test]
+async fn nested_supervisor_is_restarted_by_its_parent() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let exits = Arc::new(Mutex::new(vec![]));
+    let mut inner = supervisor(Strategy::OneForOne).with_intensity(0, Duration::from_secs(10));
+    let a = inner.add(worker("a", &events));
+    let mut outer = supervisor(Strategy::OneForOne).on_child_exit({
+        let exits = exits.clone();
+        move |name, reason| exits.lock().unwrap().push(format!("{name}: {reason}"))
+    });
+    outer.add_supervisor(Supervisor::new("inner", Strategy::OneForOne));
+    outer.add_supervisor(inner);
+    let handle = outer.start();
+
+    a.send(WorkerMessage::Panic).await.unwrap();
+    assert_eq!(get(&a).await, 0);
+    assert_eq!(count(&events, "a started"), 2);
+    assert_eq!(*exits.lock().unwrap(), ["inner: normal", "test: escalated"]);
+
+    handle.shutdown().await;
+    assert!(a.is_stopped());
+}
+
+#[tokio::test]
+async fn mailbox_is_handed_over_on_restart() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne);
+    let a = sup.add(worker("a", &events));
+    let handle = sup.start();
+
+    a.send(WorkerMessage::Sleep(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    a.send(WorkerMessage::Add(1)).await.unwrap();
+    a.send(WorkerMessage::Panic).await.unwrap();
+    a.send(WorkerMessage::Add(2)).await.unwrap();
+    a.send(WorkerMessage::Add(3)).await.unwrap();
+
+    // パニックする前の状態は失われるが、メールボックスに残っていたメッセージは新しいインスタンスが処理する
+    assert_eq!(get(&a).await, 5);
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn mailbox_is_dead_lettered_on_restart() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let dead_letters = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne);
+    let a = sup.add(
+        worker("a", &events)
+            .with_mailbox_policy(MailboxPolicy::DeadLetter)
+            .with_dead_letter_handler({
+                let dead_letters = dead_letters.clone();
+                move |msg| {
+                    if let WorkerMessage::Add(value) = msg {
+                        dead_letters.lock().unwrap().push(value);
+                    }
+                }
+            }),
+    );
+    let handle = sup.start();
+
+    a.send(WorkerMessage::Sleep(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    a.send(WorkerMessage::Panic).await.unwrap();
+    a.send(WorkerMessage::Add(2)).await.unwrap();
+    a.send(WorkerMessage::Add(3)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(get(&a).await, 0);
+    assert_eq!(*dead_letters.lock().unwrap(), [2, 3]);
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn stalled_child_is_restarted() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let exits = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne).on_child_exit({
+        let exits = exits.clone();
+        move |name, reason| exits.lock().unwrap().push(format!("{name}: {reason}"))
+    });
+    let a = sup.add(worker("a", &events).with_stall_timeout(Duration::from_millis(50)));
+    let handle = sup.start();
+
+    a.send(WorkerMessage::Sleep(Duration::from_secs(10)))
+        .await
+        .unwrap();
+    assert_eq!(get(&a).await, 0);
+    assert_eq!(*exits.lock().unwrap(), ["a: stalled"]);
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn killed_child_is_restarted() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne);
+    let a = sup.add(worker("a", &events));
+    let handle = sup.start();
+    assert_eq!(get(&a).await, 0);
+
+    a.send(WorkerMessage::Sleep(Duration::from_secs(10)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    a.kill();
+    assert_eq!(get(&a).await, 0);
+    assert_eq!(count(&events, "a started"), 2);
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn unresponsive_child_is_killed_after_shutdown_timeout() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let exits = Arc::new(Mutex::new(vec![]));
+    let mut sup = supervisor(Strategy::OneForOne)
+        .with_shutdown_timeout(Duration::from_millis(50))
+        .on_child_exit({
+            let exits = exits.clone();
+            move |name, reason| exits.lock().unwrap().push(format!("{name}: {reason}"))
+        });
+    let a = sup.add(worker("a", &events));
+    let handle = sup.start();
+    assert_eq!(get(&a).await, 0);
+
+    // 処理中のメッセージが終わらないため、停止の要求に応じない
+    a.send(WorkerMessage::Sleep(Duration::from_secs(60)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let reason = tokio::time::timeout(TIMEOUT, handle.shutdown())
+        .await
+        .expect("supervisor must kill the child after the shutdown timeout");
+    assert_eq!(reason, ExitReason::Shutdown);
+    assert_eq!(*exits.lock().unwrap(), ["a: killed"]);
+    assert!(a.is_stopped());
+}
+
+#[test]
+fn backoff_doubles_up_to_max() {
+    let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+    let delays = (1..=4).map(|attempt| backoff.delay(attempt).as_millis());
+    assert_eq!(delays.collect::<Vec<_>>(), [10, 20, 40, 50]);
+}
@@ -0,0 +1,95 @@
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    task::{Context, Poll},
+};
+
+use async_rust::{
+    per_thread::PerThread,
+    runtime::{FutureType, Runtime},
+    spawn_task, task_local,
+};
+
+task_local! {
+    static COUNTER: Cell<u32> = Cell::new(0);
+    static WORKERS: std::cell::RefCell<HashSet<std::thread::ThreadId>> = Default::default();
+}
+
+static TOTAL: PerThread<u32> = PerThread::new(|| 0);
+
+/// 1回だけPendingを返して、ワーカーにタスクを再度スケジューリングさせる。
+async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// タスクローカルのカウンターを`n`回インクリメントし、そのたびにワーカーに制御を返す。
+///
+/// カウンターの値と、タスクをポーリングしたスレッドの数を返す。
+async fn count(n: u32, on_increment: fn()) -> (u32, usize) {
+    for _ in 0..n {
+        COUNTER.with(|counter| counter.set(counter.get() + 1));
+        WORKERS.with(|workers| workers.borrow_mut().insert(std::thread::current().id()));
+        on_increment();
+        yield_now().await;
+    }
+    (
+        COUNTER.with(Cell::get),
+        WORKERS.with(|workers| workers.borrow().len()),
+    )
+}
+
+#[test]
+fn task_local_values_follow_the_task() {
+    Runtime::new().with_high_num(2).with_low_num(2).run();
+
+    let tasks: Vec<_> = (0..16)
+        .map(|i| {
+            let order = if i % 2 == 0 {
+                FutureType::High
+            } else {
+                FutureType::Low
+            };
+            spawn_task!(count(50, || TOTAL.with(|total| *total += 1)), order)
+        })
+        .collect();
+    let results: Vec<_> = tasks
+        .into_iter()
+        .map(futures_lite::future::block_on)
+        .collect();
+
+    // どのワーカーでポーリングされても、タスクごとに独立した値が保持される
+    assert!(results.iter().all(|(count, _)| *count == 50));
+    // スレッドごとの値を集計すると、すべてのタスクの合計になる
+    assert_eq!(TOTAL.fold(0, |sum, total| sum + total), 16 * 50);
+
+    // タスクの外からはアクセスできない
+    assert!(COUNTER.try_with(|_| ()).is_err());
+}
+
+#[test]
+fn task_local_values_survive_migration() {
+    let mut future = Box::pin(task_local::scope(count(2, || ())));
+    let waker = futures::task::noop_waker();
+
+    // 別々のスレッドでポーリングしても、同じタスクの値を参照する
+    for _ in 0..2 {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut cx = Context::from_waker(&waker);
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            });
+        });
+    }
+    let mut cx = Context::from_waker(&waker);
+    assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready((2, 2)));
+}
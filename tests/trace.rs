@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use async_rust::{
+    runtime::{FutureType, Runtime},
+    spawn_task,
+    trace::{self, MemorySubscriber, Priority, TaskEventKind},
+};
+
+/// 指定したタスクで`last`のイベントが発生するまで待機して、イベントの種類を返す。
+fn wait_for(
+    subscriber: &MemorySubscriber,
+    task_id: u64,
+    last: TaskEventKind,
+) -> Vec<TaskEventKind> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let kinds = subscriber.kinds_of(task_id);
+        if kinds.contains(&last) || Instant::now() > deadline {
+            return kinds;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn task_lifecycle_events() {
+    Runtime::new().with_high_num(1).with_low_num(1).run();
+    let subscriber = MemorySubscriber::new();
+    trace::set_subscriber(subscriber.clone());
+
+    // 完了するタスク
+    let task = spawn_task!(async { 1 + 1 }, FutureType::High);
+    let id = task.metadata().id();
+    assert_eq!(futures_lite::future::block_on(task), 2);
+    assert_eq!(
+        wait_for(&subscriber, id, TaskEventKind::Completed),
+        [
+            TaskEventKind::Spawned,
+            TaskEventKind::PollStarted,
+            TaskEventKind::PollEnded,
+            TaskEventKind::Completed,
+        ]
+    );
+    let events = subscriber.events();
+    let spawned = events.iter().find(|e| e.task_id == id).unwrap();
+    assert_eq!(spawned.priority, Some(Priority::High));
+    assert_eq!(spawned.worker_id, None);
+    assert!(
+        events
+            .iter()
+            .filter(|e| e.task_id == id && e.kind != TaskEventKind::Spawned)
+            .all(|e| e.worker_id.is_some())
+    );
+
+    // 起床されてから完了するタスク
+    let task = spawn_task!(async {
+        let mut yielded = false;
+        std::future::poll_fn(|cx| {
+            if yielded {
+                std::task::Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    });
+    let id = task.metadata().id();
+    futures_lite::future::block_on(task);
+    let kinds = wait_for(&subscriber, id, TaskEventKind::Completed);
+    assert_eq!(
+        kinds,
+        [
+            TaskEventKind::Spawned,
+            TaskEventKind::PollStarted,
+            TaskEventKind::Woken,
+            TaskEventKind::PollEnded,
+            TaskEventKind::PollStarted,
+            TaskEventKind::PollEnded,
+            TaskEventKind::Completed,
+        ]
+    );
+
+    // パニックするタスク
+    let task = spawn_task!(async { panic!("boom") }, FutureType::Low);
+    let id = task.metadata().id();
+    assert_eq!(
+        wait_for(&subscriber, id, TaskEventKind::Panicked),
+        [
+            TaskEventKind::Spawned,
+            TaskEventKind::PollStarted,
+            TaskEventKind::Panicked,
+        ]
+    );
+    drop(task);
+
+    // 完了する前にハンドルを破棄されたタスク
+    let task = spawn_task!(std::future::pending::<()>());
+    let id = task.metadata().id();
+    wait_for(&subscriber, id, TaskEventKind::PollEnded);
+    drop(task);
+    // キャンセルされたタスクはワーカーで破棄される
+    assert_eq!(
+        wait_for(&subscriber, id, TaskEventKind::Cancelled)[3..6],
+        [
+            TaskEventKind::Woken,
+            TaskEventKind::PollStarted,
+            TaskEventKind::Cancelled,
+        ]
+    );
+
+    trace::clear_subscriber();
+}